@@ -0,0 +1,19 @@
+use std::process::Command;
+
+// Embeds the commit tydle is built from as `TYDLE_GIT_COMMIT`, for `--version`. Builds outside of a
+// git checkout, like from crates.io, go without it.
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=TYDLE_GIT_COMMIT={}", commit.trim());
+    }
+}
@@ -0,0 +1,62 @@
+/* Generated by scripts/generate-header.sh, do not edit. */
+
+#ifndef TYDLE_H
+#define TYDLE_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Blocking wrapper of `tydle::Tydle`. Cloning is cheap, clones share the runtime and the caches.
+ */
+typedef struct Tydle Tydle;
+
+/**
+ * Create an instance with the default options, or NULL if that failed.
+ */
+Tydle *tydle_new(void);
+
+/**
+ * The `YtStreamResponse` of the video `video_id` as JSON.
+ *
+ * # Safety
+ *
+ * `tydle` must come from `tydle_new` and `video_id` must be a NUL-terminated string.
+ */
+char *tydle_get_streams_json(const Tydle *tydle, const char *video_id);
+
+/**
+ * The final URL of the stream `itag` of `streams_json`, as returned by `tydle_get_streams_json`.
+ *
+ * # Safety
+ *
+ * `tydle` must come from `tydle_new` and `streams_json` must be a NUL-terminated string.
+ */
+char *tydle_resolve_url(const Tydle *tydle, const char *streams_json, uint16_t itag);
+
+/**
+ * Free an instance created by `tydle_new`. Does nothing with NULL.
+ *
+ * # Safety
+ *
+ * `tydle` must come from `tydle_new` and must not be used afterwards.
+ */
+void tydle_free(Tydle *tydle);
+
+/**
+ * Free a string returned by tydle. Does nothing with NULL.
+ *
+ * # Safety
+ *
+ * `s` must come from tydle and must not be used afterwards.
+ */
+void tydle_string_free(char *s);
+
+/**
+ * Why the last call on this thread failed, or NULL. Valid until the next failure on the thread.
+ */
+const char *tydle_last_error(void);
+
+#endif  /* TYDLE_H */
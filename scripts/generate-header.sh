@@ -0,0 +1,8 @@
+#!/bin/sh
+# Generate include/tydle.h for the C API of the `capi` feature with cbindgen
+# (`cargo install cbindgen`).
+set -eu
+
+DIR="$(dirname "$0")/.."
+
+cbindgen --config "$DIR/cbindgen.toml" --crate tydle --output "$DIR/include/tydle.h" "$DIR"
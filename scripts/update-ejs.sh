@@ -0,0 +1,12 @@
+#!/bin/sh
+# Download yt-dlp's EJS solver into src/cipher/ejs for the `vendored-ejs` feature.
+# The version must match `EJS_VERSION` in src/cipher/js.rs.
+set -eu
+
+VERSION="${1:-0.3.1}"
+DIR="$(dirname "$0")/../src/cipher/ejs"
+
+mkdir -p "$DIR"
+for file in yt.solver.lib.min.js yt.solver.core.min.js; do
+    curl -fsSL -o "$DIR/$file" "https://github.com/yt-dlp/ejs/releases/download/$VERSION/$file"
+done
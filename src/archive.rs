@@ -0,0 +1,65 @@
+use std::{collections::HashSet, path::PathBuf, sync::Mutex};
+
+use anyhow::{Result, anyhow};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+/// Extractor name written before each ID, as yt-dlp does, so that the archives of both can be
+/// shared.
+const EXTRACTOR: &str = "youtube";
+
+/// The IDs of the videos already downloaded, as listed in `--download-archive`.
+pub struct DownloadArchive {
+    path: PathBuf,
+    video_ids: Mutex<HashSet<String>>,
+}
+
+impl DownloadArchive {
+    /// Read the archive at `path`, which is created by the first download when it doesn't exist.
+    pub async fn load<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let content = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let video_ids = content
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix(EXTRACTOR))
+            .map(|video_id| video_id.trim().to_string())
+            .filter(|video_id| !video_id.is_empty())
+            .collect();
+
+        Ok(Self {
+            path,
+            video_ids: Mutex::new(video_ids),
+        })
+    }
+
+    pub fn contains(&self, video_id: &str) -> bool {
+        self.video_ids
+            .lock()
+            .is_ok_and(|video_ids| video_ids.contains(video_id))
+    }
+
+    /// Append `video_id` to the archive.
+    pub async fn record(&self, video_id: &str) -> Result<()> {
+        if !self
+            .video_ids
+            .lock()
+            .map_err(|e| anyhow!("{}", e))?
+            .insert(video_id.to_string())
+        {
+            return Ok(());
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(format!("{} {}\n", EXTRACTOR, video_id).as_bytes())
+            .await?;
+
+        Ok(())
+    }
+}
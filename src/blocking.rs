@@ -0,0 +1,186 @@
+//! A blocking version of `Tydle`, for programs that aren't async like GUI apps and scripts.
+//!
+//! Like `reqwest::blocking`, it drives the async API on an internal runtime, so its methods must
+//! not be called from within an async runtime.
+//!
+//! ```no_run
+//! use tydle::{TydleOptions, VideoId, blocking::Tydle};
+//!
+//! let tydle = Tydle::new(TydleOptions::default()).unwrap();
+//! let video_info = tydle.get_video_info(&VideoId::new("dQw4w9WgXcQ").unwrap()).unwrap();
+//! println!("{}", video_info.title);
+//! ```
+
+use std::{future::Future, sync::Arc};
+
+use anyhow::Result;
+use tokio::runtime::{Builder, Runtime};
+
+#[cfg(feature = "decipher")]
+use crate::{Cipher, yt_interface::YtStream};
+use crate::{
+    Extract, Fetch, TydleError, TydleOptions,
+    cookies::DomainCookies,
+    metrics::TydleMetrics,
+    yt_interface::{
+        PlaylistId, VideoId, YtChannel, YtChannelFeed, YtManifest, YtPlaylist, YtStreamResponse,
+        YtSubtitle, YtSubtitleFormat, YtThumbnail, YtVideoInfo,
+    },
+};
+
+/// Blocking wrapper of `tydle::Tydle`. Cloning is cheap, clones share the runtime and the caches.
+#[derive(Clone)]
+pub struct Tydle {
+    inner: crate::Tydle,
+    runtime: Arc<Runtime>,
+}
+
+impl Tydle {
+    pub fn new(options: TydleOptions) -> Result<Self> {
+        // A worker thread keeps the tasks tydle spawns, like warming the player up, running
+        // between calls.
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("tydle-blocking")
+            .enable_all()
+            .build()?;
+        let inner = {
+            let _guard = runtime.enter();
+            crate::Tydle::new(options)?
+        };
+
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// The async `Tydle` this one wraps.
+    pub fn inner(&self) -> &crate::Tydle {
+        &self.inner
+    }
+
+    fn block_on<F: Future>(&self, fut: F) -> F::Output {
+        self.runtime.block_on(fut)
+    }
+
+    /// See `Extract::get_manifest`.
+    pub fn get_manifest(&self, video_id: &VideoId) -> Result<YtManifest, TydleError> {
+        self.block_on(self.inner.get_manifest(video_id))
+    }
+
+    /// See `Extract::get_video_info`.
+    pub fn get_video_info(&self, video_id: &VideoId) -> Result<YtVideoInfo, TydleError> {
+        self.block_on(self.inner.get_video_info(video_id))
+    }
+
+    /// See `Extract::get_video_info_from_manifest`.
+    pub fn get_video_info_from_manifest(
+        &self,
+        manifest: &YtManifest,
+    ) -> Result<YtVideoInfo, TydleError> {
+        self.block_on(self.inner.get_video_info_from_manifest(manifest))
+    }
+
+    /// See `Extract::get_streams_from_manifest`.
+    pub fn get_streams_from_manifest(
+        &self,
+        manifest: &YtManifest,
+    ) -> Result<YtStreamResponse, TydleError> {
+        self.block_on(self.inner.get_streams_from_manifest(manifest))
+    }
+
+    /// See `Extract::get_streams`.
+    pub fn get_streams(&self, video_id: &VideoId) -> Result<YtStreamResponse, TydleError> {
+        self.block_on(self.inner.get_streams(video_id))
+    }
+
+    /// See `Extract::get_playlist`.
+    pub fn get_playlist(&self, playlist_id: &PlaylistId) -> Result<YtPlaylist, TydleError> {
+        self.block_on(self.inner.get_playlist(playlist_id))
+    }
+
+    /// See `Extract::get_channel_feed`.
+    pub fn get_channel_feed(&self, channel: &YtChannel) -> Result<YtChannelFeed, TydleError> {
+        self.block_on(self.inner.get_channel_feed(channel))
+    }
+
+    /// See `Extract::get_subscriptions_feed`.
+    pub fn get_subscriptions_feed(&self) -> Result<YtPlaylist, TydleError> {
+        self.block_on(self.inner.get_subscriptions_feed())
+    }
+
+    /// See `Fetch::get_subtitle`.
+    pub fn get_subtitle(
+        &self,
+        subtitle: &YtSubtitle,
+        format: YtSubtitleFormat,
+    ) -> Result<String, TydleError> {
+        self.block_on(self.inner.get_subtitle(subtitle, format))
+    }
+
+    /// See `Fetch::get_thumbnail`.
+    pub fn get_thumbnail(&self, thumbnail: &YtThumbnail) -> Result<Vec<u8>, TydleError> {
+        self.block_on(self.inner.get_thumbnail(thumbnail))
+    }
+
+    /// See `Cipher::decipher_signature`.
+    #[cfg(feature = "decipher")]
+    pub fn decipher_signature(
+        &self,
+        signature: &str,
+        player_url: &str,
+    ) -> Result<String, TydleError> {
+        self.block_on(self.inner.decipher_signature(signature, player_url))
+    }
+
+    /// See `Cipher::decipher_url`.
+    #[cfg(feature = "decipher")]
+    pub fn decipher_url(&self, url: &str, player_url: &str) -> Result<String, TydleError> {
+        self.block_on(self.inner.decipher_url(url, player_url))
+    }
+
+    /// See `tydle::Tydle::resolve_url`.
+    #[cfg(feature = "decipher")]
+    pub fn resolve_url(
+        &self,
+        stream: &YtStream,
+        stream_response: &YtStreamResponse,
+    ) -> Result<String, TydleError> {
+        self.block_on(self.inner.resolve_url(stream, stream_response))
+    }
+
+    /// See `Cipher::resolve_streams`.
+    #[cfg(feature = "decipher")]
+    pub fn resolve_streams(
+        &self,
+        stream_response: YtStreamResponse,
+    ) -> Result<YtStreamResponse, TydleError> {
+        self.block_on(self.inner.resolve_streams(stream_response))
+    }
+
+    /// See `tydle::Tydle::warm_player`.
+    pub fn warm_player(&self, video_id: &VideoId) -> Result<(), TydleError> {
+        self.block_on(self.inner.warm_player(video_id))
+    }
+
+    /// See `tydle::Tydle::check_auth`.
+    pub fn check_auth(&self) -> Result<(), TydleError> {
+        self.block_on(self.inner.check_auth())
+    }
+
+    /// See `tydle::Tydle::export_cookies`.
+    pub fn export_cookies(&self) -> Result<DomainCookies, TydleError> {
+        self.inner.export_cookies()
+    }
+
+    /// See `tydle::Tydle::metrics`.
+    pub fn metrics(&self) -> Result<TydleMetrics, TydleError> {
+        self.inner.metrics()
+    }
+
+    /// See `tydle::Tydle::clear_player_caches`.
+    pub fn clear_player_caches(&self) -> Result<(), TydleError> {
+        self.inner.clear_player_caches()
+    }
+}
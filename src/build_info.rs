@@ -0,0 +1,59 @@
+//! What this build of tydle is made of, for bug reports.
+
+use crate::{extractor::client::INNERTUBE_CLIENTS, yt_interface::YtClient};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Commit tydle was built from, when it was built from a git checkout.
+pub const GIT_COMMIT: Option<&str> = option_env!("TYDLE_GIT_COMMIT");
+/// Release of yt-dlp's EJS solver signatures are deciphered with.
+#[cfg(feature = "decipher")]
+pub use crate::cipher::EJS_VERSION;
+
+/// Cargo features tydle was built with.
+pub fn features() -> Vec<&'static str> {
+    [
+        ("logging", cfg!(feature = "logging")),
+        ("cli", cfg!(feature = "cli")),
+        ("blocking", cfg!(feature = "blocking")),
+        ("serde", cfg!(feature = "serde")),
+        ("capi", cfg!(feature = "capi")),
+        ("uniffi", cfg!(feature = "uniffi")),
+        ("decipher", cfg!(feature = "decipher")),
+        ("cipher", cfg!(feature = "cipher")),
+        ("quickjs", cfg!(feature = "quickjs")),
+        ("boa", cfg!(feature = "boa")),
+        ("vendored-ejs", cfg!(feature = "vendored-ejs")),
+        ("server", cfg!(feature = "server")),
+        ("cast", cfg!(feature = "cast")),
+        ("redis-cache", cfg!(feature = "redis-cache")),
+        ("self-update", cfg!(feature = "self-update")),
+        ("test-fixtures", cfg!(feature = "test-fixtures")),
+        ("browser-cookies", cfg!(feature = "browser-cookies")),
+        (
+            "encrypted-credentials",
+            cfg!(feature = "encrypted-credentials"),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(feature, enabled)| enabled.then_some(feature))
+    .collect()
+}
+
+/// The `clientVersion` every InnerTube client is sent with.
+pub fn client_versions() -> Vec<(YtClient, &'static str)> {
+    let mut versions = INNERTUBE_CLIENTS
+        .iter()
+        .map(|(client, innertube_client)| {
+            let version = innertube_client
+                .innertube_context
+                .get("client")
+                .and_then(|context| context.get("clientVersion"))
+                .and_then(|version| version.as_str())
+                .unwrap_or_default();
+            (*client, version)
+        })
+        .collect::<Vec<_>>();
+    versions.sort_by_key(|(client, _)| *client);
+
+    versions
+}
@@ -1,73 +1,547 @@
-use std::{collections::HashMap, hash::Hash, sync::RwLock};
+use std::{collections::HashMap, sync::RwLock, time::Duration};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::{Result, anyhow};
 use fancy_regex::Regex;
 use url::Url;
 
-pub struct CacheStore<T = String> {
-    cache: RwLock<HashMap<T, String>>,
-}
+use crate::{error::TydleError, http::MaybeSendSync, utils::unix_timestamp_secs};
 
-impl CacheStore {
-    pub fn new<T>() -> CacheStore<T> {
-        CacheStore {
-            cache: Default::default(),
-        }
+/// How long entries of the default `DiskCache` are kept, unless they were added with a shorter TTL.
+#[cfg(not(target_arch = "wasm32"))]
+pub const DISK_CACHE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Key of the visitor data reused across extractions.
+pub(crate) const VISITOR_DATA_KEY: &str = "youtube-visitor-data";
+/// How long visitor data is reused before YouTube is asked for a new one.
+pub(crate) const VISITOR_DATA_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Prefix of the keys of PO tokens, followed by the client, visitor data, data sync ID and video.
+pub(crate) const PO_TOKEN_KEY_PREFIX: &str = "youtube-po-token/";
+/// How long a PO token is reused for the same client and video.
+pub(crate) const PO_TOKEN_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+/// Prefix of the keys of player JS, followed by the `player_js_cache_key` of the player.
+pub(crate) const PLAYER_JS_KEY_PREFIX: &str = "youtube-player/";
+/// Prefixes of the keys of everything derived from a player, which stops being valid with it.
+pub(crate) const PLAYER_KEY_PREFIXES: [&str; 4] = [
+    PLAYER_JS_KEY_PREFIX,
+    "youtube-sts/",
+    "youtube-sig/",
+    "youtube-n/",
+];
+
+/// Prefix of the first line of `DiskCache` entries, holding their key.
+#[cfg(not(target_arch = "wasm32"))]
+const KEY_HEADER: &str = "tydle-key:";
+/// Prefix of the line of `DiskCache` entries that expire on their own, after their key.
+#[cfg(not(target_arch = "wasm32"))]
+const EXPIRES_HEADER: &str = "tydle-expires:";
+
+/// Storage for the player JS, signature timestamps and deciphered signatures reused between extractions.
+///
+/// Set `TydleOptions::cache` to share a cache between instances or keep it across restarts.
+pub trait Cache: MaybeSendSync {
+    fn get(&self, key: &str) -> Result<Option<String>>;
+    fn add(&self, key: String, value: String) -> Result<()>;
+    /// Add `key` for at most `ttl`, for values that go stale sooner than the rest. Caches that
+    /// can't expire single entries keep it like `add`.
+    fn add_with_ttl(&self, key: String, value: String, ttl: Duration) -> Result<()> {
+        let _ = ttl;
+        self.add(key, value)
+    }
+    fn contains(&self, key: &str) -> Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+    /// Remove `key`, doing nothing when it isn't cached.
+    fn remove(&self, key: &str) -> Result<()>;
+    /// Remove every entry whose key starts with `prefix`. Caches that can't list their keys remove
+    /// every entry like `clear`.
+    fn remove_prefixed(&self, prefix: &str) -> Result<()> {
+        let _ = prefix;
+        self.clear()
     }
+    /// Remove every entry.
+    fn clear(&self) -> Result<()>;
 }
 
-pub trait CacheAccess<T> {
-    fn add(&self, key: T, value: String) -> Result<()>;
-    fn contains(&self, key: &T) -> Result<bool>;
-    fn get(&self, key: &T) -> Result<Option<String>>;
+/// A value of `MemoryCache` and the Unix timestamp it expires at, if it does.
+struct MemoryEntry {
+    value: String,
+    expires_at: Option<f64>,
 }
 
-pub trait PlayerCacheHandle {
-    fn get_player_id_and_path(&self, player_url: &String) -> Result<(String, String)>;
-    fn extract_player_info(&self, player_url: &String) -> Result<String>;
-    // fn store_player_data_from_cache(
-    //     &mut self,
-    //     name: &str,
-    //     player_url: String,
-    //     data: String,
-    // ) -> Result<()>;
-    fn player_js_cache_key(&self, player_url: &String) -> Result<String>;
-    fn load_player_data_from_cache(&self, name: &str, player_url: String)
-    -> Result<Option<String>>;
+/// Cache kept in memory for the lifetime of the instance, used by default.
+#[derive(Default)]
+pub struct MemoryCache {
+    cache: RwLock<HashMap<String, MemoryEntry>>,
 }
 
-impl<T> CacheAccess<T> for CacheStore<T>
-where
-    T: Eq + Hash,
-{
-    fn get(&self, key: &T) -> Result<Option<String>> {
+impl MemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Cache for MemoryCache {
+    fn get(&self, key: &str) -> Result<Option<String>> {
         Ok(self
             .cache
             .read()
-            .map_err(|e| anyhow!(e.to_string()))?
+            .map_err(|e| TydleError::Cache(e.to_string()))?
             .get(key)
-            .cloned())
+            .filter(|entry| {
+                entry
+                    .expires_at
+                    .is_none_or(|expires_at| unix_timestamp_secs() < expires_at)
+            })
+            .map(|entry| entry.value.clone()))
     }
 
-    fn add(&self, key: T, value: String) -> Result<()> {
+    fn add(&self, key: String, value: String) -> Result<()> {
         self.cache
             .write()
-            .map_err(|e| anyhow!(e.to_string()))?
-            .insert(key, value);
+            .map_err(|e| TydleError::Cache(e.to_string()))?
+            .insert(
+                key,
+                MemoryEntry {
+                    value,
+                    expires_at: None,
+                },
+            );
 
         Ok(())
     }
 
-    fn contains(&self, key: &T) -> Result<bool> {
-        Ok(self
-            .cache
-            .read()
-            .map_err(|e| anyhow!(e.to_string()))?
-            .contains_key(key))
+    fn add_with_ttl(&self, key: String, value: String, ttl: Duration) -> Result<()> {
+        self.cache
+            .write()
+            .map_err(|e| TydleError::Cache(e.to_string()))?
+            .insert(
+                key,
+                MemoryEntry {
+                    value,
+                    expires_at: Some(unix_timestamp_secs() + ttl.as_secs_f64()),
+                },
+            );
+
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.cache
+            .write()
+            .map_err(|e| TydleError::Cache(e.to_string()))?
+            .remove(key);
+
+        Ok(())
+    }
+
+    fn remove_prefixed(&self, prefix: &str) -> Result<()> {
+        self.cache
+            .write()
+            .map_err(|e| TydleError::Cache(e.to_string()))?
+            .retain(|key, _| !key.starts_with(prefix));
+
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.cache
+            .write()
+            .map_err(|e| TydleError::Cache(e.to_string()))?
+            .clear();
+
+        Ok(())
+    }
+}
+
+/// Cache persisted in a directory, one file per key, so that it survives restarts.
+/// Entries older than the TTL are treated as missing.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct DiskCache {
+    dir: PathBuf,
+    ttl: Option<Duration>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DiskCache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: None,
+        }
+    }
+
+    pub fn ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// `$XDG_CACHE_HOME/tydle`, falling back to `~/.cache/tydle` or `%LOCALAPPDATA%\tydle`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+
+        Some(base.join("tydle"))
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    /// Delete every entry that outlived the TTL, returning how many were removed.
+    pub fn remove_expired(&self) -> Result<usize> {
+        self.remove_where(|path| self.is_expired(path))
+    }
+
+    /// Delete the entries matching `predicate`, leaving alone the files the cache didn't write.
+    fn remove_where(&self, predicate: impl Fn(&std::path::Path) -> bool) -> Result<usize> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let mut removed = 0;
+
+        for entry in entries {
+            let path = entry?.path();
+            if is_entry(&path) && predicate(&path) {
+                std::fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        use sha1::{Digest, Sha1};
+
+        // Keys hold URLs and signatures, hash them into valid file names.
+        let hash = Sha1::digest(key.as_bytes());
+        let name: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+
+        self.dir.join(name)
+    }
+
+    fn outlived_ttl(&self, path: &std::path::Path) -> bool {
+        let Some(ttl) = self.ttl else {
+            return false;
+        };
+
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > ttl)
+    }
+
+    /// Whether the entry at `path` outlived the TTL of the cache or its own.
+    fn is_expired(&self, path: &std::path::Path) -> bool {
+        self.outlived_ttl(path)
+            || std::fs::read_to_string(path)
+                .ok()
+                .is_some_and(|entry| split_expiry(split_key(&entry).1).0.is_some_and(is_past))
+    }
+
+    /// The key of the entry at `path`, read from its first line only since player JS is large.
+    /// Entries written before keys were stored have none.
+    fn key_at(path: &std::path::Path) -> Option<String> {
+        use std::io::BufRead;
+
+        let mut line = String::new();
+        std::io::BufReader::new(std::fs::File::open(path).ok()?)
+            .read_line(&mut line)
+            .ok()?;
+
+        Some(
+            line.strip_prefix(KEY_HEADER)?
+                .strip_suffix('\n')?
+                .to_string(),
+        )
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<()> {
+        let path = self.path(key);
+        // Write to a temporary file first so that concurrent readers never see a partial entry.
+        static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+        let tmp_path = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(&tmp_path, format!("{}{}\n{}", KEY_HEADER, key, value)))
+            .and_then(|_| std::fs::rename(&tmp_path, &path))
+            .map_err(|e| TydleError::Cache(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Whether `path` was written by a `DiskCache`: a hashed key, or the temporary file of one.
+#[cfg(not(target_arch = "wasm32"))]
+fn is_entry(path: &std::path::Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let (hash, extension) = name.split_once('.').unwrap_or((name, ""));
+
+    hash.len() == 40
+        && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        && (extension.is_empty() || extension.ends_with(".tmp"))
+        && path.is_file()
+}
+
+/// The key and the rest of a `DiskCache` entry.
+#[cfg(not(target_arch = "wasm32"))]
+fn split_key(entry: &str) -> (Option<&str>, &str) {
+    entry
+        .strip_prefix(KEY_HEADER)
+        .and_then(|rest| rest.split_once('\n'))
+        .map_or((None, entry), |(key, rest)| (Some(key), rest))
+}
+
+/// The expiry, in seconds since the epoch, and the value of a `DiskCache` entry.
+#[cfg(not(target_arch = "wasm32"))]
+fn split_expiry(entry: &str) -> (Option<u64>, &str) {
+    entry
+        .strip_prefix(EXPIRES_HEADER)
+        .and_then(|rest| rest.split_once('\n'))
+        .and_then(|(expires, value)| Some((Some(expires.parse().ok()?), value)))
+        .unwrap_or((None, entry))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn is_past(timestamp: u64) -> bool {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .is_ok_and(|now| now.as_secs() >= timestamp)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Cache for DiskCache {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        let path = self.path(key);
+
+        if self.outlived_ttl(&path) {
+            let _ = std::fs::remove_file(&path);
+            return Ok(None);
+        }
+
+        match std::fs::read_to_string(&path) {
+            Ok(entry) => match split_expiry(split_key(&entry).1) {
+                (Some(expires), _) if is_past(expires) => {
+                    let _ = std::fs::remove_file(&path);
+                    Ok(None)
+                }
+                (_, value) => Ok(Some(value.to_string())),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(TydleError::Cache(e.to_string()).into()),
+        }
+    }
+
+    fn add(&self, key: String, value: String) -> Result<()> {
+        self.write(&key, &value)
+    }
+
+    fn add_with_ttl(&self, key: String, value: String, ttl: Duration) -> Result<()> {
+        let expires = SystemTime::now().duration_since(UNIX_EPOCH)? + ttl;
+
+        self.write(
+            &key,
+            &format!("{}{}\n{}", EXPIRES_HEADER, expires.as_secs(), value),
+        )
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        match std::fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(TydleError::Cache(e.to_string()).into())
+            }
+            _ => Ok(()),
+        }
     }
+
+    fn remove_prefixed(&self, prefix: &str) -> Result<()> {
+        self.remove_where(|path| Self::key_at(path).is_some_and(|key| key.starts_with(prefix)))
+            .map_err(|e| TydleError::Cache(e.to_string()))?;
+
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.remove_where(|_| true)
+            .map_err(|e| TydleError::Cache(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Cache kept in Redis, so that every instance connected to the same server shares the player JS,
+/// deciphered signatures and PO tokens instead of solving them again.
+/// Keys are prefixed with `tydle:` by default, so that `clear` leaves the other keys alone.
+#[cfg(all(feature = "redis-cache", not(target_arch = "wasm32")))]
+pub struct RedisCache {
+    client: redis::Client,
+    /// Reopened by the next command after a failed one.
+    connection: std::sync::Mutex<Option<redis::Connection>>,
+    prefix: String,
+    ttl: Option<Duration>,
 }
 
-impl PlayerCacheHandle for CacheStore<(String, String)> {
+#[cfg(all(feature = "redis-cache", not(target_arch = "wasm32")))]
+impl RedisCache {
+    /// Connect to the Redis server at `url`, like "redis://127.0.0.1:6379/0", on the first command.
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            client: redis::Client::open(url)?,
+            connection: std::sync::Mutex::new(None),
+            prefix: "tydle:".into(),
+            ttl: None,
+        })
+    }
+
+    pub fn prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    fn with_connection<T>(
+        &self,
+        command: impl FnOnce(&mut redis::Connection) -> redis::RedisResult<T>,
+    ) -> Result<T> {
+        const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+        let mut connection = self
+            .connection
+            .lock()
+            .map_err(|e| TydleError::Cache(e.to_string()))?;
+        let con = match connection.as_mut() {
+            Some(con) => con,
+            None => connection.insert(
+                self.client
+                    .get_connection_with_timeout(CONNECT_TIMEOUT)
+                    .map_err(|e| TydleError::Cache(e.to_string()))?,
+            ),
+        };
+
+        let result = command(con);
+        if result.is_err() {
+            *connection = None;
+        }
+
+        Ok(result.map_err(|e| TydleError::Cache(e.to_string()))?)
+    }
+
+    /// Delete the keys matching the glob `pattern`.
+    fn remove_matching(&self, pattern: &str) -> Result<()> {
+        use redis::Commands;
+
+        let keys: Vec<String> =
+            self.with_connection(|con| Ok(con.scan_match(pattern)?.collect()))?;
+
+        for keys in keys.chunks(1000) {
+            self.with_connection(|con| con.del::<_, ()>(keys))?;
+        }
+
+        Ok(())
+    }
+
+    fn set(&self, key: &str, value: String, ttl: Option<Duration>) -> Result<()> {
+        use redis::Commands;
+
+        let key = self.key(key);
+        match ttl.map(|ttl| ttl.as_secs().max(1)) {
+            Some(secs) => self.with_connection(|con| con.set_ex(key, value, secs)),
+            None => self.with_connection(|con| con.set(key, value)),
+        }
+    }
+}
+
+#[cfg(all(feature = "redis-cache", not(target_arch = "wasm32")))]
+impl Cache for RedisCache {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        use redis::Commands;
+
+        self.with_connection(|con| con.get(self.key(key)))
+    }
+
+    fn add(&self, key: String, value: String) -> Result<()> {
+        self.set(&key, value, self.ttl)
+    }
+
+    fn add_with_ttl(&self, key: String, value: String, ttl: Duration) -> Result<()> {
+        self.set(&key, value, Some(self.ttl.map_or(ttl, |max| max.min(ttl))))
+    }
+
+    fn contains(&self, key: &str) -> Result<bool> {
+        use redis::Commands;
+
+        self.with_connection(|con| con.exists(self.key(key)))
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        use redis::Commands;
+
+        self.with_connection(|con| con.del(self.key(key)))
+    }
+
+    fn remove_prefixed(&self, prefix: &str) -> Result<()> {
+        // Escape the glob characters of `prefix`, so that it only matches itself.
+        let prefix: String = prefix
+            .chars()
+            .flat_map(|c| match c {
+                '*' | '?' | '[' | ']' | '\\' => vec!['\\', c],
+                c => vec![c],
+            })
+            .collect();
+
+        self.remove_matching(&format!("{}{}*", self.prefix, prefix))
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.remove_matching(&format!("{}*", self.prefix))
+    }
+}
+
+pub trait PlayerCacheHandle {
+    fn get_player_id_and_path(&self, player_url: &String) -> Result<(String, String)>;
+    fn extract_player_info(&self, player_url: &String) -> Result<String>;
+    fn store_player_data_to_cache(
+        &self,
+        name: &str,
+        player_url: String,
+        data: String,
+    ) -> Result<()>;
+    fn player_js_cache_key(&self, player_url: &String) -> Result<String>;
+    /// Key of the player JS of `player_url` itself.
+    fn player_code_cache_key(&self, player_url: &str) -> Result<String>;
+    fn load_player_data_from_cache(&self, name: &str, player_url: String)
+    -> Result<Option<String>>;
+    /// Forget the player JS and signature timestamp of `player_url`, so that they are downloaded again.
+    #[cfg(feature = "decipher")]
+    fn invalidate_player(&self, player_url: &str) -> Result<()>;
+}
+
+impl PlayerCacheHandle for dyn Cache {
     fn extract_player_info(&self, player_url: &String) -> Result<String> {
         const PLAYER_INFO_RE: [&str; 3] = [
             r"/s/player/(?P<id>[a-zA-Z0-9_-]{8,})/(?:tv-)?player",
@@ -105,44 +579,52 @@ impl PlayerCacheHandle for CacheStore<(String, String)> {
         Ok(format!("{}-{}", player_id, player_path))
     }
 
+    fn player_code_cache_key(&self, player_url: &str) -> Result<String> {
+        Ok(format!(
+            "{}{}",
+            PLAYER_JS_KEY_PREFIX,
+            self.player_js_cache_key(&player_url.to_string())?
+        ))
+    }
+
     fn load_player_data_from_cache(
         &self,
         name: &str,
         player_url: String,
     ) -> Result<Option<String>> {
-        let cache_id = (
-            format!("youtube-{}", name),
-            self.player_js_cache_key(&player_url)?,
+        let cache_id = format!(
+            "youtube-{}/{}",
+            name,
+            self.player_js_cache_key(&player_url)?
         );
 
-        if let Some(data) = self
-            .cache
-            .read()
-            .map_err(|e| anyhow!(e.to_string()))?
-            .get(&cache_id)
-        {
-            return Ok(Some(data.clone()));
+        self.get(&cache_id)
+    }
+
+    fn store_player_data_to_cache(
+        &self,
+        name: &str,
+        player_url: String,
+        data: String,
+    ) -> Result<()> {
+        let cache_id = format!(
+            "youtube-{}/{}",
+            name,
+            self.player_js_cache_key(&player_url)?
+        );
+
+        if !self.contains(&cache_id)? {
+            self.add(cache_id, data)?;
         }
 
-        Ok(None)
+        Ok(())
     }
 
-    // fn store_player_data_from_cache(
-    //     &mut self,
-    //     name: &str,
-    //     player_url: String,
-    //     data: String,
-    // ) -> Result<()> {
-    //     let cache_id = (
-    //         format!("youtube-{}", name),
-    //         self.player_js_cache_key(&player_url)?,
-    //     );
+    #[cfg(feature = "decipher")]
+    fn invalidate_player(&self, player_url: &str) -> Result<()> {
+        let player_js_key = self.player_js_cache_key(&player_url.to_string())?;
 
-    //     if !self.cache.contains_key(&cache_id) {
-    //         self.cache.insert(cache_id, data);
-    //         return Ok(());
-    //     }
-
-    //     Ok(())
-    // }
+        self.remove(&format!("youtube-sts/{}", player_js_key))?;
+        self.remove(&self.player_code_cache_key(player_url)?)
+    }
 }
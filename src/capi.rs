@@ -0,0 +1,135 @@
+//! C API for C, C++ and Swift programs, generated into `include/tydle.h` by
+//! `scripts/generate-header.sh`.
+//!
+//! Strings returned by these functions belong to the caller, who frees them with
+//! `tydle_string_free`. On failure they return NULL and `tydle_last_error` tells why.
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char},
+    ptr,
+};
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::{TydleError, TydleOptions, VideoId, YtStreamResponse, blocking::Tydle};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = CString::new(message).ok());
+}
+
+/// Hand the string `f` returns over to C, or NULL with the error kept for `tydle_last_error`.
+fn into_c_string(f: impl FnOnce() -> Result<String>) -> *mut c_char {
+    match f().and_then(|s| Ok(CString::new(s)?)) {
+        Ok(s) => s.into_raw(),
+        Err(e) => {
+            set_last_error(e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+///
+/// `s` must be NULL or a NUL-terminated string that outlives `'a`.
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str> {
+    if s.is_null() {
+        bail!("`{}` is NULL.", name);
+    }
+
+    Ok(unsafe { CStr::from_ptr(s) }.to_str()?)
+}
+
+/// Create an instance with the default options, or NULL if that failed.
+#[unsafe(no_mangle)]
+pub extern "C" fn tydle_new() -> *mut Tydle {
+    match Tydle::new(TydleOptions::default()) {
+        Ok(tydle) => Box::into_raw(Box::new(tydle)),
+        Err(e) => {
+            set_last_error(e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// The `YtStreamResponse` of the video `video_id` as JSON.
+///
+/// # Safety
+///
+/// `tydle` must come from `tydle_new` and `video_id` must be a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tydle_get_streams_json(
+    tydle: *const Tydle,
+    video_id: *const c_char,
+) -> *mut c_char {
+    into_c_string(|| {
+        let tydle = unsafe { tydle.as_ref() }.ok_or(anyhow!("`tydle` is NULL."))?;
+        let video_id = VideoId::new(unsafe { str_arg(video_id, "video_id") }?)?;
+
+        Ok(serde_json::to_string(&tydle.get_streams(&video_id)?)?)
+    })
+}
+
+/// The final URL of the stream `itag` of `streams_json`, as returned by `tydle_get_streams_json`.
+///
+/// # Safety
+///
+/// `tydle` must come from `tydle_new` and `streams_json` must be a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tydle_resolve_url(
+    tydle: *const Tydle,
+    streams_json: *const c_char,
+    itag: u16,
+) -> *mut c_char {
+    into_c_string(|| {
+        let tydle = unsafe { tydle.as_ref() }.ok_or(anyhow!("`tydle` is NULL."))?;
+        let stream_response: YtStreamResponse =
+            serde_json::from_str(unsafe { str_arg(streams_json, "streams_json") }?)?;
+        let stream = stream_response
+            .streams
+            .iter()
+            .find(|stream| stream.itag == itag)
+            .ok_or(TydleError::FormatNotFound(itag.to_string()))?;
+
+        Ok(tydle.resolve_url(stream, &stream_response)?)
+    })
+}
+
+/// Free an instance created by `tydle_new`. Does nothing with NULL.
+///
+/// # Safety
+///
+/// `tydle` must come from `tydle_new` and must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tydle_free(tydle: *mut Tydle) {
+    if !tydle.is_null() {
+        drop(unsafe { Box::from_raw(tydle) });
+    }
+}
+
+/// Free a string returned by tydle. Does nothing with NULL.
+///
+/// # Safety
+///
+/// `s` must come from tydle and must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tydle_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Why the last call on this thread failed, or NULL. Valid until the next failure on the thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn tydle_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |e| e.as_ptr())
+    })
+}
@@ -0,0 +1,284 @@
+//! The Cast v2 protocol: protobuf framed JSON messages over TLS, just enough of it to launch the
+//! Default Media Receiver and load a URL in it.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow, bail};
+use mdns_sd::{ServiceDaemon, ServiceEvent};
+use serde_json::{Value, json};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_native_tls::{TlsConnector, TlsStream, native_tls};
+
+use super::{Renderer, RendererKind};
+
+const SERVICE_TYPE: &str = "_googlecast._tcp.local.";
+const DEFAULT_MEDIA_RECEIVER: &str = "CC1AD845";
+const SENDER_ID: &str = "sender-0";
+const RECEIVER_ID: &str = "receiver-0";
+
+const NS_CONNECTION: &str = "urn:x-cast:com.google.cast.tp.connection";
+const NS_HEARTBEAT: &str = "urn:x-cast:com.google.cast.tp.heartbeat";
+const NS_RECEIVER: &str = "urn:x-cast:com.google.cast.receiver";
+const NS_MEDIA: &str = "urn:x-cast:com.google.cast.media";
+
+/// How long the device gets to launch the receiver and load the media.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(20);
+
+pub async fn discover(timeout: Duration) -> Result<Vec<Renderer>> {
+    let daemon = ServiceDaemon::new()?;
+    let events = daemon.browse(SERVICE_TYPE)?;
+    let mut found = HashMap::new();
+
+    let _ = tokio::time::timeout(timeout, async {
+        while let Ok(event) = events.recv_async().await {
+            let ServiceEvent::ServiceResolved(info) = event else {
+                continue;
+            };
+            // Prefer IPv4, which every Chromecast listens on.
+            let Some(ip) = info
+                .get_addresses()
+                .iter()
+                .min_by_key(|ip| ip.is_ipv6())
+                .copied()
+            else {
+                continue;
+            };
+
+            found.insert(
+                info.get_fullname().to_string(),
+                Renderer {
+                    name: info
+                        .get_property_val_str("fn")
+                        .unwrap_or(info.get_fullname())
+                        .to_string(),
+                    kind: RendererKind::Chromecast {
+                        addr: SocketAddr::new(ip, info.get_port()),
+                    },
+                },
+            );
+        }
+    })
+    .await;
+
+    let _ = daemon.shutdown();
+
+    Ok(found.into_values().collect())
+}
+
+pub async fn play(addr: SocketAddr, url: &str, content_type: &str, title: &str) -> Result<()> {
+    let mut connection = CastConnection::connect(addr).await?;
+
+    connection
+        .send(RECEIVER_ID, NS_CONNECTION, json!({ "type": "CONNECT" }))
+        .await?;
+    connection
+        .send(
+            RECEIVER_ID,
+            NS_RECEIVER,
+            json!({ "type": "LAUNCH", "appId": DEFAULT_MEDIA_RECEIVER, "requestId": 1 }),
+        )
+        .await?;
+
+    let transport_id = connection
+        .wait_for(|namespace, payload| {
+            if namespace != NS_RECEIVER || payload["type"] != "RECEIVER_STATUS" {
+                return None;
+            }
+            payload["status"]["applications"]
+                .as_array()?
+                .iter()
+                .find(|app| app["appId"] == DEFAULT_MEDIA_RECEIVER)?["transportId"]
+                .as_str()
+                .map(str::to_string)
+        })
+        .await?;
+
+    connection
+        .send(&transport_id, NS_CONNECTION, json!({ "type": "CONNECT" }))
+        .await?;
+    connection
+        .send(
+            &transport_id,
+            NS_MEDIA,
+            json!({
+                "type": "LOAD",
+                "requestId": 2,
+                "autoplay": true,
+                "media": {
+                    "contentId": url,
+                    "contentType": content_type,
+                    "streamType": "BUFFERED",
+                    "metadata": { "metadataType": 0, "title": title },
+                },
+            }),
+        )
+        .await?;
+
+    connection
+        .wait_for(|namespace, payload| {
+            if namespace != NS_MEDIA {
+                return None;
+            }
+            match payload["type"].as_str()? {
+                "MEDIA_STATUS" => Some(Ok(())),
+                "LOAD_FAILED" | "LOAD_CANCELLED" | "INVALID_REQUEST" => Some(Err(anyhow!(
+                    "The Chromecast failed to load the video: {}",
+                    payload
+                ))),
+                _ => None,
+            }
+        })
+        .await?
+}
+
+struct CastConnection {
+    stream: TlsStream<TcpStream>,
+}
+
+impl CastConnection {
+    async fn connect(addr: SocketAddr) -> Result<Self> {
+        // Chromecasts present self-signed certificates.
+        let connector = TlsConnector::from(
+            native_tls::TlsConnector::builder()
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true)
+                .build()?,
+        );
+        let tcp = TcpStream::connect(addr).await?;
+        let domain = match addr.ip() {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => ip.to_string(),
+        };
+
+        Ok(Self {
+            stream: connector.connect(&domain, tcp).await?,
+        })
+    }
+
+    async fn send(&mut self, destination: &str, namespace: &str, payload: Value) -> Result<()> {
+        let message = encode_message(destination, namespace, &payload.to_string());
+
+        self.stream
+            .write_all(&(message.len() as u32).to_be_bytes())
+            .await?;
+        self.stream.write_all(&message).await?;
+
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<(String, Value)> {
+        let mut len = [0; 4];
+        self.stream.read_exact(&mut len).await?;
+        let mut message = vec![0; u32::from_be_bytes(len) as usize];
+        self.stream.read_exact(&mut message).await?;
+
+        let (namespace, payload) = decode_message(&message)?;
+        Ok((namespace, serde_json::from_str(&payload)?))
+    }
+
+    /// Read messages, answering heartbeats, until `matches` returns something for one.
+    async fn wait_for<T>(&mut self, matches: impl Fn(&str, &Value) -> Option<T>) -> Result<T> {
+        tokio::time::timeout(RESPONSE_TIMEOUT, async {
+            loop {
+                let (namespace, payload) = self.receive().await?;
+
+                if namespace == NS_HEARTBEAT && payload["type"] == "PING" {
+                    self.send(RECEIVER_ID, NS_HEARTBEAT, json!({ "type": "PONG" }))
+                        .await?;
+                    continue;
+                }
+                if let Some(matched) = matches(&namespace, &payload) {
+                    return Ok(matched);
+                }
+            }
+        })
+        .await
+        .map_err(|_| anyhow!("The Chromecast didn't answer in time."))?
+    }
+}
+
+/// Encode a `CastMessage` protobuf with a string payload.
+fn encode_message(destination: &str, namespace: &str, payload: &str) -> Vec<u8> {
+    let mut message = Vec::new();
+
+    // protocol_version = CASTV2_1_0
+    message.extend([0x08, 0x00]);
+    for (field, value) in [(2, SENDER_ID), (3, destination), (4, namespace)] {
+        encode_string(&mut message, field, value);
+    }
+    // payload_type = STRING
+    message.extend([0x28, 0x00]);
+    encode_string(&mut message, 6, payload);
+
+    message
+}
+
+fn encode_string(message: &mut Vec<u8>, field: u8, value: &str) {
+    message.push(field << 3 | 2);
+    encode_varint(message, value.len() as u64);
+    message.extend(value.as_bytes());
+}
+
+fn encode_varint(message: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        message.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    message.push(value as u8);
+}
+
+fn decode_varint(message: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut value = 0;
+
+    for shift in (0..64).step_by(7) {
+        let byte = *message
+            .get(*pos)
+            .ok_or(anyhow!("Truncated Cast message."))?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    bail!("Invalid varint in a Cast message.")
+}
+
+/// The namespace and string payload of a `CastMessage` protobuf.
+fn decode_message(message: &[u8]) -> Result<(String, String)> {
+    let mut pos = 0;
+    let mut namespace = String::new();
+    let mut payload = String::new();
+
+    while pos < message.len() {
+        let key = decode_varint(message, &mut pos)?;
+        match key & 7 {
+            0 => {
+                decode_varint(message, &mut pos)?;
+            }
+            2 => {
+                let len = decode_varint(message, &mut pos)? as usize;
+                let value = message
+                    .get(pos..pos + len)
+                    .ok_or(anyhow!("Truncated Cast message."))?;
+                pos += len;
+
+                match key >> 3 {
+                    4 => namespace = String::from_utf8_lossy(value).into_owned(),
+                    6 => payload = String::from_utf8_lossy(value).into_owned(),
+                    _ => {}
+                }
+            }
+            wire_type => bail!("Unexpected wire type {} in a Cast message.", wire_type),
+        }
+    }
+
+    Ok((namespace, payload))
+}
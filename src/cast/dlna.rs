@@ -0,0 +1,164 @@
+//! UPnP media renderers, found with SSDP and controlled through the SOAP actions of their
+//! AVTransport service.
+
+use std::{collections::HashSet, net::Ipv4Addr, time::Duration};
+
+use anyhow::{Result, anyhow, bail};
+use fancy_regex::Regex;
+use reqwest::Client;
+use tokio::net::UdpSocket;
+use url::Url;
+
+use super::{Renderer, RendererKind};
+
+const SSDP_ADDR: (Ipv4Addr, u16) = (Ipv4Addr::new(239, 255, 255, 250), 1900);
+const AV_TRANSPORT: &str = "urn:schemas-upnp-org:service:AVTransport:1";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub async fn discover(timeout: Duration) -> Result<Vec<Renderer>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: {}\r\nST: {}\r\n\r\n",
+        timeout.as_secs().max(1),
+        AV_TRANSPORT
+    );
+    socket.send_to(search.as_bytes(), SSDP_ADDR).await?;
+
+    let mut locations = HashSet::new();
+    let mut buf = [0; 2048];
+    let _ = tokio::time::timeout(timeout, async {
+        while let Ok((len, _)) = socket.recv_from(&mut buf).await {
+            let response = String::from_utf8_lossy(&buf[..len]);
+            if let Some(location) = response.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("LOCATION")
+                    .then(|| value.trim().to_string())
+            }) {
+                locations.insert(location);
+            }
+        }
+    })
+    .await;
+
+    let client = Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    let mut renderers = Vec::new();
+    for location in locations {
+        match describe(&client, &location).await {
+            Ok(renderer) => renderers.push(renderer),
+            Err(e) => log::debug!("Skipping the renderer at {}: {:#}", location, e),
+        }
+    }
+
+    Ok(renderers)
+}
+
+/// The name and AVTransport control URL of the device described at `location`.
+async fn describe(client: &Client, location: &str) -> Result<Renderer> {
+    let description = client
+        .get(location)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let name = tag_text(&description, "friendlyName")?.unwrap_or(location.to_string());
+    let service_re = Regex::new(r"(?s)<service>(.*?)</service>")?;
+    let control_path = service_re
+        .captures_iter(&description)
+        .filter_map(|cap| cap.ok()?.get(1).map(|m| m.as_str()))
+        .find(|service| service.contains(AV_TRANSPORT))
+        .map(|service| tag_text(service, "controlURL"))
+        .transpose()?
+        .flatten()
+        .ok_or(anyhow!("The device has no AVTransport service."))?;
+
+    // Relative control URLs are resolved against URLBase, or the location of the description.
+    let base = match tag_text(&description, "URLBase")? {
+        Some(base) => Url::parse(&base)?,
+        None => Url::parse(location)?,
+    };
+
+    Ok(Renderer {
+        name,
+        kind: RendererKind::Dlna {
+            control_url: base.join(&control_path)?,
+        },
+    })
+}
+
+fn tag_text(xml: &str, tag: &str) -> Result<Option<String>> {
+    let re = Regex::new(&format!(r"(?s)<{}(?:\s[^>]*)?>(.*?)</{}>", tag, tag))?;
+
+    Ok(re
+        .captures(xml)?
+        .and_then(|cap| cap.get(1))
+        .map(|m| m.as_str().trim().to_string()))
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub async fn play(control_url: &Url, url: &str, content_type: &str, title: &str) -> Result<()> {
+    let client = Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    // Renderers that read the metadata show the title and check the protocol info against what
+    // they can play.
+    let metadata = format!(
+        r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/"><item id="0" parentID="-1" restricted="1"><dc:title>{}</dc:title><upnp:class>object.item.videoItem</upnp:class><res protocolInfo="http-get:*:{}:*">{}</res></item></DIDL-Lite>"#,
+        escape_xml(title),
+        content_type,
+        escape_xml(url)
+    );
+
+    soap_action(
+        &client,
+        control_url,
+        "SetAVTransportURI",
+        &format!(
+            "<InstanceID>0</InstanceID><CurrentURI>{}</CurrentURI><CurrentURIMetaData>{}</CurrentURIMetaData>",
+            escape_xml(url),
+            escape_xml(&metadata)
+        ),
+    )
+    .await?;
+    soap_action(
+        &client,
+        control_url,
+        "Play",
+        "<InstanceID>0</InstanceID><Speed>1</Speed>",
+    )
+    .await
+}
+
+async fn soap_action(client: &Client, control_url: &Url, action: &str, args: &str) -> Result<()> {
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><u:{action} xmlns:u="{service}">{args}</u:{action}></s:Body></s:Envelope>"#,
+        action = action,
+        service = AV_TRANSPORT,
+        args = args
+    );
+
+    let response = client
+        .post(control_url.clone())
+        .header("Content-Type", r#"text/xml; charset="utf-8""#)
+        .header("SOAPAction", format!("\"{}#{}\"", AV_TRANSPORT, action))
+        .body(body)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let fault = tag_text(&response.text().await?, "errorDescription")?;
+        bail!(
+            "The renderer rejected {}: {}",
+            action,
+            fault.unwrap_or(status.to_string())
+        );
+    }
+
+    Ok(())
+}
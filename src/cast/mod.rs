@@ -0,0 +1,101 @@
+//! Playing videos on the Chromecasts and DLNA renderers of the local network, which fetch the
+//! resolved googlevideo URL themselves.
+
+mod chromecast;
+mod dlna;
+
+use std::{fmt, net::SocketAddr, time::Duration};
+
+use anyhow::{Result, bail};
+use url::Url;
+
+/// How long renderers are looked for before picking one.
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone)]
+pub enum RendererKind {
+    Chromecast {
+        addr: SocketAddr,
+    },
+    /// A UPnP media renderer, controlled through its AVTransport service.
+    Dlna {
+        control_url: Url,
+    },
+}
+
+/// A device of the local network that can play a video from its URL.
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    pub name: String,
+    pub kind: RendererKind,
+}
+
+impl fmt::Display for Renderer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            RendererKind::Chromecast { addr } => write!(f, "{} (Chromecast, {})", self.name, addr),
+            RendererKind::Dlna { control_url } => {
+                write!(f, "{} (DLNA, {})", self.name, control_url)
+            }
+        }
+    }
+}
+
+impl Renderer {
+    /// Start playing `url`, a progressive stream of `content_type`, with `title` shown on screen.
+    /// Returns once the renderer accepted it, the playback goes on without tydle.
+    pub async fn play(&self, url: &str, content_type: &str, title: &str) -> Result<()> {
+        match &self.kind {
+            RendererKind::Chromecast { addr } => {
+                chromecast::play(*addr, url, content_type, title).await
+            }
+            RendererKind::Dlna { control_url } => {
+                dlna::play(control_url, url, content_type, title).await
+            }
+        }
+    }
+}
+
+/// Look for Chromecasts over mDNS and DLNA renderers over SSDP for `timeout`.
+pub async fn discover(timeout: Duration) -> Result<Vec<Renderer>> {
+    let (chromecasts, renderers) =
+        tokio::join!(chromecast::discover(timeout), dlna::discover(timeout));
+
+    let mut found = Vec::new();
+    for result in [chromecasts, renderers] {
+        match result {
+            Ok(renderers) => found.extend(renderers),
+            Err(e) => log::warn!("Failed to look for renderers: {:#}", e),
+        }
+    }
+
+    Ok(found)
+}
+
+/// Play `url` on the first renderer whose name contains `name`, ignoring case.
+pub async fn cast(name: &str, url: &str, content_type: &str, title: &str) -> Result<()> {
+    log::info!("Looking for renderers on the local network");
+    let renderers = discover(DISCOVERY_TIMEOUT).await?;
+
+    let Some(renderer) = renderers
+        .iter()
+        .find(|renderer| renderer.name.to_lowercase().contains(&name.to_lowercase()))
+    else {
+        let names = renderers
+            .iter()
+            .map(|renderer| renderer.to_string())
+            .collect::<Vec<_>>();
+        bail!(
+            "No renderer named \"{}\" was found. Found: {}",
+            name,
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(", ")
+            }
+        );
+    };
+
+    log::info!("Casting {} to {}", title, renderer);
+    renderer.play(url, content_type, title).await
+}
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::http::{BoxFuture, HttpRequest, HttpTransport, MaybeSendSync, ReqwestTransport};
+
+/// A challenge YouTube sets before its streams can be played.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Challenge {
+    /// Encrypted `s` value of a stream's signature cipher.
+    Signature { value: String },
+    /// `n` query parameter of a stream URL, throttled until it is deciphered.
+    Nsignature { value: String },
+    /// Proof of origin token sent with the player request of `client`.
+    PoToken {
+        client: String,
+        video_id: String,
+        visitor_data: Option<String>,
+        data_sync_id: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChallengeRequest {
+    /// Player JS the challenge comes from, when it depends on one.
+    pub player_url: Option<String>,
+    #[serde(flatten)]
+    pub challenge: Challenge,
+}
+
+/// Solves challenges outside of tydle, for instance with an external service, so that no
+/// JavaScript has to run in-process.
+///
+/// Pass one as `TydleOptions::challenge_solver`. Signatures it doesn't solve are deciphered
+/// in-process as usual.
+pub trait ChallengeSolver: MaybeSendSync {
+    /// Solve `request`, or return `None` to let tydle handle it.
+    fn solve<'a>(&'a self, request: &'a ChallengeRequest) -> BoxFuture<'a, Result<Option<String>>>;
+}
+
+#[derive(Deserialize)]
+struct ChallengeResponse {
+    result: Option<String>,
+}
+
+/// Posts every challenge as JSON to an HTTP endpoint.
+///
+/// The body is the `ChallengeRequest`, for instance
+/// `{"player_url": "...", "type": "nsignature", "value": "..."}`, and the endpoint answers with
+/// `{"result": "..."}`, or a `null` result for challenges it doesn't solve.
+pub struct HttpChallengeSolver {
+    endpoint: Url,
+    transport: Arc<dyn HttpTransport>,
+}
+
+impl HttpChallengeSolver {
+    pub fn new(endpoint: Url) -> Self {
+        Self {
+            endpoint,
+            transport: Arc::new(ReqwestTransport::default()),
+        }
+    }
+
+    pub fn transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.transport = transport;
+        self
+    }
+}
+
+impl ChallengeSolver for HttpChallengeSolver {
+    fn solve<'a>(&'a self, request: &'a ChallengeRequest) -> BoxFuture<'a, Result<Option<String>>> {
+        Box::pin(async move {
+            let response = self
+                .transport
+                .send(
+                    HttpRequest::post(self.endpoint.clone())
+                        .header("Content-Type", "application/json")
+                        .body(serde_json::to_vec(request)?),
+                )
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?;
+
+            Ok(serde_json::from_slice::<ChallengeResponse>(&response)?.result)
+        })
+    }
+}
@@ -1,13 +1,19 @@
 use std::sync::Arc;
 
 use anyhow::{Result, bail};
+use url::Url;
 
 use crate::{
-    cache::{CacheAccess, CacheStore, PlayerCacheHandle},
-    cipher::js::SignatureJsHandle,
-    utils::{parse_query_string, replace_n_sig_query_param},
+    cache::{Cache, PlayerCacheHandle},
+    challenge::{Challenge, ChallengeRequest, ChallengeSolver},
+    cipher::{evaluator::JsEvaluator, js::SignatureJsHandle, native::NativeSignatureFunction},
+    cookies::{CookieJar, CookieStore},
+    http::{HttpRequest, HttpTransport},
+    metrics::Metrics,
+    utils::{parse_query_string, replace_n_sig_query_param, unix_timestamp_secs},
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SignatureType {
     Nsignature,
     Signature,
@@ -23,20 +29,74 @@ impl SignatureType {
 }
 
 pub struct SignatureDecipher {
-    pub player_cache: Arc<CacheStore<(String, String)>>,
-    pub code_cache: Arc<CacheStore>,
+    pub cache: Arc<dyn Cache>,
+    /// Downloads the player JS when it isn't cached, and the EJS modules when they aren't vendored.
+    pub transport: Arc<dyn HttpTransport>,
+    pub evaluator: Arc<dyn JsEvaluator>,
+    /// Asked first for every signature.
+    pub challenge_solver: Option<Arc<dyn ChallengeSolver>>,
+    /// Cookies sent along with the player JS download, shared with the extractor.
+    pub(crate) cookie_jar: Option<Arc<CookieJar>>,
+    /// Records how long the deciphers that miss the cache take.
+    pub(crate) metrics: Option<Arc<Metrics>>,
+    /// Directory the EJS modules are loaded from before anything else.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub ejs_dir: Option<std::path::PathBuf>,
 }
 
 impl SignatureDecipher {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn ejs_dir(mut self, ejs_dir: Option<std::path::PathBuf>) -> Self {
+        self.ejs_dir = ejs_dir;
+        self
+    }
+
+    pub fn challenge_solver(mut self, challenge_solver: Option<Arc<dyn ChallengeSolver>>) -> Self {
+        self.challenge_solver = challenge_solver;
+        self
+    }
+
+    pub(crate) fn cookie_jar(mut self, cookie_jar: Option<Arc<CookieJar>>) -> Self {
+        self.cookie_jar = cookie_jar;
+        self
+    }
+
+    pub(crate) fn metrics(mut self, metrics: Option<Arc<Metrics>>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
     pub fn new(
-        player_cache: Arc<CacheStore<(String, String)>>,
-        code_cache: Arc<CacheStore>,
+        cache: Arc<dyn Cache>,
+        transport: Arc<dyn HttpTransport>,
+        evaluator: Arc<dyn JsEvaluator>,
     ) -> Self {
         Self {
-            player_cache,
-            code_cache,
+            cache,
+            transport,
+            evaluator,
+            challenge_solver: None,
+            cookie_jar: None,
+            metrics: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            ejs_dir: None,
         }
     }
+
+    /// Keyed on the player ID, so that a new player never reuses the results of an older one.
+    fn signature_cache_id(
+        &self,
+        signature_type: SignatureType,
+        encrypted_signature: &str,
+        player_url: &str,
+    ) -> Result<String> {
+        Ok(format!(
+            "youtube-{}/{}/{}",
+            signature_type.as_str(),
+            self.cache.player_js_cache_key(&player_url.to_string())?,
+            encrypted_signature
+        ))
+    }
 }
 
 pub trait SignatureDecipherHandle {
@@ -53,6 +113,15 @@ pub trait SignatureDecipherHandle {
         player_url: String,
     ) -> Result<String>;
     async fn decipher(&self, signature: String, player_url: String) -> Result<String>;
+    /// Deciphers the `n` query parameter of `url`, leaving it untouched when it has none.
+    async fn decipher_n(&self, url: String, player_url: String) -> Result<String>;
+    /// Forget the cached deciphered `n` parameter of `url`, so that the next `decipher_n`
+    /// deciphers it again.
+    fn forget_n(&self, url: &str, player_url: &str) -> Result<()>;
+    /// Loads the EJS modules ahead of the first signature that needs them.
+    async fn warm_up(&self) -> Result<()>;
+    /// The player JS at `player_url`, downloaded when it isn't cached.
+    async fn load_player_code(&self, player_url: &str) -> Result<String>;
 }
 
 impl SignatureDecipherHandle for SignatureDecipher {
@@ -62,44 +131,92 @@ impl SignatureDecipherHandle for SignatureDecipher {
         example_sig: String,
         signature_type: SignatureType,
     ) -> Result<String> {
-        let player_js_code_key = self.player_cache.player_js_cache_key(&player_url)?;
+        if let Some(solver) = &self.challenge_solver {
+            let value = example_sig.clone();
+            let request = ChallengeRequest {
+                player_url: Some(player_url.clone()),
+                challenge: match signature_type {
+                    SignatureType::Signature => Challenge::Signature { value },
+                    SignatureType::Nsignature => Challenge::Nsignature { value },
+                },
+            };
 
-        if let Some(code) = self.code_cache.get(&player_js_code_key)? {
-            let res = self
-                .parse_signature_js(code, example_sig, signature_type)
-                .await?;
-            return Ok(res);
+            if let Some(solved) = solver.solve(&request).await? {
+                return Ok(solved);
+            }
         }
 
-        bail!(
-            "The player.js was not downloaded before, deciphering failed because the code was not found."
-        )
+        let code = self.load_player_code(&player_url).await?;
+
+        // Most players only reverse, splice and swap the signature, which doesn't need a JS runtime.
+        if let SignatureType::Signature = signature_type {
+            match NativeSignatureFunction::parse(&code) {
+                Ok(function) => return Ok(function.apply(&example_sig)),
+                Err(e) => tracing::debug!(
+                    "Falling back to the JS runtime to decipher the signature: {:#}",
+                    e
+                ),
+            }
+        }
+
+        self.parse_signature_js(code, example_sig, signature_type)
+            .await
     }
 
+    #[tracing::instrument(
+        name = "decipher",
+        skip_all,
+        fields(signature_type = signature_type.as_str(), player_url = %player_url)
+    )]
     async fn decrypt_signature(
         &self,
         signature_type: SignatureType,
         encrypted_signature: String,
         player_url: String,
     ) -> Result<String> {
-        let cache_id = (
-            format!("{}-{}", signature_type.as_str(), player_url),
-            encrypted_signature.clone(),
-        );
+        let cache_id =
+            self.signature_cache_id(signature_type, &encrypted_signature, &player_url)?;
 
-        if let Some(cached_deciphered_value) = self.player_cache.get(&cache_id)? {
+        if let Some(cached_deciphered_value) = self.cache.get(&cache_id)? {
             return Ok(cached_deciphered_value);
         }
 
-        let extracted_signature = self
-            .extract_signature_function(player_url, encrypted_signature, signature_type)
-            .await?;
+        let started = unix_timestamp_secs();
+
+        let mut extracted_signature = self
+            .extract_signature_function(
+                player_url.clone(),
+                encrypted_signature.clone(),
+                signature_type,
+            )
+            .await
+            .and_then(|sig| verify_signature(signature_type, &encrypted_signature, sig));
+
+        // The cached player may be truncated or outdated, retry once with a fresh one.
+        if extracted_signature.is_err() {
+            tracing::warn!(
+                "Deciphering with the cached player {} failed, downloading it again.",
+                player_url
+            );
+            self.cache.invalidate_player(&player_url)?;
+            extracted_signature = self
+                .extract_signature_function(player_url, encrypted_signature.clone(), signature_type)
+                .await
+                .and_then(|sig| verify_signature(signature_type, &encrypted_signature, sig));
+        }
+
+        let extracted_signature = extracted_signature?;
+        self.cache.add(cache_id, extracted_signature.clone())?;
+        if let Some(metrics) = &self.metrics {
+            metrics.record_decipher(unix_timestamp_secs() - started);
+        }
+
         Ok(extracted_signature)
     }
 
     async fn decipher(&self, signature: String, player_url: String) -> Result<String> {
-        #[cfg(feature = "logging")]
-        log::info!("Deciphering signature: \"{}\"", signature);
+        // The signature cipher holds the stream URL, which is as good as a credential in logs.
+        tracing::debug!("Deciphering a signature with the player {}", player_url);
         let sc = parse_query_string(&signature).unwrap_or_default();
 
         let (Some(fmt_url), Some(encrypted_sig)) = (sc.get("url").cloned(), sc.get("s").cloned())
@@ -117,18 +234,88 @@ impl SignatureDecipherHandle for SignatureDecipher {
             decrypted_signature,
         );
 
-        Ok(
-            match parse_query_string(&url_with_sig)
-                .unwrap_or_default()
-                .get("n")
-            {
-                Some(nsig) => replace_n_sig_query_param(
-                    &url_with_sig,
-                    self.decrypt_signature(SignatureType::Nsignature, nsig.clone(), player_url)
-                        .await?,
-                )?,
-                None => url_with_sig,
-            },
-        )
+        self.decipher_n(url_with_sig, player_url).await
+    }
+
+    async fn decipher_n(&self, url: String, player_url: String) -> Result<String> {
+        let Some(nsig) = parse_query_string(&url).unwrap_or_default().remove("n") else {
+            return Ok(url);
+        };
+
+        Ok(replace_n_sig_query_param(
+            &url,
+            self.decrypt_signature(SignatureType::Nsignature, nsig, player_url)
+                .await?,
+        )?)
+    }
+
+    fn forget_n(&self, url: &str, player_url: &str) -> Result<()> {
+        let Some(nsig) = parse_query_string(url).unwrap_or_default().remove("n") else {
+            return Ok(());
+        };
+
+        self.cache
+            .remove(&self.signature_cache_id(SignatureType::Nsignature, &nsig, player_url)?)
+    }
+
+    async fn warm_up(&self) -> Result<()> {
+        self.get_js_modules().await?;
+        Ok(())
     }
+
+    async fn load_player_code(&self, player_url: &str) -> Result<String> {
+        let player_js_key = self.cache.player_code_cache_key(player_url)?;
+
+        if let Some(code) = self.cache.get(&player_js_key)? {
+            return Ok(code);
+        }
+
+        tracing::info!("Downloading player {}", player_url);
+        let mut request = HttpRequest::get(Url::parse(player_url)?);
+        if let Some(cookie_jar) = &self.cookie_jar {
+            request = cookie_jar.add_to_request(request)?;
+        }
+
+        let code = self
+            .transport
+            .send(request)
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        if code.is_empty() {
+            bail!("The player {} is empty.", player_url);
+        }
+        self.cache.add(player_js_key, code.clone())?;
+
+        Ok(code)
+    }
+}
+
+/// Reject results that show the player didn't decipher anything, as the n function
+/// returns its input or an `enhanced_except_` marker when it throws.
+fn verify_signature(
+    signature_type: SignatureType,
+    encrypted_signature: &str,
+    deciphered: String,
+) -> Result<String> {
+    let failed = match signature_type {
+        SignatureType::Signature => deciphered.is_empty(),
+        SignatureType::Nsignature => {
+            deciphered.is_empty()
+                || deciphered == encrypted_signature
+                || deciphered.starts_with("enhanced_except_")
+        }
+    };
+
+    if failed {
+        bail!(
+            "Deciphering the {} value \"{}\" gave an invalid result.",
+            signature_type.as_str(),
+            encrypted_signature
+        );
+    }
+
+    Ok(deciphered)
 }
@@ -0,0 +1,300 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+#[cfg(any(target_arch = "wasm32", feature = "quickjs", feature = "boa"))]
+use anyhow::anyhow;
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::bail;
+
+use crate::http::{BoxFuture, MaybeSendSync};
+
+/// Runs the JavaScript that deciphers signatures.
+///
+/// Pass one as `TydleOptions::js_evaluator` to pick another runtime than the default, which is
+/// Deno with the `cipher` feature, then QuickJS (`quickjs`), Boa (`boa`) and finally a `node` subprocess.
+pub trait JsEvaluator: MaybeSendSync {
+    /// Run `script` and return its completion value converted to a string.
+    fn evaluate<'a>(&'a self, script: String) -> BoxFuture<'a, Result<String>>;
+}
+
+/// The evaluator used when `TydleOptions::js_evaluator` isn't set.
+pub fn default_evaluator() -> Arc<dyn JsEvaluator> {
+    #[cfg(target_arch = "wasm32")]
+    let evaluator = EvalEvaluator;
+    #[cfg(all(not(target_arch = "wasm32"), feature = "cipher"))]
+    let evaluator = DenoEvaluator;
+    #[cfg(all(
+        not(target_arch = "wasm32"),
+        not(feature = "cipher"),
+        feature = "quickjs"
+    ))]
+    let evaluator = QuickJsEvaluator;
+    #[cfg(all(
+        not(target_arch = "wasm32"),
+        not(any(feature = "cipher", feature = "quickjs")),
+        feature = "boa"
+    ))]
+    let evaluator = BoaEvaluator;
+    #[cfg(all(
+        not(target_arch = "wasm32"),
+        not(any(feature = "cipher", feature = "quickjs", feature = "boa"))
+    ))]
+    let evaluator = SubprocessEvaluator::node();
+
+    Arc::new(evaluator)
+}
+
+/// Embedded Deno (V8) runtime.
+#[cfg(all(not(target_arch = "wasm32"), feature = "cipher"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DenoEvaluator;
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "cipher"))]
+impl JsEvaluator for DenoEvaluator {
+    fn evaluate<'a>(&'a self, script: String) -> BoxFuture<'a, Result<String>> {
+        use deno_core::JsRuntime;
+
+        // A JS runtime can't move between threads, so it gets a blocking thread of its own
+        // to keep deciphering usable from multi-threaded executors.
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || -> Result<String> {
+                let mut deno = JsRuntime::new(Default::default());
+                let global_value = deno.execute_script("<evaluate>", script)?;
+
+                tokio::runtime::Handle::current()
+                    .block_on(deno.run_event_loop(Default::default()))?;
+
+                let local_value = global_value.open(deno.v8_isolate());
+
+                let mut scope = deno.handle_scope();
+                Ok(local_value.to_rust_string_lossy(&mut scope))
+            })
+            .await?
+        })
+    }
+}
+
+/// Embedded QuickJS runtime, much smaller than Deno.
+#[cfg(all(not(target_arch = "wasm32"), feature = "quickjs"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuickJsEvaluator;
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "quickjs"))]
+impl JsEvaluator for QuickJsEvaluator {
+    fn evaluate<'a>(&'a self, script: String) -> BoxFuture<'a, Result<String>> {
+        use rquickjs::{CatchResultExt, Context, Runtime};
+
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || -> Result<String> {
+                let runtime = Runtime::new()?;
+                let context = Context::full(&runtime)?;
+
+                context.with(|ctx| {
+                    ctx.eval::<rquickjs::Coerced<String>, _>(script)
+                        .catch(&ctx)
+                        .map(|value| value.0)
+                        .map_err(|e| anyhow!("QuickJS evaluation failed: {}", e))
+                })
+            })
+            .await?
+        })
+    }
+}
+
+/// Embedded Boa runtime, written in pure Rust.
+#[cfg(all(not(target_arch = "wasm32"), feature = "boa"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BoaEvaluator;
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "boa"))]
+impl JsEvaluator for BoaEvaluator {
+    fn evaluate<'a>(&'a self, script: String) -> BoxFuture<'a, Result<String>> {
+        use boa_engine::{Context, Source};
+
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || -> Result<String> {
+                let mut context = Context::default();
+                let value = context
+                    .eval(Source::from_bytes(&script))
+                    .and_then(|value| value.to_string(&mut context))
+                    .map_err(|e| anyhow!("Boa evaluation failed: {}", e))?;
+
+                Ok(value.to_std_string_escaped())
+            })
+            .await?
+        })
+    }
+}
+
+/// Runs scripts with an external `node` or `deno` executable, for builds without an embedded runtime.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct SubprocessEvaluator {
+    program: String,
+    args: Vec<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SubprocessEvaluator {
+    /// `program` is run with `args` and must read the script to run from stdin.
+    pub fn new<P: Into<String>>(program: P, args: Vec<String>) -> Self {
+        Self {
+            program: program.into(),
+            args,
+        }
+    }
+
+    pub fn node() -> Self {
+        Self::new("node", vec!["-".into()])
+    }
+
+    pub fn deno() -> Self {
+        Self::new("deno", vec!["run".into(), "-".into()])
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl JsEvaluator for SubprocessEvaluator {
+    fn evaluate<'a>(&'a self, script: String) -> BoxFuture<'a, Result<String>> {
+        use std::process::Stdio;
+        use tokio::{io::AsyncWriteExt, process::Command};
+
+        Box::pin(async move {
+            // Indirect eval gives the completion value of the script, which is then printed.
+            let wrapped = format!(
+                "console.log(String((0, eval)({})));",
+                serde_json::to_string(&script)?
+            );
+
+            let mut child = Command::new(&self.program)
+                .args(&self.args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()?;
+
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(wrapped.as_bytes()).await?;
+            }
+
+            let output = child.wait_with_output().await?;
+            if !output.status.success() {
+                bail!(
+                    "{} exited with {}: {}",
+                    self.program,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+
+            let stdout = String::from_utf8(output.stdout)?;
+            Ok(stdout.trim_end_matches(['\n', '\r']).to_string())
+        })
+    }
+}
+
+/// Evaluates scripts with the `eval()` of the JavaScript context tydle runs in.
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EvalEvaluator;
+
+#[cfg(target_arch = "wasm32")]
+impl JsEvaluator for EvalEvaluator {
+    fn evaluate<'a>(&'a self, script: String) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let value =
+                js_sys::eval(&script).map_err(|err| anyhow!("JS eval failed: {:?}", err))?;
+
+            value
+                .as_string()
+                .ok_or(anyhow!("The script didn't evaluate to a string."))
+        })
+    }
+}
+
+/// Evaluates scripts inside a dedicated Web Worker, so that deciphering doesn't block the main thread.
+///
+/// Each script runs in a worker of its own, started from a blob URL, which the page's
+/// Content Security Policy must allow with `worker-src blob:`.
+#[cfg(target_arch = "wasm32")]
+pub struct WorkerEvaluator {
+    script_url: String,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl WorkerEvaluator {
+    const WORKER_SCRIPT: &str = "onmessage = (event) => {
+        let response;
+        try {
+            response = { value: String((0, eval)(event.data)) };
+        } catch (err) {
+            response = { error: String(err) };
+        }
+        postMessage(response);
+    };";
+
+    pub fn new() -> Result<Self> {
+        let parts = js_sys::Array::of1(&Self::WORKER_SCRIPT.into());
+        let options = web_sys::BlobPropertyBag::new();
+        options.set_type("text/javascript");
+
+        let blob = web_sys::Blob::new_with_str_sequence_and_options(&parts, &options)
+            .map_err(|err| anyhow!("Could not create the worker script: {:?}", err))?;
+        let script_url = web_sys::Url::create_object_url_with_blob(&blob)
+            .map_err(|err| anyhow!("Could not create the worker script URL: {:?}", err))?;
+
+        Ok(Self { script_url })
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Drop for WorkerEvaluator {
+    fn drop(&mut self) {
+        let _ = web_sys::Url::revoke_object_url(&self.script_url);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl JsEvaluator for WorkerEvaluator {
+    fn evaluate<'a>(&'a self, script: String) -> BoxFuture<'a, Result<String>> {
+        use wasm_bindgen::{JsCast, JsValue, closure::Closure};
+
+        Box::pin(async move {
+            let worker = web_sys::Worker::new(&self.script_url)
+                .map_err(|err| anyhow!("Could not start the worker: {:?}", err))?;
+
+            let response = js_sys::Promise::new(&mut |resolve, reject| {
+                let onmessage = Closure::once_into_js(move |event: web_sys::MessageEvent| {
+                    let _ = resolve.call1(&JsValue::NULL, &event.data());
+                });
+                let onerror = Closure::once_into_js(move |event: JsValue| {
+                    let _ = reject.call1(&JsValue::NULL, &event);
+                });
+
+                worker.set_onmessage(Some(onmessage.unchecked_ref()));
+                worker.set_onerror(Some(onerror.unchecked_ref()));
+            });
+
+            let sent = worker.post_message(&script.into());
+            let response = match sent {
+                Ok(()) => wasm_bindgen_futures::JsFuture::from(response).await,
+                Err(err) => Err(err),
+            };
+            worker.terminate();
+
+            let response = response.map_err(|err| anyhow!("The worker failed: {:?}", err))?;
+            let field = |name: &str| {
+                js_sys::Reflect::get(&response, &name.into())
+                    .ok()
+                    .and_then(|value| value.as_string())
+            };
+
+            match (field("value"), field("error")) {
+                (Some(value), _) => Ok(value),
+                (None, Some(error)) => Err(anyhow!("JS eval failed in the worker: {}", error)),
+                (None, None) => Err(anyhow!("The worker didn't send a result.")),
+            }
+        })
+    }
+}
@@ -1,3 +1,7 @@
 mod js;
+mod native;
 
 pub mod decipher;
+pub mod evaluator;
+
+pub use js::EJS_VERSION;
@@ -0,0 +1,229 @@
+use anyhow::{Result, anyhow, bail};
+use fancy_regex::Regex;
+
+/// Step of the signature function of older players, as found in their helper object.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SignatureOp {
+    Reverse,
+    /// Drop the first `n` characters.
+    Splice(usize),
+    /// Swap the first character with the one at `n % len`.
+    Swap(usize),
+}
+
+/// Signature function of a player, parsed from its JS so that it runs without a JS runtime.
+///
+/// Only players whose function is a plain sequence of reverse, splice and swap calls on a helper
+/// object are supported, which covers the common cases. Anything else is left to the JS runtime.
+#[derive(Debug, Clone)]
+pub struct NativeSignatureFunction {
+    ops: Vec<SignatureOp>,
+}
+
+impl NativeSignatureFunction {
+    pub fn parse(code: &str) -> Result<Self> {
+        const FUNCNAME_RE: [&str; 5] = [
+            r"\b(?P<var>[a-zA-Z0-9_$]+)&&\((?P=var)=(?P<sig>[a-zA-Z0-9_$]{2,})\(decodeURIComponent\((?P=var)\)\)",
+            r#"(?P<sig>[a-zA-Z0-9_$]+)\s*=\s*function\(\s*(?P<arg>[a-zA-Z0-9_$]+)\s*\)\s*{\s*(?P=arg)\s*=\s*(?P=arg)\.split\(\s*""\s*\)\s*;\s*[^}]+;\s*return\s+(?P=arg)\.join\(\s*""\s*\)"#,
+            r#"(?:\b|[^a-zA-Z0-9_$])(?P<sig>[a-zA-Z0-9_$]{2,})\s*=\s*function\(\s*a\s*\)\s*{\s*a\s*=\s*a\.split\(\s*""\s*\)"#,
+            r"\b[cs]\s*&&\s*[adf]\.set\([^,]+\s*,\s*encodeURIComponent\s*\(\s*(?P<sig>[a-zA-Z0-9$]+)\(",
+            r"\b[a-zA-Z0-9]+\s*&&\s*[a-zA-Z0-9]+\.set\([^,]+\s*,\s*encodeURIComponent\s*\(\s*(?P<sig>[a-zA-Z0-9$]+)\(",
+        ];
+
+        let func_name = FUNCNAME_RE
+            .iter()
+            .find_map(|re| {
+                Regex::new(re)
+                    .ok()?
+                    .captures(code)
+                    .ok()??
+                    .name("sig")
+                    .map(|m| m.as_str().to_string())
+            })
+            .ok_or(anyhow!("Could not find the signature function name."))?;
+
+        let name = fancy_regex::escape(&func_name);
+        let func_re = Regex::new(&format!(
+            r"(?:function\s+{name}|[{{;,]\s*{name}\s*=\s*function|(?:var|const|let)\s+{name}\s*=\s*function)\s*\((?P<arg>[a-zA-Z0-9_$]+)\)\s*\{{(?P<code>[^}}]+)\}}"
+        ))?;
+        let caps = func_re
+            .captures(code)?
+            .ok_or(anyhow!("Could not find the body of {}.", func_name))?;
+        let arg = &caps["arg"];
+        let statements: Vec<&str> = caps["code"].split(';').map(str::trim).collect();
+
+        let (Some(first), Some(last)) = (statements.first(), statements.last()) else {
+            bail!("The signature function is empty.");
+        };
+        if !first.starts_with(&format!("{}={}.split(", arg, arg))
+            || !last.starts_with(&format!("return {}.join(", arg))
+        {
+            bail!("Unexpected shape of the signature function.");
+        }
+
+        let call_re = Regex::new(&format!(
+            r#"^(?P<obj>[a-zA-Z0-9_$]+)(?:\.(?P<key>[a-zA-Z0-9_$]+)|\["(?P<qkey>[^"]+)"\])\({}(?:,(?P<n>\d+))?\)$"#,
+            fancy_regex::escape(arg)
+        ))?;
+        let mut helper: Option<(String, Vec<(String, SignatureOp)>)> = None;
+        let mut ops = Vec::new();
+
+        for statement in &statements[1..statements.len() - 1] {
+            let caps = call_re
+                .captures(statement)?
+                .ok_or(anyhow!("Unsupported statement \"{}\".", statement))?;
+            let obj = &caps["obj"];
+            let key = caps
+                .name("key")
+                .or(caps.name("qkey"))
+                .map(|m| m.as_str())
+                .unwrap_or_default();
+            let n: usize = caps.name("n").map_or(Ok(0), |n| n.as_str().parse())?;
+
+            if helper.as_ref().is_none_or(|(name, _)| name != obj) {
+                helper = Some((obj.to_string(), parse_helper_object(code, obj)?));
+            }
+            let (_, methods) = helper.as_ref().unwrap();
+            let op = methods
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, op)| *op)
+                .ok_or(anyhow!("Unknown helper method {}.{}.", obj, key))?;
+
+            ops.push(match op {
+                SignatureOp::Reverse => SignatureOp::Reverse,
+                SignatureOp::Splice(_) => SignatureOp::Splice(n),
+                SignatureOp::Swap(_) => SignatureOp::Swap(n),
+            });
+        }
+
+        Ok(Self { ops })
+    }
+
+    pub fn apply(&self, signature: &str) -> String {
+        let mut chars: Vec<char> = signature.chars().collect();
+
+        for op in &self.ops {
+            match *op {
+                SignatureOp::Reverse => chars.reverse(),
+                SignatureOp::Splice(n) => {
+                    chars.drain(..n.min(chars.len()));
+                }
+                SignatureOp::Swap(n) if !chars.is_empty() => {
+                    let len = chars.len();
+                    chars.swap(0, n % len);
+                }
+                SignatureOp::Swap(_) => {}
+            }
+        }
+
+        chars.into_iter().collect()
+    }
+}
+
+/// Find `var {obj}={...}` in the player and classify each of its methods.
+fn parse_helper_object(code: &str, obj: &str) -> Result<Vec<(String, SignatureOp)>> {
+    let obj_re = Regex::new(&format!(
+        r"(?:(?:var|const|let)\s+|[;,]\s*){}\s*=\s*\{{",
+        fancy_regex::escape(obj)
+    ))?;
+    let start = obj_re
+        .find(code)?
+        .ok_or(anyhow!("Could not find the helper object {}.", obj))?
+        .end();
+
+    // The methods are small enough that the object ends at the first unbalanced brace.
+    let mut depth = 1;
+    let end = code[start..]
+        .char_indices()
+        .find_map(|(i, c)| {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            (depth == 0).then_some(start + i)
+        })
+        .ok_or(anyhow!("The helper object {} is not closed.", obj))?;
+
+    let method_re = Regex::new(
+        r#"(?P<key>[a-zA-Z0-9_$]+|"[^"]+")\s*:\s*function\s*\([^)]*\)\s*\{(?P<body>[^}]*)\}"#,
+    )?;
+    let mut methods = Vec::new();
+
+    for caps in method_re.captures_iter(&code[start..end]) {
+        let caps = caps?;
+        let key = caps["key"].trim_matches('"').to_string();
+        let body = &caps["body"];
+
+        // Methods that are none of these are reported once the signature function calls them.
+        let op = if body.contains("reverse") {
+            SignatureOp::Reverse
+        } else if body.contains("splice") {
+            SignatureOp::Splice(0)
+        } else if body.contains('%') || body.contains("var c=") {
+            SignatureOp::Swap(0)
+        } else {
+            continue;
+        };
+        methods.push((key, op));
+    }
+
+    Ok(methods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELPER: &str = r#"var Xy={aB:function(a){a.reverse()},"cD":function(a,b){a.splice(0,b)},eF:function(a,b){var c=a[0];a[0]=a[b%a.length];a[b%a.length]=c},gH:function(a){a.push("x")}};"#;
+    const CALL: &str = "c&&(c=Qz(decodeURIComponent(c)));";
+
+    /// A synthetic player whose signature function runs `body` between the split and the join.
+    fn player(body: &str) -> String {
+        format!(
+            r#"{}
+Qz=function(a){{a=a.split("");{}return a.join("")}};
+{}"#,
+            HELPER, body, CALL
+        )
+    }
+
+    #[test]
+    fn applies_reverse_splice_and_swap() {
+        let function = NativeSignatureFunction::parse(&player(
+            r#"Xy.aB(a,1);Xy.eF(a,3);Xy["cD"](a,2);Xy.eF(a,5);"#,
+        ))
+        .unwrap();
+
+        assert_eq!(
+            function.ops,
+            [
+                SignatureOp::Reverse,
+                SignatureOp::Swap(3),
+                SignatureOp::Splice(2),
+                SignatureOp::Swap(5),
+            ]
+        );
+        assert_eq!(function.apply("abcdefghij"), "cjfedhba");
+    }
+
+    #[test]
+    fn rejects_unsupported_statements() {
+        let err =
+            NativeSignatureFunction::parse(&player("for(var i=0;i<3;i++)Xy.aB(a,1);")).unwrap_err();
+
+        assert!(
+            err.to_string().starts_with("Unsupported statement"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_helper_methods() {
+        let err = NativeSignatureFunction::parse(&player("Xy.aB(a,1);Xy.gH(a,2);")).unwrap_err();
+
+        assert_eq!(err.to_string(), "Unknown helper method Xy.gH.");
+    }
+}
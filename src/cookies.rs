@@ -1,20 +1,31 @@
+#[cfg(all(feature = "browser-cookies", not(target_arch = "wasm32")))]
+pub mod browser;
+
 use std::{
     ops::{Deref, DerefMut},
     sync::RwLock,
 };
 
 use anyhow::{Result, anyhow};
-#[cfg(target_arch = "wasm32")]
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
+#[cfg(any(feature = "serde", target_arch = "wasm32"))]
+use serde::Serialize;
+use url::Url;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::{JsValue, prelude::wasm_bindgen};
 
+use crate::{http::HttpRequest, utils::unix_timestamp_secs};
+
 #[cfg_attr(
-    target_arch = "wasm32",
-    derive(Serialize, Deserialize, tsify::Tsify),
-    tsify(into_wasm_abi, from_wasm_abi),
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(Serialize, Deserialize),
     serde(rename_all = "camelCase")
 )]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
 #[derive(Debug, Clone)]
 pub struct Cookie {
     pub name: String,
@@ -41,12 +52,96 @@ impl Default for Cookie {
     }
 }
 
+impl Cookie {
+    /// Parse the value of a `Set-Cookie` header received in response to `url`.
+    ///
+    /// Returns `None` when the header is malformed or sets a cookie for another domain than `url`'s.
+    pub fn from_set_cookie(header: &str, url: &Url) -> Option<Self> {
+        let host = url.host_str()?;
+        let mut parts = header.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let mut cookie = Cookie {
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+            domain: host.to_string(),
+            ..Default::default()
+        };
+        let mut max_age: Option<i64> = None;
+
+        for attribute in parts {
+            let (key, value) = attribute
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .unwrap_or((attribute.trim(), ""));
+
+            match key.to_ascii_lowercase().as_str() {
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    if host != domain && !host.ends_with(&format!(".{}", domain)) {
+                        return None;
+                    }
+                    cookie.domain = format!(".{}", domain);
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "expires" => {
+                    if let Ok(expires) = httpdate::parse_http_date(value) {
+                        // Dates before the epoch still have to expire the cookie, unlike a 0 expiration.
+                        cookie.expiration = expires
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map_or(1, |since_epoch| since_epoch.as_secs().max(1));
+                    }
+                }
+                "max-age" => max_age = value.parse().ok(),
+                _ => {}
+            }
+        }
+
+        // `Max-Age` takes precedence over `Expires`.
+        if let Some(max_age) = max_age {
+            cookie.expiration = (unix_timestamp_secs() as i64 + max_age).max(1) as u64;
+        }
+
+        Some(cookie)
+    }
+
+    /// Whether a browser would send the cookie with a request to `url`.
+    pub fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let domain_matches = match self.domain.strip_prefix('.') {
+            Some(domain) => host == domain || host.ends_with(&self.domain),
+            None => host == self.domain,
+        };
+        let path_matches = match url.path().strip_prefix(self.path.as_str()) {
+            Some(rest) => self.path.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        };
+
+        domain_matches
+            && path_matches
+            && (!self.secure || url.scheme() == "https")
+            && !self.is_expired()
+    }
+
+    /// Whether the cookie has expired. Session cookies, with an expiration of 0, never do.
+    pub fn is_expired(&self) -> bool {
+        self.expiration != 0 && self.expiration as f64 <= unix_timestamp_secs()
+    }
+}
+
 #[cfg_attr(
-    target_arch = "wasm32",
-    derive(Serialize, Deserialize, tsify::Tsify),
-    tsify(into_wasm_abi, from_wasm_abi),
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(Serialize, Deserialize),
     serde(rename_all = "camelCase")
 )]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
 #[derive(Debug, Default, Clone)]
 pub struct DomainCookies(Vec<Cookie>);
 
@@ -98,6 +193,28 @@ impl DomainCookies {
 
         parts.join("; ")
     }
+
+    /// Format the cookies as a Netscape cookie file, the format `parse_netscape_cookies` reads.
+    pub fn to_netscape(&self) -> String {
+        let mut content = String::from("# Netscape HTTP Cookie File\n");
+
+        for cookie in &self.0 {
+            let flag = |value: bool| if value { "TRUE" } else { "FALSE" };
+
+            content.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                cookie.domain,
+                flag(cookie.domain.starts_with('.')),
+                cookie.path,
+                flag(cookie.secure),
+                cookie.expiration,
+                cookie.name,
+                cookie.value
+            ));
+        }
+
+        content
+    }
 }
 
 #[derive(Debug)]
@@ -115,7 +232,24 @@ impl CookieJar {
 
 pub(crate) trait CookieStore {
     fn get_all(&self, domain: &str) -> Result<DomainCookies>;
+    /// Cookies a browser would send with a request to `url`.
+    fn get_for_url(&self, url: &Url) -> Result<DomainCookies>;
+    /// Every cookie of the jar, whatever its domain.
+    fn all(&self) -> Result<DomainCookies>;
+    /// Add `cookie`, replacing the one with the same name, domain and path. Expired cookies
+    /// remove that one instead.
     fn set(&self, cookie: Cookie) -> Result<()>;
+
+    /// Add a `Cookie` header with the cookies that apply to the URL of `request`.
+    fn add_to_request(&self, request: HttpRequest) -> Result<HttpRequest> {
+        let cookies = self.get_for_url(&request.url)?;
+
+        if cookies.is_empty() {
+            return Ok(request);
+        }
+
+        Ok(request.header("Cookie", cookies.header_value()))
+    }
 }
 
 impl CookieStore for CookieJar {
@@ -129,14 +263,89 @@ impl CookieStore for CookieJar {
             .collect())
     }
 
+    fn get_for_url(&self, url: &Url) -> Result<DomainCookies> {
+        let cookies = self.cookies.read().map_err(|e| anyhow!(e.to_string()))?;
+
+        Ok(cookies.iter().filter(|c| c.matches(url)).cloned().collect())
+    }
+
+    fn all(&self) -> Result<DomainCookies> {
+        let cookies = self.cookies.read().map_err(|e| anyhow!(e.to_string()))?;
+
+        Ok(cookies.clone())
+    }
+
     fn set(&self, cookie: Cookie) -> Result<()> {
         let mut cookies = self.cookies.write().map_err(|e| anyhow!(e.to_string()))?;
-        cookies.push(cookie);
+        cookies.retain(|c| {
+            c.name != cookie.name || c.domain != cookie.domain || c.path != cookie.path
+        });
+
+        if !cookie.is_expired() {
+            cookies.push(cookie);
+        }
 
         Ok(())
     }
 }
 
+/// Cookie as exported by browser extensions like EditThisCookie or Cookie-Editor.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonCookie {
+    name: String,
+    value: String,
+    domain: String,
+    #[serde(default = "default_path")]
+    path: String,
+    #[serde(default)]
+    secure: bool,
+    #[serde(default)]
+    http_only: bool,
+    #[serde(default)]
+    host_only: bool,
+    /// Missing for session cookies.
+    expiration_date: Option<f64>,
+}
+
+fn default_path() -> String {
+    "/".to_string()
+}
+
+/// Parse cookies exported as a JSON array by browser extensions into `DomainCookies`
+pub fn parse_json_cookies(cookie_content: &str) -> Result<DomainCookies> {
+    let json_cookies: Vec<JsonCookie> = serde_json::from_str(cookie_content)?;
+
+    Ok(DomainCookies::new(
+        json_cookies
+            .into_iter()
+            .map(|cookie| Cookie {
+                domain: if cookie.host_only || cookie.domain.starts_with('.') {
+                    cookie.domain
+                } else {
+                    format!(".{}", cookie.domain)
+                },
+                name: cookie.name,
+                value: cookie.value,
+                path: cookie.path,
+                secure: cookie.secure,
+                expiration: cookie.expiration_date.unwrap_or_default() as u64,
+                http_only: cookie.http_only,
+            })
+            .collect(),
+    ))
+}
+
+/// Parse a cookie file into `DomainCookies`, either a JSON export of a browser extension or,
+/// failing that, a Netscape formatted file.
+pub fn parse_cookies(cookie_content: String) -> Result<DomainCookies> {
+    if cookie_content.trim_start().starts_with('[') {
+        return parse_json_cookies(&cookie_content);
+    }
+
+    parse_netscape_cookies(cookie_content)
+}
+
 /// Parse a Netscape formatted cookie file into `DomainCookies`
 pub fn parse_netscape_cookies(cookie_content: String) -> Result<DomainCookies> {
     let mut cookies = DomainCookies::new(vec![]);
@@ -186,6 +395,15 @@ pub fn parse_netscape_cookies(cookie_content: String) -> Result<DomainCookies> {
     Ok(cookies)
 }
 
+/// Parse a JSON or Netscape formatted cookie file into `DomainCookies`
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_name = "parseCookies")]
+pub fn parse_cookies_js(
+    #[wasm_bindgen(js_name = "cookieContent")] cookie_content: String,
+) -> Result<DomainCookies, JsValue> {
+    parse_cookies(cookie_content).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
 /// Parse a Netscape formatted cookie file into a `HashMap`
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(js_name = "parseNetscapeCookies")]
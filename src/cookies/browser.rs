@@ -0,0 +1,504 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use rusqlite::{Connection, OpenFlags};
+
+use crate::cookies::{Cookie, DomainCookies};
+
+/// Only the cookies of these domains are read, as they are the only ones tydle sends.
+const COOKIE_DOMAIN: &str = "youtube.com";
+
+/// Seconds between 1601-01-01, the epoch of Chromium's timestamps, and the Unix epoch.
+const CHROMIUM_EPOCH_OFFSET: u64 = 11_644_473_600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Browser {
+    Brave,
+    Chrome,
+    Chromium,
+    Edge,
+    Firefox,
+    Opera,
+    Vivaldi,
+}
+
+impl Browser {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Brave => "brave",
+            Self::Chrome => "chrome",
+            Self::Chromium => "chromium",
+            Self::Edge => "edge",
+            Self::Firefox => "firefox",
+            Self::Opera => "opera",
+            Self::Vivaldi => "vivaldi",
+        }
+    }
+
+    /// Directory holding the profiles of the browser.
+    fn user_data_dir(&self) -> Option<PathBuf> {
+        #[cfg(target_os = "windows")]
+        {
+            let local = std::env::var_os("LOCALAPPDATA").map(PathBuf::from)?;
+            let roaming = std::env::var_os("APPDATA").map(PathBuf::from)?;
+
+            Some(match self {
+                Self::Brave => local.join(r"BraveSoftware\Brave-Browser\User Data"),
+                Self::Chrome => local.join(r"Google\Chrome\User Data"),
+                Self::Chromium => local.join(r"Chromium\User Data"),
+                Self::Edge => local.join(r"Microsoft\Edge\User Data"),
+                Self::Firefox => roaming.join(r"Mozilla\Firefox\Profiles"),
+                Self::Opera => roaming.join(r"Opera Software\Opera Stable"),
+                Self::Vivaldi => local.join(r"Vivaldi\User Data"),
+            })
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            let support =
+                PathBuf::from(std::env::var_os("HOME")?).join("Library/Application Support");
+
+            Some(match self {
+                Self::Brave => support.join("BraveSoftware/Brave-Browser"),
+                Self::Chrome => support.join("Google/Chrome"),
+                Self::Chromium => support.join("Chromium"),
+                Self::Edge => support.join("Microsoft Edge"),
+                Self::Firefox => support.join("Firefox/Profiles"),
+                Self::Opera => support.join("com.operasoftware.Opera"),
+                Self::Vivaldi => support.join("Vivaldi"),
+            })
+        }
+
+        #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+        {
+            let home = PathBuf::from(std::env::var_os("HOME")?);
+            let config = std::env::var_os("XDG_CONFIG_HOME")
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".config"));
+
+            Some(match self {
+                Self::Brave => config.join("BraveSoftware/Brave-Browser"),
+                Self::Chrome => config.join("google-chrome"),
+                Self::Chromium => config.join("chromium"),
+                Self::Edge => config.join("microsoft-edge"),
+                Self::Firefox => {
+                    let snap = home.join("snap/firefox/common/.mozilla/firefox");
+                    if snap.is_dir() {
+                        snap
+                    } else {
+                        home.join(".mozilla/firefox")
+                    }
+                }
+                Self::Opera => config.join("opera"),
+                Self::Vivaldi => config.join("vivaldi"),
+            })
+        }
+    }
+
+    /// Name the browser stores its cookie password under in the OS keyring.
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    fn keyring_name(&self) -> &'static str {
+        match self {
+            Self::Brave => "Brave",
+            Self::Chrome => "Chrome",
+            Self::Edge if cfg!(target_os = "macos") => "Microsoft Edge",
+            Self::Opera if cfg!(target_os = "macos") => "Opera",
+            Self::Vivaldi if cfg!(target_os = "macos") => "Vivaldi",
+            Self::Vivaldi => "Chrome",
+            _ => "Chromium",
+        }
+    }
+}
+
+impl FromStr for Browser {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "brave" => Self::Brave,
+            "chrome" => Self::Chrome,
+            "chromium" => Self::Chromium,
+            "edge" => Self::Edge,
+            "firefox" => Self::Firefox,
+            "opera" => Self::Opera,
+            "vivaldi" => Self::Vivaldi,
+            _ => bail!("Unsupported browser \"{}\".", s),
+        })
+    }
+}
+
+impl DomainCookies {
+    /// Read the YouTube cookies of `browser` from its cookie database.
+    ///
+    /// `profile` is either the name of a profile (`Default` for Chromium based browsers and the most
+    /// recently used one for Firefox when `None`) or the path to its directory.
+    pub fn from_browser(browser: Browser, profile: Option<&str>) -> Result<Self> {
+        let profile_dir = match profile {
+            Some(profile) if Path::new(profile).is_absolute() => PathBuf::from(profile),
+            _ => {
+                let user_data_dir = browser.user_data_dir().ok_or(anyhow!(
+                    "Could not locate the data of {}.",
+                    browser.as_str()
+                ))?;
+
+                match (browser, profile) {
+                    (Browser::Firefox, profile) => find_firefox_profile(&user_data_dir, profile)?,
+                    // Opera keeps its only profile right in its data directory.
+                    (Browser::Opera, None) => user_data_dir,
+                    (_, profile) => user_data_dir.join(profile.unwrap_or("Default")),
+                }
+            }
+        };
+
+        match browser {
+            Browser::Firefox => read_firefox_cookies(&profile_dir.join("cookies.sqlite")),
+            _ => {
+                let database = ["Network/Cookies", "Cookies"]
+                    .iter()
+                    .map(|file| profile_dir.join(file))
+                    .find(|path| path.is_file())
+                    .ok_or(anyhow!(
+                        "Could not find the cookie database of {} in {}.",
+                        browser.as_str(),
+                        profile_dir.display()
+                    ))?;
+
+                read_chromium_cookies(browser, &database)
+            }
+        }
+    }
+}
+
+/// Find the profile named `name`, or the one whose cookies changed last.
+fn find_firefox_profile(profiles_dir: &Path, name: Option<&str>) -> Result<PathBuf> {
+    let mut profiles: Vec<(PathBuf, std::time::SystemTime)> = std::fs::read_dir(profiles_dir)
+        .with_context(|| {
+            format!(
+                "Could not read Firefox profiles in {}",
+                profiles_dir.display()
+            )
+        })?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            name.is_none_or(|name| {
+                path.file_name()
+                    .and_then(|file_name| file_name.to_str())
+                    .is_some_and(|file_name| {
+                        file_name == name || file_name.ends_with(&format!(".{}", name))
+                    })
+            })
+        })
+        .filter_map(|path| {
+            let modified = path
+                .join("cookies.sqlite")
+                .metadata()
+                .ok()?
+                .modified()
+                .ok()?;
+            Some((path, modified))
+        })
+        .collect();
+
+    profiles.sort_by_key(|(_, modified)| *modified);
+    profiles.pop().map(|(path, _)| path).ok_or(anyhow!(
+        "Could not find a Firefox profile in {}.",
+        profiles_dir.display()
+    ))
+}
+
+/// Open a copy of `database`, as browsers lock theirs while they run. The copy is made in a
+/// directory only the user can read, along with the `-wal` and `-shm` files holding what the
+/// running browser wrote recently, and is removed with the returned `TempDir`, which has to
+/// outlive the connection.
+fn open_database_copy(database: &Path) -> Result<(tempfile::TempDir, Connection)> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("tydle-cookies-");
+    #[cfg(unix)]
+    builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o700));
+    let dir = builder.tempdir()?;
+    let file_name = database
+        .file_name()
+        .ok_or(anyhow!("{} is not a file.", database.display()))?;
+    let copy = dir.path().join(file_name);
+
+    std::fs::copy(database, &copy)
+        .with_context(|| format!("Could not copy {}", database.display()))?;
+    for suffix in ["-wal", "-shm"] {
+        let mut journal = database.as_os_str().to_owned();
+        journal.push(suffix);
+        let mut journal_copy = copy.as_os_str().to_owned();
+        journal_copy.push(suffix);
+
+        match std::fs::copy(&journal, &journal_copy) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(anyhow::Error::from(e)
+                    .context(format!("Could not copy {}", Path::new(&journal).display())));
+            }
+            _ => {}
+        }
+    }
+    // Read-write, so that SQLite can read the WAL of the copy.
+    let connection = Connection::open_with_flags(&copy, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+
+    Ok((dir, connection))
+}
+
+fn read_firefox_cookies(database: &Path) -> Result<DomainCookies> {
+    let (_copy, connection) = open_database_copy(database)?;
+    let mut statement = connection.prepare(
+        "SELECT host, name, value, path, expiry, isSecure, isHttpOnly FROM moz_cookies WHERE host LIKE ?1",
+    )?;
+
+    let cookies = statement
+        .query_map([format!("%{}", COOKIE_DOMAIN)], |row| {
+            let expiry: i64 = row.get(4)?;
+
+            Ok(Cookie {
+                domain: row.get(0)?,
+                name: row.get(1)?,
+                value: row.get(2)?,
+                path: row.get(3)?,
+                // Recent versions store the expiry in milliseconds.
+                expiration: if expiry > 100_000_000_000 {
+                    expiry / 1000
+                } else {
+                    expiry
+                }
+                .max(0) as u64,
+                secure: row.get(5)?,
+                http_only: row.get(6)?,
+            })
+        })?
+        .collect::<rusqlite::Result<DomainCookies>>()?;
+
+    Ok(cookies)
+}
+
+fn read_chromium_cookies(browser: Browser, database: &Path) -> Result<DomainCookies> {
+    let (_copy, connection) = open_database_copy(database)?;
+    let decryptor = ChromiumDecryptor::new(browser, database)?;
+
+    // Since version 24, decrypted values start with the SHA256 of their domain.
+    let version: i64 = connection
+        .query_row("SELECT value FROM meta WHERE key = 'version'", [], |row| {
+            row.get::<_, String>(0)
+        })
+        .ok()
+        .and_then(|version| version.parse().ok())
+        .unwrap_or_default();
+
+    let mut statement = connection.prepare(
+        "SELECT host_key, name, value, encrypted_value, path, expires_utc, is_secure, is_httponly FROM cookies WHERE host_key LIKE ?1",
+    )?;
+    let rows = statement.query_map([format!("%{}", COOKIE_DOMAIN)], |row| {
+        Ok((
+            Cookie {
+                domain: row.get(0)?,
+                name: row.get(1)?,
+                value: row.get(2)?,
+                path: row.get(4)?,
+                expiration: (row.get::<_, i64>(5)?.max(0) as u64 / 1_000_000)
+                    .saturating_sub(CHROMIUM_EPOCH_OFFSET),
+                secure: row.get(6)?,
+                http_only: row.get(7)?,
+            },
+            row.get::<_, Vec<u8>>(3)?,
+        ))
+    })?;
+
+    let mut cookies = DomainCookies::new(vec![]);
+    let mut failed = 0;
+
+    for row in rows {
+        let (mut cookie, encrypted_value) = row?;
+
+        if cookie.value.is_empty() && !encrypted_value.is_empty() {
+            let Some(mut value) = decryptor.decrypt(&encrypted_value) else {
+                failed += 1;
+                continue;
+            };
+            if version >= 24 && value.len() >= 32 {
+                value.drain(..32);
+            }
+            cookie.value = String::from_utf8(value)?;
+        }
+
+        cookies.push(cookie);
+    }
+
+    if failed > 0 {
+        tracing::warn!(
+            "Could not decrypt {} cookies of {}.",
+            failed,
+            browser.as_str()
+        );
+
+        if cookies.is_empty() {
+            bail!("Could not decrypt the cookies of {}.", browser.as_str());
+        }
+    }
+
+    Ok(cookies)
+}
+
+/// Decrypts the `encrypted_value` of Chromium cookies, prefixed by `v10` or `v11`.
+struct ChromiumDecryptor {
+    #[cfg(not(target_os = "windows"))]
+    v10_key: [u8; 16],
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    v11_key: Option<[u8; 16]>,
+    #[cfg(target_os = "windows")]
+    key: Option<Vec<u8>>,
+}
+
+impl ChromiumDecryptor {
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    fn new(browser: Browser, _database: &Path) -> Result<Self> {
+        // v11 values use the password kept in the keyring, read through libsecret's CLI.
+        let v11_key = std::process::Command::new("secret-tool")
+            .args([
+                "lookup",
+                "application",
+                &browser.keyring_name().to_ascii_lowercase(),
+            ])
+            .output()
+            .ok()
+            .filter(|output| output.status.success() && !output.stdout.is_empty())
+            .map(|output| derive_key(&output.stdout, 1));
+
+        Ok(Self {
+            v10_key: derive_key(b"peanuts", 1),
+            v11_key,
+        })
+    }
+
+    #[cfg(target_os = "macos")]
+    fn new(browser: Browser, _database: &Path) -> Result<Self> {
+        let name = browser.keyring_name();
+        let output = std::process::Command::new("security")
+            .args(["find-generic-password", "-w", "-a", name, "-s"])
+            .arg(format!("{} Safe Storage", name))
+            .output()?;
+
+        if !output.status.success() {
+            bail!("Could not read the password of {} from the keychain.", name);
+        }
+
+        let password = String::from_utf8_lossy(&output.stdout);
+        Ok(Self {
+            v10_key: derive_key(password.trim_end().as_bytes(), 1003),
+        })
+    }
+
+    #[cfg(target_os = "windows")]
+    fn new(_browser: Browser, database: &Path) -> Result<Self> {
+        use base64::Engine;
+
+        // `Local State` sits in the user data directory, above the profile.
+        let local_state = database
+            .ancestors()
+            .map(|dir| dir.join("Local State"))
+            .find(|path| path.is_file());
+        let key = local_state
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+            .and_then(|state| {
+                state["os_crypt"]["encrypted_key"]
+                    .as_str()
+                    .map(str::to_string)
+            })
+            .and_then(|key| base64::engine::general_purpose::STANDARD.decode(key).ok())
+            .and_then(|key| key.strip_prefix(b"DPAPI").map(<[u8]>::to_vec))
+            .and_then(|key| dpapi_decrypt(&key));
+
+        Ok(Self { key })
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn decrypt(&self, encrypted_value: &[u8]) -> Option<Vec<u8>> {
+        let (prefix, ciphertext) = encrypted_value.split_at_checked(3)?;
+
+        match prefix {
+            b"v10" => aes_cbc_decrypt(&self.v10_key, ciphertext),
+            #[cfg(not(target_os = "macos"))]
+            b"v11" => aes_cbc_decrypt(self.v11_key.as_ref()?, ciphertext),
+            _ => None,
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    fn decrypt(&self, encrypted_value: &[u8]) -> Option<Vec<u8>> {
+        use aes_gcm::{Aes256Gcm, KeyInit, aead::Aead};
+
+        match encrypted_value.split_at_checked(3)? {
+            (b"v10", rest) => {
+                let (nonce, ciphertext) = rest.split_at_checked(12)?;
+                let cipher = Aes256Gcm::new_from_slice(self.key.as_ref()?).ok()?;
+
+                cipher.decrypt(nonce.into(), ciphertext).ok()
+            }
+            // Values written by older versions are encrypted with DPAPI directly. Newer prefixes,
+            // like the app-bound `v20`, can't be decrypted outside of the browser.
+            (prefix, _) if prefix.starts_with(b"v") => None,
+            _ => dpapi_decrypt(encrypted_value),
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn derive_key(password: &[u8], iterations: u32) -> [u8; 16] {
+    let mut key = [0u8; 16];
+    pbkdf2::pbkdf2_hmac::<sha1::Sha1>(password, b"saltysalt", iterations, &mut key);
+    key
+}
+
+#[cfg(not(target_os = "windows"))]
+fn aes_cbc_decrypt(key: &[u8; 16], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    use aes::cipher::{BlockDecryptMut, KeyIvInit, block_padding::Pkcs7};
+
+    cbc::Decryptor::<aes::Aes128>::new(key.into(), &[b' '; 16].into())
+        .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+        .ok()
+}
+
+#[cfg(target_os = "windows")]
+fn dpapi_decrypt(data: &[u8]) -> Option<Vec<u8>> {
+    use windows_sys::Win32::{
+        Foundation::LocalFree,
+        Security::Cryptography::{CRYPT_INTEGER_BLOB, CryptUnprotectData},
+    };
+
+    let input = CRYPT_INTEGER_BLOB {
+        cbData: data.len() as u32,
+        pbData: data.as_ptr() as *mut u8,
+    };
+    let mut output = CRYPT_INTEGER_BLOB {
+        cbData: 0,
+        pbData: std::ptr::null_mut(),
+    };
+
+    // SAFETY: `input` points to `data`, which outlives the call, and `output` is freed with
+    // `LocalFree` once copied, as the documentation of `CryptUnprotectData` requires.
+    unsafe {
+        if CryptUnprotectData(
+            &input,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null(),
+            0,
+            &mut output,
+        ) == 0
+        {
+            return None;
+        }
+
+        let decrypted = std::slice::from_raw_parts(output.pbData, output.cbData as usize).to_vec();
+        LocalFree(output.pbData as _);
+        Some(decrypted)
+    }
+}
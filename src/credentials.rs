@@ -0,0 +1,232 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow, bail};
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, KeyInit, Nonce,
+    aead::{Aead, AeadCore, OsRng, rand_core::RngCore},
+};
+use sha2::Sha256;
+
+use crate::{
+    cache::{Cache, PO_TOKEN_KEY_PREFIX, VISITOR_DATA_KEY},
+    error::TydleError,
+};
+
+/// Marks the files written by `encrypt`, followed by the salt, the nonce and the ciphertext.
+const MAGIC: &[u8] = b"TYDLE-ENC1\n";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const PBKDF2_ITERATIONS: u32 = 600_000;
+/// Service and user of the OS keyring entry `store_passphrase` writes.
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+const KEYRING_SERVICE: &str = "tydle";
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+const KEYRING_USER: &str = "credentials-passphrase";
+
+/// Whether `data` was written by `encrypt`.
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Key {
+    let mut key = Key::default();
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ITERATIONS, &mut key);
+    key
+}
+
+/// Encrypt credentials like a cookie jar with ChaCha20-Poly1305, under a key derived from
+/// `passphrase`, so that they aren't stored in plain text.
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+
+    encrypt_with_key(plaintext, &salt, &derive_key(passphrase, &salt))
+}
+
+fn encrypt_with_key(plaintext: &[u8], salt: &[u8], key: &Key) -> Result<Vec<u8>> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let ciphertext = ChaCha20Poly1305::new(key)
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| anyhow!("Failed to encrypt the credentials."))?;
+
+    Ok([MAGIC, salt, &nonce, &ciphertext].concat())
+}
+
+/// Decrypt data written by `encrypt`. Fails if `passphrase` is wrong or the data was tampered with.
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let salt = salt(data)?;
+
+    decrypt_with_key(data, &derive_key(passphrase, salt))
+}
+
+/// The salt of data written by `encrypt`.
+fn salt(data: &[u8]) -> Result<&[u8]> {
+    let Some(data) = data.strip_prefix(MAGIC) else {
+        bail!("The credentials weren't encrypted by tydle.");
+    };
+    if data.len() < SALT_LEN + NONCE_LEN {
+        bail!("The encrypted credentials are truncated.");
+    }
+
+    Ok(&data[..SALT_LEN])
+}
+
+fn decrypt_with_key(data: &[u8], key: &Key) -> Result<Vec<u8>> {
+    let data = &data[MAGIC.len() + SALT_LEN..];
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let nonce = Nonce::from(<[u8; NONCE_LEN]>::try_from(nonce)?);
+
+    ChaCha20Poly1305::new(key)
+        .decrypt(&nonce, ciphertext)
+        .map_err(|_| anyhow!("Failed to decrypt the credentials, is the passphrase right?"))
+}
+
+/// The passphrase saved in the keyring of the OS with `store_passphrase`, if any.
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+pub fn stored_passphrase() -> Result<Option<String>> {
+    match keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?.get_password() {
+        Ok(passphrase) => Ok(Some(passphrase)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Save `passphrase` in the keyring of the OS, the Keychain on macOS, the Credential Manager on
+/// Windows and the Secret Service, like GNOME Keyring or KWallet, on Linux, for `stored_passphrase`
+/// to read across logins.
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+pub fn store_passphrase(passphrase: &str) -> Result<()> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?.set_password(passphrase)?;
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn stored_passphrase() -> Result<Option<String>> {
+    Ok(None)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn store_passphrase(_passphrase: &str) -> Result<()> {
+    bail!("There is no supported keyring on this OS.")
+}
+
+/// Cache that encrypts the visitor data and PO tokens kept in another, which identify the session
+/// like cookies do. Other entries, like the player JS, are passed through as they are.
+///
+/// Entries that can't be decrypted, like ones written before encryption was enabled, are treated
+/// as missing.
+pub struct EncryptedCache {
+    cache: Arc<dyn Cache>,
+    passphrase: String,
+    /// Salt of the entries added by this instance, so that their key is derived once.
+    salt: [u8; SALT_LEN],
+    /// Keys derived from the passphrase, by salt.
+    keys: Mutex<HashMap<Vec<u8>, Key>>,
+}
+
+impl EncryptedCache {
+    pub fn new<S: Into<String>>(cache: Arc<dyn Cache>, passphrase: S) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+
+        Self {
+            cache,
+            passphrase: passphrase.into(),
+            salt,
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn is_credential(key: &str) -> bool {
+        key == VISITOR_DATA_KEY || key.starts_with(PO_TOKEN_KEY_PREFIX)
+    }
+
+    fn key(&self, salt: &[u8]) -> Result<Key> {
+        let mut keys = self
+            .keys
+            .lock()
+            .map_err(|e| TydleError::Cache(e.to_string()))?;
+
+        Ok(*keys
+            .entry(salt.to_vec())
+            .or_insert_with(|| derive_key(&self.passphrase, salt)))
+    }
+
+    /// `value` encrypted and hex encoded, since caches hold text.
+    fn encrypt(&self, value: &str) -> Result<String> {
+        let data = encrypt_with_key(value.as_bytes(), &self.salt, &self.key(&self.salt)?)?;
+
+        Ok(data.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    fn decrypt(&self, value: &str) -> Result<String> {
+        let data = (0..value.len())
+            .step_by(2)
+            .map(|i| {
+                value
+                    .get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    .ok_or(anyhow!(
+                        "The cached credentials weren't encrypted by tydle."
+                    ))
+            })
+            .collect::<Result<Vec<u8>>>()?;
+        let key = self.key(salt(&data)?)?;
+
+        Ok(String::from_utf8(decrypt_with_key(&data, &key)?)?)
+    }
+}
+
+impl Cache for EncryptedCache {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        let Some(value) = self.cache.get(key)? else {
+            return Ok(None);
+        };
+        if !Self::is_credential(key) {
+            return Ok(Some(value));
+        }
+
+        match self.decrypt(&value) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => {
+                tracing::warn!("Ignoring the cached {}: {}", key, e);
+                Ok(None)
+            }
+        }
+    }
+
+    fn add(&self, key: String, value: String) -> Result<()> {
+        let value = match Self::is_credential(&key) {
+            true => self.encrypt(&value)?,
+            false => value,
+        };
+
+        self.cache.add(key, value)
+    }
+
+    fn add_with_ttl(&self, key: String, value: String, ttl: Duration) -> Result<()> {
+        let value = match Self::is_credential(&key) {
+            true => self.encrypt(&value)?,
+            false => value,
+        };
+
+        self.cache.add_with_ttl(key, value, ttl)
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.cache.remove(key)
+    }
+
+    fn remove_prefixed(&self, prefix: &str) -> Result<()> {
+        self.cache.remove_prefixed(prefix)
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.cache.clear()
+    }
+}
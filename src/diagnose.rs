@@ -0,0 +1,239 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Result, anyhow};
+use fancy_regex::Regex;
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value, json};
+use tokio::fs;
+use tydle::{
+    ClientOutcome, Extract, Tydle, TydleError, VideoId, YtClient, YtStreamResponse,
+    http::{HttpRequest, HttpResponse, Middleware},
+};
+use url::Url;
+
+/// URLs in error messages, like the ones of `TydleError::HttpStatus` and of network errors.
+static URL_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"https?://[^\s"'<>()]+"#).unwrap());
+
+/// A request sent during the diagnosis, without anything that identifies the user.
+#[derive(Debug, Clone)]
+struct RequestSummary {
+    method: String,
+    url: String,
+    status: Option<u16>,
+}
+
+/// Middleware recording a redacted summary of every request and response.
+#[derive(Default)]
+pub struct RequestLog {
+    requests: Mutex<Vec<RequestSummary>>,
+}
+
+impl Middleware for RequestLog {
+    fn on_request(&self, request: HttpRequest) -> Result<HttpRequest> {
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.push(RequestSummary {
+                method: format!("{:?}", request.method).to_uppercase(),
+                url: redact_url(&request.url),
+                status: None,
+            });
+        }
+
+        Ok(request)
+    }
+
+    fn on_response(&self, response: &HttpResponse) -> Result<()> {
+        let url = redact_url(&response.url);
+        if let Ok(mut requests) = self.requests.lock()
+            && let Some(request) = requests
+                .iter_mut()
+                .find(|request| request.status.is_none() && request.url == url)
+        {
+            request.status = Some(response.status);
+        }
+
+        Ok(())
+    }
+}
+
+/// `url` with the values of its query dropped, since they hold signatures, tokens and IPs, and the
+/// server of googlevideo hosts, which gives away the location.
+fn redact_url(url: &Url) -> String {
+    let host = url.host_str().unwrap_or_default();
+    let host = match host.strip_suffix(".googlevideo.com") {
+        Some(_) => "*.googlevideo.com",
+        None => host,
+    };
+    let params = url
+        .query_pairs()
+        .map(|(name, _)| format!("{}=REDACTED", name))
+        .collect::<Vec<_>>();
+
+    let mut redacted = format!("{}://{}{}", url.scheme(), host, url.path());
+    if !params.is_empty() {
+        redacted.push('?');
+        redacted.push_str(&params.join("&"));
+    }
+
+    redacted
+}
+
+/// `message` with the URLs it mentions redacted like `redact_url` does.
+fn redact_message(message: &str) -> String {
+    URL_RE
+        .replace_all(message, |caps: &fancy_regex::Captures| {
+            match Url::parse(&caps[0]) {
+                Ok(url) => redact_url(&url),
+                Err(_) => "REDACTED".to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// The version of the player, like "6e1dd460" in ".../s/player/6e1dd460/player_ias.vflset/...".
+fn player_version(player_url: &str) -> Option<&str> {
+    player_url.split("/s/player/").nth(1)?.split('/').next()
+}
+
+fn outcome_json(outcome: &ClientOutcome) -> Value {
+    match outcome {
+        ClientOutcome::Succeeded { formats } => {
+            json!({ "outcome": "succeeded", "formats": formats })
+        }
+        ClientOutcome::Skipped { reason } => {
+            json!({ "outcome": "skipped", "reason": redact_message(reason) })
+        }
+        ClientOutcome::Failed { reason } => {
+            json!({ "outcome": "failed", "reason": redact_message(reason) })
+        }
+    }
+}
+
+/// Extract `video_id` and write what happened to `tydle-diagnose-{video_id}-{timestamp}.json` in
+/// the working directory, returning its path. The report is written when the extraction fails too.
+pub async fn diagnose(
+    tydle: &Tydle,
+    request_log: &Arc<RequestLog>,
+    video_id: &str,
+    default_client: YtClient,
+    authenticated: bool,
+) -> Result<String> {
+    let video_id = VideoId::new(video_id)?;
+    log::info!("Diagnosing {}", video_id.as_str());
+
+    let mut report = Map::new();
+    report.insert("tydle_version".into(), env!("CARGO_PKG_VERSION").into());
+    report.insert(
+        "platform".into(),
+        format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH).into(),
+    );
+    report.insert("video_id".into(), video_id.as_str().into());
+    report.insert("default_client".into(), default_client.as_str().into());
+    report.insert("authenticated".into(), authenticated.into());
+
+    let result = tydle.get_streams(&video_id).await;
+    match &result {
+        Ok(response) => add_response(&mut report, tydle, &video_id, response).await,
+        Err(err) => {
+            report.insert("error".into(), redact_message(&err.to_string()).into());
+            if let TydleError::ExtractionFailed { attempts } = err {
+                let clients = attempts
+                    .iter()
+                    .map(|(client, err)| {
+                        let reason = redact_message(&err.to_string());
+                        (
+                            client.as_str().to_string(),
+                            json!({ "outcome": "failed", "reason": reason }),
+                        )
+                    })
+                    .collect::<Map<_, _>>();
+                report.insert("clients".into(), clients.into());
+            }
+        }
+    }
+
+    if let Ok(metrics) = tydle.metrics() {
+        report.insert(
+            "metrics".into(),
+            json!({
+                "api_calls": metrics.api_calls,
+                "bytes_downloaded": metrics.bytes_downloaded,
+                "cache_hits": metrics.cache_hits,
+                "cache_misses": metrics.cache_misses,
+                "deciphers": metrics.deciphers,
+            }),
+        );
+    }
+
+    let requests = request_log
+        .requests
+        .lock()
+        .map_err(|e| anyhow!("{}", e))?
+        .iter()
+        .map(|request| json!({ "method": request.method, "url": request.url, "status": request.status }))
+        .collect::<Vec<_>>();
+    report.insert("requests".into(), requests.into());
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let path = format!("tydle-diagnose-{}-{}.json", video_id.as_str(), timestamp);
+    fs::write(&path, serde_json::to_string_pretty(&report)?).await?;
+
+    match result {
+        Ok(_) => log::info!("Wrote the report to {}", path),
+        Err(_) => log::warn!("The extraction failed, wrote the report to {} anyway", path),
+    }
+
+    Ok(path)
+}
+
+async fn add_response(
+    report: &mut Map<String, Value>,
+    tydle: &Tydle,
+    video_id: &VideoId,
+    response: &YtStreamResponse,
+) {
+    let sts = match tydle
+        .signature_timestamp(video_id, &response.player_url)
+        .await
+    {
+        Ok(sts) => json!(sts),
+        Err(e) => json!(format!("Failed to extract: {}", e)),
+    };
+    report.insert(
+        "player".into(),
+        json!({
+            "url": response.player_url,
+            "version": player_version(&response.player_url),
+            "sts": sts,
+        }),
+    );
+
+    let mut chosen_clients = response
+        .client_results
+        .iter()
+        .filter(|(_, outcome)| matches!(outcome, ClientOutcome::Succeeded { .. }))
+        .map(|(client, _)| client.as_str())
+        .collect::<Vec<_>>();
+    chosen_clients.sort();
+    let clients = response
+        .client_results
+        .iter()
+        .map(|(client, outcome)| (client.as_str().to_string(), outcome_json(outcome)))
+        .collect::<Map<_, _>>();
+
+    report.insert("clients".into(), clients.into());
+    report.insert("chosen_clients".into(), chosen_clients.into());
+    report.insert("formats".into(), response.streams.len().into());
+    report.insert("is_live".into(), response.is_live.into());
+    report.insert(
+        "warnings".into(),
+        response
+            .warnings
+            .iter()
+            .map(|warning| redact_message(&warning.to_string()))
+            .collect::<Vec<_>>()
+            .into(),
+    );
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use reqwest::{
+    ClientBuilder, Url,
+    dns::{Addrs, Name, Resolving},
+};
+use serde::Deserialize;
+
+pub use reqwest::dns::Resolve;
+
+/// DNS settings applied to the HTTP clients tydle builds.
+#[derive(Clone, Default)]
+pub struct DnsOptions {
+    /// Resolver used instead of the system one, like [`DohResolver`].
+    pub resolver: Option<Arc<dyn Resolve>>,
+    /// Addresses that hosts resolve to without any lookup, e.g. a known good `googlevideo.com` edge.
+    pub host_overrides: HashMap<String, Vec<IpAddr>>,
+}
+
+impl fmt::Debug for DnsOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DnsOptions")
+            .field("resolver", &self.resolver.as_ref().map(|_| "custom"))
+            .field("host_overrides", &self.host_overrides)
+            .finish()
+    }
+}
+
+impl DnsOptions {
+    /// Configure `builder` to resolve hosts according to these options.
+    pub fn apply(&self, mut builder: ClientBuilder) -> ClientBuilder {
+        if let Some(resolver) = &self.resolver {
+            builder = builder.dns_resolver(Arc::new(SharedResolver(resolver.clone())));
+        }
+
+        for (host, ips) in &self.host_overrides {
+            // A port of 0 makes reqwest use the port of the requested URL.
+            let addrs = ips
+                .iter()
+                .map(|ip| SocketAddr::new(*ip, 0))
+                .collect::<Vec<_>>();
+            builder = builder.resolve_to_addrs(host, &addrs);
+        }
+
+        builder
+    }
+}
+
+/// `ClientBuilder::dns_resolver` only accepts sized resolvers.
+struct SharedResolver(Arc<dyn Resolve>);
+
+impl Resolve for SharedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        self.0.resolve(name)
+    }
+}
+
+#[derive(Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DohAnswer {
+    data: String,
+}
+
+/// Resolves hosts over DNS-over-HTTPS using the JSON API offered by providers like
+/// `https://cloudflare-dns.com/dns-query` or `https://dns.google/resolve`.
+#[derive(Debug, Clone)]
+pub struct DohResolver {
+    endpoint: Url,
+    client: reqwest::Client,
+}
+
+impl DohResolver {
+    /// The host of `endpoint` itself is resolved with the system resolver.
+    pub fn new(endpoint: &str) -> Result<Self> {
+        Ok(Self {
+            endpoint: Url::parse(endpoint)?,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    async fn lookup(&self, name: &str, record_type: &str) -> Result<Vec<IpAddr>> {
+        let response: DohResponse = self
+            .client
+            .get(self.endpoint.clone())
+            .query(&[("name", name), ("type", record_type)])
+            .header("Accept", "application/dns-json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // CNAME answers are skipped, their targets are listed as separate answers.
+        Ok(response
+            .answer
+            .iter()
+            .filter_map(|answer| answer.data.parse().ok())
+            .collect())
+    }
+}
+
+impl Resolve for DohResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+
+        Box::pin(async move {
+            let (v4, v6) = tokio::join!(
+                resolver.lookup(name.as_str(), "A"),
+                resolver.lookup(name.as_str(), "AAAA")
+            );
+
+            let mut ips = v4.unwrap_or_default();
+            ips.extend(v6.unwrap_or_default());
+
+            if ips.is_empty() {
+                return Err(format!("DNS-over-HTTPS lookup of {} failed.", name.as_str()).into());
+            }
+
+            let addrs: Addrs = Box::new(ips.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
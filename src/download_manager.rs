@@ -0,0 +1,326 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+};
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use tokio::{
+    fs,
+    sync::{Mutex, Semaphore},
+};
+use tydle::{
+    CancellationToken, Extract, FormatSelection, FormatSpec, Tydle, TydleError, VideoId, YtStream,
+    YtStreamResponse,
+};
+use url::Url;
+
+use crate::{
+    events::{Event, Events},
+    ffmpeg::merge_streams,
+    sidecar::info_dict,
+    stream_downloader::{DownloadOptions, StreamDownloader, retry_throttled},
+};
+
+/// A video to download and the format to download it in.
+#[derive(Debug, Clone)]
+pub struct DownloadJob {
+    pub video_id: VideoId,
+    pub format: FormatSpec,
+    /// `format` as it was written, for error messages.
+    pub format_name: String,
+    /// ID of the queue job, sent along with the events of the download.
+    pub job_id: Option<u64>,
+}
+
+impl DownloadJob {
+    pub fn new(video_id: &str, format: &str) -> Result<Self> {
+        Ok(Self {
+            video_id: VideoId::new(video_id)?,
+            format: FormatSpec::parse(format)?,
+            format_name: format.to_string(),
+            job_id: None,
+        })
+    }
+}
+
+/// Progress of every job a `DownloadManager` was given.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct DownloadProgress {
+    pub jobs: usize,
+    pub running: usize,
+    pub finished: usize,
+    pub failed: usize,
+    /// Bytes downloaded by every job so far.
+    pub downloaded_bytes: u64,
+    /// Size of the formats of the jobs that started, where known.
+    pub total_bytes: u64,
+}
+
+/// Downloads many videos at once with one `Tydle`, so that they share its player and code caches,
+/// and one connection pool.
+pub struct DownloadManager {
+    tydle: Tydle,
+    downloader: StreamDownloader,
+    output_dir: PathBuf,
+    /// Limits how many jobs download at the same time.
+    slots: Semaphore,
+    /// Connections each job opens to the host of its stream.
+    workers: usize,
+    host_connections: usize,
+    /// Limits the connections to each host across jobs, by host.
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+    events: Events,
+    downloaded_bytes: Arc<AtomicU64>,
+    total_bytes: AtomicU64,
+    jobs: AtomicUsize,
+    running: AtomicUsize,
+    finished: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+impl DownloadManager {
+    /// Run at most `concurrency` jobs at the same time, with at most `host_connections` connections
+    /// to a single host between them.
+    pub fn new(
+        tydle: Tydle,
+        mut options: DownloadOptions,
+        output_dir: PathBuf,
+        concurrency: usize,
+        host_connections: usize,
+    ) -> Result<Self> {
+        let downloaded_bytes = Arc::new(AtomicU64::new(0));
+        let workers = options.workers;
+        options.downloaded_bytes = Some(Arc::clone(&downloaded_bytes));
+        let downloader = StreamDownloader::new(options)?;
+
+        Ok(Self {
+            tydle,
+            downloader,
+            output_dir,
+            slots: Semaphore::new(concurrency.max(1)),
+            workers,
+            host_connections: host_connections.max(1),
+            hosts: Mutex::new(HashMap::new()),
+            events: Events::new(),
+            downloaded_bytes,
+            total_bytes: AtomicU64::new(0),
+            jobs: AtomicUsize::new(0),
+            running: AtomicUsize::new(0),
+            finished: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+        })
+    }
+
+    /// Send the events of every job to `events`.
+    pub fn events(mut self, events: Events) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn downloader(&self) -> &StreamDownloader {
+        &self.downloader
+    }
+
+    pub fn progress(&self) -> DownloadProgress {
+        DownloadProgress {
+            jobs: self.jobs.load(Ordering::Relaxed),
+            running: self.running.load(Ordering::Relaxed),
+            finished: self.finished.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            downloaded_bytes: self.downloaded_bytes.load(Ordering::Relaxed),
+            total_bytes: self.total_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Download `job` into the output directory once a slot is free, merging the video and audio
+    /// when its format picks both. `started` is awaited when the job gets its slot, before anything
+    /// is downloaded. Returns the path the video was saved to.
+    pub async fn download(
+        &self,
+        job: &DownloadJob,
+        token: &CancellationToken,
+        started: impl Future<Output = Result<()>>,
+    ) -> Result<String> {
+        self.jobs.fetch_add(1, Ordering::Relaxed);
+        let _slot = self.slots.acquire().await?;
+        started.await?;
+
+        self.running.fetch_add(1, Ordering::Relaxed);
+        let mut event = Event::new(job.video_id.as_str(), job.job_id);
+        let result = self.run(job, token, &mut event).await;
+        self.running.fetch_sub(1, Ordering::Relaxed);
+
+        if let Err(e) = &result {
+            self.events.failed(&event, e).await;
+        }
+
+        match &result {
+            Ok(_) => self.finished.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.failed.fetch_add(1, Ordering::Relaxed),
+        };
+
+        result
+    }
+
+    async fn run(
+        &self,
+        job: &DownloadJob,
+        token: &CancellationToken,
+        event: &mut Event,
+    ) -> Result<String> {
+        // The events carry the info JSON, which needs the metadata along with the streams.
+        let yt_stream_response = if self.events.is_empty() {
+            self.tydle.get_streams(&job.video_id).await?
+        } else {
+            let manifest = self.tydle.get_manifest(&job.video_id).await?;
+            let yt_stream_response = self.tydle.get_streams_from_manifest(&manifest).await?;
+            let video_info = self.tydle.get_video_info_from_manifest(&manifest).await?;
+            event.info = Some(info_dict(
+                &job.video_id,
+                &video_info,
+                &yt_stream_response.streams,
+            ));
+            yt_stream_response
+        };
+        let selection = yt_stream_response
+            .streams
+            .select(&job.format)
+            .ok_or(TydleError::FormatNotFound(job.format_name.clone()))?;
+        self.total_bytes
+            .fetch_add(selection.file_size().unwrap_or(0), Ordering::Relaxed);
+        self.events.emit(event).await;
+
+        let download_bytes = Arc::new(AtomicU64::new(0));
+        let path = self
+            .events
+            .watch_progress(
+                event,
+                &download_bytes,
+                selection.file_size(),
+                self.download_selection(
+                    job,
+                    &yt_stream_response,
+                    &selection,
+                    &download_bytes,
+                    token,
+                ),
+            )
+            .await?;
+        self.events.finished(event, &path).await;
+
+        Ok(path)
+    }
+
+    async fn download_selection(
+        &self,
+        job: &DownloadJob,
+        yt_stream_response: &YtStreamResponse,
+        selection: &FormatSelection,
+        download_bytes: &Arc<AtomicU64>,
+        token: &CancellationToken,
+    ) -> Result<String> {
+        let output = self.output_dir.join(format!(
+            "{}.{}",
+            job.video_id.as_str(),
+            selection.ext().as_str()
+        ));
+        let output = output
+            .to_str()
+            .ok_or(anyhow!("The output directory is not valid UTF-8."))?;
+
+        let Some(audio) = &selection.audio else {
+            return self
+                .download_stream(
+                    &job.video_id,
+                    yt_stream_response,
+                    &selection.stream,
+                    output,
+                    download_bytes,
+                    token,
+                )
+                .await;
+        };
+
+        let stem = output.rsplit_once('.').map_or(output, |(stem, _)| stem);
+        let video_part = self
+            .download_stream(
+                &job.video_id,
+                yt_stream_response,
+                &selection.stream,
+                &format!(
+                    "{}.f{}.{}",
+                    stem, selection.stream.itag, selection.stream.ext
+                ),
+                download_bytes,
+                token,
+            )
+            .await?;
+        let audio_part = self
+            .download_stream(
+                &job.video_id,
+                yt_stream_response,
+                audio,
+                &format!("{}.f{}.{}", stem, audio.itag, audio.ext),
+                download_bytes,
+                token,
+            )
+            .await?;
+
+        log::info!("Merging formats into {}", output);
+        merge_streams(&video_part, &audio_part, output).await?;
+        fs::remove_file(&video_part).await?;
+        fs::remove_file(&audio_part).await?;
+
+        Ok(output.to_string())
+    }
+
+    /// Download `stream` into `output` once its host has connections to spare.
+    async fn download_stream(
+        &self,
+        video_id: &VideoId,
+        yt_stream_response: &YtStreamResponse,
+        stream: &YtStream,
+        output: &str,
+        download_bytes: &Arc<AtomicU64>,
+        token: &CancellationToken,
+    ) -> Result<String> {
+        let url = self.tydle.resolve_url(stream, yt_stream_response).await?;
+        let host = Url::parse(&url)?.host_str().unwrap_or_default().to_string();
+        let host_slots = self
+            .hosts
+            .lock()
+            .await
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.host_connections)))
+            .clone();
+        let _connections = host_slots
+            .acquire_many(self.workers.min(self.host_connections) as u32)
+            .await?;
+
+        retry_throttled(
+            &self.tydle,
+            video_id,
+            stream,
+            yt_stream_response,
+            &url,
+            |url| async move {
+                self.downloader
+                    .download_counted(
+                        &url,
+                        output,
+                        stream.file_size,
+                        Some(Arc::clone(download_bytes)),
+                        token,
+                    )
+                    .await
+            },
+        )
+        .await
+    }
+}
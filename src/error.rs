@@ -0,0 +1,110 @@
+use crate::yt_interface::YtClient;
+
+/// Error returned by the `Extract`, `Fetch` and `Cipher` methods of `Tydle`.
+pub type Error = TydleError;
+
+#[cfg_attr(
+    all(feature = "uniffi", not(target_arch = "wasm32")),
+    derive(uniffi::Error),
+    uniffi(flat_error)
+)]
+#[derive(Debug, thiserror::Error)]
+pub enum TydleError {
+    /// A request to YouTube failed to connect, timed out or returned an error status.
+    #[error("Network error: {0}")]
+    Network(#[from] reqwest::Error),
+    /// A request returned an error status.
+    #[error("HTTP status {status} from {url}")]
+    HttpStatus { status: u16, url: String },
+    /// A response from YouTube couldn't be parsed.
+    #[error("Failed to parse the response: {0}")]
+    Parse(String),
+    /// YouTube wants the request to confirm it's not coming from a bot.
+    #[error(
+        "Bot check required: {0}. Pass the cookies of a signed in account, or wait a while before retrying from this IP address."
+    )]
+    BotCheck(String),
+    /// The video isn't available in the country the request was made from.
+    #[error("Geo restricted: {0}")]
+    GeoRestricted(String),
+    /// The video requires an authenticated session to be played.
+    #[error("Login required: {0}")]
+    LoginRequired(String),
+    /// The video is age-restricted and none of the clients that can work around it could play it.
+    /// Signing in with an account whose age is verified usually does.
+    #[error("Age restricted: {0}")]
+    AgeRestricted(String),
+    /// The video is private. Signing in with an account it was shared with gives access to it.
+    #[error("Private video: {0}")]
+    Private(String),
+    /// Authentication cookies were passed, but YouTube treated the requests as signed out.
+    #[error(
+        "Authentication expired: {0}. Export fresh cookies from a browser that is signed in to YouTube and pass them again."
+    )]
+    AuthExpired(String),
+    /// The video is a premiere or live stream that hasn't started yet.
+    /// `start_time` is the scheduled start as a Unix timestamp, when YouTube announced one.
+    #[error("Upcoming: {reason}")]
+    Upcoming {
+        reason: String,
+        start_time: Option<u64>,
+    },
+    /// YouTube refused to play the video for any other reason.
+    #[error("Unplayable: {0}")]
+    Unplayable(String),
+    /// YouTube returned the player response of a different video than the one requested.
+    #[error(
+        "Received invalid player response for video with ID \"{expected}\", got \"{got}\" instead."
+    )]
+    InvalidPlayerResponse { expected: String, got: String },
+    /// None of the streams of the video matched the requested format.
+    #[error("No stream matches the format \"{0}\".")]
+    FormatNotFound(String),
+    /// A download ended with a different number of bytes than the server or format announced.
+    #[error("Incomplete download, expected {expected} bytes but got {got}.")]
+    IncompleteDownload { expected: u64, got: u64 },
+    /// Every client tydle tried to extract the video with failed.
+    #[error("Failed to extract any player response.{}", format_attempts(.attempts))]
+    ExtractionFailed {
+        attempts: Vec<(YtClient, TydleError)>,
+    },
+    /// The signature of a stream couldn't be deciphered with the player's JavaScript.
+    #[error("Failed to decipher the signature: {0:#}")]
+    Cipher(anyhow::Error),
+    /// The player or code cache couldn't be accessed.
+    #[error("Cache error: {0}")]
+    Cache(String),
+    /// The operation was aborted through the instance's cancellation token.
+    #[error("The operation was cancelled.")]
+    Cancelled,
+    /// The operation didn't finish within `TydleOptions::overall_deadline_secs`.
+    #[error("The operation did not finish before its deadline.")]
+    DeadlineExceeded,
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+fn format_attempts(attempts: &[(YtClient, TydleError)]) -> String {
+    attempts
+        .iter()
+        .map(|(client, err)| format!("\n  {}: {}", client.as_str(), err))
+        .collect()
+}
+
+impl From<anyhow::Error> for TydleError {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<TydleError>() {
+            Ok(tydle_err) => return tydle_err,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<reqwest::Error>() {
+            Ok(reqwest_err) => return Self::Network(reqwest_err),
+            Err(err) => err,
+        };
+
+        match err.downcast_ref::<serde_json::Error>() {
+            Some(json_err) => Self::Parse(json_err.to_string()),
+            None => Self::Other(err),
+        }
+    }
+}
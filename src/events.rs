@@ -0,0 +1,203 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::Value;
+
+pub type EventFut<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+/// Percentages of a download a progress event is sent at.
+const MILESTONES: [u8; 3] = [25, 50, 75];
+/// How often the progress of a download is checked against the milestones.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What happened to a download.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EventKind {
+    Started,
+    Progress {
+        percent: u8,
+        downloaded_bytes: u64,
+        total_bytes: u64,
+    },
+    Finished {
+        path: String,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+/// A download event, as sent to every `EventSink`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    #[serde(flatten)]
+    pub kind: EventKind,
+    pub video_id: String,
+    /// ID of the queue job the download belongs to, in server mode.
+    pub job_id: Option<u64>,
+    /// The info JSON of the video, as written by `--write-info-json`.
+    pub info: Option<Value>,
+}
+
+impl Event {
+    pub fn new(video_id: &str, job_id: Option<u64>) -> Self {
+        Self {
+            kind: EventKind::Started,
+            video_id: video_id.to_string(),
+            job_id,
+            info: None,
+        }
+    }
+
+    /// This event with its kind replaced by `kind`.
+    pub fn with(&self, kind: EventKind) -> Self {
+        Self {
+            kind,
+            ..self.clone()
+        }
+    }
+}
+
+/// Something that gets told when downloads start, progress, finish and fail.
+pub trait EventSink: Send + Sync {
+    fn name(&self) -> &str;
+    fn send<'a>(&'a self, event: &'a Event) -> EventFut<'a>;
+}
+
+impl fmt::Debug for dyn EventSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// POSTs every event as JSON to a URL.
+pub struct WebhookSink {
+    client: Client,
+    url: String,
+}
+
+impl WebhookSink {
+    pub fn new<S: Into<String>>(client: Client, url: S) -> Self {
+        Self {
+            client,
+            url: url.into(),
+        }
+    }
+}
+
+impl EventSink for WebhookSink {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    fn send<'a>(&'a self, event: &'a Event) -> EventFut<'a> {
+        Box::pin(async move {
+            self.client
+                .post(&self.url)
+                .json(event)
+                .send()
+                .await?
+                .error_for_status()?;
+
+            Ok(())
+        })
+    }
+}
+
+/// The sinks events are sent to. A sink failing is logged and never fails the download.
+#[derive(Debug, Clone, Default)]
+pub struct Events {
+    sinks: Vec<Arc<dyn EventSink>>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sink<S: EventSink + 'static>(mut self, sink: S) -> Self {
+        self.sinks.push(Arc::new(sink));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    pub async fn emit(&self, event: &Event) {
+        for sink in &self.sinks {
+            if let Err(e) = sink.send(event).await {
+                log::warn!("Failed to send an event to {}: {:#}", sink.name(), e);
+            }
+        }
+    }
+
+    pub async fn finished(&self, event: &Event, path: &str) {
+        self.emit(&event.with(EventKind::Finished {
+            path: path.to_string(),
+        }))
+        .await;
+    }
+
+    pub async fn failed(&self, event: &Event, error: &anyhow::Error) {
+        self.emit(&event.with(EventKind::Failed {
+            error: format!("{:#}", error),
+        }))
+        .await;
+    }
+
+    /// Run `download`, emitting `Progress` as `download_bytes` reaches each milestone of
+    /// `total_bytes`.
+    pub async fn watch_progress<F: Future>(
+        &self,
+        event: &Event,
+        download_bytes: &AtomicU64,
+        total_bytes: Option<u64>,
+        download: F,
+    ) -> F::Output {
+        let Some(total_bytes) = total_bytes.filter(|total| *total > 0 && !self.is_empty()) else {
+            return download.await;
+        };
+
+        let mut milestones = MILESTONES.into_iter().peekable();
+        let mut ticks = tokio::time::interval(PROGRESS_INTERVAL);
+        tokio::pin!(download);
+
+        loop {
+            tokio::select! {
+                output = &mut download => return output,
+                _ = ticks.tick() => {
+                    let downloaded_bytes = download_bytes.load(Ordering::Relaxed);
+                    let percent = (downloaded_bytes * 100 / total_bytes).min(100) as u8;
+
+                    // Only the last milestone passed since the previous check is sent.
+                    let Some(percent) = std::iter::from_fn(|| {
+                        milestones.next_if(|milestone| percent >= *milestone)
+                    })
+                    .last() else {
+                        continue;
+                    };
+
+                    self.emit(&event.with(EventKind::Progress {
+                        percent,
+                        downloaded_bytes,
+                        total_bytes,
+                    }))
+                    .await;
+                }
+            }
+        }
+    }
+}
@@ -1,15 +1,19 @@
 use std::collections::HashMap;
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use maplit::hashmap;
 use reqwest::Url;
 use serde_json::{Value, json};
 
 use crate::{
+    cache::{VISITOR_DATA_KEY, VISITOR_DATA_TTL},
+    cookies::CookieStore,
+    error::TydleError,
     extractor::{
         auth::ExtractorAuthHandle, client::INNERTUBE_CLIENTS, cookies::ExtractorCookieHandle,
         extract::YtExtractor, ytcfg::ExtractorYtCfgHandle,
     },
+    http::HttpRequest,
     yt_interface::{YtClient, YtEndpoint},
 };
 
@@ -32,6 +36,18 @@ pub trait ExtractorApiHandle {
         api_key: Option<String>,
         default_client: Option<&YtClient>,
     ) -> Result<HashMap<String, Value>>;
+    /// Same as `call_api`, but returns the undecoded response body so callers can pick out only what they need.
+    async fn call_api_raw(
+        &self,
+        endpoint: YtEndpoint,
+        query: HashMap<String, Value>,
+        headers: Option<HashMap<&str, String>>,
+        context: Option<HashMap<String, Value>>,
+        api_key: Option<String>,
+        default_client: Option<&YtClient>,
+    ) -> Result<String>;
+    /// Ask the API for the visitor data of a new anonymous session.
+    async fn fetch_visitor_data(&self) -> Result<String>;
 }
 
 impl ExtractorApiHandle for YtExtractor {
@@ -60,7 +76,7 @@ impl ExtractorApiHandle for YtExtractor {
 
         let mut headers = hashmap! {
             "X-YouTube-Client-Name" => innertube_client.innertube_context_client_name.to_string(),
-            "X-YouTube-Client-Version" => self.select_client_version(Some(client)).to_string(),
+            "X-YouTube-Client-Version" => self.select_client_version(Some(client)),
             "Origin" => origin,
         };
 
@@ -90,17 +106,35 @@ impl ExtractorApiHandle for YtExtractor {
         api_key: Option<String>,
         default_client: Option<&YtClient>,
     ) -> Result<HashMap<String, Value>> {
+        let response = self
+            .call_api_raw(endpoint, query, headers, context, api_key, default_client)
+            .await?;
+
+        Ok(serde_json::from_str(&response)?)
+    }
+
+    // Only the endpoint is recorded, the headers carry cookies and the SAPISIDHASH.
+    #[tracing::instrument(name = "innertube_api", skip_all, fields(endpoint = endpoint.as_str()))]
+    async fn call_api_raw(
+        &self,
+        endpoint: YtEndpoint,
+        query: HashMap<String, Value>,
+        headers: Option<HashMap<&str, String>>,
+        context: Option<HashMap<String, Value>>,
+        api_key: Option<String>,
+        default_client: Option<&YtClient>,
+    ) -> Result<String> {
         let client = default_client.unwrap_or(&self.tydle_options.default_client);
 
         let host_name = self.select_api_hostname(Some(client));
         let ep = endpoint.as_str();
+        self.metrics.record_api_call(client.as_str())?;
+        // InnerTube only answers over https, whatever `prefer_insecure` says.
         let api_url = format!("https://{}/youtubei/v1/{}", host_name, ep);
         let yt_url = Url::parse(api_url.as_str())?;
 
-        #[cfg(feature = "logging")]
-        log::info!("Requesting YouTube API at {}", api_url);
+        tracing::info!("Requesting YouTube API at {}", api_url);
 
-        let http_client = reqwest::Client::new();
         let mut real_headers =
             self.generate_api_headers(Default::default(), None, None, None, None, Some(client))?;
         let mut data: HashMap<String, Value> = HashMap::new();
@@ -127,19 +161,14 @@ impl ExtractorApiHandle for YtExtractor {
             real_headers.extend(availabe_headers);
         }
 
-        let mut request_builder = http_client
-            .post(yt_url)
-            .json(&data)
+        let mut request_builder = HttpRequest::post(yt_url)
+            .body(serde_json::to_vec(&data)?)
             .query(&[("prettyPrint", "false")]);
 
-        let yt_cookies = self.get_youtube_cookies()?;
-
-        if !yt_cookies.is_empty() {
-            request_builder = request_builder.header("Cookie", yt_cookies.header_value());
-        }
+        request_builder = self.cookie_jar.add_to_request(request_builder)?;
 
         if let Some(available_api_key) = api_key {
-            request_builder = request_builder.query(&[("key", available_api_key)]);
+            request_builder = request_builder.query(&[("key", available_api_key.as_str())]);
         }
 
         for (k, v) in real_headers {
@@ -148,7 +177,54 @@ impl ExtractorApiHandle for YtExtractor {
 
         request_builder = request_builder.header("Content-Type", "application/json");
 
-        let response = request_builder.send().await?;
-        Ok(response.json().await?)
+        if !self.tydle_options.source_address.is_empty() {
+            request_builder = request_builder.header(
+                "X-Forwarded-For",
+                self.tydle_options.source_address.as_str(),
+            );
+        }
+
+        request_builder = request_builder.timeout(self.request_timeout());
+
+        self.pace_request().await;
+        let response = self.transport.send(request_builder).await?;
+        self.store_response_cookies(&response)?;
+
+        if response.status == 401 && self.has_auth_cookies()? {
+            return Err(TydleError::AuthExpired(format!(
+                "the YouTube API rejected the session with HTTP status 401 at {}",
+                response.url
+            ))
+            .into());
+        }
+
+        response.text().await
+    }
+
+    async fn fetch_visitor_data(&self) -> Result<String> {
+        let response = self
+            .call_api(
+                YtEndpoint::VisitorId,
+                HashMap::new(),
+                None,
+                None,
+                None,
+                Some(&YtClient::Web),
+            )
+            .await?;
+
+        let visitor_data = response
+            .get("responseContext")
+            .and_then(|ctx| ctx.get("visitorData"))
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .ok_or(anyhow!("The API returned no visitor data."))?;
+        self.cache.add_with_ttl(
+            VISITOR_DATA_KEY.into(),
+            visitor_data.clone(),
+            VISITOR_DATA_TTL,
+        )?;
+
+        Ok(visitor_data)
     }
 }
@@ -1,13 +1,21 @@
 use std::{collections::HashMap, sync::atomic::Ordering};
 
 use anyhow::{Result, anyhow};
+use reqwest::Url;
 use serde_json::Value;
 
 use crate::{
     cookies::{Cookie, CookieStore},
-    extractor::{cookies::ExtractorCookieHandle, extract::YtExtractor, json::ExtractorJsonHandle},
+    error::TydleError,
+    extractor::{
+        cookies::ExtractorCookieHandle,
+        extract::{InfoExtractor, YtExtractor},
+        json::ExtractorJsonHandle,
+        ytcfg::ExtractorYtCfgHandle,
+    },
+    http::HttpRequest,
     utils::{convert_to_query_string, parse_query_string},
-    yt_interface::{PREFERRED_LOCALE, YT_URL},
+    yt_interface::{PREFERRED_LOCALE, YT_DOMAIN, YT_URL, YtClient},
 };
 
 pub trait ExtractorAuthHandle {
@@ -16,6 +24,11 @@ pub trait ExtractorAuthHandle {
     fn initialize_pref(&self) -> Result<()>;
     fn is_authenticated(&self) -> Result<bool>;
     fn has_auth_cookies(&self) -> Result<bool>;
+    /// Fail with `TydleError::AuthExpired` if auth cookies were passed but `ytcfg` belongs to a
+    /// signed out session.
+    fn check_logged_in(&self, ytcfg: &HashMap<String, Value>) -> Result<()>;
+    /// Download the YouTube homepage and check that the auth cookies still sign it in.
+    async fn check_auth(&self) -> Result<()>;
     /// Extract current delegated session ID required to download private playlists of secondary channels.
     fn get_delegated_session_id(&self, ytcfg: &[&HashMap<String, Value>]) -> Option<String>;
     /// Extract current account dataSyncId in the format DELEGATED_SESSION_ID||USER_SESSION_ID or USER_SESSION_ID||
@@ -44,8 +57,7 @@ impl ExtractorAuthHandle for YtExtractor {
         self.passed_auth_cookies.store(false, Ordering::Relaxed);
         if self.has_auth_cookies()? {
             self.passed_auth_cookies.store(true, Ordering::Relaxed);
-            #[cfg(feature = "logging")]
-            log::info!("Found YouTube account cookies.");
+            tracing::info!("Found YouTube account cookies.");
         }
 
         Ok(())
@@ -67,7 +79,7 @@ impl ExtractorAuthHandle for YtExtractor {
         self.cookie_jar.set(Cookie {
             name: "SOCS".into(),
             value: "CAI".into(),
-            domain: YT_URL.into(),
+            domain: YT_DOMAIN.into(),
             secure: true,
             ..Default::default()
         })?;
@@ -93,7 +105,7 @@ impl ExtractorAuthHandle for YtExtractor {
         self.cookie_jar.set(Cookie {
             name: "PREF".into(),
             value: pref_qs,
-            domain: YT_URL.into(),
+            domain: YT_DOMAIN.into(),
             ..Default::default()
         })?;
         Ok(())
@@ -113,6 +125,64 @@ impl ExtractorAuthHandle for YtExtractor {
                 || sid_cookies.yt_3psapisid.is_some()))
     }
 
+    fn check_logged_in(&self, ytcfg: &HashMap<String, Value>) -> Result<()> {
+        let logged_in = ytcfg.get("LOGGED_IN").and_then(|v| v.as_bool());
+
+        if logged_in == Some(false) && self.has_auth_cookies()? {
+            return Err(TydleError::AuthExpired(
+                "YouTube returned a signed out page although authentication cookies were passed"
+                    .into(),
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    async fn check_auth(&self) -> Result<()> {
+        if !self.has_auth_cookies()? {
+            return Err(
+                TydleError::LoginRequired("No authentication cookies were passed.".into()).into(),
+            );
+        }
+
+        tracing::info!("Checking the authentication cookies on {}", YT_URL);
+        let mut request = self
+            .cookie_jar
+            .add_to_request(HttpRequest::get(Url::parse(YT_URL)?))?;
+
+        let web_client = self.innertube_client(&YtClient::Web);
+        let client = web_client.innertube_context.get("client").unwrap();
+        if let Some(user_agent) = client.get("userAgent") {
+            request = request.header("User-Agent", user_agent.as_str().unwrap_or_default());
+        }
+
+        request = request.timeout(self.request_timeout());
+
+        self.pace_request().await;
+        let response = self.transport.send(request).await?;
+        self.store_response_cookies(&response)?;
+
+        if response.status == 401 {
+            return Err(TydleError::AuthExpired(format!(
+                "YouTube rejected the session with HTTP status 401 at {}",
+                response.url
+            ))
+            .into());
+        }
+
+        let ytcfg = self.extract_ytcfg(response.error_for_status()?.text().await?)?;
+
+        if !ytcfg.contains_key("LOGGED_IN") {
+            return Err(TydleError::Parse(
+                "Could not find whether the YouTube homepage is signed in.".into(),
+            )
+            .into());
+        }
+
+        self.check_logged_in(&ytcfg)
+    }
+
     fn get_delegated_session_id(&self, ytcfgs: &[&HashMap<String, Value>]) -> Option<String> {
         for ytcfg in ytcfgs {
             for (_, v) in *ytcfg {
@@ -7,10 +7,12 @@ use std::collections::HashMap;
 
 use crate::{
     extractor::token_policy::{
-        GvsPoTokenPolicy, PlayerPoTokenPolicy, StreamingProtocol, SubsPoTokenPolicy,
-        WEB_PO_TOKEN_POLICIES, create_default_gvs_po_token_policy,
+        GvsPoTokenPolicy, PlayerPoTokenPolicy, SubsPoTokenPolicy, WEB_PO_TOKEN_POLICIES,
+        create_default_gvs_po_token_policy,
+    },
+    yt_interface::{
+        PREFERRED_LOCALE, PoTokenRequirements, StreamingProtocol, YtClient, YtClientInfo,
     },
-    yt_interface::{PREFERRED_LOCALE, YtClient},
 };
 
 #[derive(Debug, Clone, Serialize)]
@@ -54,6 +56,31 @@ impl InnerTubeClient {
 
         Ok(HashMap::new())
     }
+
+    pub fn to_client_info(&self, client: YtClient) -> YtClientInfo {
+        let gvs_policies = self.gvs_po_token_policy.values();
+        let gvs_required = gvs_policies.clone().any(|p| p.required);
+
+        YtClientInfo {
+            client,
+            supports_cookies: self.supports_cookies,
+            require_auth: self.require_auth,
+            require_js_player: self.require_js_player,
+            po_token: PoTokenRequirements {
+                gvs_required,
+                gvs_recommended: gvs_policies.clone().any(|p| p.recommended),
+                player_required: self.player_po_token_policy.required,
+                subs_required: self.subs_po_token_policy.required,
+                not_required_for_premium: gvs_policies
+                    .filter(|p| p.required)
+                    .all(|p| p.not_required_for_premium)
+                    && (!self.player_po_token_policy.required
+                        || self.player_po_token_policy.not_required_for_premium)
+                    && (!self.subs_po_token_policy.required
+                        || self.subs_po_token_policy.not_required_for_premium),
+            },
+        }
+    }
 }
 
 pub static INNERTUBE_CLIENTS: Lazy<HashMap<YtClient, InnerTubeClient>> = Lazy::new(|| {
@@ -502,3 +529,75 @@ pub static INNERTUBE_CLIENTS: Lazy<HashMap<YtClient, InnerTubeClient>> = Lazy::n
 
     m
 });
+
+/// Values replacing the ones of the compiled client contexts, to keep up with new client versions
+/// and user agents between releases. Clients it doesn't mention keep the compiled context.
+///
+/// Written as JSON like `{"web": {"clientVersion": "2.20260101.00.00"}}`, with the fields of
+/// `INNERTUBE_CONTEXT.client` for each client name.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Default)]
+pub struct ClientOverrides {
+    // Keys are leaked once when parsed, since the compiled contexts are keyed by static strings.
+    clients: HashMap<YtClient, Vec<(&'static str, Value)>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ClientOverrides {
+    /// Parse overrides, skipping the clients this version of tydle doesn't know about.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let parsed: HashMap<String, serde_json::Map<String, Value>> = serde_json::from_str(json)?;
+        let mut clients = HashMap::new();
+
+        for (name, fields) in parsed {
+            let Ok(client) = name.parse::<YtClient>() else {
+                tracing::warn!("Skipping the overrides of the unknown client \"{}\".", name);
+                continue;
+            };
+            let fields = fields
+                .into_iter()
+                .map(|(key, value)| (&*Box::leak(key.into_boxed_str()), value))
+                .collect();
+            clients.insert(client, fields);
+        }
+
+        Ok(Self { clients })
+    }
+
+    /// Read overrides from a JSON file.
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Download overrides from a JSON endpoint, like a file of a GitHub repository.
+    pub async fn fetch(client: &reqwest::Client, url: &str) -> Result<Self> {
+        let json = client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        Self::from_json(&json)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    /// `innertube_client`, the compiled definition of `client`, with the overrides of `client`.
+    pub(crate) fn apply(&self, client: &YtClient, innertube_client: &mut InnerTubeClient) {
+        let Some(fields) = self.clients.get(client) else {
+            return;
+        };
+        let context = innertube_client
+            .innertube_context
+            .entry("client")
+            .or_default();
+
+        for (key, value) in fields {
+            context.insert(key, value.clone());
+        }
+    }
+}
@@ -5,8 +5,9 @@ use sha1::{Digest, Sha1};
 
 use crate::{
     YT_DOMAIN,
-    cookies::{CookieStore, DomainCookies},
+    cookies::{Cookie, CookieStore, DomainCookies},
     extractor::extract::YtExtractor,
+    http::HttpResponse,
     utils::unix_timestamp_secs,
     yt_interface::YT_URL,
 };
@@ -35,6 +36,8 @@ impl SidCookies {
 pub trait ExtractorCookieHandle {
     fn get_cookies(&self, url: &str) -> Result<DomainCookies>;
     fn get_youtube_cookies(&self) -> Result<DomainCookies>;
+    /// Keep the cookies YouTube sets in `response` for the following requests.
+    fn store_response_cookies(&self, response: &HttpResponse) -> Result<()>;
     /// Get SAPISID, 1PSAPISID, 3PSAPISID cookie values.
     fn get_sid_cookies(&self) -> Result<SidCookies>;
     fn make_sid_authorization(
@@ -62,6 +65,20 @@ impl ExtractorCookieHandle for YtExtractor {
         self.get_cookies(YT_DOMAIN)
     }
 
+    fn store_response_cookies(&self, response: &HttpResponse) -> Result<()> {
+        let set_cookies = response
+            .headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("set-cookie"))
+            .filter_map(|(_, value)| Cookie::from_set_cookie(value, &response.url));
+
+        for cookie in set_cookies {
+            self.cookie_jar.set(cookie)?;
+        }
+
+        Ok(())
+    }
+
     fn get_sid_cookies(&self) -> Result<SidCookies> {
         let yt_cookies = self.get_youtube_cookies()?;
         let yt_sapisid = yt_cookies
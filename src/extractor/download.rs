@@ -1,19 +1,22 @@
 use std::collections::HashMap;
 
-use anyhow::{Error, Result};
+use anyhow::Result;
 use fancy_regex::Regex;
 use reqwest::Url;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::{
+    cookies::CookieStore,
     extractor::{
         api::ExtractorApiHandle,
-        client::INNERTUBE_CLIENTS,
         cookies::ExtractorCookieHandle,
-        extract::{InfoExtractor, YtExtractor},
+        extract::{INITIAL_DATA_KEYS, InfoExtractor, YtExtractor},
+        json::ExtractorJsonHandle,
         player::ExtractorPlayerHandle,
         ytcfg::ExtractorYtCfgHandle,
     },
+    http::HttpRequest,
     yt_interface::{PlayerIdentifier, VideoId, YtClient, YtEndpoint},
 };
 
@@ -38,6 +41,24 @@ pub trait ExtractorDownloadHandle {
         webpage_client: &YtClient,
         video_id: &VideoId,
     ) -> Result<String>;
+    /// Download a resource belonging to a video, like subtitles or thumbnails, with the extractor's cookies.
+    async fn download_resource(&self, resource_url: &str) -> Result<Vec<u8>>;
+    /// Send `request` with the `ETag` and `Last-Modified` of the copy cached for its URL, and reuse that
+    /// copy when the server answers 304 Not Modified. Responses carrying either header are cached.
+    async fn download_if_modified(&self, request: HttpRequest) -> Result<String>;
+    /// Write `content` to `name` in the directory of `video_id` under `debug_dump_dir`, if set.
+    /// Failing to write it is only logged.
+    fn dump_page(&self, video_id: &VideoId, name: &str, content: &str);
+    /// `dump_page` with `value` as pretty JSON, only serialized when `debug_dump_dir` is set.
+    fn dump_json<T: Serialize>(&self, video_id: &VideoId, name: &str, value: &T);
+}
+
+/// Response body cached along with the validators to revalidate it with.
+#[derive(Serialize, Deserialize)]
+struct ConditionalResponse {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: String,
 }
 
 impl ExtractorDownloadHandle for YtExtractor {
@@ -58,8 +79,8 @@ impl ExtractorDownloadHandle for YtExtractor {
             let mut query = self.generate_checkok_params();
             query.insert("videoId".into(), video_id.as_str().into());
 
-            initial_data = Some(
-                self.call_api(
+            let response = self
+                .call_api_raw(
                     YtEndpoint::Next,
                     query,
                     None,
@@ -67,21 +88,32 @@ impl ExtractorDownloadHandle for YtExtractor {
                     None,
                     Some(webpage_client),
                 )
-                .await?,
-            );
+                .await?;
+
+            initial_data = Some(self.extract_json_keys(&response, INITIAL_DATA_KEYS)?);
         }
 
         Ok(initial_data.unwrap())
     }
 
     async fn download_player_url(&self, video_id: &VideoId) -> Result<Option<String>> {
-        let formatted_url = Url::parse("https://www.youtube.com/iframe_api")?;
+        let formatted_url = Url::parse(&format!(
+            "{}://www.youtube.com/iframe_api",
+            self.http_scheme()
+        ))?;
 
-        #[cfg(feature = "logging")]
-        log::info!("Downloading player iFrame API {}", formatted_url);
+        tracing::info!(
+            "{}: Downloading player iFrame API {}",
+            video_id.as_str(),
+            formatted_url
+        );
 
+        // Only changes with the player, so it's revalidated instead of downloaded for every video.
         let iframe_webpage = self
-            .download_initial_webpage(formatted_url, &YtClient::Web, video_id)
+            .download_if_modified(
+                self.cookie_jar
+                    .add_to_request(HttpRequest::get(formatted_url))?,
+            )
             .await?;
 
         let player_version_re = Regex::new(r"player\\?/([0-9a-fA-F]{8})\\?/")?;
@@ -109,20 +141,25 @@ impl ExtractorDownloadHandle for YtExtractor {
             .await
     }
 
+    #[tracing::instrument(
+        name = "download_webpage",
+        skip_all,
+        fields(video_id = video_id.as_str(), client = webpage_client.as_str(), url = %webpage_url)
+    )]
     async fn download_initial_webpage(
         &self,
         webpage_url: Url,
         webpage_client: &YtClient,
         video_id: &VideoId,
     ) -> Result<String> {
-        #[cfg(feature = "logging")]
-        log::info!("{}: Downloading webpage {}", video_id.as_str(), webpage_url);
-        let mut webpage_request = self.http_client.get(webpage_url).query(&[
+        tracing::info!("{}: Downloading webpage {}", video_id.as_str(), webpage_url);
+        let mut webpage_request = HttpRequest::get(webpage_url).query(&[
             ("bpctr", "9999999999"),
             ("has_verified", "1"),
             ("v", video_id.as_str()),
         ]);
-        let innertube_client = INNERTUBE_CLIENTS.get(webpage_client).unwrap();
+        // Authenticated sessions have to keep the user agent their cookies are bound to.
+        let innertube_client = self.select_default_ytcfg(Some(webpage_client))?;
 
         let client = innertube_client.innertube_context.get("client").unwrap();
         if let Some(user_agent) = client.get("userAgent") {
@@ -130,11 +167,7 @@ impl ExtractorDownloadHandle for YtExtractor {
                 webpage_request.header("User-Agent", user_agent.as_str().unwrap_or_default());
         }
 
-        let yt_cookies = self.get_youtube_cookies()?;
-
-        if !yt_cookies.is_empty() {
-            webpage_request = webpage_request.header("Cookie", yt_cookies.header_value());
-        }
+        webpage_request = self.cookie_jar.add_to_request(webpage_request)?;
 
         if !self.tydle_options.source_address.is_empty() {
             webpage_request = webpage_request.header(
@@ -143,10 +176,109 @@ impl ExtractorDownloadHandle for YtExtractor {
             );
         }
 
-        let response = webpage_request.send().await?;
+        webpage_request = webpage_request.timeout(self.request_timeout());
+
+        self.pace_request().await;
+        let response = self.transport.send(webpage_request).await?;
+        self.store_response_cookies(&response)?;
 
-        let webpage = response.text().await.map_err(|e| Error::new(e))?;
+        let webpage = response.text().await?;
 
         Ok(webpage)
     }
+
+    async fn download_resource(&self, resource_url: &str) -> Result<Vec<u8>> {
+        tracing::info!("Downloading resource {}", resource_url);
+        let mut resource_request = self
+            .cookie_jar
+            .add_to_request(HttpRequest::get(Url::parse(resource_url)?))?;
+
+        resource_request = resource_request.timeout(self.request_timeout());
+
+        self.pace_request().await;
+        let response = self
+            .transport
+            .send(resource_request)
+            .await?
+            .error_for_status()?;
+
+        response.bytes().await
+    }
+
+    async fn download_if_modified(&self, mut request: HttpRequest) -> Result<String> {
+        let cache_key = format!("http-response/{}", request.url);
+        let cached = match self.cache.get(&cache_key)? {
+            Some(cached) => serde_json::from_str::<ConditionalResponse>(&cached).ok(),
+            None => None,
+        };
+
+        if let Some(cached) = &cached {
+            if let Some(etag) = &cached.etag {
+                request = request.header("If-None-Match", etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header("If-Modified-Since", last_modified);
+            }
+        }
+
+        request = request.timeout(self.request_timeout());
+
+        self.pace_request().await;
+        let response = self.transport.send(request).await?;
+        self.store_response_cookies(&response)?;
+
+        if response.status == 304
+            && let Some(cached) = cached
+        {
+            tracing::debug!(
+                "{} has not been modified, using the cached copy.",
+                response.url
+            );
+            return Ok(cached.body);
+        }
+
+        let response = response.error_for_status()?;
+        let etag = response.header("ETag").map(|v| v.to_string());
+        let last_modified = response.header("Last-Modified").map(|v| v.to_string());
+        let body = response.text().await?;
+
+        if etag.is_some() || last_modified.is_some() {
+            self.cache.add(
+                cache_key,
+                serde_json::to_string(&ConditionalResponse {
+                    etag,
+                    last_modified,
+                    body: body.clone(),
+                })?,
+            )?;
+        }
+
+        Ok(body)
+    }
+
+    #[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+    fn dump_page(&self, video_id: &VideoId, name: &str, content: &str) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(dump_dir) = &self.tydle_options.debug_dump_dir {
+            let dir = dump_dir.join(video_id.as_str());
+            let path = dir.join(name);
+
+            match std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(&path, content)) {
+                Ok(_) => tracing::debug!("Wrote {}", path.display()),
+                Err(e) => tracing::warn!("Failed to write {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    fn dump_json<T: Serialize>(&self, video_id: &VideoId, name: &str, value: &T) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.tydle_options.debug_dump_dir.is_none() {
+            return;
+        }
+
+        match serde_json::to_string_pretty(value) {
+            Ok(json) => self.dump_page(video_id, name, &json),
+            Err(e) => tracing::warn!("Failed to serialize {}: {}", name, e),
+        }
+    }
 }
@@ -1,35 +1,54 @@
 use std::{
     collections::{HashMap, HashSet},
     sync::{Arc, atomic::AtomicBool},
+    time::Duration,
 };
 
 use anyhow::{Result, anyhow, bail};
 use fancy_regex::Regex;
 use serde_json::{Map, Value};
+use url::Url;
 
 use crate::{
     TydleOptions,
-    cache::CacheStore,
+    cache::Cache,
+    challenge::{ChallengeSolver, HttpChallengeSolver},
     cookies::CookieJar,
+    error::TydleError,
     extractor::{
         auth::ExtractorAuthHandle, client::INNERTUBE_CLIENTS, download::ExtractorDownloadHandle,
         json::ExtractorJsonHandle, player::ExtractorPlayerHandle, ytcfg::ExtractorYtCfgHandle,
     },
+    geo::random_country_ip,
+    http::{HttpTransport, ReqwestTransport},
+    metrics::{Metrics, MetricsCache, MetricsTransport},
     utils::{file_size_from_tbr, mime_type_to_ext, parse_codecs},
     yt_interface::{
-        AudioTrackInfo, Codec, Ext, STREAMING_DATA_CLIENT_NAME, VideoId, YtAgeLimit, YtChannel,
-        YtClient, YtManifest, YtMediaType, YtStream, YtStreamResponse, YtStreamSource, YtThumbnail,
-        YtVideoInfo,
+        AudioTrackInfo, ClientOutcome, Codec, Ext, ExtractionStrategy, ExtractionWarning,
+        STREAMING_DATA_CLIENT_NAME, StreamingProtocol, VideoId, YtAgeLimit, YtAvailability,
+        YtChannel, YtClient, YtManifest, YtMediaType, YtStream, YtStreamResponse, YtStreamSource,
+        YtSubtitle, YtThumbnail, YtVideoInfo,
     },
+    yt_scraper::{ExtractorScraperHandle, decoder},
 };
 
+/// Top-level keys of `ytInitialData` the extractor reads, everything else is skipped without being decoded.
+pub(crate) const INITIAL_DATA_KEYS: &[&str] = &["topbar"];
+
 pub struct YtExtractor {
     pub passed_auth_cookies: AtomicBool,
-    pub http_client: reqwest::Client,
-    pub cookie_jar: CookieJar,
-    pub player_cache: Arc<CacheStore<(String, String)>>,
-    pub code_cache: Arc<CacheStore>,
+    pub transport: Arc<dyn HttpTransport>,
+    pub cookie_jar: Arc<CookieJar>,
+    pub cache: Arc<dyn Cache>,
+    pub challenge_solver: Option<Arc<dyn ChallengeSolver>>,
+    pub(crate) metrics: Arc<Metrics>,
     pub tydle_options: TydleOptions,
+    /// When the last request to YouTube was sent, used to pace requests.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub last_request: std::sync::Mutex<Option<std::time::Instant>>,
+    /// Routes requests are sent through, rotated for every video.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub proxy_pool: Option<Arc<crate::proxy::PoolTransport>>,
 }
 
 pub trait InfoExtractor {
@@ -48,44 +67,187 @@ pub trait InfoExtractor {
     fn extract_formats(
         &self,
         player_responses: Vec<HashMap<String, Value>>,
+        warnings: &mut Vec<ExtractionWarning>,
     ) -> Result<Vec<YtStream>>;
     async fn extract_streams(&self, video_id: &VideoId) -> Result<YtStreamResponse>;
+    fn extract_stream_response(
+        &self,
+        player_url: String,
+        player_responses: Vec<HashMap<String, Value>>,
+        warnings: Vec<ExtractionWarning>,
+    ) -> Result<YtStreamResponse>;
     fn generate_checkok_params(&self) -> HashMap<String, Value>;
     fn is_premium_subscriber(&self, initial_data: &HashMap<String, Value>) -> Result<bool>;
     fn extract_ytcfg(&self, webpage_content: String) -> Result<HashMap<String, Value>>;
     fn extract_yt_initial_data(&self, webpage_content: &String) -> Result<HashMap<String, Value>>;
-    fn get_clients(&self, is_premium_subscriber: bool) -> Result<Vec<YtClient>>;
+    fn get_clients(
+        &self,
+        is_premium_subscriber: bool,
+        warnings: &mut Vec<ExtractionWarning>,
+    ) -> Result<Vec<YtClient>>;
     async fn extract(
         &self,
         webpage_url: &str,
         webpage_client: &YtClient,
         video_id: &VideoId,
+        warnings: &mut Vec<ExtractionWarning>,
     ) -> Result<(Vec<HashMap<String, Value>>, String)>;
 }
 
 impl YtExtractor {
-    pub fn new(
-        player_cache: Arc<CacheStore<(String, String)>>,
-        code_cache: Arc<CacheStore>,
-        tydle_options: TydleOptions,
-    ) -> Result<Self> {
-        let cookie_jar = CookieJar::new_with_cookies(tydle_options.auth_cookies.clone());
+    pub fn new(cache: Arc<dyn Cache>, mut tydle_options: TydleOptions) -> Result<Self> {
+        let metrics = Arc::new(Metrics::default());
+        let cache: Arc<dyn Cache> = Arc::new(MetricsCache::new(cache, metrics.clone()));
+        let cookie_jar = Arc::new(CookieJar::new_with_cookies(
+            tydle_options.auth_cookies.clone(),
+        ));
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut proxy_pool = None;
+        #[cfg(not(target_arch = "wasm32"))]
+        let transport: Arc<dyn HttpTransport> =
+            match (&tydle_options.transport, &tydle_options.http_client) {
+                (Some(transport), _) => transport.clone(),
+                (None, Some(http_client)) => Arc::new(ReqwestTransport::new(http_client.clone())),
+                (None, None) => {
+                    let builder = || {
+                        let mut builder = tydle_options
+                            .dns
+                            .apply(reqwest::Client::builder())
+                            .local_address(tydle_options.source_ip);
+
+                        // Also covers the requests that don't set a timeout of their own, like the
+                        // player and EJS downloads of the decipherer.
+                        if let Some(timeout) = tydle_options.request_timeout_secs {
+                            builder = builder.timeout(Duration::from_secs(timeout));
+                        }
+                        if let Some(timeout) = tydle_options.connect_timeout_secs {
+                            builder = builder.connect_timeout(Duration::from_secs(timeout));
+                        }
+
+                        builder
+                    };
+
+                    match &tydle_options.proxy_pool {
+                        Some(options) => {
+                            let pool =
+                                Arc::new(crate::proxy::PoolTransport::new(options, builder)?);
+                            proxy_pool = Some(pool.clone());
+                            pool
+                        }
+                        None => Arc::new(ReqwestTransport::new(builder().build()?)),
+                    }
+                }
+            };
+        #[cfg(all(feature = "test-fixtures", not(target_arch = "wasm32")))]
+        let transport: Arc<dyn HttpTransport> = match &tydle_options.record_fixtures_dir {
+            Some(dir) => Arc::new(crate::fixtures::RecordingTransport::new(transport, dir)),
+            None => transport,
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let transport: Arc<dyn HttpTransport> = match tydle_options.middlewares.is_empty() {
+            true => transport,
+            false => Arc::new(crate::http::MiddlewareTransport::new(
+                transport,
+                tydle_options.middlewares.clone(),
+            )),
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let transport: Arc<dyn HttpTransport> = match tydle_options.requests_per_minute {
+            Some(requests_per_minute) => Arc::new(
+                crate::extractor::scheduler::RequestScheduler::new(transport, requests_per_minute),
+            ),
+            None => transport,
+        };
+        #[cfg(target_arch = "wasm32")]
+        let transport: Arc<dyn HttpTransport> = Arc::new(ReqwestTransport::default());
+        let transport: Arc<dyn HttpTransport> =
+            Arc::new(MetricsTransport::new(transport, metrics.clone()));
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let challenge_solver = tydle_options.challenge_solver.clone();
+        #[cfg(target_arch = "wasm32")]
+        let challenge_solver = None;
+        let challenge_solver = match (challenge_solver, &tydle_options.challenge_solver_url) {
+            (Some(solver), _) => Some(solver),
+            (None, Some(url)) => Some(Arc::new(
+                HttpChallengeSolver::new(Url::parse(url)?).transport(transport.clone()),
+            ) as Arc<dyn ChallengeSolver>),
+            (None, None) => None,
+        };
+
+        if tydle_options.source_address.is_empty()
+            && let Some(country) = &tydle_options.geo_bypass_country
+        {
+            tydle_options.source_address = random_country_ip(country)?.to_string();
+            tracing::info!(
+                "Using {} as the X-Forwarded-For address of country {}",
+                tydle_options.source_address,
+                country
+            );
+        }
 
         let extractor = Self {
             passed_auth_cookies: AtomicBool::new(false),
-            http_client: reqwest::Client::new(),
+            transport,
             cookie_jar,
-            player_cache,
-            code_cache,
-            tydle_options, // x_forwarded_for_ip: None,
+            cache,
+            challenge_solver,
+            metrics,
+            tydle_options,
+            #[cfg(not(target_arch = "wasm32"))]
+            last_request: std::sync::Mutex::new(None),
+            #[cfg(not(target_arch = "wasm32"))]
+            proxy_pool,
         };
 
         extractor.initialize_pref()?;
         extractor.initialize_consent()?;
         extractor.initialize_cookie_auth()?;
 
+        if extractor.tydle_options.prefer_insecure && extractor.has_auth_cookies()? {
+            bail!(
+                "prefer_insecure can't be used with authentication cookies, they are only sent over https."
+            );
+        }
+
         Ok(extractor)
     }
+
+    /// Per-request timeout configured through `TydleOptions::request_timeout_secs`.
+    pub fn request_timeout(&self) -> Option<Duration> {
+        self.tydle_options
+            .request_timeout_secs
+            .map(Duration::from_secs)
+    }
+
+    /// Wait until `TydleOptions::sleep_requests_secs` have passed since the previous request.
+    /// Called before every request to YouTube.
+    pub async fn pace_request(&self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(interval) = self
+            .tydle_options
+            .sleep_requests_secs
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        {
+            let wait = {
+                let mut last_request = self.last_request.lock().unwrap();
+                let now = std::time::Instant::now();
+                let next = last_request.map_or(now, |last| (last + interval).max(now));
+                // Reserve the slot before sleeping so concurrent requests queue up behind it.
+                *last_request = Some(next);
+                next - now
+            };
+
+            if !wait.is_zero() {
+                tracing::debug!(
+                    "Sleeping {:.2}s before the next request",
+                    wait.as_secs_f64()
+                );
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
 }
 
 impl InfoExtractor for YtExtractor {
@@ -131,33 +293,27 @@ impl InfoExtractor for YtExtractor {
             return Ok(HashMap::new());
         }
 
-        let search_re = Regex::new(r"ytcfg\.set\s*\(\s*({.+?})\s*\)\s*;")?;
-        let json_str = search_re
-            .captures(&webpage_content)?
-            .and_then(|cap| cap.get(1))
-            .map(|m| m.as_str())
-            .unwrap_or("{}");
-
-        let ytcfg: HashMap<String, Value> = serde_json::from_str(json_str)?;
-
-        Ok(ytcfg)
+        match decoder::extract_script_json(&webpage_content, r"ytcfg\.set\s*\(\s*(?=\{)")? {
+            Some(ytcfg) => Ok(serde_json::from_value(ytcfg)?),
+            None => Ok(HashMap::new()),
+        }
     }
 
     fn extract_yt_initial_data(&self, webpage_content: &String) -> Result<HashMap<String, Value>> {
-        let re = Regex::new(
-            r#"(?:window\s*\[\s*["']ytInitialData["']\s*\]|ytInitialData)\s*=\s*(\{.*?\})\s*(?:;|</script>)"#,
-        )?;
-        let json_str = re
-            .captures(&webpage_content)?
-            .and_then(|cap| cap.get(1))
-            .map(|m| m.as_str())
-            .ok_or_else(|| anyhow!("ytInitialData not found"))?;
-
-        let json_val: HashMap<String, Value> = serde_json::from_str(json_str)?;
-        Ok(json_val)
+        let json_str = decoder::find_script_json(
+            webpage_content,
+            r#"(?:window\s*\[\s*["']ytInitialData["']\s*\]|ytInitialData)\s*="#,
+        )?
+        .ok_or_else(|| anyhow!("ytInitialData not found"))?;
+
+        self.extract_json_keys(&json_str, INITIAL_DATA_KEYS)
     }
 
-    fn get_clients(&self, is_premium_subscriber: bool) -> Result<Vec<YtClient>> {
+    fn get_clients(
+        &self,
+        is_premium_subscriber: bool,
+        warnings: &mut Vec<ExtractionWarning>,
+    ) -> Result<Vec<YtClient>> {
         let mut clients = if is_premium_subscriber {
             // Premium does not require POT. (except for subtitles)
             vec![
@@ -186,12 +342,8 @@ impl InfoExtractor for YtExtractor {
                 }
             }
 
-            for _client in &unsupported_clients {
-                #[cfg(feature = "logging")]
-                log::warn!(
-                    "Skipping client \"{}\" since it does not support cookies.",
-                    _client.as_str()
-                );
+            for client in &unsupported_clients {
+                ExtractionWarning::CookiesUnsupported { client: *client }.push_to(warnings);
 
                 clients.retain(|c| !unsupported_clients.iter().any(|u| u.as_str() == c.as_str()));
             }
@@ -206,6 +358,7 @@ impl InfoExtractor for YtExtractor {
     fn extract_formats(
         &self,
         player_responses: Vec<HashMap<String, Value>>,
+        warnings: &mut Vec<ExtractionWarning>,
     ) -> Result<Vec<YtStream>> {
         let mut streams: Vec<YtStream> = vec![];
 
@@ -240,8 +393,7 @@ impl InfoExtractor for YtExtractor {
 
                 // Skip livestream.
                 if target_duration_sec.is_some() {
-                    #[cfg(feature = "logging")]
-                    log::info!(
+                    tracing::info!(
                         "Skipped a format. Found livestream because livestreams are not supported."
                     );
                     continue;
@@ -282,25 +434,12 @@ impl InfoExtractor for YtExtractor {
 
                 let has_drm = fmt.get("drmFamilies").is_some();
 
-                #[cfg(feature = "logging")]
                 if has_drm {
-                    let mut warn_msg = format!(
-                        "Some {} client https formats have been skipped as they are DRM protected.",
-                        client_name
-                    );
-
-                    if client_name == "tv" {
-                        warn_msg += format!(
-                            "{} may have an experiment that applies DRM to all videos on the `tv` client.\nSee  https://github.com/yt-dlp/yt-dlp/issues/12563  for more details.",
-                            if self.is_authenticated()? {
-                                "Your account"
-                            } else {
-                                "The current session"
-                            }
-                        ).as_str();
+                    ExtractionWarning::DrmProtected {
+                        client: client_name.parse().unwrap_or_default(),
+                        authenticated: self.is_authenticated()?,
                     }
-
-                    log::warn!("{warn_msg}");
+                    .push_to(warnings);
                 }
 
                 let mut stream_source = None;
@@ -325,11 +464,11 @@ impl InfoExtractor for YtExtractor {
                     .and_then(|ds| Some(ds.parse::<f64>().unwrap_or_default()))
                     .unwrap_or_default();
 
-                let tbr = fmt
+                let bitrate = fmt
                     .get("averageBitrate")
                     .or_else(|| fmt.get("bitrate"))
-                    .and_then(|v| v.as_f64())
-                    .unwrap_or(1000 as f64);
+                    .and_then(|v| v.as_f64());
+                let tbr = bitrate.unwrap_or(1000 as f64);
 
                 let name = fmt
                     .get("qualityLabel")
@@ -357,6 +496,12 @@ impl InfoExtractor for YtExtractor {
                     .and_then(|v| v.as_str())
                     .map(|s| s.replace("SPATIAL_AUDIO_TYPE_", "").to_lowercase());
 
+                let is_drc = fmt
+                    .get("isDrc")
+                    .and_then(|dr| dr.as_bool())
+                    .unwrap_or_default();
+                let format_note = format_note(&name, audio_display.as_deref(), is_default, is_drc);
+
                 let re = Regex::new(r#"((?:[^/]+)/(?:[^;]+))(?:;\s*codecs="([^"]+)")?"#)?;
 
                 let (ext, (vcodec, acodec)) = match re.captures(
@@ -394,7 +539,9 @@ impl InfoExtractor for YtExtractor {
                     file_size: fmt
                         .get("contentLength")
                         .and_then(|v| v.as_str().and_then(|s| s.parse().ok())),
-                    file_size_approx: file_size_from_tbr(tbr, format_duration),
+                    // Only estimated from the bitrate YouTube sent, not the fallback used to sort.
+                    file_size_approx: bitrate
+                        .map_or(0.0, |bitrate| file_size_from_tbr(bitrate, format_duration)),
                     height: fmt.get("height").and_then(|h| h.as_u64()),
                     width: fmt.get("width").and_then(|w| w.as_u64()),
                     format_duration,
@@ -412,25 +559,52 @@ impl InfoExtractor for YtExtractor {
                     tbr,
                     fps,
                     quality_label: name,
+                    format_note,
                     audio_track: AudioTrackInfo {
                         display_name: audio_display,
                         is_default,
                     },
                     projection,
                     spatial_audio,
-                    client: YtClient::from_str(client_name),
-                    is_drc: fmt
-                        .get("isDrc")
-                        .and_then(|dr| dr.as_bool())
-                        .unwrap_or_default(),
+                    client: client_name.parse().unwrap_or_default(),
+                    is_drc,
                     ext,
                     is_dash: acodec.as_ref().is_some_and(|ac| ac == "none")
                         || vcodec.as_ref().is_some_and(|vc| vc == "none"),
+                    protocol: match fmt.get("type").and_then(|t| t.as_str()) {
+                        Some("FORMAT_STREAM_TYPE_OTF") => StreamingProtocol::Dash,
+                        _ => StreamingProtocol::Https,
+                    },
                     codec: Codec { vcodec, acodec },
+                    init_range: parse_byte_range(fmt.get("initRange")),
+                    index_range: parse_byte_range(fmt.get("indexRange")),
                 });
             }
         }
 
+        // GVS PO Tokens are never sent, so YouTube is likely to refuse the formats that need one.
+        let mut reported = HashSet::new();
+        for stream in &streams {
+            let Some(policy) = INNERTUBE_CLIENTS
+                .get(&stream.client)
+                .and_then(|client| client.gvs_po_token_policy.get(&stream.protocol))
+            else {
+                continue;
+            };
+
+            if policy.required && reported.insert((stream.client, stream.protocol)) {
+                tracing::debug!(
+                    "The {} formats of the {} client require a GVS PO Token{}, downloading them may fail.",
+                    stream.protocol,
+                    stream.client,
+                    match policy.not_required_for_premium {
+                        true => " without YouTube Premium",
+                        false => "",
+                    }
+                );
+            }
+        }
+
         Ok(streams)
     }
 
@@ -446,8 +620,10 @@ impl InfoExtractor for YtExtractor {
         let mut extracted_media_type: Option<YtMediaType> = None;
         let mut extracted_view_count: Option<u64> = None;
         let mut extracted_thumbnails: Vec<YtThumbnail> = vec![];
+        let mut extracted_subtitles: Vec<YtSubtitle> = vec![];
         let mut extracted_description: Option<String> = None;
         let mut extracted_age_limit: Option<YtAgeLimit> = None;
+        let mut extracted_availability: Option<YtAvailability> = None;
 
         for player_response in player_responses {
             let Some(vd_value) = player_response.get("videoDetails") else {
@@ -558,6 +734,32 @@ impl InfoExtractor for YtExtractor {
                     .collect();
             }
 
+            if extracted_subtitles.is_empty() {
+                extracted_subtitles = player_response
+                    .get("captions")
+                    .and_then(|c| c.get("playerCaptionsTracklistRenderer"))
+                    .and_then(|r| r.get("captionTracks"))
+                    .and_then(|t| t.as_array())
+                    .cloned()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|track| {
+                        let url = track.get("baseUrl").and_then(|u| u.as_str())?;
+                        let language_code = track.get("languageCode").and_then(|l| l.as_str())?;
+
+                        Some(YtSubtitle {
+                            url: url.to_string(),
+                            language_code: language_code.to_string(),
+                            name: track.get("name").and_then(|n| self.get_text(n, None, None)),
+                            is_auto_generated: track
+                                .get("kind")
+                                .and_then(|k| k.as_str())
+                                .is_some_and(|k| k == "asr"),
+                        })
+                    })
+                    .collect();
+            }
+
             if extracted_description.is_none() {
                 extracted_description = video_details
                     .get("shortDescription")
@@ -579,6 +781,21 @@ impl InfoExtractor for YtExtractor {
                     },
                 )
             }
+
+            if extracted_availability.is_none() {
+                let is_flag_set = |map: &Map<String, Value>, key: &str| {
+                    map.get(key).and_then(|v| v.as_bool()).unwrap_or_default()
+                };
+                // Android responses have no microformat to tell unlisted videos apart.
+                if is_flag_set(&video_details, "isPrivate") {
+                    extracted_availability = Some(YtAvailability::Private);
+                } else if !microformats.is_empty() {
+                    extracted_availability = Some(match is_flag_set(&microformats, "isUnlisted") {
+                        true => YtAvailability::Unlisted,
+                        false => YtAvailability::Public,
+                    });
+                }
+            }
         }
 
         if let (
@@ -602,8 +819,10 @@ impl InfoExtractor for YtExtractor {
                 channel: YtChannel::new(channel_id, extracted_channel_name)?,
                 keywords: extracted_keywords.unwrap_or_default(),
                 thumbnails: extracted_thumbnails,
+                subtitles: extracted_subtitles,
                 age_limit: extracted_age_limit.unwrap_or_default(),
                 media_type: extracted_media_type.unwrap_or_default(),
+                availability: extracted_availability.unwrap_or_default(),
             });
         }
 
@@ -617,12 +836,20 @@ impl InfoExtractor for YtExtractor {
         webpage_url: &str,
         webpage_client: &YtClient,
         video_id: &VideoId,
+        warnings: &mut Vec<ExtractionWarning>,
     ) -> Result<(Vec<HashMap<String, Value>>, String)> {
-        let webpage = self
+        let mut webpage = self
             .download_webpage(webpage_url, webpage_client, video_id)
             .await?;
+        if is_bot_check_page(&webpage) {
+            ExtractionWarning::BotCheckPage.push_to(warnings);
+            webpage.clear();
+        }
+        self.dump_page(video_id, "watch.html", &webpage);
 
         let mut webpage_ytcfg = self.extract_ytcfg(webpage.clone())?;
+        self.check_logged_in(&webpage_ytcfg)?;
+        self.dump_json(video_id, "ytcfg.json", &webpage_ytcfg);
 
         if webpage_ytcfg.is_empty() {
             webpage_ytcfg = self
@@ -630,17 +857,43 @@ impl InfoExtractor for YtExtractor {
                 .to_json_val_hashmap()?;
         }
 
-        let initial_data = self
-            .download_initial_data(video_id, &webpage, webpage_client, &webpage_ytcfg)
-            .await?;
+        let strategy = self.tydle_options.extraction_strategy;
+        if strategy == ExtractionStrategy::Webpage {
+            return self
+                .scrape_player_responses(video_id, &webpage, &webpage_ytcfg)
+                .await;
+        }
 
-        let is_premium_subscriber = self.is_premium_subscriber(&initial_data)?;
-        let clients = self.get_clients(is_premium_subscriber)?;
-        let player_responses = self
-            .extract_player_responses(&clients, video_id, &webpage, webpage_client, &webpage_ytcfg)
-            .await?;
+        let api_result = async {
+            let initial_data = self
+                .download_initial_data(video_id, &webpage, webpage_client, &webpage_ytcfg)
+                .await?;
+
+            let is_premium_subscriber = self.is_premium_subscriber(&initial_data)?;
+            let clients = self.get_clients(is_premium_subscriber, warnings)?;
+            self.extract_player_responses(
+                &clients,
+                video_id,
+                &webpage,
+                webpage_client,
+                &webpage_ytcfg,
+                warnings,
+            )
+            .await
+        }
+        .await;
 
-        Ok(player_responses)
+        match api_result {
+            Err(e) if strategy == ExtractionStrategy::Fallback => {
+                ExtractionWarning::ApiFailed {
+                    reason: e.to_string(),
+                }
+                .push_to(warnings);
+                self.scrape_player_responses(video_id, &webpage, &webpage_ytcfg)
+                    .await
+            }
+            result => result,
+        }
     }
 
     fn http_scheme(&self) -> &str {
@@ -651,28 +904,125 @@ impl InfoExtractor for YtExtractor {
     }
 
     async fn extract_manifest(&self, video_id: &VideoId) -> Result<YtManifest> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(proxy_pool) = &self.proxy_pool {
+            proxy_pool.pool().rotate();
+        }
+
         let webpage_url = format!("{}://www.youtube.com/watch", self.http_scheme());
-        let (initial_extracted_data, player_url) =
-            self.extract(&webpage_url, &YtClient::Web, video_id).await?;
+        let mut warnings = Vec::new();
+        let (initial_extracted_data, player_url) = self
+            .extract(&webpage_url, &YtClient::Web, video_id, &mut warnings)
+            .await?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.tydle_options.debug_dump_dir.is_some() && !player_url.is_empty() {
+            match self.load_player(video_id, player_url.clone()).await {
+                Ok(code) => self.dump_page(video_id, "player.js", &code),
+                Err(e) => tracing::warn!("Failed to load the player to dump it: {}", e),
+            }
+        }
 
-        Ok(YtManifest::new(initial_extracted_data, player_url))
+        let mut manifest = YtManifest::new(initial_extracted_data, player_url);
+        manifest.warnings = warnings;
+
+        Ok(manifest)
     }
 
     async fn extract_streams(&self, video_id: &VideoId) -> Result<YtStreamResponse> {
         let yt_manifest = self.extract_manifest(video_id).await?;
 
-        let formats = self.extract_formats(yt_manifest.extracted_manifest)?;
-        let stream_response = YtStreamResponse::new(yt_manifest.player_url, formats);
-
-        Ok(stream_response)
+        self.extract_stream_response(
+            yt_manifest.player_url,
+            yt_manifest.extracted_manifest,
+            yt_manifest.warnings,
+        )
     }
 
     async fn extract_streams_from_manifest(
         &self,
         manifest: &YtManifest,
     ) -> Result<YtStreamResponse> {
-        let formats = self.extract_formats(manifest.extracted_manifest.clone())?;
-        Ok(YtStreamResponse::new(manifest.player_url.clone(), formats))
+        self.extract_stream_response(
+            manifest.player_url.clone(),
+            manifest.extracted_manifest.clone(),
+            manifest.warnings.clone(),
+        )
+    }
+
+    fn extract_stream_response(
+        &self,
+        player_url: String,
+        player_responses: Vec<HashMap<String, Value>>,
+        mut warnings: Vec<ExtractionWarning>,
+    ) -> Result<YtStreamResponse> {
+        let mut is_live = false;
+        let mut hls_manifest_url: Option<String> = None;
+        let mut dash_manifest_url: Option<String> = None;
+
+        for player_response in &player_responses {
+            is_live |= player_response
+                .get("videoDetails")
+                .and_then(|vd| vd.get("isLive"))
+                .and_then(|il| il.as_bool())
+                .unwrap_or_default();
+
+            let Some(streaming_data) = player_response.get("streamingData") else {
+                continue;
+            };
+
+            if hls_manifest_url.is_none() {
+                hls_manifest_url = streaming_data
+                    .get("hlsManifestUrl")
+                    .and_then(|u| u.as_str())
+                    .map(|u| u.to_string());
+            }
+
+            if dash_manifest_url.is_none() {
+                dash_manifest_url = streaming_data
+                    .get("dashManifestUrl")
+                    .and_then(|u| u.as_str())
+                    .map(|u| u.to_string());
+            }
+        }
+
+        if hls_manifest_url.is_none()
+            && dash_manifest_url.is_none()
+            && let Some(upcoming) = player_responses
+                .iter()
+                .filter_map(|pr| self.playability_error(pr))
+                .find(|err| matches!(err, TydleError::Upcoming { .. }))
+        {
+            return Err(upcoming.into());
+        }
+
+        let responded_clients: Vec<YtClient> = player_responses
+            .iter()
+            .filter_map(|pr| pr.get(STREAMING_DATA_CLIENT_NAME)?.as_str()?.parse().ok())
+            .collect();
+        let formats = self.extract_formats(player_responses, &mut warnings)?;
+
+        let mut client_results = HashMap::new();
+        for client in responded_clients {
+            client_results.insert(
+                client,
+                ClientOutcome::Succeeded {
+                    formats: formats.iter().filter(|f| f.client == client).count(),
+                },
+            );
+        }
+        // A client that responded can still have been dropped afterwards, like for age-gates.
+        client_results.extend(warnings.iter().filter_map(|w| w.client_outcome()));
+
+        let mut stream_response = YtStreamResponse::new(player_url, formats);
+        stream_response.client_results = client_results;
+
+        stream_response.is_live = is_live;
+        stream_response.hls_manifest_url = hls_manifest_url;
+        stream_response.dash_manifest_url = dash_manifest_url;
+        stream_response.warnings = warnings;
+
+        Ok(stream_response)
     }
 
     async fn extract_video_info(&self, video_id: &VideoId) -> Result<YtVideoInfo> {
@@ -687,3 +1037,41 @@ impl InfoExtractor for YtExtractor {
         Ok(yt_video_info)
     }
 }
+
+/// Parse a `{"start": "0", "end": "219"}` range of a format.
+/// Whether `webpage` is the "unusual traffic" interstitial served instead of the watch page.
+fn is_bot_check_page(webpage: &str) -> bool {
+    webpage.contains("/sorry/index") || webpage.contains("unusual traffic from your computer")
+}
+
+/// Like yt-dlp's format note, "English original (default), 1080p60 HDR, DRC". The quality label
+/// carries "Premium" for the enhanced bitrate formats.
+fn format_note(
+    quality_label: &str,
+    audio_track: Option<&str>,
+    is_default_audio: bool,
+    is_drc: bool,
+) -> String {
+    let audio_track = audio_track.map(|name| match is_default_audio {
+        true => format!("{} (default)", name),
+        false => name.to_string(),
+    });
+
+    [
+        audio_track,
+        Some(quality_label.to_string()).filter(|label| !label.is_empty()),
+        is_drc.then(|| "DRC".to_string()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+fn parse_byte_range(range: Option<&Value>) -> Option<(u64, u64)> {
+    let range = range?;
+    let start = range.get("start")?.as_str()?.parse().ok()?;
+    let end = range.get("end")?.as_str()?.parse().ok()?;
+
+    Some((start, end))
+}
@@ -0,0 +1,81 @@
+use anyhow::{Result, anyhow};
+use fancy_regex::Regex;
+use url::Url;
+
+use crate::{
+    extractor::{download::ExtractorDownloadHandle, extract::InfoExtractor, extract::YtExtractor},
+    http::HttpRequest,
+    yt_interface::{YtChannel, YtChannelFeed, YtFeedEntry},
+    yt_scraper::decoder::decode_html_entities,
+};
+
+pub trait ExtractorFeedHandle {
+    /// The latest uploads of `channel` from its Atom feed, which needs neither the API nor
+    /// cookies. Unchanged feeds are answered from the cache.
+    async fn extract_channel_feed(&self, channel: &YtChannel) -> Result<YtChannelFeed>;
+}
+
+impl ExtractorFeedHandle for YtExtractor {
+    async fn extract_channel_feed(&self, channel: &YtChannel) -> Result<YtChannelFeed> {
+        let feed_url = Url::parse_with_params(
+            &format!("{}://www.youtube.com/feeds/videos.xml", self.http_scheme()),
+            &[("channel_id", channel.get_id())],
+        )?;
+        tracing::info!("{}: Downloading channel feed", channel.get_id());
+
+        let feed = self
+            .download_if_modified(HttpRequest::get(feed_url))
+            .await?;
+        let entry_re = Regex::new(r"(?s)<entry>(.*?)</entry>")?;
+
+        // Everything before the first entry describes the channel itself.
+        let header = feed.split("<entry>").next().unwrap_or_default();
+        let name = tag_text(header, "title")?.or(channel.get_name().map(str::to_string));
+
+        let entries = entry_re
+            .captures_iter(&feed)
+            .map(|cap| parse_entry(cap?.get(1).map(|m| m.as_str()).unwrap_or_default()))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(YtChannelFeed {
+            channel: YtChannel::new(channel.get_id(), name)?,
+            entries,
+        })
+    }
+}
+
+fn parse_entry(entry: &str) -> Result<YtFeedEntry> {
+    let required =
+        |tag: &str| tag_text(entry, tag)?.ok_or(anyhow!("A channel feed entry has no <{}>.", tag));
+
+    Ok(YtFeedEntry {
+        video_id: required("yt:videoId")?,
+        title: required("title")?,
+        published: required("published")?,
+        updated: required("updated")?,
+        description: tag_text(entry, "media:description")?.filter(|d| !d.is_empty()),
+        thumbnail: tag_attribute(entry, "media:thumbnail", "url")?,
+        view_count: tag_attribute(entry, "media:statistics", "views")?
+            .and_then(|views| views.parse().ok()),
+    })
+}
+
+/// The decoded text of the first `tag` element in `xml`.
+fn tag_text(xml: &str, tag: &str) -> Result<Option<String>> {
+    let re = Regex::new(&format!(r"(?s)<{}(?:\s[^>]*)?>(.*?)</{}>", tag, tag))?;
+
+    Ok(re
+        .captures(xml)?
+        .and_then(|cap| cap.get(1))
+        .map(|m| decode_html_entities(m.as_str().trim())))
+}
+
+/// The decoded value of `attribute` on the first `tag` element in `xml`.
+fn tag_attribute(xml: &str, tag: &str, attribute: &str) -> Result<Option<String>> {
+    let re = Regex::new(&format!(r#"<{}\s[^>]*\b{}="([^"]*)""#, tag, attribute))?;
+
+    Ok(re
+        .captures(xml)?
+        .and_then(|cap| cap.get(1))
+        .map(|m| decode_html_entities(m.as_str())))
+}
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use anyhow::{Result, anyhow};
 use fancy_regex::Regex;
-use serde_json::Value;
+use serde_json::{Value, value::RawValue};
 
 use crate::extractor::extract::YtExtractor;
 
@@ -21,6 +21,8 @@ pub trait ExtractorJsonHandle {
         path_list: Option<Vec<Vec<&str>>>,
         max_runs: Option<usize>,
     ) -> Option<String>;
+    /// Parse only the top-level `keys` of the JSON object in `json_str`, leaving every other key undecoded.
+    fn extract_json_keys(&self, json_str: &str, keys: &[&str]) -> Result<HashMap<String, Value>>;
 }
 
 impl ExtractorJsonHandle for YtExtractor {
@@ -190,4 +192,13 @@ impl ExtractorJsonHandle for YtExtractor {
 
         Ok(default_value)
     }
+
+    fn extract_json_keys(&self, json_str: &str, keys: &[&str]) -> Result<HashMap<String, Value>> {
+        let raw: HashMap<String, &RawValue> = serde_json::from_str(json_str)?;
+
+        raw.into_iter()
+            .filter(|(k, _)| keys.contains(&k.as_str()))
+            .map(|(k, v)| Ok((k, serde_json::from_str(v.get())?)))
+            .collect()
+    }
 }
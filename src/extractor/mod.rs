@@ -1,11 +1,15 @@
 mod api;
-mod auth;
-mod client;
+pub(crate) mod auth;
 mod cookies;
-mod download;
 mod json;
-mod player;
+pub(crate) mod playlist;
 mod token_policy;
-mod ytcfg;
+pub(crate) mod ytcfg;
 
+pub(crate) mod client;
+pub(crate) mod download;
 pub mod extract;
+pub(crate) mod feed;
+pub(crate) mod player;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod scheduler;
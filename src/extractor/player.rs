@@ -1,28 +1,85 @@
 use std::collections::HashMap;
 
-use anyhow::{Result, anyhow};
+use anyhow::Result;
 use fancy_regex::Regex;
 use maplit::hashmap;
 use serde_json::{Map, Value, json};
 
 use crate::{
     STREAMING_DATA_CLIENT_NAME, STREAMING_DATA_INNERTUBE_CONTEXT,
-    cache::{CacheAccess, PlayerCacheHandle},
+    cache::{
+        PO_TOKEN_KEY_PREFIX, PO_TOKEN_TTL, PlayerCacheHandle, VISITOR_DATA_KEY, VISITOR_DATA_TTL,
+    },
+    challenge::{Challenge, ChallengeRequest},
+    error::TydleError,
     extractor::{
         api::ExtractorApiHandle,
         auth::ExtractorAuthHandle,
+        client::INNERTUBE_CLIENTS,
         download::ExtractorDownloadHandle,
         extract::{InfoExtractor, YtExtractor},
         json::ExtractorJsonHandle,
         ytcfg::ExtractorYtCfgHandle,
     },
     yt_interface::{
-        PLAYER_JS_MAIN_VARIANT, PlayerIdentifier, VideoId, YT_URL, YtClient, YtEndpoint,
+        ExtractionWarning, PLAYER_JS_MAIN_VARIANT, PlayerIdentifier, VideoId, YtClient, YtEndpoint,
     },
 };
 
+/// How far `extract_player_responses` got working around an age-gate. Each step queues its
+/// fallback clients once, so age-gated fallbacks can't queue each other again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AgeGateStep {
+    NotGated,
+    /// `web_embedded` was queued, which plays some embeddable videos without an age check.
+    Embedded,
+    /// `tv_embedded` and `web_creator` were queued, which skip the account verification of signed
+    /// in sessions.
+    Verified,
+    /// No fallback is left.
+    Exhausted,
+}
+
+impl AgeGateStep {
+    /// The step after `client` was age-gated, or after `web_embedded` refused to play an
+    /// unembeddable video, along with the clients to try in it.
+    fn next(self, client: &YtClient, authenticated: bool) -> (Self, &'static [YtClient]) {
+        match self {
+            Self::NotGated if *client != YtClient::WebEmbedded => {
+                (Self::Embedded, &[YtClient::WebEmbedded])
+            }
+            Self::NotGated | Self::Embedded
+                if *client == YtClient::WebEmbedded && authenticated =>
+            {
+                (
+                    Self::Verified,
+                    &[YtClient::TvEmbedded, YtClient::WebCreator],
+                )
+            }
+            Self::NotGated | Self::Embedded if *client == YtClient::WebEmbedded => {
+                (Self::Exhausted, &[])
+            }
+            // Clients that aren't the fallbacks of the current step are already covered by them.
+            step => (step, &[]),
+        }
+    }
+
+    /// The error to return when the video was age-gated and none of `prs` has formats.
+    fn error(self, prs: &[HashMap<String, Value>], reason: Option<String>) -> Option<TydleError> {
+        if self == Self::NotGated || prs.iter().any(|pr| pr.contains_key("streamingData")) {
+            return None;
+        }
+
+        Some(TydleError::AgeRestricted(
+            reason.unwrap_or("Sign in to confirm your age.".into()),
+        ))
+    }
+}
+
 pub trait ExtractorPlayerHandle {
     fn is_unplayable(&self, player_response: &HashMap<String, Value>) -> bool;
+    /// Classify why YouTube refused to return a playable response, if it did.
+    fn playability_error(&self, player_response: &HashMap<String, Value>) -> Option<TydleError>;
     fn is_age_gated(&self, player_response: &HashMap<String, Value>) -> bool;
     fn generate_player_context(&self, sts: Option<i64>) -> HashMap<String, Value>;
     async fn load_player(&self, video_id: &VideoId, player_url: String) -> Result<String>;
@@ -59,6 +116,7 @@ pub trait ExtractorPlayerHandle {
         webpage: &String,
         webpage_client: &YtClient,
         webpage_ytcfg: &HashMap<String, Value>,
+        warnings: &mut Vec<ExtractionWarning>,
     ) -> Result<(Vec<HashMap<String, Value>>, String)>;
 }
 
@@ -66,11 +124,17 @@ impl ExtractorPlayerHandle for YtExtractor {
     fn construct_player_url(&self, player_identifier: PlayerIdentifier) -> Result<String> {
         match player_identifier {
             PlayerIdentifier::PlayerUrl(player_url) => {
-                return Ok(format!("{}{}", YT_URL, player_url));
+                return Ok(format!(
+                    "{}://www.youtube.com{}",
+                    self.http_scheme(),
+                    player_url
+                ));
             }
             PlayerIdentifier::PlayerId(player_id) => Ok(format!(
-                "{}/s/player/{}/{}",
-                YT_URL, player_id, PLAYER_JS_MAIN_VARIANT
+                "{}://www.youtube.com/s/player/{}/{}",
+                self.http_scheme(),
+                player_id,
+                PLAYER_JS_MAIN_VARIANT
             )),
         }
     }
@@ -127,19 +191,11 @@ impl ExtractorPlayerHandle for YtExtractor {
             return true;
         }
 
-        let reasons_array: Vec<Value> = player_response
-            .get("playabilityStatus")
-            .and_then(|ps| ps.as_object())
-            .and_then(|o| o.get("status"))
-            .and_then(|s| s.as_object())
-            .and_then(|s| s.get("reason"))
-            .and_then(|r| r.as_array())
-            .cloned()
-            .unwrap_or_else(|| vec![]);
-
-        let reasons: Vec<&str> = reasons_array
+        let playability_status = player_response.get("playabilityStatus");
+        let reasons: Vec<String> = ["status", "reason"]
             .iter()
-            .map(|x| x.as_str().unwrap_or_default())
+            .filter_map(|key| playability_status?.get(key)?.as_str())
+            .map(str::to_lowercase)
             .collect();
 
         const AGE_GATE_REASONS: [&str; 5] = [
@@ -173,11 +229,66 @@ impl ExtractorPlayerHandle for YtExtractor {
         false
     }
 
+    fn playability_error(&self, player_response: &HashMap<String, Value>) -> Option<TydleError> {
+        let playability_status = player_response.get("playabilityStatus")?;
+        let status = playability_status
+            .get("status")
+            .and_then(|s| s.as_str())
+            .unwrap_or_default();
+
+        if status.is_empty() || status == "OK" {
+            return None;
+        }
+
+        let reason = playability_status
+            .get("reason")
+            .and_then(|r| r.as_str())
+            .map(|r| r.to_string())
+            .or_else(|| {
+                self.get_text(
+                    playability_status,
+                    Some(vec![vec![
+                        "errorScreen",
+                        "playerErrorMessageRenderer",
+                        "reason",
+                    ]]),
+                    None,
+                )
+            })
+            .unwrap_or(status.to_string());
+
+        Some(match status {
+            // A signed in session doesn't get the bot check, so the cookies were rejected.
+            "LOGIN_REQUIRED"
+                if reason.contains("not a bot") && self.has_auth_cookies().unwrap_or_default() =>
+            {
+                TydleError::AuthExpired(reason)
+            }
+            "LOGIN_REQUIRED" if reason.contains("not a bot") => TydleError::BotCheck(reason),
+            _ if self.is_age_gated(player_response) => TydleError::AgeRestricted(reason),
+            _ if reason.to_lowercase().contains("private") => TydleError::Private(reason),
+            "LOGIN_REQUIRED" => TydleError::LoginRequired(reason),
+            "LIVE_STREAM_OFFLINE" => TydleError::Upcoming {
+                reason,
+                start_time: playability_status
+                    .get("liveStreamability")
+                    .and_then(|ls| ls.get("liveStreamabilityRenderer"))
+                    .and_then(|lsr| lsr.get("offlineSlate"))
+                    .and_then(|os| os.get("liveStreamOfflineSlateRenderer"))
+                    .and_then(|slate| slate.get("scheduledStartTime"))
+                    .and_then(|t| t.as_str())
+                    .and_then(|t| t.parse().ok()),
+            },
+            _ if reason.contains("country") => TydleError::GeoRestricted(reason),
+            _ => TydleError::Unplayable(reason),
+        })
+    }
+
     async fn load_player(&self, video_id: &VideoId, player_url: String) -> Result<String> {
-        let player_js_key = self.player_cache.player_js_cache_key(&player_url)?;
+        let player_js_key = self.cache.player_code_cache_key(&player_url)?;
 
-        if self.code_cache.contains(&player_js_key)? {
-            return Ok(self.code_cache.get(&player_js_key)?.unwrap().clone());
+        if let Some(code) = self.cache.get(&player_js_key)? {
+            return Ok(code);
         }
 
         let code = self
@@ -185,7 +296,7 @@ impl ExtractorPlayerHandle for YtExtractor {
             .await?;
 
         if !code.is_empty() {
-            self.code_cache.add(player_js_key, code.clone())?;
+            self.cache.add(player_js_key, code.clone())?;
         }
 
         Ok(code)
@@ -202,13 +313,13 @@ impl ExtractorPlayerHandle for YtExtractor {
         }
 
         if let Some(sts) = self
-            .player_cache
+            .cache
             .load_player_data_from_cache("sts", player_url.clone())?
         {
             return Ok(Some(sts.parse::<i64>()?));
         }
 
-        let code = self.load_player(video_id, player_url).await?;
+        let code = self.load_player(video_id, player_url.clone()).await?;
 
         let re = Regex::new(r"(?:signatureTimestamp|sts)\s*:\s*(?P<sts>[0-9]{5})")?;
         let code_caps = re.captures(&code)?;
@@ -223,6 +334,9 @@ impl ExtractorPlayerHandle for YtExtractor {
             return Ok(None);
         };
 
+        self.cache
+            .store_player_data_to_cache("sts", player_url, sts.to_string())?;
+
         Ok(Some(sts))
     }
 
@@ -247,6 +361,12 @@ impl ExtractorPlayerHandle for YtExtractor {
         player_context
     }
 
+    // The visitor data, session IDs and PO token are left out of the span.
+    #[tracing::instrument(
+        name = "player_response",
+        skip_all,
+        fields(client = client.as_str(), video_id = video_id.as_str())
+    )]
     async fn extract_player_response(
         &self,
         client: &YtClient,
@@ -297,6 +417,48 @@ impl ExtractorPlayerHandle for YtExtractor {
 
         yt_query.extend(player_context);
 
+        let po_token_required = INNERTUBE_CLIENTS
+            .get(client)
+            .is_some_and(|innertube_client| innertube_client.player_po_token_policy.required);
+        if let Some(solver) = self.challenge_solver.as_ref().filter(|_| po_token_required) {
+            let request = ChallengeRequest {
+                player_url: None,
+                challenge: Challenge::PoToken {
+                    client: client.as_str().to_string(),
+                    video_id: video_id.as_str().to_string(),
+                    visitor_data: visitor_data.clone(),
+                    data_sync_id: data_sync_id.clone(),
+                },
+            };
+
+            let cache_key = format!(
+                "{}{}/{}/{}/{}",
+                PO_TOKEN_KEY_PREFIX,
+                client.as_str(),
+                visitor_data.as_deref().unwrap_or_default(),
+                data_sync_id.as_deref().unwrap_or_default(),
+                video_id.as_str()
+            );
+            let po_token = match self.cache.get(&cache_key)? {
+                Some(po_token) => Some(po_token),
+                None => {
+                    let po_token = solver.solve(&request).await?;
+                    if let Some(po_token) = &po_token {
+                        self.cache
+                            .add_with_ttl(cache_key, po_token.clone(), PO_TOKEN_TTL)?;
+                    }
+                    po_token
+                }
+            };
+
+            if let Some(po_token) = po_token {
+                yt_query.insert(
+                    "serviceIntegrityDimensions".into(),
+                    json!({ "poToken": po_token }),
+                );
+            }
+        }
+
         let player_response = self
             .call_api(
                 YtEndpoint::Player,
@@ -318,6 +480,7 @@ impl ExtractorPlayerHandle for YtExtractor {
         webpage: &String,
         webpage_client: &YtClient,
         webpage_ytcfg: &HashMap<String, Value>,
+        warnings: &mut Vec<ExtractionWarning>,
     ) -> Result<(Vec<HashMap<String, Value>>, String)> {
         let initial_pr = self.search_json(r"ytInitialPlayerResponse\s*=", &webpage, None, None)?;
         let mut prs: Vec<HashMap<String, Value>> = vec![];
@@ -325,11 +488,12 @@ impl ExtractorPlayerHandle for YtExtractor {
         let mut init_pr_copy = initial_pr.clone();
         init_pr_copy.insert("streamingData".into(), Value::Null);
 
+        // The page of a private video has a player response without `videoDetails`, which must not
+        // count as a response of the video.
         if !initial_pr.is_empty()
             && self
                 .invalid_player_response(&initial_pr, video_id)
-                .unwrap_or_default()
-                .is_empty()
+                .is_none()
         {
             // Android player_response does not have microFormats which are needed for extraction of some data.
             // So we return the initial_pr with formats stripped out even if not requested by the user.
@@ -343,14 +507,19 @@ impl ExtractorPlayerHandle for YtExtractor {
         let mut player_url: Option<String> = None;
         let mut visitor_data: Option<String> = None;
         let mut data_sync_id: Option<String> = None;
+        let mut attempts: Vec<(YtClient, TydleError)> = vec![];
+        let mut tried_clients: Vec<YtClient> = vec![];
+        let mut age_gate = AgeGateStep::NotGated;
+        let mut age_gate_reason: Option<String> = None;
+        let mut retried_bot_check = false;
 
         while !actual_clients.is_empty() {
             let popped_client = actual_clients.pop().unwrap();
+            tried_clients.push(popped_client);
             let client = popped_client.as_str();
             let variant = popped_client.get_variant();
 
-            #[cfg(feature = "logging")]
-            log::info!(
+            tracing::info!(
                 "Extracting player response from \"{}\" client's manifest.",
                 client
             );
@@ -376,6 +545,15 @@ impl ExtractorPlayerHandle for YtExtractor {
             if visitor_data.is_none() {
                 visitor_data =
                     self.select_visitor_data(&[webpage_ytcfg, &initial_pr, player_ytcfg]);
+                // Keep the same visitor across runs, also when no page was fetched to get one.
+                match &visitor_data {
+                    Some(visitor_data) => self.cache.add_with_ttl(
+                        VISITOR_DATA_KEY.into(),
+                        visitor_data.clone(),
+                        VISITOR_DATA_TTL,
+                    )?,
+                    None => visitor_data = self.cache.get(VISITOR_DATA_KEY)?,
+                }
             }
 
             if data_sync_id.is_none() {
@@ -416,7 +594,7 @@ impl ExtractorPlayerHandle for YtExtractor {
             //     .collect(),
             // );
 
-            let mut player_response = self
+            let mut player_response = match self
                 .extract_player_response(
                     &popped_client,
                     video_id,
@@ -431,16 +609,101 @@ impl ExtractorPlayerHandle for YtExtractor {
                     &visitor_data,
                     &data_sync_id,
                 )
-                .await?;
-
-            if let Some(_invalid_pr_id) = self.invalid_player_response(&player_response, video_id) {
-                #[cfg(feature = "logging")]
-                log::warn!(
-                    "Skipped {}. Received invalid player response for video with ID \"{}\", got {} instead.",
-                    client,
-                    video_id.as_str(),
-                    _invalid_pr_id
+                .await
+            {
+                Ok(pr) => pr,
+                Err(e) => {
+                    ExtractionWarning::ClientFailed {
+                        client: popped_client,
+                        reason: e.to_string(),
+                    }
+                    .push_to(warnings);
+                    attempts.push((popped_client, e.into()));
+                    continue;
+                }
+            };
+
+            self.dump_json(
+                video_id,
+                &format!("player_response.{}.json", client),
+                &player_response,
+            );
+
+            let embedding_is_disabled =
+                variant == "web_embedded" && self.is_unplayable(&player_response);
+            if self.is_age_gated(&player_response) || embedding_is_disabled {
+                let authenticated = self.is_authenticated()?;
+                if authenticated {
+                    ExtractionWarning::AccountVerificationRequired {
+                        client: popped_client,
+                    }
+                    .push_to(warnings);
+                } else {
+                    ExtractionWarning::AgeRestricted {
+                        client: popped_client,
+                    }
+                    .push_to(warnings);
+                }
+                if age_gate_reason.is_none() {
+                    age_gate_reason = self
+                        .playability_error(&player_response)
+                        .map(|err| err.to_string());
+                }
+
+                let fallbacks;
+                (age_gate, fallbacks) = age_gate.next(&popped_client, authenticated);
+                for fallback in fallbacks {
+                    if !tried_clients.contains(fallback) && !actual_clients.contains(fallback) {
+                        tracing::info!(
+                            "\"{}\" client is age-gated, trying \"{}\".",
+                            client,
+                            fallback.as_str()
+                        );
+                        actual_clients.push(*fallback);
+                    }
+                }
+                continue;
+            }
+
+            if let Some(invalid_pr_id) = self.invalid_player_response(&player_response, video_id) {
+                let err = self.playability_error(&player_response).unwrap_or(
+                    TydleError::InvalidPlayerResponse {
+                        expected: video_id.to_string(),
+                        got: invalid_pr_id,
+                    },
                 );
+
+                ExtractionWarning::ClientFailed {
+                    client: popped_client,
+                    reason: err.to_string(),
+                }
+                .push_to(warnings);
+                // Other clients get the same answer for a private video.
+                let is_private = matches!(err, TydleError::Private(_));
+                // The bot check is tied to the session, which a new visitor and clients that
+                // don't need PO tokens can get around.
+                if matches!(err, TydleError::BotCheck(_)) && !retried_bot_check {
+                    retried_bot_check = true;
+                    ExtractionWarning::BotCheck {
+                        client: popped_client,
+                    }
+                    .push_to(warnings);
+
+                    match self.fetch_visitor_data().await {
+                        Ok(fresh_visitor_data) => visitor_data = Some(fresh_visitor_data),
+                        Err(e) => tracing::warn!("Failed to get fresh visitor data. {}", e),
+                    }
+                    for fallback in [YtClient::Tv, YtClient::AndroidSdkless] {
+                        if !tried_clients.contains(&fallback) && !actual_clients.contains(&fallback)
+                        {
+                            actual_clients.push(fallback);
+                        }
+                    }
+                }
+                attempts.push((popped_client, err));
+                if is_private {
+                    break;
+                }
                 continue;
             }
 
@@ -468,43 +731,131 @@ impl ExtractorPlayerHandle for YtExtractor {
 
                 prs.push(player_response.clone());
             }
+        }
 
-            // web_embedded can work around age-gate and age-verification for some embeddable videos.
-            if self.is_age_gated(&player_response) && variant != "web_embedded" {
-                actual_clients.push(YtClient::WebEmbedded);
-            }
+        if let Some(err) = age_gate.error(&prs, age_gate_reason) {
+            return Err(err.into());
+        }
 
-            // Unauthenticated users will only get web_embedded client formats if age-gated.
-            if self.is_age_gated(&player_response) && !self.is_authenticated()? {
-                #[cfg(feature = "logging")]
-                log::warn!(
-                    "Skipping client \"{}\" since the video is age-restricted and unavailable without authentication.",
-                    client
-                );
-                continue;
+        if prs.is_empty() {
+            if let Some(i) = attempts
+                .iter()
+                .position(|(_, err)| matches!(err, TydleError::Private(_)))
+            {
+                return Err(attempts.swap_remove(i).1.into());
             }
+            if let Some(i) = attempts
+                .iter()
+                .rposition(|(_, err)| matches!(err, TydleError::BotCheck(_)))
+            {
+                return Err(attempts.swap_remove(i).1.into());
+            }
+            return Err(TydleError::ExtractionFailed { attempts }.into());
+        }
 
-            let embedding_is_disabled =
-                variant == "web_embedded" && self.is_unplayable(&player_response);
+        Ok((prs, player_url.unwrap_or_default()))
+    }
+}
 
-            if self.is_authenticated()?
-                && (self.is_age_gated(&player_response) || embedding_is_disabled)
-            {
-                #[cfg(feature = "logging")]
-                log::warn!(
-                    "Skipping client \"{}\" since the video is age-restricted and YouTube is requiring account verification.",
-                    client
-                );
-                actual_clients.push(YtClient::TvEmbedded);
-                actual_clients.push(YtClient::WebCreator);
-                continue;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Walk the steps like `extract_player_responses` does when every client it tries is
+    /// age-gated, returning the clients in the order they were tried.
+    fn try_all_gated(first: YtClient, authenticated: bool) -> (AgeGateStep, Vec<YtClient>) {
+        let mut step = AgeGateStep::NotGated;
+        let mut queue = vec![first];
+        let mut tried = vec![];
+        while let Some(client) = queue.pop() {
+            tried.push(client);
+            let fallbacks;
+            (step, fallbacks) = step.next(&client, authenticated);
+            for fallback in fallbacks {
+                if !tried.contains(fallback) && !queue.contains(fallback) {
+                    queue.push(*fallback);
+                }
             }
         }
+        (step, tried)
+    }
 
-        if prs.is_empty() {
-            return Err(anyhow!("Failed to extract any player response."));
+    #[test]
+    fn unauthenticated_falls_back_to_web_embedded() {
+        let (step, fallbacks) = AgeGateStep::NotGated.next(&YtClient::Web, false);
+        assert_eq!(step, AgeGateStep::Embedded);
+        assert_eq!(fallbacks, [YtClient::WebEmbedded]);
+
+        let (step, fallbacks) = step.next(&YtClient::WebEmbedded, false);
+        assert_eq!(step, AgeGateStep::Exhausted);
+        assert!(fallbacks.is_empty());
+    }
+
+    #[test]
+    fn unverified_account_falls_back_to_tv_embedded_and_web_creator() {
+        let (step, fallbacks) = AgeGateStep::NotGated.next(&YtClient::Web, true);
+        assert_eq!(step, AgeGateStep::Embedded);
+        assert_eq!(fallbacks, [YtClient::WebEmbedded]);
+
+        let (step, fallbacks) = step.next(&YtClient::WebEmbedded, true);
+        assert_eq!(step, AgeGateStep::Verified);
+        assert_eq!(fallbacks, [YtClient::TvEmbedded, YtClient::WebCreator]);
+    }
+
+    #[test]
+    fn gated_fallbacks_queue_nothing() {
+        for client in [
+            YtClient::Web,
+            YtClient::WebEmbedded,
+            YtClient::TvEmbedded,
+            YtClient::WebCreator,
+        ] {
+            for step in [AgeGateStep::Verified, AgeGateStep::Exhausted] {
+                assert_eq!(step.next(&client, true), (step, &[][..]));
+                assert_eq!(step.next(&client, false), (step, &[][..]));
+            }
         }
+        let (step, fallbacks) = AgeGateStep::Embedded.next(&YtClient::Web, false);
+        assert_eq!(step, AgeGateStep::Embedded);
+        assert!(fallbacks.is_empty());
+    }
 
-        Ok((prs, player_url.unwrap_or_default()))
+    #[test]
+    fn every_client_is_tried_once() {
+        let (step, tried) = try_all_gated(YtClient::Web, false);
+        assert_eq!(step, AgeGateStep::Exhausted);
+        assert_eq!(tried, [YtClient::Web, YtClient::WebEmbedded]);
+
+        let (step, tried) = try_all_gated(YtClient::Web, true);
+        assert_eq!(step, AgeGateStep::Verified);
+        assert_eq!(
+            tried,
+            [
+                YtClient::Web,
+                YtClient::WebEmbedded,
+                YtClient::WebCreator,
+                YtClient::TvEmbedded
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_fallbacks_are_age_restricted() {
+        let (step, _) = try_all_gated(YtClient::Web, true);
+        let err = step.error(&[HashMap::new()], Some("Sign in".into()));
+        assert!(matches!(err, Some(TydleError::AgeRestricted(reason)) if reason == "Sign in"));
+
+        let (step, _) = try_all_gated(YtClient::Web, false);
+        assert!(matches!(
+            step.error(&[], None),
+            Some(TydleError::AgeRestricted(_))
+        ));
+    }
+
+    #[test]
+    fn playable_fallback_is_not_an_error() {
+        let pr = hashmap! { "streamingData".to_string() => json!({}) };
+        assert!(AgeGateStep::Verified.error(&[pr], None).is_none());
+        assert!(AgeGateStep::NotGated.error(&[], None).is_none());
     }
 }
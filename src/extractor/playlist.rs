@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use serde_json::Value;
+
+use crate::{
+    error::TydleError,
+    extractor::{
+        api::ExtractorApiHandle,
+        auth::ExtractorAuthHandle,
+        download::ExtractorDownloadHandle,
+        extract::{InfoExtractor, YtExtractor},
+        json::ExtractorJsonHandle,
+    },
+    yt_interface::{PlaylistId, YtClient, YtEndpoint, YtPlaylist, YtPlaylistEntry},
+};
+
+/// Browse ID of the subscriptions feed of the signed in account.
+const SUBSCRIPTIONS_BROWSE_ID: &str = "FEsubscriptions";
+
+type EntriesExtractor<T> = fn(&T, &Value) -> (Vec<YtPlaylistEntry>, Option<String>);
+
+pub trait ExtractorPlaylistHandle {
+    async fn extract_playlist(&self, playlist_id: &PlaylistId) -> Result<YtPlaylist>;
+    /// The videos of the signed in account's subscriptions feed, newest first.
+    async fn extract_subscriptions_feed(&self) -> Result<YtPlaylist>;
+    /// API headers of the signed in session, including the delegated session of secondary
+    /// channels, taken from the ytcfg of the page at `page_path`.
+    async fn session_headers(&self, page_path: &str) -> Result<HashMap<&str, String>>;
+    async fn browse(
+        &self,
+        query: HashMap<String, Value>,
+        headers: Option<&HashMap<&str, String>>,
+    ) -> Result<Value>;
+    /// Append the entries of every page after the one `continuation` was found in.
+    async fn follow_continuations(
+        &self,
+        entries: &mut Vec<YtPlaylistEntry>,
+        continuation: Option<String>,
+        headers: Option<&HashMap<&str, String>>,
+        name: &str,
+        extract_entries: EntriesExtractor<Self>,
+    ) -> Result<()>;
+    fn extract_playlist_entries(&self, data: &Value) -> (Vec<YtPlaylistEntry>, Option<String>);
+    fn extract_feed_entries(&self, data: &Value) -> (Vec<YtPlaylistEntry>, Option<String>);
+}
+
+impl ExtractorPlaylistHandle for YtExtractor {
+    async fn extract_playlist(&self, playlist_id: &PlaylistId) -> Result<YtPlaylist> {
+        let authenticated = self.has_auth_cookies()?;
+        if playlist_id.is_personal() && !authenticated {
+            return Err(TydleError::LoginRequired(format!(
+                "The {} playlist needs authentication cookies.",
+                playlist_id.as_str()
+            ))
+            .into());
+        }
+
+        // Private playlists may belong to a secondary channel of the account.
+        let headers = if authenticated {
+            let page_path = format!("playlist?list={}", playlist_id.as_str());
+            Some(self.session_headers(&page_path).await?)
+        } else {
+            None
+        };
+
+        let mut query: HashMap<String, Value> = HashMap::new();
+        query.insert(
+            "browseId".into(),
+            format!("VL{}", playlist_id.as_str()).into(),
+        );
+
+        let data = self.browse(query, headers.as_ref()).await?;
+
+        if let Some(alert) = find_renderers(&data, "alertRenderer")
+            .into_iter()
+            .find(|alert| alert.get("type").and_then(|t| t.as_str()) == Some("ERROR"))
+        {
+            let reason = self
+                .get_text(alert, Some(vec![vec!["text"]]), None)
+                .unwrap_or("The playlist does not exist.".into());
+            if !authenticated && reason.to_lowercase().contains("private") {
+                return Err(TydleError::LoginRequired(reason).into());
+            }
+            bail!("{}", reason);
+        }
+
+        let title = data
+            .get("metadata")
+            .and_then(|m| m.get("playlistMetadataRenderer"))
+            .and_then(|m| m.get("title"))
+            .and_then(|t| t.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        let (mut entries, continuation) = self.extract_playlist_entries(&data);
+        self.follow_continuations(
+            &mut entries,
+            continuation,
+            headers.as_ref(),
+            &format!("playlist {}", playlist_id.as_str()),
+            Self::extract_playlist_entries,
+        )
+        .await?;
+
+        Ok(YtPlaylist {
+            id: playlist_id.as_str().to_string(),
+            title,
+            entries,
+        })
+    }
+
+    async fn extract_subscriptions_feed(&self) -> Result<YtPlaylist> {
+        if !self.has_auth_cookies()? {
+            return Err(TydleError::LoginRequired(
+                "The subscriptions feed needs authentication cookies.".into(),
+            )
+            .into());
+        }
+
+        let headers = self.session_headers("feed/subscriptions").await?;
+
+        let mut query: HashMap<String, Value> = HashMap::new();
+        query.insert("browseId".into(), SUBSCRIPTIONS_BROWSE_ID.into());
+        let data = self.browse(query, Some(&headers)).await?;
+
+        let (mut entries, continuation) = self.extract_feed_entries(&data);
+        self.follow_continuations(
+            &mut entries,
+            continuation,
+            Some(&headers),
+            "the subscriptions feed",
+            Self::extract_feed_entries,
+        )
+        .await?;
+
+        Ok(YtPlaylist {
+            id: SUBSCRIPTIONS_BROWSE_ID.into(),
+            title: "Subscriptions".into(),
+            entries,
+        })
+    }
+
+    async fn session_headers(&self, page_path: &str) -> Result<HashMap<&str, String>> {
+        let page_url = format!("{}://www.youtube.com/{}", self.http_scheme(), page_path);
+        let page = String::from_utf8(self.download_resource(&page_url).await?)?;
+
+        let ytcfg = self.extract_ytcfg(page)?;
+        self.check_logged_in(&ytcfg)?;
+
+        self.generate_api_headers(ytcfg, None, None, None, None, Some(&YtClient::Web))
+    }
+
+    async fn browse(
+        &self,
+        query: HashMap<String, Value>,
+        headers: Option<&HashMap<&str, String>>,
+    ) -> Result<Value> {
+        Ok(self
+            .call_api(
+                YtEndpoint::Browse,
+                query,
+                headers.cloned(),
+                None,
+                None,
+                Some(&YtClient::Web),
+            )
+            .await?
+            .into_iter()
+            .collect::<serde_json::Map<_, _>>()
+            .into())
+    }
+
+    async fn follow_continuations(
+        &self,
+        entries: &mut Vec<YtPlaylistEntry>,
+        mut continuation: Option<String>,
+        headers: Option<&HashMap<&str, String>>,
+        name: &str,
+        extract_entries: EntriesExtractor<Self>,
+    ) -> Result<()> {
+        let mut page = 1;
+
+        while let Some(token) = continuation {
+            page += 1;
+            tracing::info!("Downloading {} page {}", name, page);
+
+            let mut query: HashMap<String, Value> = HashMap::new();
+            query.insert("continuation".into(), token.into());
+            let response = self.browse(query, headers).await?;
+
+            let (page_entries, next) = extract_entries(self, &response);
+            if page_entries.is_empty() {
+                break;
+            }
+
+            entries.extend(page_entries);
+            continuation = next;
+        }
+
+        Ok(())
+    }
+
+    fn extract_playlist_entries(&self, data: &Value) -> (Vec<YtPlaylistEntry>, Option<String>) {
+        let entries = find_renderers(data, "playlistVideoRenderer")
+            .into_iter()
+            // Deleted and private videos are listed without being playable.
+            .filter(|renderer| {
+                renderer
+                    .get("isPlayable")
+                    .and_then(|p| p.as_bool())
+                    .unwrap_or(true)
+            })
+            .filter_map(|renderer| {
+                Some(YtPlaylistEntry {
+                    video_id: renderer.get("videoId")?.as_str()?.to_string(),
+                    title: self
+                        .get_text(renderer, Some(vec![vec!["title"]]), None)
+                        .unwrap_or_default(),
+                    duration: renderer
+                        .get("lengthSeconds")
+                        .and_then(|l| l.as_str())
+                        .and_then(|l| l.parse().ok()),
+                })
+            })
+            .collect();
+
+        (entries, find_continuation(data))
+    }
+
+    fn extract_feed_entries(&self, data: &Value) -> (Vec<YtPlaylistEntry>, Option<String>) {
+        let videos = find_renderers(data, "videoRenderer")
+            .into_iter()
+            .filter_map(|renderer| {
+                Some(YtPlaylistEntry {
+                    video_id: renderer.get("videoId")?.as_str()?.to_string(),
+                    title: self
+                        .get_text(renderer, Some(vec![vec!["title"]]), None)
+                        .unwrap_or_default(),
+                    duration: self
+                        .get_text(renderer, Some(vec![vec!["lengthText"]]), None)
+                        .and_then(|length| parse_length_text(&length)),
+                })
+            });
+        // Newer layouts list videos as lockups, which only carry the duration in a badge.
+        let lockups = find_renderers(data, "lockupViewModel")
+            .into_iter()
+            .filter(|lockup| {
+                lockup.get("contentType").and_then(|t| t.as_str())
+                    == Some("LOCKUP_CONTENT_TYPE_VIDEO")
+            })
+            .filter_map(|lockup| {
+                Some(YtPlaylistEntry {
+                    video_id: lockup.get("contentId")?.as_str()?.to_string(),
+                    title: lockup
+                        .pointer("/metadata/lockupMetadataViewModel/title/content")
+                        .and_then(|t| t.as_str())
+                        .unwrap_or_default()
+                        .to_string(),
+                    duration: find_renderers(lockup, "thumbnailBadgeViewModel")
+                        .into_iter()
+                        .filter_map(|badge| badge.get("text")?.as_str())
+                        .find_map(parse_length_text),
+                })
+            });
+
+        (videos.chain(lockups).collect(), find_continuation(data))
+    }
+}
+
+fn find_continuation(data: &Value) -> Option<String> {
+    find_renderers(data, "continuationItemRenderer")
+        .into_iter()
+        .find_map(|renderer| {
+            renderer
+                .get("continuationEndpoint")?
+                .get("continuationCommand")?
+                .get("token")?
+                .as_str()
+                .map(str::to_string)
+        })
+}
+
+/// Seconds of a duration displayed like `1:02:03`.
+fn parse_length_text(text: &str) -> Option<u64> {
+    text.trim().split(':').try_fold(0, |seconds, part| {
+        Some(seconds * 60 + part.parse::<u64>().ok()?)
+    })
+}
+
+/// Collect every object stored under the key `name` anywhere in `value`.
+fn find_renderers<'a>(value: &'a Value, name: &str) -> Vec<&'a Value> {
+    let mut found = Vec::new();
+    let mut stack = vec![value];
+
+    while let Some(value) = stack.pop() {
+        match value {
+            Value::Object(map) => {
+                for (k, v) in map.iter().rev() {
+                    if k == name {
+                        found.push(v);
+                    } else {
+                        stack.push(v);
+                    }
+                }
+            }
+            Value::Array(arr) => stack.extend(arr.iter().rev()),
+            _ => {}
+        }
+    }
+
+    found
+}
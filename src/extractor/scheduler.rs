@@ -0,0 +1,145 @@
+//! Rate limiting of the requests tydle sends, so that crawls of many videos stay under the limits
+//! YouTube bans addresses for.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Result;
+
+use crate::http::{BoxFuture, HttpRequest, HttpResponse, HttpTransport};
+
+/// Times a request answered with 429 Too Many Requests is sent again.
+const RATE_LIMITED_RETRIES: u32 = 3;
+/// Pause after a 429 response without a usable `Retry-After`.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Requests a host can take at once before they are spread over the minute.
+#[derive(Debug)]
+struct TokenBucket {
+    /// Negative when requests are queued for tokens that aren't there yet.
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// Caps the requests sent to each host with a token bucket refilled `requests_per_minute` times a
+/// minute, queueing the ones over it. A 429 response pauses every request, whatever its host, for
+/// as long as its `Retry-After` asks.
+pub struct RequestScheduler {
+    transport: Arc<dyn HttpTransport>,
+    /// Tokens added to every bucket per second.
+    rate: f64,
+    /// Tokens a bucket holds at most, which is the largest burst sent without waiting.
+    capacity: f64,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+    paused_until: Mutex<Option<Instant>>,
+}
+
+impl RequestScheduler {
+    pub fn new(transport: Arc<dyn HttpTransport>, requests_per_minute: u32) -> Self {
+        let requests_per_minute = requests_per_minute.max(1) as f64;
+
+        Self {
+            transport,
+            rate: requests_per_minute / 60.0,
+            // Bursts of up to a tenth of the minute's requests.
+            capacity: (requests_per_minute / 10.0).max(1.0),
+            buckets: Mutex::new(HashMap::new()),
+            paused_until: Mutex::new(None),
+        }
+    }
+
+    /// Wait for a global pause to end and for a token of `host` to be available.
+    async fn acquire(&self, host: &str) {
+        loop {
+            let paused_until = *self.paused_until.lock().unwrap();
+            match paused_until.filter(|until| *until > Instant::now()) {
+                Some(until) => tokio::time::sleep_until(until.into()).await,
+                None => break,
+            }
+        }
+
+        let wait = {
+            let mut buckets = self.buckets.lock().unwrap();
+            let now = Instant::now();
+            let bucket = buckets.entry(host.to_string()).or_insert(TokenBucket {
+                tokens: self.capacity,
+                refilled: now,
+            });
+
+            let refill = now.duration_since(bucket.refilled).as_secs_f64() * self.rate;
+            bucket.tokens = (bucket.tokens + refill).min(self.capacity);
+            bucket.refilled = now;
+            // Take the token before sleeping so later requests queue up behind this one.
+            bucket.tokens -= 1.0;
+
+            Duration::from_secs_f64((-bucket.tokens).max(0.0) / self.rate)
+        };
+
+        if !wait.is_zero() {
+            tracing::debug!(
+                "Waiting {:.2}s for the request rate limit of {}",
+                wait.as_secs_f64(),
+                host
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Hold every request back for `retry_after`, unless a longer pause is already in effect.
+    fn pause(&self, retry_after: Duration) {
+        let until = Instant::now() + retry_after;
+        let mut paused_until = self.paused_until.lock().unwrap();
+
+        if paused_until.is_none_or(|paused_until| paused_until < until) {
+            *paused_until = Some(until);
+        }
+    }
+}
+
+impl HttpTransport for RequestScheduler {
+    fn send<'a>(&'a self, request: HttpRequest) -> BoxFuture<'a, Result<HttpResponse>> {
+        Box::pin(async move {
+            let host = request.url.host_str().unwrap_or_default().to_string();
+            let mut attempt = 0;
+
+            loop {
+                self.acquire(&host).await;
+                let response = self.transport.send(request.clone()).await?;
+
+                if response.status != 429 || attempt >= RATE_LIMITED_RETRIES {
+                    return Ok(response);
+                }
+
+                let retry_after = response
+                    .header("Retry-After")
+                    .and_then(parse_retry_after)
+                    .unwrap_or(DEFAULT_RETRY_AFTER);
+                attempt += 1;
+                tracing::warn!(
+                    "{} is rate limited, pausing every request for {}s ({}/{}).",
+                    host,
+                    retry_after.as_secs(),
+                    attempt,
+                    RATE_LIMITED_RETRIES
+                );
+                self.pause(retry_after);
+            }
+        })
+    }
+}
+
+/// The delay of a `Retry-After` header, given either in seconds or as an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(secs) = value.trim().parse() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
@@ -1,14 +1,9 @@
-use std::{collections::HashMap, hash::Hash};
+use std::collections::HashMap;
 
 use once_cell::sync::Lazy;
 use serde::Serialize;
 
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Serialize)]
-pub enum StreamingProtocol {
-    Https,
-    Dash,
-    Hls,
-}
+use crate::yt_interface::StreamingProtocol;
 
 #[derive(Debug, Clone, Copy, Serialize)]
 pub struct GvsPoTokenPolicy {
@@ -14,7 +14,7 @@ use crate::{
 
 pub trait ExtractorYtCfgHandle {
     fn select_api_hostname(&self, default_client: Option<&YtClient>) -> &str;
-    fn select_client_version(&self, default_client: Option<&YtClient>) -> &str;
+    fn select_client_version(&self, default_client: Option<&YtClient>) -> String;
     fn select_context(
         &self,
         ytcfg: Option<&HashMap<String, Value>>,
@@ -22,6 +22,8 @@ pub trait ExtractorYtCfgHandle {
     ) -> Result<HashMap<String, Value>>;
     fn select_visitor_data(&self, ytcfgs: &[&HashMap<String, Value>]) -> Option<String>;
     fn select_default_ytcfg(&self, default_client: Option<&YtClient>) -> Result<InnerTubeClient>;
+    /// The definition of `client`, with the `client_overrides` of the options.
+    fn innertube_client(&self, client: &YtClient) -> InnerTubeClient;
 }
 
 impl ExtractorYtCfgHandle for YtExtractor {
@@ -31,16 +33,16 @@ impl ExtractorYtCfgHandle for YtExtractor {
         return innertube_client.innertube_host;
     }
 
-    fn select_client_version(&self, default_client: Option<&YtClient>) -> &str {
+    fn select_client_version(&self, default_client: Option<&YtClient>) -> String {
         let client = default_client.unwrap_or(&self.tydle_options.default_client);
-        let innertube_client = INNERTUBE_CLIENTS.get(client).unwrap();
+        let innertube_client = self.innertube_client(client);
 
         let innertube_client_context = innertube_client.innertube_context.get("client").unwrap();
         innertube_client_context
             .get("clientVersion")
-            .unwrap()
-            .as_str()
-            .unwrap()
+            .and_then(|version| version.as_str())
+            .unwrap_or_default()
+            .to_string()
     }
 
     fn select_context(
@@ -55,16 +57,10 @@ impl ExtractorYtCfgHandle for YtExtractor {
                 if !cfg.is_empty() {
                     cfg
                 } else {
-                    &INNERTUBE_CLIENTS
-                        .get(client)
-                        .unwrap()
-                        .to_json_val_hashmap()?
+                    &self.innertube_client(client).to_json_val_hashmap()?
                 }
             }
-            None => &INNERTUBE_CLIENTS
-                .get(client)
-                .unwrap()
-                .to_json_val_hashmap()?,
+            None => &self.innertube_client(client).to_json_val_hashmap()?,
         };
 
         let mut client_context = innertube_client
@@ -118,17 +114,25 @@ impl ExtractorYtCfgHandle for YtExtractor {
 
     fn select_default_ytcfg(&self, default_client: Option<&YtClient>) -> Result<InnerTubeClient> {
         let client = default_client.unwrap_or(&self.tydle_options.default_client);
-        let mut ytcfg = INNERTUBE_CLIENTS.get(client).cloned().unwrap();
+        let mut ytcfg = self.innertube_client(client);
 
         if let (Some(auth_ua), true) = (&ytcfg.authenticated_user_agent, self.is_authenticated()?) {
-            let innertube_client_context = ytcfg
-                .innertube_context
-                .entry("client")
-                .or_insert_with(HashMap::new);
+            let innertube_client_context = ytcfg.innertube_context.entry("client").or_default();
 
             innertube_client_context.insert("userAgent", (*auth_ua).into());
         }
 
         Ok(ytcfg)
     }
+
+    fn innertube_client(&self, client: &YtClient) -> InnerTubeClient {
+        #[cfg_attr(target_arch = "wasm32", allow(unused_mut))]
+        let mut innertube_client = INNERTUBE_CLIENTS.get(client).cloned().unwrap();
+        #[cfg(not(target_arch = "wasm32"))]
+        self.tydle_options
+            .client_overrides
+            .apply(client, &mut innertube_client);
+
+        innertube_client
+    }
 }
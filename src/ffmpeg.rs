@@ -0,0 +1,50 @@
+use anyhow::{Result, bail};
+use tokio::process::Command;
+
+/// Run ffmpeg with `args`, failing with its error output if it exits unsuccessfully.
+pub async fn run_ffmpeg(args: &[&str]) -> Result<()> {
+    let output = Command::new("ffmpeg")
+        .arg("-loglevel")
+        .arg("error")
+        .args(args)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        bail!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Number of video streams of `media`, as counted by ffprobe.
+pub async fn count_video_streams(media: &str) -> Result<usize> {
+    let output = Command::new("ffprobe")
+        .args(["-loglevel", "error", "-select_streams", "v"])
+        .args(["-show_entries", "stream=index", "-of", "csv=p=0", media])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        bail!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .count())
+}
+
+/// Merge the video of `video` and the audio of `audio` into `output` without re-encoding them.
+pub async fn merge_streams(video: &str, audio: &str, output: &str) -> Result<()> {
+    run_ffmpeg(&[
+        "-y", "-i", video, "-i", audio, "-map", "0:v:0", "-map", "1:a:0", "-c", "copy", output,
+    ])
+    .await
+}
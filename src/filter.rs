@@ -0,0 +1,289 @@
+use std::{str::FromStr, sync::Arc};
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::yt_interface::{YtAgeLimit, YtMediaType, YtVideoInfo};
+
+/// Hook deciding whether an extracted item should be processed further.
+pub type Predicate<T> = Arc<dyn Fn(&T) -> bool + Send + Sync>;
+
+/// Filter on video metadata like `duration<3600 & view_count>1000`.
+///
+/// Conditions are separated by `&` and must all hold. Numeric fields (`duration`, `view_count`,
+/// `age_limit`) support `<`, `<=`, `>`, `>=`, `=` and `!=`; text fields (`title`, `description`,
+/// `channel`, `channel_id`, `media_type`, `availability`) support `=`, `!=`, `*=` (contains), `^=`
+/// (starts with) and `$=` (ends with). A bare field checks that it is set and non-zero, `!field` the opposite.
+/// Appending `?` to an operator also lets videos without the field through.
+///
+/// ```
+/// use tydle::filter::MatchFilter;
+///
+/// let filter: MatchFilter = "duration<3600 & title*=live".parse().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct MatchFilter {
+    conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Present {
+        field: String,
+        negate: bool,
+    },
+    Compare {
+        field: String,
+        op: Operator,
+        value: String,
+        allow_missing: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    Contains,
+    StartsWith,
+    EndsWith,
+}
+
+enum FieldValue {
+    Number(f64),
+    Text(String),
+}
+
+impl MatchFilter {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let conditions = split_conditions(expr)
+            .into_iter()
+            .map(str::trim)
+            .filter(|condition| !condition.is_empty())
+            .map(Condition::parse)
+            .collect::<Result<Vec<_>>>()?;
+
+        if conditions.is_empty() {
+            bail!("The match filter \"{}\" is empty.", expr);
+        }
+
+        Ok(Self { conditions })
+    }
+
+    /// Whether `video_info` passes every condition of the filter.
+    pub fn matches(&self, video_info: &YtVideoInfo) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(video_info))
+    }
+
+    pub fn into_predicate(self) -> Predicate<YtVideoInfo> {
+        Arc::new(move |video_info| self.matches(video_info))
+    }
+}
+
+/// `expr` split at the `&` outside of quoted values, so that `title*='Q&A'` stays one condition.
+fn split_conditions(expr: &str) -> Vec<&str> {
+    let mut conditions = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+
+    for (i, c) in expr.char_indices() {
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            ('&', None) => {
+                conditions.push(&expr[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    conditions.push(&expr[start..]);
+
+    conditions
+}
+
+impl FromStr for MatchFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl Condition {
+    fn parse(condition: &str) -> Result<Self> {
+        const OPERATORS: &[(&str, Operator)] = &[
+            ("<=", Operator::Le),
+            (">=", Operator::Ge),
+            ("!=", Operator::Ne),
+            ("*=", Operator::Contains),
+            ("^=", Operator::StartsWith),
+            ("$=", Operator::EndsWith),
+            ("<", Operator::Lt),
+            (">", Operator::Gt),
+            ("=", Operator::Eq),
+        ];
+
+        // The first operator wins, the longer one at the same position so that `<=` isn't read as `<`.
+        let found = OPERATORS
+            .iter()
+            .filter_map(|(token, op)| condition.find(token).map(|pos| (pos, *token, *op)))
+            .min_by_key(|(pos, token, _)| (*pos, usize::MAX - token.len()));
+
+        let Some((pos, token, op)) = found else {
+            let (field, negate) = match condition.strip_prefix('!') {
+                Some(field) => (field.trim(), true),
+                None => (condition, false),
+            };
+            check_field(field)?;
+
+            return Ok(Condition::Present {
+                field: field.to_string(),
+                negate,
+            });
+        };
+
+        let field = condition[..pos].trim();
+        let rest = &condition[pos + token.len()..];
+        let (value, allow_missing) = match rest.strip_prefix('?') {
+            Some(value) => (value, true),
+            None => (rest, false),
+        };
+        let value = value.trim().trim_matches(|c| c == '\'' || c == '"');
+        check_field(field)?;
+
+        if is_numeric(field)
+            && matches!(
+                op,
+                Operator::Contains | Operator::StartsWith | Operator::EndsWith
+            )
+        {
+            bail!(
+                "\"{}\" can't be used on the numeric field {}.",
+                token,
+                field
+            );
+        }
+        if !is_numeric(field)
+            && matches!(
+                op,
+                Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge
+            )
+        {
+            bail!("\"{}\" can't be used on the text field {}.", token, field);
+        }
+        if is_numeric(field) {
+            parse_number(value)?;
+        }
+
+        Ok(Condition::Compare {
+            field: field.to_string(),
+            op,
+            value: value.to_string(),
+            allow_missing,
+        })
+    }
+
+    fn matches(&self, video_info: &YtVideoInfo) -> bool {
+        match self {
+            Condition::Present { field, negate } => {
+                let present = match field_value(video_info, field) {
+                    Some(FieldValue::Number(n)) => n != 0.0,
+                    Some(FieldValue::Text(s)) => !s.is_empty(),
+                    None => false,
+                };
+                present != *negate
+            }
+            Condition::Compare {
+                field,
+                op,
+                value,
+                allow_missing,
+            } => match field_value(video_info, field) {
+                None => *allow_missing,
+                Some(FieldValue::Number(actual)) => {
+                    let Ok(expected) = parse_number(value) else {
+                        return false;
+                    };
+                    match op {
+                        Operator::Lt => actual < expected,
+                        Operator::Le => actual <= expected,
+                        Operator::Gt => actual > expected,
+                        Operator::Ge => actual >= expected,
+                        Operator::Eq => actual == expected,
+                        Operator::Ne => actual != expected,
+                        _ => false,
+                    }
+                }
+                Some(FieldValue::Text(actual)) => {
+                    let actual = actual.to_lowercase();
+                    let expected = value.to_lowercase();
+                    match op {
+                        Operator::Eq => actual == expected,
+                        Operator::Ne => actual != expected,
+                        Operator::Contains => actual.contains(&expected),
+                        Operator::StartsWith => actual.starts_with(&expected),
+                        Operator::EndsWith => actual.ends_with(&expected),
+                        _ => false,
+                    }
+                }
+            },
+        }
+    }
+}
+
+fn is_numeric(field: &str) -> bool {
+    matches!(field, "duration" | "view_count" | "age_limit")
+}
+
+fn check_field(field: &str) -> Result<()> {
+    match field {
+        "duration" | "view_count" | "age_limit" | "title" | "description" | "channel"
+        | "channel_id" | "media_type" | "availability" => Ok(()),
+        _ => bail!("Unknown match filter field \"{}\".", field),
+    }
+}
+
+fn field_value(video_info: &YtVideoInfo, field: &str) -> Option<FieldValue> {
+    Some(match field {
+        "duration" => FieldValue::Number(video_info.duration as f64),
+        "view_count" => FieldValue::Number(video_info.view_count as f64),
+        "age_limit" => FieldValue::Number(match video_info.age_limit {
+            YtAgeLimit::Adult => 18.0,
+            YtAgeLimit::None => 0.0,
+        }),
+        "title" => FieldValue::Text(video_info.title.clone()),
+        "description" => FieldValue::Text(video_info.description.clone()),
+        "channel" => FieldValue::Text(video_info.channel.get_name()?.to_string()),
+        "channel_id" => FieldValue::Text(video_info.channel.get_id().to_string()),
+        "media_type" => FieldValue::Text(
+            match video_info.media_type {
+                YtMediaType::LiveStream => "live",
+                YtMediaType::Short => "short",
+                YtMediaType::Video => "video",
+            }
+            .to_string(),
+        ),
+        "availability" => FieldValue::Text(video_info.availability.as_str().to_string()),
+        _ => return None,
+    })
+}
+
+/// Parse a number with an optional `k`, `m` or `b` suffix, like `1.5m`.
+fn parse_number(value: &str) -> Result<f64> {
+    let invalid = || anyhow!("Invalid number \"{}\" in match filter.", value);
+    let lower = value.to_lowercase();
+    let (number, multiplier) = match lower.chars().last() {
+        Some('k') => (&lower[..lower.len() - 1], 1e3),
+        Some('m') => (&lower[..lower.len() - 1], 1e6),
+        Some('b') => (&lower[..lower.len() - 1], 1e9),
+        _ => (lower.as_str(), 1.0),
+    };
+
+    Ok(number.trim().parse::<f64>().map_err(|_| invalid())? * multiplier)
+}
@@ -0,0 +1,243 @@
+//! Recording the responses of YouTube to disk and replaying them, so that extraction can be tested
+//! without the network.
+//!
+//! Record with `TydleOptions::record_fixtures_dir`, then extract the same videos with a
+//! `ReplayTransport` of that directory as `TydleOptions::transport` and a `MemoryCache`, so that
+//! nothing cached by an earlier run skips a request.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Result, anyhow};
+use fancy_regex::Regex;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+use url::Url;
+
+use crate::http::{BoxFuture, HttpBody, HttpRequest, HttpResponse, HttpTransport};
+
+/// Query parameters whose values identify the user or the session.
+const REDACTED_PARAMS: &[&str] = &["ip", "ipbits", "ei", "pot", "sig", "lsig", "cpn"];
+/// Response headers kept in fixtures, the others are either irrelevant or identify the user.
+const KEPT_HEADERS: &[&str] = &["content-type", "etag", "last-modified", "location"];
+const REDACTED: &str = "REDACTED";
+
+/// String values of these keys are replaced in the JSON and HTML of responses.
+static REDACTED_JSON_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#""(visitorData|VISITOR_DATA|DATASYNC_ID|DELEGATED_SESSION_ID|USER_SESSION_ID|ID_TOKEN|SESSION_INDEX|serializedShareEntity|remoteHost)"\s*:\s*"(?:[^"\\]|\\.)*""#,
+    )
+    .unwrap()
+});
+/// Addresses the stream URLs are bound to, in plain and percent-encoded URLs.
+static IP_PARAM_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"([?&/]ip(?:=|%3D|/))[0-9a-fA-F.:%]+").unwrap());
+
+/// A response, as written to `{name}-{fingerprint}.json` in the fixture directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fixture {
+    pub method: String,
+    pub url: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// The body, when it's text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// The body, when it isn't text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_bytes: Option<Vec<u8>>,
+}
+
+/// Sends requests through another transport and writes the sanitized response of each to a
+/// directory, for `ReplayTransport` to answer with. The responses are passed on as they are.
+pub struct RecordingTransport {
+    transport: Arc<dyn HttpTransport>,
+    dir: PathBuf,
+}
+
+impl RecordingTransport {
+    pub fn new<P: Into<PathBuf>>(transport: Arc<dyn HttpTransport>, dir: P) -> Self {
+        Self {
+            transport,
+            dir: dir.into(),
+        }
+    }
+}
+
+impl HttpTransport for RecordingTransport {
+    fn send<'a>(&'a self, request: HttpRequest) -> BoxFuture<'a, Result<HttpResponse>> {
+        Box::pin(async move {
+            let path = fixture_path(&self.dir, &request);
+            let method = request.method;
+            let response = self.transport.send(request).await?;
+
+            let status = response.status;
+            let url = response.url.clone();
+            let headers = response.headers.clone();
+            let bytes = response.bytes().await?;
+
+            let (body, body_bytes) = match std::str::from_utf8(&bytes) {
+                Ok(text) => (Some(sanitize_body(text)), None),
+                Err(_) => (None, Some(bytes.clone())),
+            };
+            let fixture = Fixture {
+                method: method.as_str().to_string(),
+                url: sanitize_url(&url),
+                status,
+                headers: headers
+                    .iter()
+                    .filter(|(name, _)| KEPT_HEADERS.contains(&name.to_lowercase().as_str()))
+                    .cloned()
+                    .collect(),
+                body,
+                body_bytes,
+            };
+
+            std::fs::create_dir_all(&self.dir)?;
+            std::fs::write(&path, serde_json::to_string_pretty(&fixture)?)?;
+            tracing::debug!("Recorded {} {} to {}", method.as_str(), url, path.display());
+
+            Ok(HttpResponse {
+                status,
+                headers,
+                body: Box::new(FixtureBody(Some(bytes))),
+                url,
+            })
+        })
+    }
+}
+
+/// Answers requests with the responses a `RecordingTransport` wrote to a directory, failing the
+/// ones that weren't recorded.
+pub struct ReplayTransport {
+    dir: PathBuf,
+}
+
+impl ReplayTransport {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The fixture recorded for `request`, if any.
+    pub fn fixture(&self, request: &HttpRequest) -> Result<Option<Fixture>> {
+        let path = fixture_path(&self.dir, request);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::from_str(&std::fs::read_to_string(path)?)?))
+    }
+}
+
+impl HttpTransport for ReplayTransport {
+    fn send<'a>(&'a self, request: HttpRequest) -> BoxFuture<'a, Result<HttpResponse>> {
+        Box::pin(async move {
+            let fixture = self.fixture(&request)?.ok_or(anyhow!(
+                "No fixture of {} {} in {}",
+                request.method.as_str(),
+                sanitize_url(&request.url),
+                self.dir.display()
+            ))?;
+
+            Ok(HttpResponse {
+                status: fixture.status,
+                headers: fixture.headers,
+                body: Box::new(FixtureBody(
+                    fixture.body.map(String::into_bytes).or(fixture.body_bytes),
+                )),
+                url: request.url,
+            })
+        })
+    }
+}
+
+struct FixtureBody(Option<Vec<u8>>);
+
+impl HttpBody for FixtureBody {
+    fn chunk(&mut self) -> BoxFuture<'_, Result<Option<Vec<u8>>>> {
+        Box::pin(async move { Ok(self.0.take()) })
+    }
+}
+
+/// `url` with the values of `REDACTED_PARAMS` replaced.
+fn sanitize_url(url: &Url) -> String {
+    let mut url = url.clone();
+    if url.query().is_none() {
+        return url.to_string();
+    }
+
+    let pairs = url
+        .query_pairs()
+        .map(|(name, value)| {
+            let value = match REDACTED_PARAMS.contains(&name.as_ref()) {
+                true => REDACTED.to_string(),
+                false => value.into_owned(),
+            };
+            (name.into_owned(), value)
+        })
+        .collect::<Vec<_>>();
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+
+    url.to_string()
+}
+
+fn sanitize_body(body: &str) -> String {
+    let body = REDACTED_JSON_RE.replace_all(body, format!(r#""$1":"{}""#, REDACTED));
+    let body = IP_PARAM_RE.replace_all(&body, "${1}0.0.0.0");
+
+    body.into_owned()
+}
+
+/// What tells a request apart from the others of an extraction: its method, its sanitized URL and,
+/// for InnerTube requests, what the body asks for without the session and the PO token, which
+/// change from one run to the next.
+fn fingerprint(request: &HttpRequest) -> String {
+    let mut fingerprint = format!("{} {}", request.method.as_str(), sanitize_url(&request.url));
+
+    let body = request
+        .body
+        .as_deref()
+        .and_then(|body| serde_json::from_slice::<Value>(body).ok());
+    if let Some(Value::Object(mut body)) = body {
+        let client = body
+            .remove("context")
+            .and_then(|context| context["client"]["clientName"].as_str().map(str::to_string));
+        body.remove("serviceIntegrityDimensions");
+        body.remove("playbackContext");
+
+        fingerprint.push_str(&format!(
+            " {} {}",
+            client.unwrap_or_default(),
+            Value::Object(body)
+        ));
+    }
+
+    fingerprint
+}
+
+/// `{dir}/{method}-{host}{path}-{hash of the fingerprint}.json`, with the path made a valid file
+/// name.
+fn fixture_path(dir: &Path, request: &HttpRequest) -> PathBuf {
+    let hash = Sha1::digest(fingerprint(request).as_bytes());
+    let hash: String = hash.iter().take(6).map(|b| format!("{:02x}", b)).collect();
+    let name = format!(
+        "{}-{}{}",
+        request.method.as_str().to_lowercase(),
+        request.url.host_str().unwrap_or_default(),
+        request.url.path()
+    )
+    .chars()
+    .map(
+        |c| match c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+            true => c,
+            false => '_',
+        },
+    )
+    .collect::<String>();
+
+    dir.join(format!("{}-{}.json", name.trim_end_matches('_'), hash))
+}
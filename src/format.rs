@@ -1,21 +1,25 @@
 use anyhow::{Result, bail};
+use tydle::SizeEstimate;
 
-#[derive(Debug)]
-pub enum Format {
-    BestAudio,
-    BestVideo,
-    WorstAudio,
-    WorstVideo,
-}
+/// Parse a byte count such as `50K`, `4.2M` or `1G` into bytes.
+pub fn parse_bytes(bytes: &str) -> Result<u64> {
+    let bytes = bytes.trim();
+    let (number, multiplier) = match bytes.char_indices().last() {
+        Some((i, unit)) if unit.is_ascii_alphabetic() => (
+            &bytes[..i],
+            match unit.to_ascii_uppercase() {
+                'B' => 1u64,
+                'K' => 1024,
+                'M' => 1024 * 1024,
+                'G' => 1024 * 1024 * 1024,
+                _ => bail!("Invalid byte unit: {}", unit),
+            },
+        ),
+        _ => (bytes, 1),
+    };
 
-pub fn parse_format(format: &str) -> Result<Format> {
-    Ok(match format {
-        "bestaudio" => Format::BestAudio,
-        "bestvideo" => Format::BestVideo,
-        "worstaudio" => Format::WorstAudio,
-        "worstvideo" => Format::WorstVideo,
-        _ => bail!("Invalid format."),
-    })
+    let value: f64 = number.parse()?;
+    Ok((value * multiplier as f64) as u64)
 }
 
 pub fn compact_num(n: u64) -> String {
@@ -48,6 +52,14 @@ pub fn human_readable_size(bytes: u64) -> String {
     }
 }
 
+/// The size of `estimate`, prefixed with "~" when it's estimated from the bitrate.
+pub fn format_size_estimate(estimate: SizeEstimate) -> String {
+    match estimate.exact {
+        true => human_readable_size(estimate.bytes),
+        false => format!("~{}", human_readable_size(estimate.bytes)),
+    }
+}
+
 pub fn get_resolution(height: Option<u64>, width: Option<u64>) -> String {
     match (height, width) {
         (Some(h), Some(w)) => format!("{}x{}", h, w),
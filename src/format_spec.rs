@@ -0,0 +1,555 @@
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::yt_interface::{
+    Ext, Filterable, SizeEstimate, SortField, SortKey, YtStream, YtStreamList, YtStreams,
+};
+
+/// Which streams to download, like `bestvideo[height<=1080]+bestaudio/best`.
+///
+/// Alternatives separated by `/` are tried in order until one matches, and `a+b` merges the
+/// streams picked by `a` and `b`. A selector is one of `best` (`b`) and `worst` (`w`) for streams
+/// with both video and audio, `bestvideo` (`bv`), `worstvideo` (`wv`), `bestaudio` (`ba`),
+/// `worstaudio` (`wa`), an itag like `137` or an extension like `mp4`, followed by any number of
+/// `[field op value]` constraints. Numeric fields (`height`, `width`, `fps`, `tbr`, `asr`,
+/// `filesize`, `filesize_approx`, `itag`) support `<`, `<=`, `>`, `>=`, `=` and `!=`; text fields
+/// (`ext`, `vcodec`, `acodec`, `protocol`, `format_note`) support `=`, `!=`, `*=` (contains), `^=`
+/// (starts with) and `$=` (ends with). Appending `?` to an operator also lets streams without the field through.
+///
+/// ```
+/// use tydle::{FormatSpec, SortKey};
+///
+/// let spec: FormatSpec = "bestvideo[height<=?1080]+bestaudio/best".parse().unwrap();
+/// let spec = spec.sort_keys(SortKey::parse_list("vcodec:avc1").unwrap());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatSpec {
+    /// Each alternative holds one selector, or two to merge.
+    alternatives: Vec<Vec<Selector>>,
+    sort_keys: Vec<SortKey>,
+}
+
+/// Streams picked by a `FormatSpec`, either a single one or a video and an audio stream to
+/// download separately and merge into one file.
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "camelCase")
+)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Clone)]
+pub struct FormatSelection {
+    /// The stream to download, or the video one when `audio` is set.
+    pub stream: YtStream,
+    /// Audio stream to merge with `stream`.
+    pub audio: Option<YtStream>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Selector {
+    pick: Pick,
+    worst: bool,
+    constraints: Vec<Constraint>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Pick {
+    /// Streams with both video and audio.
+    Combined,
+    Video,
+    Audio,
+    Itag(u16),
+    Ext(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Constraint {
+    field: String,
+    op: Operator,
+    value: String,
+    allow_missing: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    Contains,
+    StartsWith,
+    EndsWith,
+}
+
+enum FieldValue {
+    Number(f64),
+    Text(String),
+}
+
+impl FormatSpec {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let alternatives = spec
+            .split('/')
+            .map(str::trim)
+            .filter(|alternative| !alternative.is_empty())
+            .map(|alternative| {
+                let selectors = alternative
+                    .split('+')
+                    .map(|selector| Selector::parse(selector.trim()))
+                    .collect::<Result<Vec<_>>>()?;
+
+                if selectors.len() > 2 {
+                    bail!("Only two formats can be merged, got \"{}\".", alternative);
+                }
+
+                Ok(selectors)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if alternatives.is_empty() {
+            bail!("The format \"{}\" is empty.", spec);
+        }
+
+        Ok(Self {
+            alternatives,
+            sort_keys: Vec::new(),
+        })
+    }
+
+    /// Rank the streams by `sort_keys` instead of only by container and bitrate.
+    pub fn sort_keys(mut self, sort_keys: Vec<SortKey>) -> Self {
+        self.sort_keys = sort_keys;
+        self
+    }
+
+    /// Whether the first alternative asks for the worst stream, for choosing a variant of a live
+    /// stream the same way.
+    pub fn prefers_worst(&self) -> bool {
+        self.alternatives[0][0].worst
+    }
+
+    /// Whether an alternative merges two streams, which have to be downloaded separately.
+    pub fn merges(&self) -> bool {
+        self.alternatives
+            .iter()
+            .any(|selectors| selectors.len() > 1)
+    }
+
+    fn select(&self, streams: &YtStreamList) -> Option<FormatSelection> {
+        self.alternatives.iter().find_map(|selectors| {
+            let mut picked = selectors
+                .iter()
+                .map(|selector| selector.select(streams, &self.sort_keys));
+
+            Some(FormatSelection {
+                stream: picked.next()??,
+                audio: match picked.next() {
+                    Some(audio) => Some(audio?),
+                    None => None,
+                },
+            })
+        })
+    }
+}
+
+impl FromStr for FormatSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl FormatSelection {
+    pub fn streams(&self) -> Vec<&YtStream> {
+        std::iter::once(&self.stream).chain(&self.audio).collect()
+    }
+
+    /// Itags of the streams, joined by `+` when they are merged.
+    pub fn format_id(&self) -> String {
+        self.streams()
+            .iter()
+            .map(|stream| stream.itag.to_string())
+            .collect::<Vec<_>>()
+            .join("+")
+    }
+
+    /// Container of the file, mkv for merged streams that don't fit into mp4 or webm.
+    pub fn ext(&self) -> Ext {
+        let Some(audio) = &self.audio else {
+            return self.stream.ext;
+        };
+
+        match (self.stream.ext, audio.ext) {
+            (Ext::Mp4, Ext::Mp4 | Ext::M4a) => Ext::Mp4,
+            (Ext::Webm, Ext::Webm) => Ext::Webm,
+            _ => Ext::Mkv,
+        }
+    }
+
+    /// Sum of the sizes of the streams, if every one is known.
+    pub fn file_size(&self) -> Option<u64> {
+        self.streams().iter().map(|stream| stream.file_size).sum()
+    }
+
+    /// Sum of the size estimates of the streams, exact only if every one is.
+    pub fn filesize_estimate(&self) -> Option<SizeEstimate> {
+        self.streams()
+            .iter()
+            .map(|stream| stream.filesize_estimate())
+            .reduce(|total, estimate| Some(total? + estimate?))?
+    }
+}
+
+impl YtStreamList {
+    /// Pick the streams `spec` asks for, or `None` when no alternative of it matches.
+    ///
+    /// ```
+    /// use tydle::{Tydle, TydleOptions, Extract, VideoId};
+    /// use anyhow::Result;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///   let ty = Tydle::new(TydleOptions { ..Default::default() })?;
+    ///   let streams = ty.get_streams(&VideoId::new("dQw4w9WgXcQ")?).await?.streams;
+    ///
+    ///   if let Some(selection) = streams.select(&"bestvideo+bestaudio/best".parse()?) {
+    ///     println!("Format: {}", selection.format_id());
+    ///   }
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    pub fn select(&self, spec: &FormatSpec) -> Option<FormatSelection> {
+        spec.select(self)
+    }
+}
+
+impl Selector {
+    fn parse(selector: &str) -> Result<Self> {
+        let (base, mut rest) = match selector.find('[') {
+            Some(pos) => (&selector[..pos], &selector[pos..]),
+            None => (selector, ""),
+        };
+
+        let (pick, worst) = match base {
+            "best" | "b" => (Pick::Combined, false),
+            "worst" | "w" => (Pick::Combined, true),
+            "bestvideo" | "bv" => (Pick::Video, false),
+            "worstvideo" | "wv" => (Pick::Video, true),
+            "bestaudio" | "ba" => (Pick::Audio, false),
+            "worstaudio" | "wa" => (Pick::Audio, true),
+            _ if !base.is_empty() && base.bytes().all(|b| b.is_ascii_digit()) => (
+                Pick::Itag(
+                    base.parse()
+                        .map_err(|_| anyhow!("Invalid itag \"{}\".", base))?,
+                ),
+                false,
+            ),
+            _ => match base.parse::<Ext>() {
+                Ok(ext) => (Pick::Ext(ext.as_str().to_string()), false),
+                Err(_) => bail!("Invalid format \"{}\".", base),
+            },
+        };
+
+        let mut constraints = Vec::new();
+        while let Some(inner) = rest.strip_prefix('[') {
+            let end = inner
+                .find(']')
+                .ok_or(anyhow!("Unclosed \"[\" in format \"{}\".", selector))?;
+            constraints.push(Constraint::parse(&inner[..end])?);
+            rest = &inner[end + 1..];
+        }
+        if !rest.is_empty() {
+            bail!("Unexpected \"{}\" in format \"{}\".", rest, selector);
+        }
+
+        Ok(Self {
+            pick,
+            worst,
+            constraints,
+        })
+    }
+
+    fn select(&self, streams: &YtStreamList, sort_keys: &[SortKey]) -> Option<YtStream> {
+        let candidates = match &self.pick {
+            Pick::Combined => streams.video_only().with_audio(),
+            Pick::Video => streams.video_only(),
+            Pick::Audio => streams.audio_only(),
+            Pick::Itag(itag) => streams.matching(|stream| stream.itag == *itag),
+            Pick::Ext(ext) => streams.matching(|stream| stream.ext.as_str() == ext),
+        }
+        .matching(|stream| {
+            self.constraints
+                .iter()
+                .all(|constraint| constraint.matches(stream))
+        });
+
+        // Without sort keys the best stream is the mp4 (or m4a) with the highest bitrate.
+        let mut best_keys = sort_keys.to_vec();
+        best_keys.push(SortKey {
+            field: SortField::Ext,
+            preferred: None,
+            reverse: false,
+        });
+
+        match self.worst {
+            false => candidates.sorted_by(&best_keys).first().cloned(),
+            true if sort_keys.is_empty() => candidates.with_lowest_bitrate().first().cloned(),
+            true => candidates.sorted_by(sort_keys).last().cloned(),
+        }
+    }
+}
+
+impl YtStreamList {
+    fn matching(&self, predicate: impl Fn(&YtStream) -> bool) -> YtStreamList {
+        YtStreamList::from(
+            self.iter()
+                .filter(|stream| predicate(stream))
+                .cloned()
+                .collect::<YtStreams>(),
+        )
+    }
+
+    fn with_audio(&self) -> YtStreamList {
+        self.matching(|stream| {
+            stream
+                .codec
+                .acodec
+                .as_deref()
+                .is_some_and(|acodec| acodec != "none")
+        })
+    }
+}
+
+impl Constraint {
+    fn parse(constraint: &str) -> Result<Self> {
+        const OPERATORS: &[(&str, Operator)] = &[
+            ("<=", Operator::Le),
+            (">=", Operator::Ge),
+            ("!=", Operator::Ne),
+            ("*=", Operator::Contains),
+            ("^=", Operator::StartsWith),
+            ("$=", Operator::EndsWith),
+            ("<", Operator::Lt),
+            (">", Operator::Gt),
+            ("=", Operator::Eq),
+        ];
+
+        // The first operator wins, the longer one at the same position so that `<=` isn't read as `<`.
+        let (pos, token, op) = OPERATORS
+            .iter()
+            .filter_map(|(token, op)| constraint.find(token).map(|pos| (pos, *token, *op)))
+            .min_by_key(|(pos, token, _)| (*pos, usize::MAX - token.len()))
+            .ok_or(anyhow!("Missing operator in \"[{}]\".", constraint))?;
+
+        let field = constraint[..pos].trim();
+        let rest = &constraint[pos + token.len()..];
+        let (value, allow_missing) = match rest.strip_prefix('?') {
+            Some(value) => (value, true),
+            None => (rest, false),
+        };
+        let value = value.trim().trim_matches(|c| c == '\'' || c == '"');
+
+        match field {
+            "height" | "width" | "fps" | "tbr" | "asr" | "filesize" | "filesize_approx"
+            | "itag" => {
+                if matches!(
+                    op,
+                    Operator::Contains | Operator::StartsWith | Operator::EndsWith
+                ) {
+                    bail!(
+                        "\"{}\" can't be used on the numeric field {}.",
+                        token,
+                        field
+                    );
+                }
+                parse_number(value)?;
+            }
+            "ext" | "vcodec" | "acodec" | "protocol" | "format_note" => {
+                if matches!(
+                    op,
+                    Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge
+                ) {
+                    bail!("\"{}\" can't be used on the text field {}.", token, field);
+                }
+            }
+            _ => bail!("Unknown format field \"{}\".", field),
+        }
+
+        Ok(Self {
+            field: field.to_string(),
+            op,
+            value: value.to_string(),
+            allow_missing,
+        })
+    }
+
+    fn matches(&self, stream: &YtStream) -> bool {
+        match field_value(stream, &self.field) {
+            None => self.allow_missing,
+            Some(FieldValue::Number(actual)) => {
+                let Ok(expected) = parse_number(&self.value) else {
+                    return false;
+                };
+                match self.op {
+                    Operator::Lt => actual < expected,
+                    Operator::Le => actual <= expected,
+                    Operator::Gt => actual > expected,
+                    Operator::Ge => actual >= expected,
+                    Operator::Eq => actual == expected,
+                    Operator::Ne => actual != expected,
+                    _ => false,
+                }
+            }
+            Some(FieldValue::Text(actual)) => {
+                let actual = actual.to_lowercase();
+                let expected = self.value.to_lowercase();
+                match self.op {
+                    Operator::Eq => actual == expected,
+                    Operator::Ne => actual != expected,
+                    Operator::Contains => actual.contains(&expected),
+                    Operator::StartsWith => actual.starts_with(&expected),
+                    Operator::EndsWith => actual.ends_with(&expected),
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+fn field_value(stream: &YtStream, field: &str) -> Option<FieldValue> {
+    let text = |value: Option<&str>| {
+        value
+            .filter(|value| *value != "none")
+            .map(|value| FieldValue::Text(value.to_string()))
+    };
+
+    match field {
+        "height" => stream
+            .height
+            .map(|height| FieldValue::Number(height as f64)),
+        "width" => stream.width.map(|width| FieldValue::Number(width as f64)),
+        "fps" => Some(stream.fps)
+            .filter(|fps| *fps > 0)
+            .map(|fps| FieldValue::Number(fps as f64)),
+        "tbr" => Some(FieldValue::Number(stream.tbr)),
+        "asr" => stream.asr.map(|asr| FieldValue::Number(asr as f64)),
+        "filesize" => stream.file_size.map(|size| FieldValue::Number(size as f64)),
+        "filesize_approx" => stream
+            .filesize_estimate()
+            .map(|estimate| FieldValue::Number(estimate.bytes as f64)),
+        "itag" => Some(FieldValue::Number(stream.itag as f64)),
+        "ext" => text(Some(stream.ext.as_str())),
+        "vcodec" => text(stream.codec.vcodec.as_deref()),
+        "acodec" => text(stream.codec.acodec.as_deref()),
+        "protocol" => text(Some(stream.protocol.as_str())),
+        "format_note" => text(Some(&stream.format_note)),
+        _ => None,
+    }
+}
+
+/// Parse a number with an optional `k`, `m` or `g` suffix, in powers of 1024 like file sizes.
+fn parse_number(value: &str) -> Result<f64> {
+    let invalid = || anyhow!("Invalid number \"{}\" in format.", value);
+    let lower = value.to_lowercase();
+    let (number, multiplier) = match lower.chars().last() {
+        Some('k') => (&lower[..lower.len() - 1], 1024.0),
+        Some('m') => (&lower[..lower.len() - 1], 1024.0 * 1024.0),
+        Some('g') => (&lower[..lower.len() - 1], 1024.0 * 1024.0 * 1024.0),
+        _ => (lower.as_str(), 1.0),
+    };
+
+    Ok(number.trim().parse::<f64>().map_err(|_| invalid())? * multiplier)
+}
+
+#[cfg(target_arch = "wasm32")]
+mod wasm_api {
+    use super::*;
+    use wasm_bindgen::{JsValue, prelude::wasm_bindgen};
+
+    /// Filters and format selection over a list of streams, like `YtStreamList` in Rust.
+    ///
+    /// ```ts
+    /// const { streams } = await tydle.fetchStreams("xITJ35Kwpv4");
+    /// const selection = new Streams(streams).select("bestaudio[ext=m4a]");
+    /// ```
+    #[wasm_bindgen(js_name = "Streams")]
+    pub struct JsStreams(YtStreamList);
+
+    #[wasm_bindgen(js_class = "Streams")]
+    impl JsStreams {
+        #[wasm_bindgen(constructor)]
+        pub fn new(streams: YtStreamList) -> JsStreams {
+            JsStreams(streams)
+        }
+
+        #[wasm_bindgen(js_name = "videoOnly")]
+        pub fn video_only(&self) -> JsStreams {
+            JsStreams(self.0.video_only())
+        }
+
+        #[wasm_bindgen(js_name = "audioOnly")]
+        pub fn audio_only(&self) -> JsStreams {
+            JsStreams(self.0.audio_only())
+        }
+
+        #[wasm_bindgen(js_name = "withLowestBitrate")]
+        pub fn with_lowest_bitrate(&self) -> JsStreams {
+            JsStreams(self.0.with_lowest_bitrate())
+        }
+
+        #[wasm_bindgen(js_name = "withHighestBitrate")]
+        pub fn with_highest_bitrate(&self) -> JsStreams {
+            JsStreams(self.0.with_highest_bitrate())
+        }
+
+        #[wasm_bindgen(js_name = "onlyUrls")]
+        pub fn only_urls(&self) -> JsStreams {
+            JsStreams(self.0.only_urls())
+        }
+
+        #[wasm_bindgen(js_name = "onlySignatures")]
+        pub fn only_signatures(&self) -> JsStreams {
+            JsStreams(self.0.only_signatures())
+        }
+
+        /// Sort by comma separated keys, like "res:1080,fps,codec:av01".
+        #[wasm_bindgen(js_name = "sortedBy")]
+        pub fn sorted_by(&self, keys: &str) -> Result<JsStreams, JsValue> {
+            let keys = SortKey::parse_list(keys).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+            Ok(JsStreams(self.0.sorted_by(&keys)))
+        }
+
+        /// Pick streams with a format expression like "bestvideo[height<=1080]+bestaudio/best",
+        /// ranked by the optional sort keys.
+        pub fn select(
+            &self,
+            spec: &str,
+            #[wasm_bindgen(js_name = "sortKeys")] sort_keys: Option<String>,
+        ) -> Result<Option<FormatSelection>, JsValue> {
+            let mut spec =
+                FormatSpec::parse(spec).map_err(|e| JsValue::from_str(&e.to_string()))?;
+            if let Some(keys) = sort_keys {
+                spec = spec.sort_keys(
+                    SortKey::parse_list(&keys).map_err(|e| JsValue::from_str(&e.to_string()))?,
+                );
+            }
+
+            Ok(self.0.select(&spec))
+        }
+
+        #[wasm_bindgen(js_name = "toArray")]
+        pub fn to_array(&self) -> YtStreamList {
+            YtStreamList::from(self.0.to_vec())
+        }
+    }
+}
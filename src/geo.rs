@@ -0,0 +1,105 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    net::Ipv4Addr,
+};
+
+use anyhow::{Result, anyhow};
+
+use crate::utils::unix_timestamp_secs;
+
+/// A major IPv4 allocation of each country, as ISO 3166-1 alpha-2 code, address and prefix length.
+const COUNTRY_IP_BLOCKS: &[(&str, [u8; 4], u32)] = &[
+    ("AD", [46, 172, 224, 0], 19),
+    ("AE", [94, 200, 0, 0], 13),
+    ("AF", [149, 54, 0, 0], 17),
+    ("AL", [46, 99, 0, 0], 16),
+    ("AM", [46, 70, 0, 0], 15),
+    ("AR", [181, 0, 0, 0], 12),
+    ("AT", [84, 112, 0, 0], 13),
+    ("AU", [1, 128, 0, 0], 11),
+    ("AZ", [5, 191, 0, 0], 16),
+    ("BA", [31, 176, 128, 0], 17),
+    ("BD", [114, 130, 0, 0], 16),
+    ("BE", [57, 0, 0, 0], 8),
+    ("BG", [95, 42, 0, 0], 15),
+    ("BR", [179, 128, 0, 0], 10),
+    ("BY", [178, 120, 0, 0], 13),
+    ("CA", [99, 224, 0, 0], 11),
+    ("CH", [85, 0, 0, 0], 13),
+    ("CL", [152, 172, 0, 0], 14),
+    ("CN", [36, 128, 0, 0], 10),
+    ("CO", [181, 240, 0, 0], 12),
+    ("CZ", [88, 100, 0, 0], 14),
+    ("DE", [53, 0, 0, 0], 8),
+    ("DK", [87, 48, 0, 0], 12),
+    ("EE", [90, 190, 0, 0], 15),
+    ("EG", [156, 160, 0, 0], 11),
+    ("ES", [88, 0, 0, 0], 11),
+    ("FI", [91, 152, 0, 0], 13),
+    ("FR", [90, 0, 0, 0], 9),
+    ("GB", [25, 0, 0, 0], 8),
+    ("GR", [94, 64, 0, 0], 13),
+    ("HK", [219, 76, 0, 0], 14),
+    ("HR", [93, 136, 0, 0], 13),
+    ("HU", [84, 0, 0, 0], 14),
+    ("ID", [39, 192, 0, 0], 10),
+    ("IE", [87, 32, 0, 0], 12),
+    ("IL", [79, 176, 0, 0], 13),
+    ("IN", [117, 192, 0, 0], 10),
+    ("IQ", [37, 236, 0, 0], 14),
+    ("IR", [2, 176, 0, 0], 12),
+    ("IS", [82, 221, 0, 0], 16),
+    ("IT", [79, 0, 0, 0], 10),
+    ("JP", [133, 0, 0, 0], 8),
+    ("KR", [175, 192, 0, 0], 10),
+    ("KZ", [2, 72, 0, 0], 13),
+    ("LT", [78, 56, 0, 0], 13),
+    ("LV", [46, 109, 0, 0], 16),
+    ("MA", [105, 128, 0, 0], 11),
+    ("MX", [187, 192, 0, 0], 11),
+    ("MY", [175, 136, 0, 0], 13),
+    ("NG", [105, 112, 0, 0], 12),
+    ("NL", [145, 96, 0, 0], 11),
+    ("NO", [84, 208, 0, 0], 13),
+    ("NZ", [49, 224, 0, 0], 14),
+    ("PE", [186, 160, 0, 0], 14),
+    ("PH", [49, 144, 0, 0], 13),
+    ("PK", [39, 32, 0, 0], 11),
+    ("PL", [83, 0, 0, 0], 11),
+    ("PT", [85, 240, 0, 0], 13),
+    ("RO", [79, 112, 0, 0], 13),
+    ("RS", [93, 86, 0, 0], 15),
+    ("RU", [5, 136, 0, 0], 13),
+    ("SA", [2, 88, 0, 0], 14),
+    ("SE", [78, 64, 0, 0], 12),
+    ("SG", [8, 128, 0, 0], 10),
+    ("SK", [78, 98, 0, 0], 15),
+    ("TH", [171, 96, 0, 0], 13),
+    ("TR", [78, 160, 0, 0], 11),
+    ("TW", [120, 96, 0, 0], 11),
+    ("UA", [37, 52, 0, 0], 14),
+    ("US", [6, 0, 0, 0], 8),
+    ("VN", [14, 160, 0, 0], 11),
+    ("ZA", [41, 0, 0, 0], 11),
+];
+
+/// Pick a random address from the allocation of `country`, a two letter country code like "DE".
+pub fn random_country_ip(country: &str) -> Result<Ipv4Addr> {
+    let (_, network, prefix_len) = COUNTRY_IP_BLOCKS
+        .iter()
+        .find(|(code, _, _)| code.eq_ignore_ascii_case(country))
+        .ok_or(anyhow!(
+            "No IP block is known for the country \"{}\".",
+            country
+        ))?;
+
+    // `RandomState` is seeded randomly per process, which is enough to spread the addresses.
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(unix_timestamp_secs().to_bits());
+    let host_mask = u32::MAX >> prefix_len;
+
+    Ok(Ipv4Addr::from(
+        u32::from_be_bytes(*network) | (hasher.finish() as u32 & host_mask),
+    ))
+}
@@ -0,0 +1,275 @@
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use url::Url;
+
+use crate::error::TydleError;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+#[cfg(target_arch = "wasm32")]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// `Send + Sync` on native targets, where requests may move between threads, and nothing on wasm.
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MaybeSendSync: Send + Sync {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send + Sync> MaybeSendSync for T {}
+#[cfg(target_arch = "wasm32")]
+pub trait MaybeSendSync {}
+#[cfg(target_arch = "wasm32")]
+impl<T> MaybeSendSync for T {}
+
+/// Sends the HTTP requests of tydle.
+///
+/// Implement it to use tydle on top of another HTTP stack and pass it as `TydleOptions::transport`.
+/// `ReqwestTransport` is used by default.
+pub trait HttpTransport: MaybeSendSync {
+    fn send<'a>(&'a self, request: HttpRequest) -> BoxFuture<'a, Result<HttpResponse>>;
+}
+
+/// Hooks run around every request sent through the transport of tydle, for telemetry, rewriting
+/// requests or recording fixtures without a custom `HttpTransport`.
+///
+/// Pass them as `TydleOptions::middlewares`.
+pub trait Middleware: MaybeSendSync {
+    /// Called before `request` is sent, returning the request to send instead.
+    fn on_request(&self, request: HttpRequest) -> Result<HttpRequest> {
+        Ok(request)
+    }
+
+    /// Called with the status and headers of every response, before its body is read.
+    fn on_response(&self, _response: &HttpResponse) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs middlewares around the requests of another transport, in order for requests and in
+/// reverse order for responses.
+pub struct MiddlewareTransport {
+    transport: Arc<dyn HttpTransport>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+impl MiddlewareTransport {
+    pub fn new(transport: Arc<dyn HttpTransport>, middlewares: Vec<Arc<dyn Middleware>>) -> Self {
+        Self {
+            transport,
+            middlewares,
+        }
+    }
+}
+
+impl HttpTransport for MiddlewareTransport {
+    fn send<'a>(&'a self, mut request: HttpRequest) -> BoxFuture<'a, Result<HttpResponse>> {
+        Box::pin(async move {
+            for middleware in &self.middlewares {
+                request = middleware.on_request(request)?;
+            }
+
+            let response = self.transport.send(request).await?;
+
+            for middleware in self.middlewares.iter().rev() {
+                middleware.on_response(&response)?;
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+/// Body of an `HttpResponse`, read one chunk at a time.
+pub trait HttpBody: MaybeSendSync {
+    /// Next chunk of the body, or `None` once all of it has been read.
+    fn chunk(&mut self) -> BoxFuture<'_, Result<Option<Vec<u8>>>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpMethod {
+    Get,
+    Post,
+}
+
+impl HttpMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Get => "GET",
+            Self::Post => "POST",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: HttpMethod,
+    /// URL of the request, including its query.
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+    /// Maximum time the whole request may take.
+    pub timeout: Option<Duration>,
+}
+
+impl HttpRequest {
+    pub fn new(method: HttpMethod, url: Url) -> Self {
+        Self {
+            method,
+            url,
+            headers: Vec::new(),
+            body: None,
+            timeout: None,
+        }
+    }
+
+    pub fn get(url: Url) -> Self {
+        Self::new(HttpMethod::Get, url)
+    }
+
+    pub fn post(url: Url) -> Self {
+        Self::new(HttpMethod::Post, url)
+    }
+
+    pub fn header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn query(mut self, pairs: &[(&str, &str)]) -> Self {
+        self.url.query_pairs_mut().extend_pairs(pairs);
+        self
+    }
+
+    pub fn body(mut self, body: Vec<u8>) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Box<dyn HttpBody>,
+    /// URL of the request, used in error messages.
+    pub url: Url,
+}
+
+impl HttpResponse {
+    /// First value of the header `name`, compared case insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Fail with `TydleError::HttpStatus` if the status isn't a success.
+    pub fn error_for_status(self) -> Result<Self> {
+        if !(200..300).contains(&self.status) {
+            return Err(TydleError::HttpStatus {
+                status: self.status,
+                url: self.url.to_string(),
+            }
+            .into());
+        }
+
+        Ok(self)
+    }
+
+    pub async fn bytes(mut self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+
+        while let Some(chunk) = self.body.chunk().await? {
+            bytes.extend_from_slice(&chunk);
+        }
+
+        Ok(bytes)
+    }
+
+    pub async fn text(self) -> Result<String> {
+        Ok(String::from_utf8_lossy(&self.bytes().await?).into_owned())
+    }
+}
+
+/// The default transport, sending requests with a `reqwest::Client`.
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+}
+
+struct ReqwestBody(Option<reqwest::Response>);
+
+impl HttpBody for ReqwestBody {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn chunk(&mut self) -> BoxFuture<'_, Result<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            let Some(response) = &mut self.0 else {
+                return Ok(None);
+            };
+            Ok(response.chunk().await?.map(|chunk| chunk.to_vec()))
+        })
+    }
+
+    // The fetch backend of reqwest can only read the body as a whole.
+    #[cfg(target_arch = "wasm32")]
+    fn chunk(&mut self) -> BoxFuture<'_, Result<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            match self.0.take() {
+                Some(response) => Ok(Some(response.bytes().await?.to_vec())),
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+impl HttpTransport for ReqwestTransport {
+    fn send<'a>(&'a self, request: HttpRequest) -> BoxFuture<'a, Result<HttpResponse>> {
+        Box::pin(async move {
+            let url = request.url.clone();
+            let mut builder = match request.method {
+                HttpMethod::Get => self.client.get(request.url),
+                HttpMethod::Post => self.client.post(request.url),
+            };
+
+            for (name, value) in request.headers {
+                builder = builder.header(name, value);
+            }
+            if let Some(body) = request.body {
+                builder = builder.body(body);
+            }
+            if let Some(timeout) = request.timeout {
+                builder = builder.timeout(timeout);
+            }
+
+            let response = builder.send().await?;
+            let headers = response
+                .headers()
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect();
+
+            Ok(HttpResponse {
+                status: response.status().as_u16(),
+                headers,
+                body: Box::new(ReqwestBody(Some(response))),
+                url,
+            })
+        })
+    }
+}
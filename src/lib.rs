@@ -1,14 +1,54 @@
 mod cache;
-#[cfg(feature = "cipher")]
+#[cfg(feature = "decipher")]
 mod cipher;
 mod extractor;
+mod geo;
 mod utils;
+mod yt_scraper;
 
+#[cfg(all(feature = "blocking", not(target_arch = "wasm32")))]
+pub mod blocking;
+pub mod build_info;
+#[cfg(all(feature = "capi", not(target_arch = "wasm32")))]
+pub mod capi;
+pub mod challenge;
 pub mod cookies;
+#[cfg(all(feature = "encrypted-credentials", not(target_arch = "wasm32")))]
+pub mod credentials;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod dns;
+pub mod error;
+pub mod filter;
+#[cfg(all(feature = "test-fixtures", not(target_arch = "wasm32")))]
+pub mod fixtures;
+pub mod format_spec;
+pub mod http;
 #[cfg(feature = "logging")]
 pub mod logger;
+pub mod manifest_gen;
+pub mod metrics;
+#[cfg(all(feature = "uniffi", not(target_arch = "wasm32")))]
+pub mod mobile;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod proxy;
 pub mod tydle;
+#[cfg(target_arch = "wasm32")]
+mod web_stream;
 pub mod yt_interface;
 
+#[cfg(all(feature = "redis-cache", not(target_arch = "wasm32")))]
+pub use crate::cache::RedisCache;
+pub use crate::cache::{Cache, MemoryCache};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::cache::{DISK_CACHE_TTL, DiskCache};
+#[cfg(feature = "decipher")]
+pub use crate::cipher::evaluator;
+pub use crate::error::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::extractor::client::ClientOverrides;
+pub use crate::format_spec::{FormatSelection, FormatSpec};
 pub use crate::tydle::*;
 pub use crate::yt_interface::*;
+
+#[cfg(all(feature = "uniffi", not(target_arch = "wasm32")))]
+uniffi::setup_scaffolding!();
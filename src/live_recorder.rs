@@ -0,0 +1,545 @@
+use anyhow::{Result, anyhow, bail};
+use fancy_regex::Regex;
+use reqwest::Client;
+use std::{path::Path, time::Duration};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+};
+use tydle::CancellationToken;
+use url::Url;
+
+use crate::ffmpeg::{merge_streams, run_ffmpeg};
+
+struct Segment {
+    sequence: u64,
+    duration: f64,
+    url: Url,
+    discontinuity: bool,
+}
+
+struct MediaPlaylist {
+    target_duration: f64,
+    segments: Vec<Segment>,
+    ended: bool,
+}
+
+struct DashSegment {
+    sequence: u64,
+    url: Url,
+}
+
+struct Representation {
+    id: String,
+    mime_type: String,
+    bandwidth: u64,
+    segments: Vec<DashSegment>,
+}
+
+/// What the recording needs of a live DASH manifest.
+struct Mpd {
+    representations: Vec<Representation>,
+    /// Seconds between refreshes of the manifest.
+    update_period: f64,
+    /// Seconds of each segment.
+    segment_duration: f64,
+    /// Whether the manifest turned static, once the broadcast ended.
+    ended: bool,
+}
+
+/// Where a recording stopped.
+enum Stop {
+    Ended,
+    MaxDuration,
+    Cancelled,
+}
+
+/// Records a live stream by polling its HLS media playlist or DASH manifest and appending the new
+/// segments to the output.
+pub struct LiveRecorder {
+    client: Client,
+    max_duration: Option<f64>,
+}
+
+impl LiveRecorder {
+    pub fn new(client: Client, max_duration: Option<f64>) -> Self {
+        Self {
+            client,
+            max_duration,
+        }
+    }
+
+    /// Record the HLS stream behind `manifest_url` into `output`, a .ts file, until the broadcast
+    /// ends, `max_duration` seconds have been recorded or `cancellation_token` is cancelled.
+    /// When `manifest_url` is a master playlist, the variant with the highest bandwidth is picked
+    /// unless `prefer_lowest` is set.
+    ///
+    /// Segments after a discontinuity, where timestamps or codec parameters may change, go to a new
+    /// part. The parts are joined by ffmpeg at the end, or kept as they are when it fails.
+    pub async fn record(
+        &self,
+        manifest_url: &str,
+        prefer_lowest: bool,
+        output: &str,
+        cancellation_token: &CancellationToken,
+    ) -> Result<()> {
+        let master_url = Url::parse(manifest_url)?;
+        let master = self.fetch(&master_url).await?;
+        let playlist_url = if master.contains("#EXT-X-STREAM-INF") {
+            select_variant(&master_url, &master, prefer_lowest)?
+        } else {
+            master_url
+        };
+
+        let mut parts = vec![output.to_string()];
+        let mut file = create(output).await?;
+        let mut last_sequence: Option<u64> = None;
+        let mut recorded = 0.0;
+
+        let stop = 'recording: loop {
+            let playlist = parse_media_playlist(&playlist_url, &self.fetch(&playlist_url).await?)?;
+
+            for segment in &playlist.segments {
+                if last_sequence.is_some_and(|last| segment.sequence <= last) {
+                    continue;
+                }
+
+                if let Some(last) = last_sequence
+                    && segment.sequence > last + 1
+                {
+                    log::warn!(
+                        "Missed {} segments, the recording will skip ahead.",
+                        segment.sequence - last - 1
+                    );
+                }
+
+                // Segments are always written after a new part is opened, so only the first part
+                // can still be empty.
+                if segment.discontinuity && last_sequence.is_some() {
+                    let part = part_path(output, parts.len() + 1);
+                    log::info!(
+                        "Discontinuity at segment {}, recording to {}.",
+                        segment.sequence,
+                        part
+                    );
+                    file.flush().await?;
+                    file = create(&part).await?;
+                    parts.push(part);
+                }
+
+                let data = tokio::select! {
+                    data = self.fetch_bytes(&segment.url) => data?,
+                    _ = cancellation_token.cancelled() => break 'recording Stop::Cancelled,
+                };
+                file.write_all(&data).await?;
+
+                last_sequence = Some(segment.sequence);
+                recorded += segment.duration;
+
+                if self.max_duration.is_some_and(|max| recorded >= max) {
+                    break 'recording Stop::MaxDuration;
+                }
+            }
+
+            if playlist.ended {
+                break Stop::Ended;
+            }
+
+            // Refresh at half the target duration so new segments are picked up before they expire.
+            let wait = Duration::from_secs_f64((playlist.target_duration / 2.0).max(1.0));
+            if sleep_or_cancel(wait, cancellation_token).await {
+                break Stop::Cancelled;
+            }
+        };
+
+        file.flush().await?;
+        drop(file);
+        if parts.len() > 1 {
+            join_parts(&parts, output).await;
+        }
+
+        self.report(stop, recorded)
+    }
+
+    /// Record the DASH stream behind `manifest_url` into `output`, merging the video and audio
+    /// representations with the highest bandwidth, or the lowest with `prefer_lowest`, with ffmpeg
+    /// once the recording stops. Stops like `record`.
+    pub async fn record_dash(
+        &self,
+        manifest_url: &str,
+        prefer_lowest: bool,
+        output: &str,
+        cancellation_token: &CancellationToken,
+    ) -> Result<()> {
+        let manifest_url = Url::parse(manifest_url)?;
+        let manifest = parse_mpd(&manifest_url, &self.fetch(&manifest_url).await?)?;
+
+        // Representations are followed by ID across refreshes of the manifest.
+        let mut tracks = Vec::new();
+        for kind in ["video", "audio"] {
+            let candidates = manifest
+                .representations
+                .iter()
+                .filter(|representation| representation.mime_type.starts_with(kind));
+            let representation = match prefer_lowest {
+                true => candidates.min_by_key(|representation| representation.bandwidth),
+                false => candidates.max_by_key(|representation| representation.bandwidth),
+            };
+            if let Some(representation) = representation {
+                let ext = match kind {
+                    "video" => "mp4",
+                    _ => "m4a",
+                };
+                let path = format!("{}.f{}.{}", output, representation.id, ext);
+                tracks.push(DashTrack {
+                    id: representation.id.clone(),
+                    file: create(&path).await?,
+                    path,
+                    last_sequence: None,
+                });
+            }
+        }
+        if tracks.is_empty() {
+            bail!("The DASH manifest does not list any representations.");
+        }
+
+        let mut manifest = manifest;
+        let mut recorded = 0.0;
+
+        let stop = 'recording: loop {
+            for (index, track) in tracks.iter_mut().enumerate() {
+                let Some(representation) = manifest
+                    .representations
+                    .iter()
+                    .find(|representation| representation.id == track.id)
+                else {
+                    continue;
+                };
+
+                for segment in &representation.segments {
+                    if track
+                        .last_sequence
+                        .is_some_and(|last| segment.sequence <= last)
+                    {
+                        continue;
+                    }
+
+                    let data = tokio::select! {
+                        data = self.fetch_bytes(&segment.url) => data?,
+                        _ = cancellation_token.cancelled() => break 'recording Stop::Cancelled,
+                    };
+                    track.file.write_all(&data).await?;
+                    track.last_sequence = Some(segment.sequence);
+
+                    // The first track, video when there is one, keeps the time.
+                    if index == 0 {
+                        recorded += manifest.segment_duration;
+                    }
+                }
+            }
+
+            if self.max_duration.is_some_and(|max| recorded >= max) {
+                break Stop::MaxDuration;
+            }
+
+            if manifest.ended {
+                break Stop::Ended;
+            }
+
+            let wait = Duration::from_secs_f64(manifest.update_period.max(1.0));
+            if sleep_or_cancel(wait, cancellation_token).await {
+                break Stop::Cancelled;
+            }
+            manifest = parse_mpd(&manifest_url, &self.fetch(&manifest_url).await?)?;
+        };
+
+        for track in &mut tracks {
+            track.file.flush().await?;
+        }
+        let paths = tracks
+            .into_iter()
+            .map(|track| track.path)
+            .collect::<Vec<_>>();
+        match paths.as_slice() {
+            [video, audio] => {
+                merge_streams(video, audio, output).await?;
+                for path in &paths {
+                    tokio::fs::remove_file(path).await?;
+                }
+            }
+            [single] => tokio::fs::rename(single, output).await?,
+            _ => {}
+        }
+
+        self.report(stop, recorded)
+    }
+
+    fn report(&self, stop: Stop, recorded: f64) -> Result<()> {
+        match stop {
+            Stop::Ended => log::info!("Live stream ended after {:.0}s of recording.", recorded),
+            Stop::MaxDuration => log::info!("Reached maximum duration after {:.0}s.", recorded),
+            Stop::Cancelled => bail!("Recording was cancelled."),
+        }
+
+        Ok(())
+    }
+
+    async fn fetch(&self, url: &Url) -> Result<String> {
+        Ok(self
+            .client
+            .get(url.clone())
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?)
+    }
+
+    async fn fetch_bytes(&self, url: &Url) -> Result<Vec<u8>> {
+        Ok(self
+            .client
+            .get(url.clone())
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?
+            .to_vec())
+    }
+}
+
+/// A representation of a DASH manifest being recorded.
+struct DashTrack {
+    id: String,
+    path: String,
+    file: File,
+    last_sequence: Option<u64>,
+}
+
+async fn create(path: &str) -> Result<File> {
+    Ok(OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)
+        .await?)
+}
+
+/// Sleep for `wait`, returning whether `cancellation_token` was cancelled meanwhile.
+async fn sleep_or_cancel(wait: Duration, cancellation_token: &CancellationToken) -> bool {
+    tokio::select! {
+        _ = tokio::time::sleep(wait) => false,
+        _ = cancellation_token.cancelled() => true,
+    }
+}
+
+/// `{output without its extension}.part{number}.ts`.
+fn part_path(output: &str, number: usize) -> String {
+    let stem = Path::new(output).with_extension("");
+    format!("{}.part{}.ts", stem.to_string_lossy(), number)
+}
+
+/// Join the recorded `parts`, the first one being `output`, into `output` with ffmpeg's concat
+/// demuxer, which rewrites the timestamps. The parts are kept when that fails.
+async fn join_parts(parts: &[String], output: &str) {
+    let joined = part_path(output, 0);
+    let list = format!("{}.txt", joined);
+    let entries = parts
+        .iter()
+        .map(|part| {
+            let name = Path::new(part)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            format!("file '{}'\n", name.replace('\'', "'\\''"))
+        })
+        .collect::<String>();
+
+    let result = async {
+        tokio::fs::write(&list, entries).await?;
+        run_ffmpeg(&[
+            "-y", "-f", "concat", "-safe", "0", "-i", &list, "-c", "copy", &joined,
+        ])
+        .await?;
+        tokio::fs::rename(&joined, output).await?;
+        for part in &parts[1..] {
+            tokio::fs::remove_file(part).await?;
+        }
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+    let _ = tokio::fs::remove_file(&list).await;
+
+    match result {
+        Ok(()) => log::info!("Joined {} parts into {}", parts.len(), output),
+        Err(e) => log::warn!(
+            "Failed to join the parts of the recording, keeping them as they are: {:#}",
+            e
+        ),
+    }
+}
+
+fn select_variant(master_url: &Url, master: &str, prefer_lowest: bool) -> Result<Url> {
+    let mut variants: Vec<(u64, &str)> = Vec::new();
+    let mut lines = master.lines().map(str::trim);
+
+    while let Some(line) = lines.next() {
+        let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") else {
+            continue;
+        };
+
+        let bandwidth = attributes
+            .split(',')
+            .find_map(|attr| attr.strip_prefix("BANDWIDTH="))
+            .and_then(|bw| bw.parse().ok())
+            .unwrap_or_default();
+
+        if let Some(uri) = lines
+            .by_ref()
+            .find(|l| !l.is_empty() && !l.starts_with('#'))
+        {
+            variants.push((bandwidth, uri));
+        }
+    }
+
+    let variant = if prefer_lowest {
+        variants.iter().min_by_key(|(bw, _)| *bw)
+    } else {
+        variants.iter().max_by_key(|(bw, _)| *bw)
+    };
+
+    let (_, uri) = variant.ok_or(anyhow!("The HLS manifest does not list any variants."))?;
+    Ok(master_url.join(uri)?)
+}
+
+fn parse_media_playlist(playlist_url: &Url, playlist: &str) -> Result<MediaPlaylist> {
+    let mut target_duration = 5.0;
+    let mut sequence = 0;
+    let mut duration = 0.0;
+    let mut discontinuity = false;
+    let mut segments = Vec::new();
+    let mut ended = false;
+
+    for line in playlist.lines().map(str::trim) {
+        if let Some(td) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            target_duration = td.parse().unwrap_or(target_duration);
+        } else if let Some(ms) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            sequence = ms.parse()?;
+        } else if let Some(inf) = line.strip_prefix("#EXTINF:") {
+            duration = inf
+                .split(',')
+                .next()
+                .and_then(|d| d.parse().ok())
+                .unwrap_or_default();
+        } else if line == "#EXT-X-DISCONTINUITY" {
+            discontinuity = true;
+        } else if line == "#EXT-X-ENDLIST" {
+            ended = true;
+        } else if !line.is_empty() && !line.starts_with('#') {
+            segments.push(Segment {
+                sequence,
+                duration,
+                url: playlist_url.join(line)?,
+                discontinuity,
+            });
+
+            sequence += 1;
+            duration = 0.0;
+            discontinuity = false;
+        }
+    }
+
+    Ok(MediaPlaylist {
+        target_duration,
+        segments,
+        ended,
+    })
+}
+
+/// Seconds of an ISO 8601 duration like "PT5S" or "PT1M2.5S".
+fn parse_iso_duration(duration: &str) -> Option<f64> {
+    let mut rest = duration.strip_prefix("PT")?;
+    let mut seconds = 0.0;
+
+    for (unit, multiplier) in [('H', 3600.0), ('M', 60.0), ('S', 1.0)] {
+        if let Some((value, after)) = rest.split_once(unit) {
+            seconds += value.parse::<f64>().ok()? * multiplier;
+            rest = after;
+        }
+    }
+
+    Some(seconds)
+}
+
+fn attribute(tag: &str, name: &str) -> Result<Option<String>> {
+    let re = Regex::new(&format!(r#"\s{}="([^"]*)""#, name))?;
+
+    Ok(re
+        .captures(tag)?
+        .and_then(|captures| captures.get(1))
+        .map(|value| value.as_str().replace("&amp;", "&")))
+}
+
+/// Parse the representations of the live DASH manifest `mpd` of YouTube, whose segments are listed
+/// as `<SegmentURL media="sq/{sequence}/...">` under the `BaseURL` of each representation.
+fn parse_mpd(manifest_url: &Url, mpd: &str) -> Result<Mpd> {
+    let mpd_tag = Regex::new(r"(?s)<MPD\b[^>]*>")?
+        .find(mpd)?
+        .ok_or(anyhow!("Invalid DASH manifest."))?
+        .as_str()
+        .to_string();
+    let duration = |name: &str| -> Result<Option<f64>> {
+        Ok(attribute(&mpd_tag, name)?.and_then(|d| parse_iso_duration(&d)))
+    };
+
+    let adaptation_set_re = Regex::new(r"(?s)<AdaptationSet\b([^>]*)>(.*?)</AdaptationSet>")?;
+    let representation_re = Regex::new(r"(?s)<Representation\b([^>]*)>(.*?)</Representation>")?;
+    let base_url_re = Regex::new(r"(?s)<BaseURL>(.*?)</BaseURL>")?;
+    let segment_url_re = Regex::new(r#"<SegmentURL\s[^>]*media="([^"]*)""#)?;
+    let sequence_re = Regex::new(r"(?:^|/)sq/(\d+)")?;
+
+    let mut representations = Vec::new();
+    for adaptation_set in adaptation_set_re.captures_iter(mpd) {
+        let adaptation_set = adaptation_set?;
+        let set_mime_type = attribute(&adaptation_set[1], "mimeType")?.unwrap_or_default();
+
+        for representation in representation_re.captures_iter(&adaptation_set[2]) {
+            let representation = representation?;
+            let (attributes, body) = (&representation[1], &representation[2]);
+
+            let base_url = match base_url_re.captures(body)? {
+                Some(captures) => manifest_url.join(&captures[1].replace("&amp;", "&"))?,
+                None => manifest_url.clone(),
+            };
+            let mut segments = Vec::new();
+            for (index, media) in segment_url_re.captures_iter(body).enumerate() {
+                let media = media?[1].replace("&amp;", "&");
+                let sequence = match sequence_re.captures(&media)? {
+                    Some(captures) => captures[1].parse()?,
+                    None => index as u64,
+                };
+                segments.push(DashSegment {
+                    sequence,
+                    url: base_url.join(&media)?,
+                });
+            }
+
+            representations.push(Representation {
+                id: attribute(attributes, "id")?.unwrap_or_default(),
+                mime_type: attribute(attributes, "mimeType")?
+                    .unwrap_or_else(|| set_mime_type.clone()),
+                bandwidth: attribute(attributes, "bandwidth")?
+                    .and_then(|bandwidth| bandwidth.parse().ok())
+                    .unwrap_or_default(),
+                segments,
+            });
+        }
+    }
+
+    Ok(Mpd {
+        representations,
+        update_period: duration("minimumUpdatePeriod")?.unwrap_or(5.0),
+        segment_duration: duration("maxSegmentDuration")?.unwrap_or(5.0),
+        ended: attribute(&mpd_tag, "type")?.is_some_and(|kind| kind == "static"),
+    })
+}
@@ -61,4 +61,8 @@ where
 }
 
 #[cfg(not(feature = "logging"))]
-pub fn init_logging(_: Option<&str>) {}
+pub fn init_logging<L>(_: L)
+where
+    L: Into<LogLevel<'static>>,
+{
+}
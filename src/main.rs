@@ -1,179 +1,1805 @@
-use std::process;
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::Path,
+    process,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use anyhow::{Result, anyhow};
-use clap::Parser;
+use anyhow::{Result, anyhow, bail};
+use clap::{ArgAction, Parser};
 use colored::Colorize;
+use once_cell::sync::Lazy;
+use serde_json::{Value, json};
 use tokio::fs;
 use tydle::{
-    Cipher, Ext, Extract, Filterable, Tydle, TydleOptions, VideoId, YtStream, YtStreamSource,
-    cookies::parse_netscape_cookies,
+    CancellationToken, ClientOverrides, DiskCache, Ext, Extract, ExtractionStrategy, FormatSpec,
+    SortKey, Tydle, TydleError, TydleOptions, VideoId, YtClient, YtStream, YtStreamResponse,
+    YtSubtitle, YtSubtitleFormat, YtThumbnail, YtVideoInfo, build_info,
+    cookies::parse_cookies,
+    dns::{DnsOptions, DohResolver},
+    filter::MatchFilter,
+    logger::{LogLevel, init_logging},
+    manifest_gen::ManifestFormat,
+    proxy::{ProxyPoolOptions, Rotation, Route},
 };
 
 use crate::{
-    format::{Format, compact_num, get_resolution, human_readable_size, parse_format},
-    stream_downloader::StreamDownloader,
+    archive::DownloadArchive,
+    diagnose::{RequestLog, diagnose},
+    events::{Event, Events, WebhookSink},
+    ffmpeg::merge_streams,
+    format::{compact_num, format_size_estimate, get_resolution, parse_bytes},
+    live_recorder::LiveRecorder,
+    play::{Player, play},
+    playlist::{Target, parse_target, select_entries},
+    postprocess::{ExecPostProcessor, LoudnormPostProcessor, PostProcessContext},
+    section::{Section, parse_section},
+    sidecar::{
+        add_selected_format, embed_subtitles, format_dict, info_dict, parse_subtitle_format,
+        subtitle_dict, thumbnail_dict, write_info_json, write_manifest, write_subtitles,
+    },
+    stream_downloader::{DownloadOptions, OverwritePolicy, StreamDownloader, retry_throttled},
+    template::{FilenameMode, Paths, has_fields, render_filename, render_template},
+    thumbnail::{ThumbnailFormat, best_thumbnail, embed_thumbnail, write_thumbnail},
 };
 
+mod archive;
+#[cfg(feature = "cast")]
+mod cast;
+mod diagnose;
+#[cfg(feature = "server")]
+mod download_manager;
+mod events;
+mod ffmpeg;
 mod format;
+mod live_recorder;
+mod play;
+mod playlist;
+mod postprocess;
+#[cfg(feature = "server")]
+mod queue;
+mod section;
+#[cfg(feature = "self-update")]
+mod self_update;
+#[cfg(feature = "server")]
+mod server;
+mod sidecar;
 mod stream_downloader;
+mod template;
+mod thumbnail;
 
 #[derive(Parser, Debug)]
-#[clap(version)]
+#[clap(
+    version,
+    long_version = LONG_VERSION.as_str(),
+    after_help = EXIT_CODES_HELP,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct TydleArgs {
+    #[cfg(feature = "server")]
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Print more diagnostics, use twice for trace output.
+    #[arg(long, short = 'v', action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+    /// Only print errors.
+    #[arg(long, short = 'q')]
+    quiet: bool,
+    /// Log level of the diagnostics (error, warn, info, debug or trace).
+    #[arg(long, conflicts_with_all = ["verbose", "quiet"])]
+    log_level: Option<LogLevel<'static>>,
     /// Client-side IP address to bind to.
     #[arg(long)]
     source_ip: Option<String>,
-    /// Netscape formatted file to read cookies from and dump cookie jar in.
+    /// Make all connections via IPv4.
+    #[arg(long, short = '4', conflicts_with_all = ["source_ip", "force_ipv6"])]
+    force_ipv4: bool,
+    /// Make all connections via IPv6.
+    #[arg(long, short = '6', conflicts_with = "source_ip")]
+    force_ipv6: bool,
+    /// Proxy, like socks5://10.0.0.1:1080, or local IP address to spread requests and downloads
+    /// over. Can be given several times.
+    #[arg(long = "proxy-pool", value_name = "ROUTE")]
+    proxy_pool: Vec<Route>,
+    /// When to move on to the next route of the proxy pool ("request" or "video").
+    #[arg(long, default_value = "request")]
+    proxy_rotation: Rotation,
+    /// Failures in a row after which a route is evicted from the proxy pool.
+    #[arg(long, default_value_t = 3)]
+    proxy_max_failures: u32,
+    /// Address to send as the X-Forwarded-For header when requesting YouTube, to work around geo restrictions.
+    #[arg(long)]
+    xff: Option<String>,
+    /// Send a random address of the given country, as a two letter code like "DE", as the
+    /// X-Forwarded-For header. Ignored when --xff is passed.
+    #[arg(long, value_name = "CODE")]
+    geo_bypass_country: Option<String>,
+    /// Client to request YouTube with by default, like "android_vr" or "tv".
+    #[arg(long)]
+    client: Option<YtClient>,
+    /// Where to extract the player responses from: "innertube", "fallback" to scrape the watch
+    /// and embed pages when the API fails, or "webpage" to only scrape them.
+    #[arg(long, value_name = "STRATEGY")]
+    extraction_strategy: Option<ExtractionStrategy>,
+    /// DNS-over-HTTPS endpoint to resolve hosts with (e.g. https://cloudflare-dns.com/dns-query).
+    #[arg(long)]
+    doh_url: Option<String>,
+    /// Resolve a host to the given address, as "HOST:IP". Can be used multiple times.
+    #[arg(long)]
+    resolve: Vec<String>,
+    /// Netscape or JSON formatted file to read cookies from and dump cookie jar in, as Netscape.
     #[arg(long)]
     cookies: Option<String>,
+    /// Load cookies from a browser, as "BROWSER[:PROFILE]". Supported browsers are brave, chrome,
+    /// chromium, edge, firefox, opera and vivaldi.
+    #[cfg(feature = "browser-cookies")]
+    #[arg(long)]
+    cookies_from_browser: Option<String>,
+    /// Encrypt the --cookies file with the passphrase in the TYDLE_CREDENTIALS_PASSPHRASE
+    /// environment variable, or the one saved with --save-credentials-passphrase. Encrypted files
+    /// are read and written back with the same passphrase. The visitor data and PO tokens kept in
+    /// the cache are encrypted with it whenever one is set.
+    #[cfg(feature = "encrypted-credentials")]
+    #[arg(long)]
+    encrypt_cookies: bool,
+    /// Save the passphrase in TYDLE_CREDENTIALS_PASSPHRASE to the keyring of the OS, which later
+    /// runs read it from when the variable isn't set.
+    #[cfg(feature = "encrypted-credentials")]
+    #[arg(long)]
+    save_credentials_passphrase: bool,
     /// Use an unencrypted connection to retrieve information about the video.
     #[arg(long)]
     prefer_insecure: bool,
+    /// Time to wait before giving up on a request, in seconds.
+    #[arg(long)]
+    socket_timeout: Option<u64>,
+    /// Time to wait for a connection to be established, in seconds.
+    #[arg(long)]
+    connect_timeout: Option<u64>,
+    /// Download over HTTP/1.1 only, with a connection for every concurrent range.
+    #[arg(long)]
+    no_http2: bool,
+    /// Maximum number of idle connections kept open to each host between ranges and downloads.
+    #[arg(long)]
+    pool_max_idle_per_host: Option<usize>,
+    /// Interval of TCP keepalive probes on download connections, in seconds. 0 disables them.
+    #[arg(long, default_value_t = 60)]
+    tcp_keepalive: u64,
+    /// Time after which extracting a video is given up on, in seconds, however many requests it takes.
+    #[arg(long)]
+    extraction_deadline: Option<u64>,
+    /// Directory holding yt-dlp's EJS solver (yt.solver.lib.min.js and yt.solver.core.min.js)
+    /// to decipher signatures without downloading it.
+    #[arg(long)]
+    ejs_dir: Option<std::path::PathBuf>,
+    /// Directory the player JS, signatures, visitor data and PO tokens are cached in between runs,
+    /// "$XDG_CACHE_HOME/tydle" or "~/.cache/tydle" by default.
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<std::path::PathBuf>,
+    /// Only cache in memory, for this run.
+    #[arg(long, conflicts_with = "cache_dir")]
+    no_cache_dir: bool,
+    /// Write the watch page, ytcfg, player responses and player JS of every video to DIR/ID, the
+    /// working directory by default, to debug extraction.
+    #[arg(
+        long,
+        value_name = "DIR",
+        num_args = 0..=1,
+        default_missing_value = "."
+    )]
+    write_pages: Option<std::path::PathBuf>,
+    /// Extract the video with full tracing and write a report for bug reports, with the outcome of
+    /// every client, the player version and signature timestamp, and the requests sent, without
+    /// their query values.
+    #[arg(long, value_name = "ID", conflicts_with = "video_ids")]
+    diagnose: Option<String>,
+    /// Update tydle to the latest release on GitHub, once its signature is verified with the public
+    /// key this build was made with.
+    #[cfg(feature = "self-update")]
+    #[arg(long, exclusive = true)]
+    update: bool,
+    /// Delete the cache directory before anything else, exiting when no video is given.
+    #[arg(long)]
+    rm_cache_dir: bool,
+    /// Redis server to cache in instead of --cache-dir, like "redis://127.0.0.1/", shared by every
+    /// tydle connected to it.
+    #[cfg(feature = "redis-cache")]
+    #[arg(long, value_name = "URL")]
+    redis_cache: Option<String>,
+    /// Record the sanitized responses of YouTube to DIR, to replay them in tests. Caches in memory
+    /// only, so that every request is sent and recorded.
+    #[cfg(feature = "test-fixtures")]
+    #[arg(long, value_name = "DIR")]
+    record_fixtures: Option<std::path::PathBuf>,
+    /// JSON file or URL of client versions and user agents replacing the compiled ones, like
+    /// '{"web": {"clientVersion": "2.20260101.00.00"}}'. The compiled ones are used when it fails
+    /// to load.
+    #[arg(long, value_name = "FILE|URL")]
+    client_overrides: Option<String>,
+    /// URL of a service that solves signature, n and PO token challenges, posted to as JSON.
+    #[arg(long)]
+    challenge_solver_url: Option<String>,
     #[arg(long)]
     /// List available formats of each video.
     list_formats: bool,
+    /// List available subtitles of each video.
+    #[arg(long)]
+    list_subs: bool,
+    /// List available thumbnails of each video.
+    #[arg(long)]
+    list_thumbnails: bool,
+    /// Print the listings and errors as JSON instead of tables and log lines.
+    #[arg(long)]
+    json: bool,
     #[arg(long)]
     get_url: bool,
-    /// Specify the type of format to download the stream of.
+    /// Print a template like "%(title)s %(duration)s" for each video instead of downloading it. Can be used multiple times.
+    #[arg(long, short = 'O')]
+    print: Vec<String>,
+    /// Run the extraction and pick a stream without downloading or writing anything.
+    #[arg(long, short = 's')]
+    simulate: bool,
+    /// Write the requested metadata files but don't download the video itself.
+    #[arg(long)]
+    skip_download: bool,
+    /// Write a manifest ("mpd" or "m3u8") referencing the selected formats on YouTube instead of
+    /// downloading them, to play them in a player. The stream URLs expire after a few hours.
+    #[arg(long, value_name = "FORMAT")]
+    write_manifest: Option<ManifestFormat>,
+    /// Play the selected formats in --player instead of downloading them.
+    #[arg(long)]
+    play: bool,
+    /// Media player --play hands the streams off to.
+    #[arg(long, default_value = "mpv")]
+    player: Player,
+    /// Play the selected format on the Chromecast or DLNA renderer of the local network whose name
+    /// contains NAME instead of downloading it. Needs a format with both video and audio, like
+    /// "best".
+    #[cfg(feature = "cast")]
+    #[arg(long, value_name = "NAME")]
+    cast: Option<String>,
+    /// Format to download, like "bestvideo[height<=1080]+bestaudio/best". Alternatives are separated
+    /// by "/" and "+" merges a video and an audio format with ffmpeg. Defaults to "bestvideo".
     #[arg(long, short)]
     format: Option<String>,
-    // Where to output the final downloaded stream.
+    /// Comma separated sort keys deciding which stream is best, e.g. "res:1080,fps,codec:av01".
+    #[arg(long, short = 'S')]
+    format_sort: Option<String>,
+    /// Where to output the final downloaded stream, either a path or a template like
+    /// "%(channel)s/%(title)s [%(id)s].%(ext)s" where ".%(ext)s" is the extension of the selected
+    /// format. Defaults to "%(id)s.%(ext)s".
     #[arg(long)]
     out: Option<String>,
-    video_id: String,
+    /// Only use ASCII letters, digits, "-", "_" and "." in the fields of --out.
+    #[arg(long)]
+    restrict_filenames: bool,
+    /// Make the fields of --out valid on Windows, which is the default there.
+    #[arg(long)]
+    windows_filenames: bool,
+    /// Directories to write to, like "home:Videos,temp:/tmp". home holds the output files and
+    /// temp the formats that are merged, relative paths of --out are in home.
+    #[arg(long, short = 'P', value_delimiter = ',')]
+    paths: Vec<String>,
+    /// Number of ranges of the stream to download concurrently. Defaults to the number of CPUs.
+    #[arg(long, short = 'N')]
+    concurrent_fragments: Option<usize>,
+    /// Size of each range request (e.g. 10M).
+    #[arg(long)]
+    http_chunk_size: Option<String>,
+    /// Number of retries for each failed range.
+    #[arg(long, short = 'R', default_value_t = 10)]
+    retries: u32,
+    /// Maximum download rate in bytes per second (e.g. 50K or 4.2M).
+    #[arg(long, short = 'r')]
+    limit_rate: Option<String>,
+    /// Download rate in bytes per second below which a download is assumed to be throttled, and
+    /// retried once with its n parameter deciphered again (e.g. 100K). Not checked by default.
+    #[arg(long)]
+    throttled_rate: Option<String>,
+    /// Restart partially downloaded files from the beginning.
+    #[arg(long)]
+    no_continue: bool,
+    /// Do not overwrite an existing output file, same as --overwrite-policy skip.
+    #[arg(long, short = 'w', conflicts_with = "overwrite_policy")]
+    no_overwrites: bool,
+    /// What to do when the output file already exists.
+    #[arg(long, value_enum, default_value_t)]
+    overwrite_policy: OverwritePolicy,
+    /// Extra HTTP header to send when downloading, as "Name:Value". Can be used multiple times.
+    #[arg(long)]
+    add_header: Vec<String>,
+    /// Write the SHA-256 of the downloaded file to a .sha256 file next to it.
+    #[arg(long)]
+    sha256: bool,
+    /// Write the best thumbnail of the video next to the output file.
+    #[arg(long)]
+    write_thumbnail: bool,
+    /// Embed the best thumbnail in the output file as cover art (m4a/mp4/mkv only).
+    #[arg(long)]
+    embed_thumbnail: bool,
+    /// Format to convert webp thumbnails to.
+    #[arg(long, value_enum, default_value_t)]
+    convert_thumbnails: ThumbnailFormat,
+    /// Write the video's metadata to a .info.json file next to the output file.
+    #[arg(long)]
+    write_info_json: bool,
+    /// Write subtitles next to the output file.
+    #[arg(long)]
+    write_subs: bool,
+    /// Also write automatically generated subtitles for languages without manual ones.
+    #[arg(long)]
+    write_auto_subs: bool,
+    /// Comma separated languages of the subtitles to write, or "all".
+    #[arg(long, value_delimiter = ',', default_value = "en")]
+    sub_langs: Vec<String>,
+    /// Format of the written subtitles (vtt, srv1, srv2, srv3, ttml or json3).
+    #[arg(long, default_value = "vtt")]
+    sub_format: String,
+    /// Normalize the loudness of the downloaded audio with two passes of ffmpeg's loudnorm filter.
+    #[arg(long)]
+    normalize_audio: bool,
+    /// Integrated loudness --normalize-audio aims for, in LUFS.
+    #[arg(
+        long,
+        value_name = "LUFS",
+        default_value_t = -16.0,
+        allow_negative_numbers = true,
+        requires = "normalize_audio"
+    )]
+    loudness_target: f64,
+    /// Maximum true peak --normalize-audio allows, in dBTP.
+    #[arg(
+        long,
+        value_name = "DBTP",
+        default_value_t = -1.5,
+        allow_negative_numbers = true,
+        requires = "normalize_audio"
+    )]
+    true_peak: f64,
+    /// Embed the subtitles of --sub-langs in the output file (mp4/mkv/webm only). Requires ffmpeg.
+    #[arg(long)]
+    embed_subs: bool,
+    /// Command to run on each downloaded file, "{}" is replaced with its path. Can be used multiple times.
+    #[arg(long)]
+    exec: Vec<String>,
+    /// URL to POST a JSON event to when a download starts, passes 25/50/75%, finishes or fails,
+    /// along with the info JSON of the video. Can be used multiple times.
+    #[arg(long, value_name = "URL")]
+    webhook: Vec<String>,
+    /// Only download a time range of the video, like "*00:01:30-00:04:00". Only mp4 and m4a formats
+    /// are downloaded partially, others are downloaded whole and then cut. Requires ffmpeg.
+    #[arg(long)]
+    download_sections: Option<String>,
+    /// Stop recording a live stream after this many seconds.
+    #[arg(long)]
+    max_duration: Option<f64>,
+    /// Seconds to sleep between requests made during extraction.
+    #[arg(long)]
+    sleep_requests: Option<f64>,
+    /// Maximum number of requests per minute to each host during extraction.
+    #[arg(long)]
+    requests_per_minute: Option<u32>,
+    /// Seconds to sleep before each download, or the minimum when used with --max-sleep-interval.
+    #[arg(long)]
+    sleep_interval: Option<f64>,
+    /// Maximum seconds to sleep before each download, a random duration is picked in between.
+    #[arg(long, requires = "sleep_interval")]
+    max_sleep_interval: Option<f64>,
+    /// Wait for upcoming premieres and live streams, retrying every MIN[-MAX] seconds until they start.
+    #[arg(long)]
+    wait_for_video: Option<String>,
+    /// Keep going with the remaining videos when one of them fails.
+    #[arg(long, short = 'i')]
+    ignore_errors: bool,
+    /// Download only the video when a URL refers to both a video and a playlist.
+    #[arg(long)]
+    no_playlist: bool,
+    /// Playlist items to download, 1-based, like "1-10,15".
+    #[arg(long)]
+    playlist_items: Option<String>,
+    /// Skip videos whose selected stream is smaller than this, like "50M".
+    #[arg(long)]
+    min_filesize: Option<String>,
+    /// Skip videos whose selected stream is larger than this, like "1G".
+    #[arg(long)]
+    max_filesize: Option<String>,
+    /// Only process videos matching a filter like "duration<3600 & view_count>1000".
+    #[arg(long)]
+    match_filter: Option<MatchFilter>,
+    /// Download playlist items in reverse order.
+    #[arg(long)]
+    playlist_reverse: bool,
+    /// Skip the videos listed in this file and add the ones downloaded to it.
+    #[arg(long, value_name = "FILE")]
+    download_archive: Option<String>,
+    /// Stop after downloading this many videos.
+    #[arg(long, value_name = "N")]
+    max_downloads: Option<usize>,
+    /// Stop at the first video already in the download archive, like when the newest uploads of a
+    /// channel were all downloaded.
+    #[arg(long, requires = "download_archive")]
+    break_on_existing: bool,
+    /// Make --break-on-existing and --max-downloads stop only the current URL, going on with the
+    /// next one.
+    #[arg(long)]
+    break_per_input: bool,
+    /// IDs or URLs of videos and playlists to download.
+    #[cfg_attr(
+        all(feature = "self-update", feature = "encrypted-credentials"),
+        arg(required_unless_present_any = [
+            "rm_cache_dir",
+            "diagnose",
+            "update",
+            "save_credentials_passphrase"
+        ])
+    )]
+    #[cfg_attr(
+        all(feature = "self-update", not(feature = "encrypted-credentials")),
+        arg(required_unless_present_any = ["rm_cache_dir", "diagnose", "update"])
+    )]
+    #[cfg_attr(
+        all(not(feature = "self-update"), feature = "encrypted-credentials"),
+        arg(required_unless_present_any = [
+            "rm_cache_dir",
+            "diagnose",
+            "save_credentials_passphrase"
+        ])
+    )]
+    #[cfg_attr(
+        all(not(feature = "self-update"), not(feature = "encrypted-credentials")),
+        arg(required_unless_present_any = ["rm_cache_dir", "diagnose"])
+    )]
+    video_ids: Vec<String>,
 }
 
+#[cfg(feature = "server")]
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Serve a REST API for extracting videos, sharing one instance and its caches between requests.
+    Serve {
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: std::net::SocketAddr,
+        /// Database the download queue is persisted in.
+        #[arg(long, default_value = "tydle-queue.db")]
+        queue_db: String,
+        /// Directory queued downloads are saved to.
+        #[arg(long, default_value = ".")]
+        output_dir: std::path::PathBuf,
+        /// Number of queued downloads that run at the same time.
+        #[arg(long, default_value_t = 2)]
+        jobs: usize,
+        /// Maximum number of connections to a single host across the running downloads.
+        #[arg(long, default_value_t = 8)]
+        host_connections: usize,
+    },
+}
+
+/// State shared by every video downloaded in one invocation.
+struct Session {
+    tydle: Tydle,
+    downloader: StreamDownloader,
+    cancellation_token: CancellationToken,
+    format: FormatSpec,
+    section: Option<Section>,
+    sub_format: YtSubtitleFormat,
+    events: Events,
+    paths: Paths,
+    /// Bytes of the current download, for the progress events.
+    downloaded_bytes: Arc<AtomicU64>,
+    min_filesize: Option<u64>,
+    max_filesize: Option<u64>,
+    wait_for_video: Option<(f64, f64)>,
+    archive: Option<DownloadArchive>,
+}
+
+/// Exit codes of the CLI, so that wrapper scripts can branch on the cause of a failure.
+mod exit_code {
+    /// Any failure without a more specific code, including batches where some videos failed.
+    /// Invalid arguments exit with 2, as reported by clap.
+    pub const ERROR: i32 = 1;
+    /// A request failed to connect, timed out or was interrupted.
+    pub const NETWORK: i32 = 3;
+    /// The video is private, removed, geo restricted, upcoming or otherwise unplayable.
+    pub const UNAVAILABLE: i32 = 4;
+    /// No stream matched the requested format.
+    pub const FORMAT_NOT_FOUND: i32 = 5;
+    /// The video requires signing in or passing a bot check.
+    pub const AUTH_REQUIRED: i32 = 6;
+    /// A download ended before all of its bytes were received.
+    pub const DOWNLOAD_INCOMPLETE: i32 = 7;
+}
+
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  Success
+  1  Other error
+  2  Invalid arguments
+  3  Network error
+  4  Video unavailable
+  5  No matching format
+  6  Login or bot check required
+  7  Incomplete download";
+
+/// Printed by `--version`, with what issue reports need to know about the build.
+static LONG_VERSION: Lazy<String> = Lazy::new(|| {
+    let mut version = build_info::VERSION.to_string();
+    if let Some(commit) = build_info::GIT_COMMIT {
+        version.push_str(&format!(" ({})", commit));
+    }
+
+    version.push_str(&format!(
+        "\nFeatures: {}",
+        build_info::features().join(", ")
+    ));
+    version.push_str(&format!("\nEJS solver: {}", build_info::EJS_VERSION));
+    version.push_str("\nInnerTube clients:");
+    for (client, client_version) in build_info::client_versions() {
+        version.push_str(&format!("\n  {:<20} {}", client.as_str(), client_version));
+    }
+
+    version
+});
+
+const DEFAULT_OUTPUT_TEMPLATE: &str = "%(id)s.%(ext)s";
+
+/// How long a webhook gets to answer an event before it's given up on.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    if let Err(e) = run().await {
-        log::error!("{}", e.to_string());
-        process::exit(1);
+    let args = TydleArgs::parse();
+    let json = args.json;
+
+    if let Err(e) = run(args).await {
+        let code = exit_code(&e);
+
+        if json {
+            eprintln!(
+                "{}",
+                serde_json::json!({ "error": format!("{:#}", e), "exit_code": code })
+            );
+        } else {
+            log::error!("{:#}", e);
+        }
+
+        process::exit(code);
     }
 
     Ok(())
 }
 
-async fn run() -> Result<()> {
-    let args = TydleArgs::parse();
-    let auth_cookies = match args.cookies {
-        Some(cookies_path) => {
-            let cookie_file_content = fs::read_to_string(cookies_path).await?;
-            parse_netscape_cookies(cookie_file_content)?
+/// Map the first typed error in the chain of `err` to its exit code.
+fn exit_code(err: &anyhow::Error) -> i32 {
+    fn tydle_error_code(err: &TydleError) -> i32 {
+        match err {
+            TydleError::BotCheck(_)
+            | TydleError::LoginRequired(_)
+            | TydleError::AgeRestricted(_)
+            | TydleError::AuthExpired(_) => exit_code::AUTH_REQUIRED,
+            TydleError::GeoRestricted(_)
+            | TydleError::Private(_)
+            | TydleError::Unplayable(_)
+            | TydleError::Upcoming { .. }
+            | TydleError::InvalidPlayerResponse { .. } => exit_code::UNAVAILABLE,
+            TydleError::Network(_) | TydleError::HttpStatus { .. } => exit_code::NETWORK,
+            TydleError::FormatNotFound(_) => exit_code::FORMAT_NOT_FOUND,
+            TydleError::IncompleteDownload { .. } => exit_code::DOWNLOAD_INCOMPLETE,
+            // Signing in fixes more than it would for any other client, so report that first.
+            TydleError::ExtractionFailed { attempts } => attempts
+                .iter()
+                .map(|(_, err)| tydle_error_code(err))
+                .min_by_key(|code| *code != exit_code::AUTH_REQUIRED)
+                .unwrap_or(exit_code::UNAVAILABLE),
+            TydleError::Other(err) => exit_code(err),
+            TydleError::Parse(_)
+            | TydleError::Cipher(_)
+            | TydleError::Cache(_)
+            | TydleError::Cancelled => exit_code::ERROR,
+            TydleError::DeadlineExceeded => exit_code::NETWORK,
+        }
+    }
+
+    for cause in err.chain() {
+        if let Some(err) = cause.downcast_ref::<TydleError>() {
+            return tydle_error_code(err);
         }
+
+        if cause.is::<reqwest::Error>() {
+            return exit_code::NETWORK;
+        }
+    }
+
+    exit_code::ERROR
+}
+
+async fn run(args: TydleArgs) -> Result<()> {
+    // Diagnostics go to stderr so that stdout only holds the requested output.
+    init_logging(
+        args.log_level
+            .clone()
+            .unwrap_or(match (args.quiet, args.verbose) {
+                _ if args.diagnose.is_some() => "trace".into(),
+                (true, _) => "error".into(),
+                (false, 0) => "info".into(),
+                (false, 1) => "debug".into(),
+                (false, _) => "trace".into(),
+            }),
+    );
+
+    #[cfg(feature = "self-update")]
+    if args.update {
+        self_update::self_update().await?;
+        return Ok(());
+    }
+
+    if args.rm_cache_dir {
+        remove_cache_dir(args.cache_dir.clone().or_else(DiskCache::default_dir)).await?;
+        if args.video_ids.is_empty() && args.diagnose.is_none() {
+            return Ok(());
+        }
+    }
+
+    #[cfg(feature = "encrypted-credentials")]
+    if args.save_credentials_passphrase {
+        save_credentials_passphrase()?;
+        if args.video_ids.is_empty() && args.diagnose.is_none() {
+            return Ok(());
+        }
+    }
+
+    #[cfg_attr(not(feature = "browser-cookies"), allow(unused_mut))]
+    let mut auth_cookies = match &args.cookies {
+        Some(cookies_path) => parse_cookies(read_cookies_file(cookies_path).await?)?,
         None => Default::default(),
     };
 
-    let format = parse_format(args.format.unwrap_or("bestvideo".into()).as_str())?;
+    #[cfg(feature = "browser-cookies")]
+    if let Some(browser_arg) = &args.cookies_from_browser {
+        let (browser, profile) = match browser_arg.split_once(':') {
+            Some((browser, profile)) => (browser, Some(profile)),
+            None => (browser_arg.as_str(), None),
+        };
+        let browser_cookies =
+            tydle::cookies::DomainCookies::from_browser(browser.parse()?, profile)
+                .map_err(|e| e.context(format!("Failed to load cookies from {}", browser)))?;
+
+        log::info!("Loaded {} cookies from {}.", browser_cookies.len(), browser);
+        auth_cookies.extend(browser_cookies.iter().cloned());
+    }
+
+    let sort_keys = args
+        .format_sort
+        .as_deref()
+        .map(SortKey::parse_list)
+        .transpose()?
+        .unwrap_or_default();
+    let format =
+        FormatSpec::parse(args.format.as_deref().unwrap_or("bestvideo"))?.sort_keys(sort_keys);
+    let sub_format = parse_subtitle_format(&args.sub_format)?;
+    let section = args
+        .download_sections
+        .as_deref()
+        .map(parse_section)
+        .transpose()?;
+    // Binding to the unspecified address of a family restricts connections to that family.
+    let source_ip = if args.force_ipv4 {
+        Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+    } else if args.force_ipv6 {
+        Some(IpAddr::V6(Ipv6Addr::UNSPECIFIED))
+    } else {
+        args.source_ip.as_deref().map(str::parse).transpose()?
+    };
+
+    let proxy_pool = (!args.proxy_pool.is_empty()).then(|| ProxyPoolOptions {
+        routes: args.proxy_pool.clone(),
+        rotation: args.proxy_rotation,
+        max_failures: args.proxy_max_failures,
+    });
+
+    let mut dns = DnsOptions::default();
+
+    if let Some(doh_url) = &args.doh_url {
+        dns.resolver = Some(Arc::new(DohResolver::new(doh_url)?));
+    }
+
+    for entry in &args.resolve {
+        let (host, ip) = entry.split_once(':').ok_or(anyhow!(
+            "Invalid host override \"{}\", expected HOST:IP.",
+            entry
+        ))?;
+        let ip: IpAddr = ip.trim_matches(['[', ']']).parse()?;
+        dns.host_overrides
+            .entry(host.to_string())
+            .or_insert_with(Vec::new)
+            .push(ip);
+    }
+
+    let mut download_options = DownloadOptions::new()
+        .connect_timeout(args.connect_timeout.map(Duration::from_secs))
+        .read_timeout(args.socket_timeout.map(Duration::from_secs))
+        .http2(!args.no_http2)
+        .pool_max_idle_per_host(args.pool_max_idle_per_host)
+        .tcp_keepalive(Some(Duration::from_secs(args.tcp_keepalive)).filter(|k| !k.is_zero()))
+        .workers(args.concurrent_fragments.unwrap_or_else(num_cpus::get))
+        .chunk_size(
+            args.http_chunk_size
+                .as_deref()
+                .map(parse_bytes)
+                .transpose()?,
+        )
+        .retries(args.retries)
+        .rate_limit(args.limit_rate.as_deref().map(parse_bytes).transpose()?)
+        .throttled_rate(
+            args.throttled_rate
+                .as_deref()
+                .map(parse_bytes)
+                .transpose()?
+                .filter(|rate| *rate > 0),
+        )
+        .resume(!args.no_continue)
+        .overwrite_policy(if args.no_overwrites {
+            OverwritePolicy::Skip
+        } else {
+            args.overwrite_policy
+        })
+        .source_ip(source_ip)
+        .proxy_pool(proxy_pool.clone())
+        .dns(dns.clone())
+        .sha256(args.sha256);
+
+    for header in &args.add_header {
+        let (name, value) = header.split_once(':').ok_or(anyhow!(
+            "Invalid header \"{}\", expected Name:Value.",
+            header
+        ))?;
+        download_options = download_options.header(name.trim(), value.trim());
+    }
+
+    // Normalize first, so that the commands of --exec see the final file.
+    if args.normalize_audio {
+        download_options = download_options.post_processor(
+            LoudnormPostProcessor::new(args.loudness_target).true_peak(args.true_peak),
+        );
+    }
+
+    for command in &args.exec {
+        download_options = download_options.post_processor(ExecPostProcessor::new(command));
+    }
+
+    let mut events = Events::new();
+    if !args.webhook.is_empty() {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()?;
+        for url in &args.webhook {
+            events = events.sink(WebhookSink::new(client.clone(), url));
+        }
+    }
+    // Downloads run one after the other, so the total counts the bytes of the current one.
+    let downloaded_bytes = Arc::new(AtomicU64::new(0));
+    if !events.is_empty() {
+        download_options.downloaded_bytes = Some(Arc::clone(&downloaded_bytes));
+    }
+
+    let cancellation_token = CancellationToken::new();
+    let ctrl_c_token = cancellation_token.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            ctrl_c_token.cancel();
+        }
+    });
+
+    #[cfg(feature = "redis-cache")]
+    let cache = args
+        .redis_cache
+        .as_deref()
+        .map(|url| -> Result<Arc<dyn tydle::Cache>> {
+            Ok(Arc::new(
+                tydle::RedisCache::new(url)?.ttl(Some(tydle::DISK_CACHE_TTL)),
+            ))
+        })
+        .transpose()?;
+    #[cfg(not(feature = "redis-cache"))]
+    let cache = None;
+
+    #[cfg(feature = "test-fixtures")]
+    let no_cache_dir = args.no_cache_dir || args.record_fixtures.is_some();
+    #[cfg(not(feature = "test-fixtures"))]
+    let no_cache_dir = args.no_cache_dir;
+
+    let client_overrides = match &args.client_overrides {
+        Some(source) => load_client_overrides(source).await,
+        None => ClientOverrides::default(),
+    };
+
+    let authenticated = !auth_cookies.is_empty();
+    let request_log = Arc::new(RequestLog::default());
 
-    tydle::logger::init_logging("info");
     let tydle = Tydle::new(TydleOptions {
         auth_cookies,
+        cache,
+        cache_dir: args.cache_dir.clone(),
+        no_cache_dir,
+        #[cfg(feature = "encrypted-credentials")]
+        credentials_passphrase: find_credentials_passphrase(),
+        #[cfg(feature = "test-fixtures")]
+        record_fixtures_dir: args.record_fixtures.clone(),
+        debug_dump_dir: args.write_pages.clone(),
         prefer_insecure: args.prefer_insecure,
-        source_address: args.source_ip.unwrap_or_default(),
+        source_address: args.xff.clone().unwrap_or_default(),
+        geo_bypass_country: args.geo_bypass_country.clone(),
+        default_client: args.client.unwrap_or_default(),
+        extraction_strategy: args.extraction_strategy.unwrap_or_default(),
+        source_ip,
+        proxy_pool,
+        dns,
+        request_timeout_secs: args.socket_timeout,
+        connect_timeout_secs: args.connect_timeout,
+        overall_deadline_secs: args.extraction_deadline,
+        ejs_dir: args.ejs_dir.clone(),
+        challenge_solver_url: args.challenge_solver_url.clone(),
+        client_overrides,
+        sleep_requests_secs: args.sleep_requests,
+        requests_per_minute: args.requests_per_minute,
+        cancellation_token: cancellation_token.clone(),
+        middlewares: match args.diagnose {
+            Some(_) => vec![request_log.clone()],
+            None => Vec::new(),
+        },
         ..Default::default()
     })?;
 
-    let video_id = VideoId::new(args.video_id)?;
-    let yt_stream_response = tydle.get_streams(&video_id).await?;
+    if let Some(video_id) = &args.diagnose {
+        diagnose(
+            &tydle,
+            &request_log,
+            video_id,
+            args.client.unwrap_or_default(),
+            authenticated,
+        )
+        .await?;
+        return Ok(());
+    }
+
+    #[cfg(feature = "server")]
+    if let Some(Command::Serve {
+        listen,
+        queue_db,
+        output_dir,
+        jobs,
+        host_connections,
+    }) = args.command
+    {
+        let queue_options = server::QueueOptions {
+            db_path: queue_db,
+            output_dir,
+            concurrency: jobs,
+            host_connections,
+            events,
+        };
+        return server::serve(
+            tydle,
+            download_options,
+            listen,
+            queue_options,
+            cancellation_token,
+        )
+        .await;
+    }
+
+    let session = Session {
+        tydle,
+        downloader: StreamDownloader::new(download_options)?,
+        cancellation_token,
+        format,
+        section,
+        sub_format,
+        events,
+        paths: Paths::parse(&args.paths)?,
+        downloaded_bytes,
+        min_filesize: args.min_filesize.as_deref().map(parse_bytes).transpose()?,
+        max_filesize: args.max_filesize.as_deref().map(parse_bytes).transpose()?,
+        wait_for_video: args
+            .wait_for_video
+            .as_deref()
+            .map(parse_wait_range)
+            .transpose()?,
+        archive: match &args.download_archive {
+            Some(path) => Some(DownloadArchive::load(path).await?),
+            None => None,
+        },
+    };
+
+    let result = download_videos(&args, &session).await;
+
+    // Keep the cookies YouTube set or rotated for the next run.
+    if let Some(cookies_path) = &args.cookies
+        && let Err(e) = save_cookies(&args, &session.tydle, cookies_path).await
+    {
+        log::warn!("Failed to save cookies to {}: {:#}", cookies_path, e);
+    }
+
+    result
+}
+
+#[cfg(feature = "encrypted-credentials")]
+const CREDENTIALS_PASSPHRASE_ENV: &str = "TYDLE_CREDENTIALS_PASSPHRASE";
+
+/// The passphrase in `CREDENTIALS_PASSPHRASE_ENV`, or else the one saved in the keyring of the OS.
+#[cfg(feature = "encrypted-credentials")]
+fn find_credentials_passphrase() -> Option<String> {
+    if let Ok(passphrase) = std::env::var(CREDENTIALS_PASSPHRASE_ENV) {
+        return Some(passphrase);
+    }
+
+    tydle::credentials::stored_passphrase().unwrap_or_else(|e| {
+        log::warn!(
+            "Failed to read the credentials passphrase from the keyring: {}",
+            e
+        );
+        None
+    })
+}
+
+#[cfg(feature = "encrypted-credentials")]
+fn credentials_passphrase() -> Result<String> {
+    find_credentials_passphrase().ok_or_else(|| {
+        anyhow!(
+            "Set {} to the passphrase of the encrypted cookies, or save it with --save-credentials-passphrase.",
+            CREDENTIALS_PASSPHRASE_ENV
+        )
+    })
+}
+
+#[cfg(feature = "encrypted-credentials")]
+fn save_credentials_passphrase() -> Result<()> {
+    let passphrase = std::env::var(CREDENTIALS_PASSPHRASE_ENV).map_err(|_| {
+        anyhow!(
+            "Set {} to the passphrase to save.",
+            CREDENTIALS_PASSPHRASE_ENV
+        )
+    })?;
+    tydle::credentials::store_passphrase(&passphrase)?;
+    log::info!("Saved the credentials passphrase to the keyring.");
+
+    Ok(())
+}
+
+async fn remove_cache_dir(dir: Option<std::path::PathBuf>) -> Result<()> {
+    let Some(dir) = dir else {
+        bail!("There is no cache directory to remove, set one with --cache-dir.");
+    };
+
+    // Like yt-dlp, refuse directories like the home one that were passed by mistake.
+    let name = dir.to_string_lossy();
+    if !name.contains("cache") && !name.contains("tmp") {
+        bail!(
+            "Not removing the directory {} - this does not look like a cache directory.",
+            dir.display()
+        );
+    }
+
+    log::info!("Removing the cache directory {}", dir.display());
+    match fs::remove_dir_all(&dir).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Client overrides of the file or URL `source`, or none when they fail to load.
+async fn load_client_overrides(source: &str) -> ClientOverrides {
+    let overrides = if source.starts_with("http://") || source.starts_with("https://") {
+        ClientOverrides::fetch(&reqwest::Client::new(), source).await
+    } else {
+        fs::read_to_string(source)
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|json| ClientOverrides::from_json(&json))
+    };
+
+    overrides.unwrap_or_else(|e| {
+        log::warn!(
+            "Failed to load the client overrides from {}, using the compiled clients: {:#}",
+            source,
+            e
+        );
+        ClientOverrides::default()
+    })
+}
+
+async fn read_cookies_file(cookies_path: &str) -> Result<String> {
+    let content = fs::read(cookies_path).await?;
+
+    #[cfg(feature = "encrypted-credentials")]
+    if tydle::credentials::is_encrypted(&content) {
+        let content = tydle::credentials::decrypt(&content, &credentials_passphrase()?)?;
+        return Ok(String::from_utf8(content)?);
+    }
+
+    Ok(String::from_utf8(content)?)
+}
+
+async fn save_cookies(
+    #[cfg_attr(not(feature = "encrypted-credentials"), allow(unused_variables))] args: &TydleArgs,
+    tydle: &Tydle,
+    cookies_path: &str,
+) -> Result<()> {
+    let content = tydle.export_cookies()?.to_netscape().into_bytes();
+
+    // Keep an encrypted file encrypted even without --encrypt-cookies.
+    #[cfg(feature = "encrypted-credentials")]
+    if args.encrypt_cookies
+        || fs::read(cookies_path)
+            .await
+            .is_ok_and(|existing| tydle::credentials::is_encrypted(&existing))
+    {
+        let content = tydle::credentials::encrypt(&content, &credentials_passphrase()?)?;
+        fs::write(cookies_path, content).await?;
+        return Ok(());
+    }
+
+    fs::write(cookies_path, content).await?;
+
+    Ok(())
+}
+
+async fn download_videos(args: &TydleArgs, session: &Session) -> Result<()> {
+    let mut failed = 0;
+    let mut inputs = Vec::new();
+
+    for arg in &args.video_ids {
+        match resolve_videos(args, &session.tydle, arg).await {
+            Ok(ids) => inputs.push((arg, ids)),
+            Err(e) => {
+                let e = e.context(format!("Failed to resolve {}", arg));
+
+                if !args.ignore_errors {
+                    return Err(e);
+                }
+
+                log::error!("{:#}", e);
+                failed += 1;
+            }
+        }
+    }
+
+    let total = inputs.iter().map(|(_, ids)| ids.len()).sum::<usize>();
+    if args.out.as_deref().is_some_and(|out| !has_fields(out)) && total > 1 {
+        bail!("--out can only be a plain path when downloading a single video.");
+    }
+
+    let mut downloads = 0;
+    'inputs: for (arg, video_ids) in &inputs {
+        if args.break_per_input {
+            downloads = 0;
+        }
+
+        for video_id in video_ids {
+            if session.cancellation_token.is_cancelled() {
+                bail!("The operation was cancelled.");
+            }
+
+            if let Some(archive) = &session.archive
+                && archive.contains(video_id)
+            {
+                if !args.break_on_existing {
+                    log::info!("Skipping {}, it is already in the archive.", video_id);
+                    continue;
+                }
+
+                log::info!("{} is already in the archive, stopping {}.", video_id, arg);
+                match args.break_per_input {
+                    true => continue 'inputs,
+                    false => break 'inputs,
+                }
+            }
+
+            match download_video(args, session, video_id).await {
+                Ok(true) => {
+                    if let Some(archive) = &session.archive {
+                        archive.record(video_id).await?;
+                    }
+                    downloads += 1;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    let e = e.context(format!("Failed to download {}", video_id));
+
+                    if !args.ignore_errors {
+                        return Err(e);
+                    }
+
+                    log::error!("{:#}", e);
+                    failed += 1;
+                }
+            }
+
+            if args.max_downloads.is_some_and(|max| downloads >= max) {
+                log::info!("Reached --max-downloads of {}, stopping.", downloads);
+                match args.break_per_input {
+                    true => continue 'inputs,
+                    false => break 'inputs,
+                }
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("{} of {} videos failed.", failed, total);
+    }
+
+    Ok(())
+}
+
+/// Expand a positional argument into the IDs of the videos to download.
+async fn resolve_videos(args: &TydleArgs, tydle: &Tydle, arg: &str) -> Result<Vec<String>> {
+    match parse_target(arg, args.no_playlist)? {
+        Target::Video(video_id) => Ok(vec![video_id]),
+        Target::Playlist(playlist_id) => {
+            let playlist = tydle.get_playlist(&playlist_id).await?;
+            log::info!(
+                "Playlist {}: {} videos",
+                if playlist.title.is_empty() {
+                    playlist_id.as_str()
+                } else {
+                    playlist.title.as_str()
+                },
+                playlist.entries.len()
+            );
+
+            let entries = select_entries(
+                playlist.entries,
+                args.playlist_items.as_deref(),
+                args.playlist_reverse,
+            )?;
+
+            Ok(entries.into_iter().map(|entry| entry.video_id).collect())
+        }
+    }
+}
+
+/// Returns whether the video was downloaded, rather than skipped or only printed.
+async fn download_video(args: &TydleArgs, session: &Session, video_id: &str) -> Result<bool> {
+    let mut event = Event::new(video_id, None);
+    let result = extract_and_download(args, session, video_id, &mut event).await;
+
+    if let Err(e) = &result {
+        session.events.failed(&event, e).await;
+    }
+
+    result
+}
+
+async fn extract_and_download(
+    args: &TydleArgs,
+    session: &Session,
+    video_id: &str,
+    event: &mut Event,
+) -> Result<bool> {
+    let Session {
+        tydle,
+        downloader,
+        cancellation_token,
+        format,
+        section: _,
+        sub_format,
+        events,
+        paths,
+        downloaded_bytes,
+        min_filesize,
+        max_filesize,
+        wait_for_video,
+        archive: _,
+    } = session;
+
+    let video_id = VideoId::new(video_id)?;
+    let (manifest, yt_stream_response) = loop {
+        let manifest = tydle.get_manifest(&video_id).await?;
+        let err = match tydle.get_streams_from_manifest(&manifest).await {
+            Ok(yt_stream_response) => break (manifest, yt_stream_response),
+            Err(err) => err,
+        };
+
+        let (Some((min, max)), TydleError::Upcoming { start_time, .. }) = (wait_for_video, &err)
+        else {
+            return Err(err.into());
+        };
+
+        // Wait until the scheduled start when it's within the range, otherwise poll at a random point of it.
+        let secs = match start_time {
+            Some(start_time) => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                (start_time.saturating_sub(now) as f64).clamp(*min, *max)
+            }
+            None => random_between(*min, *max),
+        };
+
+        log::info!("{}, retrying in {:.0} seconds.", err, secs);
+        let duration = Duration::try_from_secs_f64(secs)?;
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => {}
+            _ = cancellation_token.cancelled() => bail!("Cancelled while waiting for the video."),
+        }
+    };
 
     log::info!("Got player URL: {}", yt_stream_response.player_url);
 
     if args.list_formats {
-        list_formats(&yt_stream_response.streams);
+        list_formats(&yt_stream_response.streams, args.json)?;
     }
 
-    let download_stream = match format {
-        Format::BestAudio => {
-            let mut streams = yt_stream_response
-                .streams
-                .audio_only()
-                .with_highest_bitrate()
-                .into_iter()
-                .collect::<Vec<_>>();
+    // Printing fields implies simulating.
+    let simulate = args.simulate || !args.print.is_empty();
+    let needs_video_info = args.write_info_json
+        || args.write_subs
+        || args.write_auto_subs
+        || args.embed_subs
+        || args.write_thumbnail
+        || args.embed_thumbnail
+        || !args.exec.is_empty()
+        || args.list_subs
+        || args.list_thumbnails
+        || args.play
+        || casts(args)
+        || !events.is_empty()
+        || args.out.as_deref().is_some_and(has_fields);
+    // The match filter applies to --get-url too.
+    let video_info = if (needs_video_info && !args.get_url)
+        || !args.print.is_empty()
+        || args.match_filter.is_some()
+    {
+        Some(tydle.get_video_info_from_manifest(&manifest).await?)
+    } else {
+        None
+    };
+
+    if let Some(video_info) = &video_info {
+        if args.list_subs {
+            list_subtitles(&video_info.subtitles, args.json)?;
+        }
+
+        if args.list_thumbnails {
+            list_thumbnails(&video_info.thumbnails, args.json)?;
+        }
+    }
+
+    if let Some(video_info) = &video_info
+        && args
+            .match_filter
+            .as_ref()
+            .is_some_and(|match_filter| !match_filter.matches(video_info))
+    {
+        log::info!(
+            "Skipping {}, it does not pass the match filter.",
+            video_id.as_str()
+        );
+        return Ok(false);
+    }
+
+    // Live streams are recorded from their HLS manifest instead.
+    let selection = match yt_stream_response.is_live {
+        true => None,
+        false => yt_stream_response.streams.select(format),
+    };
+    let info = video_info.as_ref().map(|video_info| {
+        let mut info = info_dict(&video_id, video_info, &yt_stream_response.streams);
+        if let Some(selection) = &selection {
+            add_selected_format(&mut info, selection);
+        }
+        info
+    });
+    let output_path = OutputPath::new(args, paths, &video_id, info.as_ref());
+    let stem = output_path.stem.clone();
+    if !simulate {
+        create_parent_dir(&stem).await?;
+    }
+
+    let mut written_subtitles = Vec::new();
+    if let (Some(video_info), Some(info)) = (&video_info, &info) {
+        event.info = Some(info.clone());
+
+        for template in &args.print {
+            println!("{}", render_template(template, info));
+        }
+
+        if args.write_info_json && !simulate {
+            write_info_json(info, &stem).await?;
+        }
+
+        if (args.write_subs || args.write_auto_subs) && !simulate {
+            written_subtitles = write_subtitles(
+                tydle,
+                video_info,
+                &args.sub_langs,
+                args.write_auto_subs,
+                *sub_format,
+                &stem,
+            )
+            .await?;
+        }
+    }
+
+    if yt_stream_response.is_live {
+        let (manifest_url, is_hls) = match (
+            &yt_stream_response.hls_manifest_url,
+            &yt_stream_response.dash_manifest_url,
+        ) {
+            (Some(manifest_url), _) => (manifest_url, true),
+            (None, Some(manifest_url)) => (manifest_url, false),
+            (None, None) => bail!("The live stream has neither an HLS nor a DASH manifest."),
+        };
 
-            streams.sort_by_key(|s| match s.ext {
-                Ext::M4a | Ext::Mp4 => 0,
-                _ => 1,
-            });
-
-            streams
-                .first()
-                .cloned()
-                .ok_or(anyhow!("No matching stream."))
-        }
-        Format::BestVideo => {
-            let mut streams = yt_stream_response
-                .streams
-                .video_only()
-                .with_highest_bitrate()
+        if args.get_url {
+            println!("{}", manifest_url);
+            return Ok(false);
+        }
+
+        if args.play && !simulate {
+            let title = video_info
+                .as_ref()
+                .map_or(video_id.as_str(), |info| &info.title);
+            return play(
+                args.player,
+                manifest_url,
+                None,
+                title,
+                &downloader.options().headers,
+            )
+            .await
+            .map(|()| false);
+        }
+
+        #[cfg(feature = "cast")]
+        if args.cast.is_some() {
+            bail!("Live streams can't be cast, only progressive formats.");
+        }
+
+        if let Some(video_info) = &video_info
+            && args.write_thumbnail
+            && !simulate
+        {
+            save_thumbnail(tydle, video_info, &stem, args.convert_thumbnails).await?;
+        }
+
+        if simulate || args.skip_download {
+            log::info!("Skipping the recording of {}", manifest_url);
+            return Ok(false);
+        }
+
+        let output = output_path.with_ext(match is_hls {
+            true => Ext::Ts.as_str(),
+            false => Ext::Mp4.as_str(),
+        });
+        let prefer_lowest = format.prefers_worst();
+        let recorder = LiveRecorder::new(downloader.client().clone(), args.max_duration);
+
+        log::info!("Recording live stream to {}", output);
+        match is_hls {
+            true => {
+                recorder
+                    .record(manifest_url, prefer_lowest, &output, cancellation_token)
+                    .await?
+            }
+            false => {
+                recorder
+                    .record_dash(manifest_url, prefer_lowest, &output, cancellation_token)
+                    .await?
+            }
+        }
+        return Ok(true);
+    }
+
+    let selection = selection.ok_or(TydleError::FormatNotFound(
+        args.format.clone().unwrap_or("bestvideo".into()),
+    ))?;
+
+    if let Some(estimate) = selection.filesize_estimate() {
+        if min_filesize.is_some_and(|min| estimate.bytes < min) {
+            log::info!(
+                "Skipping {}, {} is smaller than the minimum file size.",
+                video_id.as_str(),
+                format_size_estimate(estimate)
+            );
+            return Ok(false);
+        }
+
+        if max_filesize.is_some_and(|max| estimate.bytes > max) {
+            log::info!(
+                "Skipping {}, {} is larger than the maximum file size.",
+                video_id.as_str(),
+                format_size_estimate(estimate)
+            );
+            return Ok(false);
+        }
+    }
+
+    let output = output_path.with_ext(selection.ext().as_str());
+    let mut sources = Vec::new();
+    for stream in selection.streams() {
+        sources.push(tydle.resolve_url(stream, &yt_stream_response).await?);
+    }
+
+    if args.get_url {
+        for source in &sources {
+            println!("{}", source);
+        }
+        return Ok(false);
+    }
+
+    if let Some(manifest_format) = args.write_manifest {
+        if !simulate {
+            let streams = selection
+                .streams()
                 .into_iter()
+                .zip(sources.iter().map(String::as_str))
                 .collect::<Vec<_>>();
+            write_manifest(manifest_format, &streams, &stem).await?;
+        }
+        return Ok(false);
+    }
 
-            streams.sort_by_key(|s| match s.ext {
-                Ext::M4a | Ext::Mp4 => 0,
-                _ => 1,
-            });
-
-            streams
-                .first()
-                .cloned()
-                .ok_or(anyhow!("No matching stream."))
-        }
-        Format::WorstAudio => {
-            let streams = yt_stream_response
-                .streams
-                .audio_only()
-                .with_lowest_bitrate();
-            streams
-                .into_iter()
-                .collect::<Vec<_>>()
-                .first()
-                .cloned()
-                .ok_or(anyhow!("No matching stream."))
-        }
-        Format::WorstVideo => {
-            let streams = yt_stream_response
-                .streams
-                .video_only()
-                .with_lowest_bitrate();
-            streams
-                .into_iter()
-                .collect::<Vec<_>>()
-                .first()
-                .cloned()
-                .ok_or(anyhow!("No matching stream."))
+    if args.play && !simulate {
+        let title = video_info
+            .as_ref()
+            .map_or(video_id.as_str(), |info| &info.title);
+        let mut headers = downloader.options().headers.clone();
+        // Streams of some clients are only served to the user agent that requested them.
+        if let Some(user_agent) = tydle.client_user_agent(&selection.stream.client)
+            && !headers
+                .keys()
+                .any(|name| name.eq_ignore_ascii_case("User-Agent"))
+        {
+            headers.insert("User-Agent".into(), user_agent);
         }
-    }?;
 
-    let output = args.out.unwrap_or(format!(
-        "{}.{}",
-        video_id.as_str(),
-        download_stream.ext.as_str()
-    ));
-    let source = match download_stream.source {
-        YtStreamSource::URL(url) => url,
-        YtStreamSource::Signature(signature) => {
-            tydle
-                .decipher_signature(signature, yt_stream_response.player_url)
+        return play(
+            args.player,
+            &sources[0],
+            sources.get(1).map(String::as_str),
+            title,
+            &headers,
+        )
+        .await
+        .map(|()| false);
+    }
+
+    #[cfg(feature = "cast")]
+    if let Some(name) = &args.cast
+        && !simulate
+    {
+        if selection.audio.is_some() || selection.stream.codec.acodec.as_deref() == Some("none") {
+            bail!(
+                "Casting needs a single format with both video and audio, like \"best\", renderers can't merge streams."
+            );
+        }
+
+        let title = video_info
+            .as_ref()
+            .map_or(video_id.as_str(), |info| &info.title);
+        let content_type = format!("video/{}", selection.ext().as_str());
+
+        return cast::cast(name, &sources[0], &content_type, title)
+            .await
+            .map(|()| false);
+    }
+
+    if simulate || args.skip_download {
+        if let Some(video_info) = &video_info
+            && args.write_thumbnail
+            && !simulate
+        {
+            save_thumbnail(tydle, video_info, &stem, args.convert_thumbnails).await?;
+        }
+
+        log::info!(
+            "Skipping the download of format {} to {}",
+            selection.format_id(),
+            output
+        );
+        return Ok(false);
+    }
+
+    sleep_before_download(args).await;
+
+    events.emit(event).await;
+    downloaded_bytes.store(0, Ordering::Relaxed);
+    let download = async {
+        let output = match &selection.audio {
+            None => {
+                download_stream(
+                    session,
+                    &video_id,
+                    &yt_stream_response,
+                    &selection.stream,
+                    &sources[0],
+                    &output,
+                )
                 .await?
+            }
+            Some(audio) => {
+                let stem = Path::new(&output).with_extension("");
+                let stem = stem.to_string_lossy();
+                if paths.temp.is_some() {
+                    create_parent_dir(&paths.temp(&stem)).await?;
+                }
+                let video_part = download_stream(
+                    session,
+                    &video_id,
+                    &yt_stream_response,
+                    &selection.stream,
+                    &sources[0],
+                    &paths.temp(&format!(
+                        "{}.f{}.{}",
+                        stem, selection.stream.itag, selection.stream.ext
+                    )),
+                )
+                .await?;
+                let audio_part = download_stream(
+                    session,
+                    &video_id,
+                    &yt_stream_response,
+                    audio,
+                    &sources[1],
+                    &paths.temp(&format!("{}.f{}.{}", stem, audio.itag, audio.ext)),
+                )
+                .await?;
+
+                log::info!("Merging formats into {}", output);
+                merge_streams(&video_part, &audio_part, &output).await?;
+                fs::remove_file(&video_part).await?;
+                fs::remove_file(&audio_part).await?;
+                output
+            }
+        };
+        Ok::<_, anyhow::Error>(output)
+    };
+    let output = events
+        .watch_progress(event, downloaded_bytes, selection.file_size(), download)
+        .await?;
+    // Post-processors see the merged file as a stream of its container.
+    let mut download_stream = selection.stream.clone();
+    download_stream.ext = selection.ext();
+
+    if let Some(video_info) = &video_info
+        && (args.write_thumbnail || args.embed_thumbnail)
+        && let Some(thumbnail) =
+            save_thumbnail(tydle, video_info, &stem, args.convert_thumbnails).await?
+    {
+        if args.embed_thumbnail {
+            log::info!("Embedding thumbnail in {}", output);
+            embed_thumbnail(&output, download_stream.ext, &thumbnail).await?;
+        }
+
+        if !args.write_thumbnail {
+            fs::remove_file(&thumbnail).await?;
+        }
+    }
+
+    if let Some(video_info) = &video_info
+        && args.embed_subs
+    {
+        // ffmpeg reads WebVTT, tracks written in another format are fetched again as WebVTT.
+        let reused = *sub_format == YtSubtitleFormat::Vtt && !written_subtitles.is_empty();
+        let subtitles = match reused {
+            true => written_subtitles,
+            false => {
+                write_subtitles(
+                    tydle,
+                    video_info,
+                    &args.sub_langs,
+                    args.write_auto_subs,
+                    YtSubtitleFormat::Vtt,
+                    &paths.temp(&stem),
+                )
+                .await?
+            }
+        };
+
+        if !subtitles.is_empty() {
+            log::info!("Embedding subtitles in {}", output);
+            embed_subtitles(&output, download_stream.ext, &subtitles).await?;
+        }
+
+        if !reused {
+            for (path, _) in &subtitles {
+                fs::remove_file(path).await?;
+            }
+        }
+    }
+
+    if let Some(video_info) = &video_info {
+        downloader
+            .post_process(&PostProcessContext {
+                path: &output,
+                video_info,
+                stream: &download_stream,
+            })
+            .await?;
+    }
+
+    events.finished(event, &output).await;
+
+    Ok(true)
+}
+
+/// Download `stream` from `source`, only the requested section if there is one.
+async fn download_stream(
+    session: &Session,
+    video_id: &VideoId,
+    stream_response: &YtStreamResponse,
+    stream: &YtStream,
+    source: &str,
+    output: &str,
+) -> Result<String> {
+    retry_throttled(
+        &session.tydle,
+        video_id,
+        stream,
+        stream_response,
+        source,
+        |source| async move {
+            match session.section {
+                Some(section) => {
+                    session
+                        .downloader
+                        .download_section(
+                            &source,
+                            output,
+                            stream,
+                            section,
+                            &session.cancellation_token,
+                        )
+                        .await
+                }
+                None => {
+                    session
+                        .downloader
+                        .download(
+                            &source,
+                            output,
+                            stream.file_size,
+                            &session.cancellation_token,
+                        )
+                        .await
+                }
+            }
+        },
+    )
+    .await
+}
+
+/// Whether the video is cast with `--cast`, which is only there with the `cast` feature.
+fn casts(args: &TydleArgs) -> bool {
+    #[cfg(feature = "cast")]
+    return args.cast.is_some();
+    #[cfg(not(feature = "cast"))]
+    {
+        let _ = args;
+        false
+    }
+}
+
+/// Where a video is written: `--out` rendered against its info dict, in the home path.
+struct OutputPath {
+    /// The path without its extension, used to name files written next to it.
+    stem: String,
+    /// The whole path, unless its extension is the `%(ext)s` of the template.
+    path: Option<String>,
+}
+
+impl OutputPath {
+    fn new(args: &TydleArgs, paths: &Paths, video_id: &VideoId, info: Option<&Value>) -> Self {
+        let template = args.out.as_deref().unwrap_or(DEFAULT_OUTPUT_TEMPLATE);
+        let mode = FilenameMode::new(args.restrict_filenames, args.windows_filenames);
+        // Templates with other fields make the video info get fetched.
+        let id_only = json!({ "id": video_id.as_str() });
+        let info = info.unwrap_or(&id_only);
+
+        match template.strip_suffix(".%(ext)s") {
+            Some(stem_template) => Self {
+                stem: paths.home(&render_filename(stem_template, info, mode)),
+                path: None,
+            },
+            None => {
+                let path = paths.home(&render_filename(template, info, mode));
+                Self {
+                    stem: Path::new(&path)
+                        .with_extension("")
+                        .to_string_lossy()
+                        .into_owned(),
+                    path: Some(path),
+                }
+            }
         }
+    }
+
+    /// The path of the video, with `ext` unless the template has its own extension.
+    fn with_ext(&self, ext: &str) -> String {
+        self.path
+            .clone()
+            .unwrap_or_else(|| format!("{}.{}", self.stem, ext))
+    }
+}
+
+/// Create the directory `path` is in, like the ones of the fields of `--out`.
+async fn create_parent_dir(path: &str) -> Result<()> {
+    match Path::new(path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => Ok(fs::create_dir_all(parent).await?),
+        _ => Ok(()),
+    }
+}
+
+async fn save_thumbnail(
+    tydle: &Tydle,
+    video_info: &YtVideoInfo,
+    stem: &str,
+    format: ThumbnailFormat,
+) -> Result<Option<String>> {
+    let Some(thumbnail) = best_thumbnail(&video_info.thumbnails) else {
+        log::warn!("The video has no thumbnails.");
+        return Ok(None);
     };
 
-    if !args.get_url {
-        let worker_count = num_cpus::get();
-        let downloader = StreamDownloader::new(worker_count);
+    let path = write_thumbnail(tydle, thumbnail, stem, format).await?;
+    log::info!("Wrote thumbnail to {}", path);
 
-        downloader.download(&source, &output).await?;
-    } else {
-        println!("{}", source);
+    Ok(Some(path))
+}
+
+async fn sleep_before_download(args: &TydleArgs) {
+    let Some(min) = args.sleep_interval else {
+        return;
+    };
+
+    let secs = random_between(min, args.max_sleep_interval.unwrap_or(min));
+
+    let Ok(duration) = Duration::try_from_secs_f64(secs) else {
+        return;
+    };
+
+    log::info!("Sleeping {:.2} seconds", secs);
+    tokio::time::sleep(duration).await;
+}
+
+/// Random number in `min..=max`. Only used to spread out requests, so the randomly seeded hasher is random enough.
+fn random_between(min: f64, max: f64) -> f64 {
+    if max <= min {
+        return min;
     }
 
-    Ok(())
+    let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+    min + (max - min) * random
+}
+
+/// Parse a `MIN[-MAX]` range of seconds.
+fn parse_wait_range(range: &str) -> Result<(f64, f64)> {
+    let (min, max) = range.split_once('-').unwrap_or((range, range));
+    let (min, max): (f64, f64) = (min.trim().parse()?, max.trim().parse()?);
+
+    if !min.is_finite() || !max.is_finite() || min < 0.0 || max < min {
+        bail!("Invalid wait range \"{}\".", range);
+    }
+
+    Ok((min, max))
 }
 
-fn list_formats(streams: &Vec<YtStream>) {
+fn list_formats(streams: &[YtStream], json: bool) -> Result<()> {
+    if json {
+        let formats: Vec<_> = streams.iter().map(format_dict).collect();
+        println!("{}", serde_json::to_string_pretty(&formats)?);
+        return Ok(());
+    }
+
     println!(
-        "{:<5} {:<8} {:<10} {:<3} | {:<12} {:<10} {:<6} | {:<14} {}",
+        "{:<5} {:<8} {:<10} {:<3} | {:<12} {:<10} {:<6} | {:<14} {:<10} {}",
         "ID".yellow(),
         "EXT".yellow(),
         "RESOLUTION".yellow(),
@@ -183,13 +1809,14 @@ fn list_formats(streams: &Vec<YtStream>) {
         "PROTO".yellow(),
         "VCODEC".yellow(),
         "ACODEC".yellow(),
+        "MORE INFO".yellow(),
     );
-    println!("{}", "-".repeat(100));
+    println!("{}", "-".repeat(120));
 
     for stream in streams {
         let resolution = get_resolution(stream.height, stream.width);
         println!(
-            "{:<5} {:<8} {:<10} {:<3} | {:<12} {:<10} {:<6} | {:<14} {}",
+            "{:<5} {:<8} {:<10} {:<3} | {:<12} {:<10} {:<6} | {:<14} {:<10} {}",
             stream.itag.to_string().green(),
             stream.ext.as_str(),
             if resolution == "" {
@@ -198,16 +1825,91 @@ fn list_formats(streams: &Vec<YtStream>) {
                 resolution.as_str()
             },
             stream.fps,
-            if let Some(file_size) = stream.file_size {
-                format!("~{}", human_readable_size(file_size))
-            } else {
-                "".into()
-            }
-            .bright_black(),
+            stream
+                .filesize_estimate()
+                .map(format_size_estimate)
+                .unwrap_or_default()
+                .bright_black(),
             compact_num(stream.tbr as u64),
-            "https", // stream.proto
+            stream.protocol.as_str(),
             stream.codec.vcodec.clone().unwrap_or_default(),
             stream.codec.acodec.clone().unwrap_or_default(),
+            stream.format_note,
+        );
+    }
+
+    Ok(())
+}
+
+fn list_subtitles(subtitles: &[YtSubtitle], json: bool) -> Result<()> {
+    if json {
+        let subtitles: Vec<_> = subtitles.iter().map(subtitle_dict).collect();
+        println!("{}", serde_json::to_string_pretty(&subtitles)?);
+        return Ok(());
+    }
+
+    if subtitles.is_empty() {
+        log::info!("The video has no subtitles.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<12} {:<10} {}",
+        "LANGUAGE".yellow(),
+        "TYPE".yellow(),
+        "NAME".yellow()
+    );
+    println!("{}", "-".repeat(50));
+
+    for subtitle in subtitles {
+        println!(
+            "{:<12} {:<10} {}",
+            subtitle.language_code.green(),
+            if subtitle.is_auto_generated {
+                "automatic"
+            } else {
+                "manual"
+            },
+            subtitle.name.as_deref().unwrap_or_default(),
+        );
+    }
+
+    Ok(())
+}
+
+fn list_thumbnails(thumbnails: &[YtThumbnail], json: bool) -> Result<()> {
+    if json {
+        let thumbnails: Vec<_> = thumbnails.iter().map(thumbnail_dict).collect();
+        println!("{}", serde_json::to_string_pretty(&thumbnails)?);
+        return Ok(());
+    }
+
+    if thumbnails.is_empty() {
+        log::info!("The video has no thumbnails.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<3} {:<10} {}",
+        "ID".yellow(),
+        "RESOLUTION".yellow(),
+        "URL".yellow()
+    );
+    println!("{}", "-".repeat(100));
+
+    for (id, thumbnail) in thumbnails.iter().enumerate() {
+        let resolution = match (thumbnail.width, thumbnail.height) {
+            (Some(width), Some(height)) => format!("{}x{}", width, height),
+            _ => "unknown".into(),
+        };
+
+        println!(
+            "{:<3} {:<10} {}",
+            id.to_string().green(),
+            resolution,
+            thumbnail.url
         );
     }
+
+    Ok(())
 }
@@ -0,0 +1,280 @@
+//! DASH and HLS manifests that point a player at the resolved URLs of chosen streams, so that it
+//! can play them adaptively without tydle downloading anything.
+
+use std::{fmt::Write, str::FromStr};
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::yt_interface::{Ext, YtStream};
+
+/// A stream along with the URL `Tydle::resolve_url` resolved it to.
+pub type ResolvedStream<'a> = (&'a YtStream, &'a str);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    /// A DASH MPD, for mpv, VLC, ffmpeg and dash.js.
+    Mpd,
+    /// An HLS master playlist and a media playlist per stream, for Safari, hls.js and AVPlayer.
+    M3u8,
+}
+
+impl FromStr for ManifestFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(format: &str) -> Result<Self> {
+        match format {
+            "mpd" | "dash" => Ok(ManifestFormat::Mpd),
+            "m3u8" | "hls" => Ok(ManifestFormat::M3u8),
+            _ => bail!(
+                "Unknown manifest format \"{}\", expected \"mpd\" or \"m3u8\".",
+                format
+            ),
+        }
+    }
+}
+
+/// An HLS master playlist and the media playlists it references by `file_name`, which have to be
+/// written next to it.
+#[derive(Debug, Clone)]
+pub struct HlsPlaylists {
+    pub master: String,
+    /// File names and contents of the media playlists.
+    pub media: Vec<(String, String)>,
+}
+
+fn is_audio(stream: &YtStream) -> bool {
+    stream.codec.vcodec.as_deref() == Some("none")
+}
+
+fn codecs(stream: &YtStream) -> String {
+    [&stream.codec.vcodec, &stream.codec.acodec]
+        .into_iter()
+        .flatten()
+        .filter(|codec| *codec != "none")
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn mime_type(stream: &YtStream) -> String {
+    format!(
+        "{}/{}",
+        if is_audio(stream) { "audio" } else { "video" },
+        match stream.ext {
+            Ext::Webm => "webm",
+            _ => "mp4",
+        }
+    )
+}
+
+/// Byte ranges of the initialization segment and segment index, which players need to seek in a
+/// stream that is a single file.
+fn segment_ranges(stream: &YtStream) -> Result<((u64, u64), (u64, u64))> {
+    stream.init_range.zip(stream.index_range).ok_or(anyhow!(
+        "Format {} has no segment index, so it can't be played from a manifest.",
+        stream.itag
+    ))
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A static DASH MPD with an adaptation set for the video streams and one for the audio streams
+/// of `streams`.
+pub fn generate_mpd(streams: &[ResolvedStream]) -> Result<String> {
+    let duration = streams
+        .iter()
+        .map(|(stream, _)| stream.format_duration / 1000.0)
+        .fold(0.0, f64::max);
+
+    let mut mpd = String::new();
+    writeln!(mpd, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        mpd,
+        r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" profiles="urn:mpeg:dash:profile:isoff-on-demand:2011" type="static" minBufferTime="PT1.5S" mediaPresentationDuration="PT{:.3}S">"#,
+        duration
+    )?;
+    writeln!(mpd, "  <Period>")?;
+
+    for audio in [false, true] {
+        let set = streams
+            .iter()
+            .filter(|(stream, _)| is_audio(stream) == audio)
+            .collect::<Vec<_>>();
+        let Some((first, _)) = set.first() else {
+            continue;
+        };
+
+        writeln!(
+            mpd,
+            r#"    <AdaptationSet mimeType="{}" subsegmentAlignment="true">"#,
+            mime_type(first)
+        )?;
+        for (stream, url) in set {
+            let ((init_start, init_end), (index_start, index_end)) = segment_ranges(stream)?;
+
+            write!(
+                mpd,
+                r#"      <Representation id="{}" bandwidth="{}" codecs="{}""#,
+                stream.itag,
+                stream.tbr.round() as u64,
+                codecs(stream)
+            )?;
+            if let (Some(width), Some(height)) = (stream.width, stream.height) {
+                write!(mpd, r#" width="{}" height="{}""#, width, height)?;
+            }
+            if !audio && stream.fps > 0 {
+                write!(mpd, r#" frameRate="{}""#, stream.fps)?;
+            }
+            if let Some(asr) = stream.asr {
+                write!(mpd, r#" audioSamplingRate="{}""#, asr)?;
+            }
+            writeln!(mpd, ">")?;
+            writeln!(mpd, "        <BaseURL>{}</BaseURL>", escape_xml(url))?;
+            writeln!(
+                mpd,
+                r#"        <SegmentBase indexRange="{}-{}"><Initialization range="{}-{}"/></SegmentBase>"#,
+                index_start, index_end, init_start, init_end
+            )?;
+            writeln!(mpd, "      </Representation>")?;
+        }
+        writeln!(mpd, "    </AdaptationSet>")?;
+    }
+
+    writeln!(mpd, "  </Period>")?;
+    writeln!(mpd, "</MPD>")?;
+
+    Ok(mpd)
+}
+
+/// An HLS master playlist for `streams`, with the audio streams as renditions of every video
+/// stream. The media playlists are named `{name}.f{itag}.m3u8`. Only mp4 streams are supported,
+/// HLS can't carry webm.
+pub fn generate_hls(streams: &[ResolvedStream], name: &str) -> Result<HlsPlaylists> {
+    let mut media = Vec::new();
+    for (stream, url) in streams {
+        if matches!(stream.ext, Ext::Webm) {
+            bail!(
+                "Format {} is webm, HLS playlists can only reference mp4 formats.",
+                stream.itag
+            );
+        }
+        media.push((
+            format!("{}.f{}.m3u8", name, stream.itag),
+            media_playlist(stream, url)?,
+        ));
+    }
+
+    let (audio, video): (Vec<_>, Vec<_>) = streams
+        .iter()
+        .zip(&media)
+        .partition(|((stream, _), _)| is_audio(stream));
+
+    let mut master = String::new();
+    writeln!(master, "#EXTM3U")?;
+    writeln!(master, "#EXT-X-VERSION:7")?;
+    writeln!(master, "#EXT-X-INDEPENDENT-SEGMENTS")?;
+
+    if video.is_empty() {
+        for ((stream, _), (file_name, _)) in &audio {
+            writeln!(
+                master,
+                r#"#EXT-X-STREAM-INF:BANDWIDTH={},CODECS="{}""#,
+                stream.tbr.round() as u64,
+                codecs(stream)
+            )?;
+            writeln!(master, "{}", file_name)?;
+        }
+        return Ok(HlsPlaylists { master, media });
+    }
+
+    for (i, ((stream, _), (file_name, _))) in audio.iter().enumerate() {
+        let track_name = stream
+            .audio_track
+            .display_name
+            .clone()
+            .unwrap_or(format!("{} kbps", (stream.tbr / 1000.0).round()));
+        writeln!(
+            master,
+            r#"#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="audio",NAME="{}",DEFAULT={},AUTOSELECT=YES,URI="{}""#,
+            track_name.replace('"', "'"),
+            if i == 0 { "YES" } else { "NO" },
+            file_name
+        )?;
+    }
+
+    let audio_bandwidth = audio
+        .iter()
+        .map(|((stream, _), _)| stream.tbr.round() as u64)
+        .max()
+        .unwrap_or(0);
+    let audio_codecs = audio.first().map(|((stream, _), _)| codecs(stream));
+
+    for ((stream, _), (file_name, _)) in &video {
+        let stream_codecs = [Some(codecs(stream)), audio_codecs.clone()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(",");
+
+        write!(
+            master,
+            r#"#EXT-X-STREAM-INF:BANDWIDTH={},CODECS="{}""#,
+            stream.tbr.round() as u64 + audio_bandwidth,
+            stream_codecs
+        )?;
+        if let (Some(width), Some(height)) = (stream.width, stream.height) {
+            write!(master, ",RESOLUTION={}x{}", width, height)?;
+        }
+        if stream.fps > 0 {
+            write!(master, ",FRAME-RATE={}", stream.fps)?;
+        }
+        if !audio.is_empty() {
+            write!(master, r#",AUDIO="audio""#)?;
+        }
+        writeln!(master)?;
+        writeln!(master, "{}", file_name)?;
+    }
+
+    Ok(HlsPlaylists { master, media })
+}
+
+/// A media playlist playing `stream` as one byte range segment after its initialization segment
+/// and segment index.
+fn media_playlist(stream: &YtStream, url: &str) -> Result<String> {
+    let ((init_start, init_end), (_, index_end)) = segment_ranges(stream)?;
+    let file_size = stream.file_size.ok_or(anyhow!(
+        "The size of format {} is unknown, so it can't be played from an HLS playlist.",
+        stream.itag
+    ))?;
+    let duration = stream.format_duration / 1000.0;
+    let media_start = index_end + 1;
+
+    let mut playlist = String::new();
+    writeln!(playlist, "#EXTM3U")?;
+    writeln!(playlist, "#EXT-X-VERSION:7")?;
+    writeln!(playlist, "#EXT-X-TARGETDURATION:{}", duration.ceil() as u64)?;
+    writeln!(playlist, "#EXT-X-PLAYLIST-TYPE:VOD")?;
+    writeln!(
+        playlist,
+        r#"#EXT-X-MAP:URI="{}",BYTERANGE="{}@{}""#,
+        url,
+        init_end - init_start + 1,
+        init_start
+    )?;
+    writeln!(playlist, "#EXTINF:{:.3},", duration)?;
+    writeln!(
+        playlist,
+        "#EXT-X-BYTERANGE:{}@{}",
+        file_size.saturating_sub(media_start),
+        media_start
+    )?;
+    writeln!(playlist, "{}", url)?;
+    writeln!(playlist, "#EXT-X-ENDLIST")?;
+
+    Ok(playlist)
+}
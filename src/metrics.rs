@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    cache::Cache,
+    http::{BoxFuture, HttpBody, HttpRequest, HttpResponse, HttpTransport},
+};
+
+/// Counters shared by the extractor, its cache and transport, and the decipherer of an instance.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    api_calls: Mutex<HashMap<String, u64>>,
+    bytes_downloaded: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    deciphers: AtomicU64,
+    decipher_micros: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record_api_call(&self, client: &str) -> Result<()> {
+        let mut api_calls = self.api_calls.lock().map_err(|e| anyhow!(e.to_string()))?;
+        *api_calls.entry(client.to_string()).or_default() += 1;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "decipher")]
+    pub fn record_decipher(&self, elapsed_secs: f64) {
+        self.deciphers.fetch_add(1, Ordering::Relaxed);
+        self.decipher_micros
+            .fetch_add((elapsed_secs * 1_000_000.0) as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Result<TydleMetrics> {
+        Ok(TydleMetrics {
+            api_calls: self
+                .api_calls
+                .lock()
+                .map_err(|e| anyhow!(e.to_string()))?
+                .clone(),
+            bytes_downloaded: self.bytes_downloaded.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+            deciphers: self.deciphers.load(Ordering::Relaxed),
+            decipher_time: Duration::from_micros(self.decipher_micros.load(Ordering::Relaxed)),
+        })
+    }
+}
+
+/// Snapshot of what a `Tydle` instance and its clones did since they were created, see `Tydle::metrics`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TydleMetrics {
+    /// InnerTube API calls by client name.
+    pub api_calls: HashMap<String, u64>,
+    /// Bytes of every response body read through the transport, including the streams downloaded
+    /// with `Tydle::transport`.
+    pub bytes_downloaded: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    /// Signatures and `n` parameters deciphered without a cached result.
+    pub deciphers: u64,
+    /// Total time spent on `deciphers`.
+    pub decipher_time: Duration,
+}
+
+impl TydleMetrics {
+    /// Share of cache lookups that found an entry, or `None` before the first lookup.
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let lookups = self.cache_hits + self.cache_misses;
+        (lookups > 0).then(|| self.cache_hits as f64 / lookups as f64)
+    }
+
+    /// Average time a decipher took, or `None` before the first one.
+    pub fn average_decipher_time(&self) -> Option<Duration> {
+        (self.deciphers > 0).then(|| self.decipher_time / self.deciphers as u32)
+    }
+
+    /// Format the metrics in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut clients: Vec<_> = self.api_calls.iter().collect();
+        clients.sort();
+
+        out.push_str("# HELP tydle_api_calls_total InnerTube API calls by client.\n");
+        out.push_str("# TYPE tydle_api_calls_total counter\n");
+        for (client, calls) in clients {
+            let _ = writeln!(
+                out,
+                "tydle_api_calls_total{{client=\"{}\"}} {}",
+                client, calls
+            );
+        }
+
+        let counters = [
+            (
+                "tydle_downloaded_bytes_total",
+                "Bytes of response bodies read.",
+                self.bytes_downloaded.to_string(),
+            ),
+            (
+                "tydle_cache_hits_total",
+                "Cache lookups that found an entry.",
+                self.cache_hits.to_string(),
+            ),
+            (
+                "tydle_cache_misses_total",
+                "Cache lookups that found nothing.",
+                self.cache_misses.to_string(),
+            ),
+            (
+                "tydle_deciphers_total",
+                "Signatures deciphered without a cached result.",
+                self.deciphers.to_string(),
+            ),
+            (
+                "tydle_decipher_seconds_total",
+                "Time spent deciphering signatures.",
+                self.decipher_time.as_secs_f64().to_string(),
+            ),
+        ];
+
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+
+        out
+    }
+}
+
+/// Counts the hits and misses of the cache it wraps.
+pub(crate) struct MetricsCache {
+    cache: Arc<dyn Cache>,
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsCache {
+    pub fn new(cache: Arc<dyn Cache>, metrics: Arc<Metrics>) -> Self {
+        Self { cache, metrics }
+    }
+}
+
+impl Cache for MetricsCache {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        let value = self.cache.get(key)?;
+        let counter = match value {
+            Some(_) => &self.metrics.cache_hits,
+            None => &self.metrics.cache_misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        Ok(value)
+    }
+
+    fn add(&self, key: String, value: String) -> Result<()> {
+        self.cache.add(key, value)
+    }
+
+    fn add_with_ttl(&self, key: String, value: String, ttl: Duration) -> Result<()> {
+        self.cache.add_with_ttl(key, value, ttl)
+    }
+
+    fn contains(&self, key: &str) -> Result<bool> {
+        self.cache.contains(key)
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.cache.remove(key)
+    }
+
+    fn remove_prefixed(&self, prefix: &str) -> Result<()> {
+        self.cache.remove_prefixed(prefix)
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.cache.clear()
+    }
+}
+
+/// Counts the bytes of the response bodies of the transport it wraps.
+pub(crate) struct MetricsTransport {
+    transport: Arc<dyn HttpTransport>,
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsTransport {
+    pub fn new(transport: Arc<dyn HttpTransport>, metrics: Arc<Metrics>) -> Self {
+        Self { transport, metrics }
+    }
+}
+
+impl HttpTransport for MetricsTransport {
+    fn send<'a>(&'a self, request: HttpRequest) -> BoxFuture<'a, Result<HttpResponse>> {
+        Box::pin(async move {
+            let mut response = self.transport.send(request).await?;
+            response.body = Box::new(CountingBody {
+                body: response.body,
+                metrics: self.metrics.clone(),
+            });
+
+            Ok(response)
+        })
+    }
+}
+
+struct CountingBody {
+    body: Box<dyn HttpBody>,
+    metrics: Arc<Metrics>,
+}
+
+impl HttpBody for CountingBody {
+    fn chunk(&mut self) -> BoxFuture<'_, Result<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            let chunk = self.body.chunk().await?;
+            if let Some(chunk) = &chunk {
+                self.metrics
+                    .bytes_downloaded
+                    .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            }
+
+            Ok(chunk)
+        })
+    }
+}
@@ -0,0 +1,213 @@
+//! Kotlin and Swift bindings generated with UniFFI, for Android and iOS apps.
+//!
+//! Build the library with the `uniffi` feature, then generate the bindings from it with
+//! `cargo run --features uniffi --bin uniffi-bindgen generate --library target/debug/libtydle.so
+//! --language kotlin --out-dir bindings`. Async methods run on tokio and are awaited like any
+//! other suspend function or Swift `async` method.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::{fs::File, io::AsyncWriteExt};
+use url::Url;
+
+use crate::{
+    Extract, TydleError, TydleOptions,
+    http::HttpRequest,
+    yt_interface::{VideoId, YtStream, YtStreamResponse, YtVideoInfo},
+};
+
+/// Size of each range request, bigger ones are throttled by YouTube.
+const CHUNK_SIZE: u64 = 10 * 1024 * 1024;
+
+/// A stream of a video, see `YtStream`.
+#[derive(uniffi::Record)]
+pub struct Stream {
+    pub itag: u16,
+    pub ext: String,
+    pub quality_label: String,
+    pub format_note: String,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub fps: u16,
+    /// Total bitrate in KBit/s.
+    pub tbr: f64,
+    pub vcodec: Option<String>,
+    pub acodec: Option<String>,
+    pub file_size: Option<u64>,
+    pub protocol: String,
+}
+
+impl From<&YtStream> for Stream {
+    fn from(stream: &YtStream) -> Self {
+        Self {
+            itag: stream.itag,
+            ext: stream.ext.as_str().to_string(),
+            quality_label: stream.quality_label.clone(),
+            format_note: stream.format_note.clone(),
+            width: stream.width,
+            height: stream.height,
+            fps: stream.fps,
+            tbr: stream.tbr,
+            vcodec: stream.codec.vcodec.clone(),
+            acodec: stream.codec.acodec.clone(),
+            file_size: stream.file_size,
+            protocol: stream.protocol.as_str().to_string(),
+        }
+    }
+}
+
+/// Metadata of a video, see `YtVideoInfo`.
+#[derive(uniffi::Record)]
+pub struct VideoInfo {
+    pub title: String,
+    pub description: String,
+    pub duration: u64,
+    pub view_count: u64,
+    pub channel_id: String,
+    pub channel_name: Option<String>,
+    pub keywords: Vec<String>,
+    pub thumbnail_urls: Vec<String>,
+}
+
+impl From<YtVideoInfo> for VideoInfo {
+    fn from(video_info: YtVideoInfo) -> Self {
+        Self {
+            channel_id: video_info.channel.get_id().to_string(),
+            channel_name: video_info.channel.get_name().map(str::to_string),
+            title: video_info.title,
+            description: video_info.description,
+            duration: video_info.duration,
+            view_count: video_info.view_count,
+            keywords: video_info.keywords,
+            thumbnail_urls: video_info
+                .thumbnails
+                .into_iter()
+                .map(|thumbnail| thumbnail.url)
+                .collect(),
+        }
+    }
+}
+
+/// Told how far a `Tydle::download` got after every range.
+#[uniffi::export(callback_interface)]
+pub trait DownloadProgress: Send + Sync {
+    fn on_progress(&self, downloaded: u64, total: Option<u64>);
+}
+
+/// `tydle::Tydle` with the default options.
+#[derive(uniffi::Object)]
+pub struct Tydle {
+    inner: crate::Tydle,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl Tydle {
+    #[uniffi::constructor]
+    pub fn new() -> Result<Arc<Self>, TydleError> {
+        Ok(Arc::new(Self {
+            inner: crate::Tydle::new(TydleOptions::default())?,
+        }))
+    }
+
+    pub async fn get_streams(&self, video_id: String) -> Result<Vec<Stream>, TydleError> {
+        let stream_response = self.inner.get_streams(&VideoId::new(video_id)?).await?;
+
+        Ok(stream_response.streams.iter().map(Stream::from).collect())
+    }
+
+    pub async fn get_video_info(&self, video_id: String) -> Result<VideoInfo, TydleError> {
+        let video_info = self.inner.get_video_info(&VideoId::new(video_id)?).await?;
+
+        Ok(video_info.into())
+    }
+
+    /// The final URL of the stream `itag` of the video.
+    pub async fn resolve_url(&self, video_id: String, itag: u16) -> Result<String, TydleError> {
+        let stream_response = self.inner.get_streams(&VideoId::new(video_id)?).await?;
+        let stream = find_stream(&stream_response, itag)?;
+
+        self.inner.resolve_url(stream, &stream_response).await
+    }
+
+    /// Download the stream `itag` of the video to `path` and return its size in bytes.
+    pub async fn download(
+        &self,
+        video_id: String,
+        itag: u16,
+        path: String,
+        progress: Option<Box<dyn DownloadProgress>>,
+    ) -> Result<u64, TydleError> {
+        let stream_response = self.inner.get_streams(&VideoId::new(video_id)?).await?;
+        let stream = find_stream(&stream_response, itag)?;
+        let url = self.inner.resolve_url(stream, &stream_response).await?;
+
+        Ok(self
+            .download_url(&url, stream.file_size, &path, progress.as_deref())
+            .await?)
+    }
+}
+
+impl Tydle {
+    async fn download_url(
+        &self,
+        url: &str,
+        file_size: Option<u64>,
+        path: &str,
+        progress: Option<&dyn DownloadProgress>,
+    ) -> Result<u64> {
+        let mut file = File::create(path).await?;
+        let mut written = 0;
+
+        while file_size.is_none_or(|size| written < size) {
+            let start = written;
+            let end = match file_size {
+                Some(size) => (start + CHUNK_SIZE).min(size) - 1,
+                None => start + CHUNK_SIZE - 1,
+            };
+            let chunk = self
+                .inner
+                .transport()
+                .send(HttpRequest::get(Url::parse(&format!(
+                    "{}&range={}-{}",
+                    url, start, end
+                ))?))
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?;
+
+            file.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+            if let Some(progress) = progress {
+                progress.on_progress(written, file_size);
+            }
+
+            // A range shorter than requested means the end of the stream was reached.
+            if (chunk.len() as u64) < end - start + 1 {
+                break;
+            }
+        }
+        file.flush().await?;
+
+        if let Some(expected) = file_size
+            && written != expected
+        {
+            return Err(TydleError::IncompleteDownload {
+                expected,
+                got: written,
+            }
+            .into());
+        }
+
+        Ok(written)
+    }
+}
+
+fn find_stream(stream_response: &YtStreamResponse, itag: u16) -> Result<&YtStream, TydleError> {
+    stream_response
+        .streams
+        .iter()
+        .find(|stream| stream.itag == itag)
+        .ok_or(TydleError::FormatNotFound(itag.to_string()))
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow, bail};
+use tokio::process::Command;
+
+/// Media player the streams are handed off to by `--play`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Player {
+    #[default]
+    Mpv,
+    Vlc,
+}
+
+impl Player {
+    fn program(&self) -> &'static str {
+        match self {
+            Player::Mpv => "mpv",
+            Player::Vlc => "vlc",
+        }
+    }
+}
+
+/// Play `url` in `player`, along with the separate audio stream at `audio_url`, sending `headers`
+/// with every request. Returns once the player exits.
+pub async fn play(
+    player: Player,
+    url: &str,
+    audio_url: Option<&str>,
+    title: &str,
+    headers: &HashMap<String, String>,
+) -> Result<()> {
+    let user_agent = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("User-Agent"))
+        .map(|(_, value)| value);
+    let other_headers = headers
+        .iter()
+        .filter(|(name, _)| !name.eq_ignore_ascii_case("User-Agent"))
+        .collect::<Vec<_>>();
+
+    let mut command = Command::new(player.program());
+    match player {
+        Player::Mpv => {
+            command.arg(format!("--force-media-title={}", title));
+            if let Some(user_agent) = user_agent {
+                command.arg(format!("--user-agent={}", user_agent));
+            }
+            if !other_headers.is_empty() {
+                // mpv splits the list on commas, which have to be escaped in the values.
+                let fields = other_headers
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, value.replace(',', "\\,")))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                command.arg(format!("--http-header-fields={}", fields));
+            }
+            if let Some(audio_url) = audio_url {
+                command.arg(format!("--audio-file={}", audio_url));
+            }
+        }
+        Player::Vlc => {
+            command.arg(format!("--meta-title={}", title));
+            if let Some(user_agent) = user_agent {
+                command.arg(format!("--http-user-agent={}", user_agent));
+            }
+            if !other_headers.is_empty() {
+                log::warn!("VLC can't send custom headers, only the user agent is sent.");
+            }
+            if let Some(audio_url) = audio_url {
+                command.arg(format!("--input-slave={}", audio_url));
+            }
+        }
+    }
+    command.arg(url);
+
+    log::info!("Playing {} in {}", title, player.program());
+    let status = command.status().await.map_err(|e| {
+        anyhow!(
+            "Failed to start {}, is it installed? {}",
+            player.program(),
+            e
+        )
+    })?;
+
+    if !status.success() {
+        bail!("{} exited with {}", player.program(), status);
+    }
+
+    Ok(())
+}
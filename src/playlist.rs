@@ -0,0 +1,103 @@
+use anyhow::{Result, anyhow, bail};
+use tydle::{PlaylistId, VideoId, YtPlaylistEntry};
+use url::Url;
+
+/// What a positional argument refers to.
+pub enum Target {
+    Video(String),
+    Playlist(PlaylistId),
+}
+
+/// Resolve a video ID, playlist ID or YouTube URL. Watch URLs that are part of a playlist
+/// resolve to the playlist unless `no_playlist` is set.
+pub fn parse_target(arg: &str, no_playlist: bool) -> Result<Target> {
+    let Ok(url) = Url::parse(arg) else {
+        return Ok(if VideoId::new(arg).is_ok() {
+            Target::Video(arg.to_string())
+        } else {
+            Target::Playlist(PlaylistId::new(arg)?)
+        });
+    };
+
+    let query_param = |name: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.into_owned())
+    };
+
+    let video_id = query_param("v").or_else(|| {
+        let mut segments = url.path_segments()?;
+        match url.host_str()? {
+            "youtu.be" => segments.next().map(str::to_string),
+            _ => match segments.next()? {
+                "shorts" | "live" | "embed" => segments.next().map(str::to_string),
+                _ => None,
+            },
+        }
+    });
+
+    match (video_id, query_param("list")) {
+        (Some(video_id), Some(_)) if no_playlist => Ok(Target::Video(video_id)),
+        (_, Some(list)) => Ok(Target::Playlist(PlaylistId::new(list)?)),
+        (Some(video_id), None) => Ok(Target::Video(video_id)),
+        (None, None) => bail!("Could not find a video or playlist in {}", arg),
+    }
+}
+
+/// Pick the entries selected by `items`, a 1-based spec like `1-10,15` (open ranges like `5-` are allowed).
+pub fn select_entries(
+    entries: Vec<YtPlaylistEntry>,
+    items: Option<&str>,
+    reverse: bool,
+) -> Result<Vec<YtPlaylistEntry>> {
+    let mut selected = match items {
+        Some(items) => {
+            let ranges = items
+                .split(',')
+                .map(|item| parse_item_range(item.trim()))
+                .collect::<Result<Vec<_>>>()?;
+
+            entries
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| {
+                    let position = i + 1;
+                    ranges.iter().any(|(start, end)| {
+                        position >= *start && end.is_none_or(|end| position <= end)
+                    })
+                })
+                .map(|(_, entry)| entry)
+                .collect()
+        }
+        None => entries,
+    };
+
+    if reverse {
+        selected.reverse();
+    }
+
+    Ok(selected)
+}
+
+fn parse_item_range(item: &str) -> Result<(usize, Option<usize>)> {
+    let invalid = || anyhow!("Invalid playlist item \"{}\".", item);
+
+    match item.split_once('-') {
+        Some((start, end)) => Ok((
+            if start.is_empty() {
+                1
+            } else {
+                start.parse().map_err(|_| invalid())?
+            },
+            if end.is_empty() {
+                None
+            } else {
+                Some(end.parse().map_err(|_| invalid())?)
+            },
+        )),
+        None => {
+            let index = item.parse().map_err(|_| invalid())?;
+            Ok((index, Some(index)))
+        }
+    }
+}
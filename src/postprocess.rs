@@ -0,0 +1,238 @@
+use std::{fmt, future::Future, pin::Pin};
+
+use anyhow::{Result, anyhow, bail};
+use serde_json::Value;
+use tokio::{fs, process::Command};
+use tydle::{Ext, YtStream, YtVideoInfo};
+
+use crate::ffmpeg::run_ffmpeg;
+
+pub type PostProcessFut<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+/// Loudness range, in LU, loudnorm keeps when normalizing.
+const LOUDNESS_RANGE: f64 = 11.0;
+
+/// Everything a post-processor gets to know about a finished download.
+pub struct PostProcessContext<'a> {
+    /// Path of the downloaded file.
+    pub path: &'a str,
+    pub video_info: &'a YtVideoInfo,
+    /// Stream the file was downloaded from.
+    pub stream: &'a YtStream,
+}
+
+/// A step that runs on a file after it has been downloaded.
+/// Post-processors run in the order they were added to `DownloadOptions`, stopping at the first error.
+pub trait PostProcessor: Send + Sync {
+    fn name(&self) -> &str;
+    fn run<'a>(&'a self, ctx: &'a PostProcessContext<'a>) -> PostProcessFut<'a>;
+}
+
+impl fmt::Debug for dyn PostProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Runs a shell command on the downloaded file. `{}` in the command is replaced with the
+/// quoted file path, which is appended to the command when it doesn't contain `{}`.
+/// `%(title)s`, `%(channel)s`, `%(ext)s` and `%(format_id)s` are replaced with the quoted
+/// fields of the video and stream.
+pub struct ExecPostProcessor {
+    command: String,
+}
+
+impl ExecPostProcessor {
+    pub fn new<S: Into<String>>(command: S) -> Self {
+        Self {
+            command: command.into(),
+        }
+    }
+
+    fn command_for(&self, ctx: &PostProcessContext) -> String {
+        let quoted = shell_quote(ctx.path);
+
+        let command = self
+            .command
+            .replace("%(title)s", &shell_quote(&ctx.video_info.title))
+            .replace(
+                "%(channel)s",
+                &shell_quote(ctx.video_info.channel.get_name().unwrap_or_default()),
+            )
+            .replace("%(ext)s", &shell_quote(ctx.stream.ext.as_str()))
+            .replace("%(format_id)s", &shell_quote(&ctx.stream.itag.to_string()));
+
+        if command.contains("{}") {
+            command.replace("{}", &quoted)
+        } else {
+            format!("{} {}", command, quoted)
+        }
+    }
+}
+
+impl PostProcessor for ExecPostProcessor {
+    fn name(&self) -> &str {
+        "Exec"
+    }
+
+    fn run<'a>(&'a self, ctx: &'a PostProcessContext<'a>) -> PostProcessFut<'a> {
+        Box::pin(async move {
+            let command = self.command_for(ctx);
+            log::info!("Executing command: {}", command);
+
+            #[cfg(windows)]
+            let status = Command::new("cmd").arg("/C").arg(&command).status().await?;
+            #[cfg(not(windows))]
+            let status = Command::new("sh").arg("-c").arg(&command).status().await?;
+
+            if !status.success() {
+                bail!("Command \"{}\" exited with {}.", command, status);
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// Normalizes the loudness of the downloaded file with the two passes of ffmpeg's loudnorm filter,
+/// measuring it first so that the second pass applies a linear gain rather than compressing it.
+pub struct LoudnormPostProcessor {
+    /// Integrated loudness to reach, in LUFS.
+    target: f64,
+    /// Maximum true peak, in dBTP.
+    true_peak: f64,
+}
+
+impl LoudnormPostProcessor {
+    pub fn new(target: f64) -> Self {
+        Self {
+            target,
+            true_peak: -1.5,
+        }
+    }
+
+    pub fn true_peak(mut self, true_peak: f64) -> Self {
+        self.true_peak = true_peak;
+        self
+    }
+
+    fn filter(&self) -> String {
+        format!(
+            "loudnorm=I={}:TP={}:LRA={}",
+            self.target, self.true_peak, LOUDNESS_RANGE
+        )
+    }
+
+    /// Run the first pass on `path`, returning what loudnorm measured.
+    async fn measure(&self, path: &str) -> Result<Value> {
+        let output = Command::new("ffmpeg")
+            .args([
+                "-hide_banner",
+                "-nostats",
+                "-i",
+                path,
+                "-map",
+                "0:a:0",
+                "-af",
+            ])
+            .arg(format!("{}:print_format=json", self.filter()))
+            .args(["-f", "null", "-"])
+            .output()
+            .await?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        if !output.status.success() {
+            bail!("ffmpeg failed: {}", stderr.trim());
+        }
+
+        // The measurement is the JSON object at the end of the output.
+        let start = stderr.rfind('{').ok_or(anyhow!(
+            "ffmpeg printed no loudness measurement for {}.",
+            path
+        ))?;
+        let end = stderr[start..]
+            .find('}')
+            .map_or(stderr.len(), |end| start + end + 1);
+
+        Ok(serde_json::from_str(&stderr[start..end])?)
+    }
+}
+
+/// Encoder the normalized audio of an `ext` file is written with.
+fn audio_encoder(ext: Ext) -> &'static str {
+    match ext {
+        Ext::Webm | Ext::Ogg => "libopus",
+        Ext::Mp3 => "libmp3lame",
+        Ext::Flac => "flac",
+        Ext::Wav => "pcm_s16le",
+        _ => "aac",
+    }
+}
+
+impl PostProcessor for LoudnormPostProcessor {
+    fn name(&self) -> &str {
+        "Loudnorm"
+    }
+
+    fn run<'a>(&'a self, ctx: &'a PostProcessContext<'a>) -> PostProcessFut<'a> {
+        Box::pin(async move {
+            log::info!("Measuring the loudness of {}", ctx.path);
+            let measured = self.measure(ctx.path).await?;
+            let field = |name: &str| -> Result<&str> {
+                measured[name]
+                    .as_str()
+                    .ok_or(anyhow!("loudnorm measured no {}.", name))
+            };
+
+            let filter = format!(
+                "{}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+                self.filter(),
+                field("input_i")?,
+                field("input_tp")?,
+                field("input_lra")?,
+                field("input_thresh")?,
+                field("target_offset")?,
+            );
+            // loudnorm upsamples to 192 kHz, go back to the rate of the stream.
+            let sample_rate = ctx.stream.asr.unwrap_or(48000).to_string();
+            let temp = format!("{}.temp.{}", ctx.path, ctx.stream.ext.as_str());
+
+            log::info!(
+                "Normalizing the loudness of {} from {} to {} LUFS",
+                ctx.path,
+                field("input_i")?,
+                self.target
+            );
+            run_ffmpeg(&[
+                "-y",
+                "-i",
+                ctx.path,
+                "-map",
+                "0",
+                "-c",
+                "copy",
+                "-af",
+                &filter,
+                "-c:a",
+                audio_encoder(ctx.stream.ext),
+                "-ar",
+                &sample_rate,
+                &temp,
+            ])
+            .await?;
+            fs::rename(&temp, ctx.path).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(windows)]
+fn shell_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\\\""))
+}
+
+#[cfg(not(windows))]
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
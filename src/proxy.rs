@@ -0,0 +1,211 @@
+use std::{
+    fmt,
+    net::IpAddr,
+    str::FromStr,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+use anyhow::{Result, anyhow, bail};
+use reqwest::ClientBuilder;
+
+use crate::http::{BoxFuture, HttpRequest, HttpResponse, HttpTransport, ReqwestTransport};
+
+/// A way out to the network, either a proxy like `socks5://10.0.0.1:1080` or a local address to bind
+/// connections to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    Proxy(String),
+    SourceIp(IpAddr),
+}
+
+impl Route {
+    /// Configure `builder` to send its requests through this route.
+    pub fn apply(&self, builder: ClientBuilder) -> Result<ClientBuilder> {
+        Ok(match self {
+            Route::Proxy(proxy) => builder.proxy(reqwest::Proxy::all(proxy)?),
+            Route::SourceIp(ip) => builder.local_address(*ip),
+        })
+    }
+}
+
+impl FromStr for Route {
+    type Err = anyhow::Error;
+
+    fn from_str(route: &str) -> Result<Self> {
+        if let Ok(ip) = route.parse() {
+            return Ok(Route::SourceIp(ip));
+        }
+
+        reqwest::Proxy::all(route).map_err(|e| anyhow!("Invalid proxy \"{}\": {}", route, e))?;
+        Ok(Route::Proxy(route.to_string()))
+    }
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Route::Proxy(proxy) => f.write_str(proxy),
+            Route::SourceIp(ip) => write!(f, "{}", ip),
+        }
+    }
+}
+
+/// When a pool moves on to its next route.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    /// Every request goes out through the next route.
+    #[default]
+    PerRequest,
+    /// Every request of a video goes out through the same route, the next video through the next
+    /// one. Stream URLs are often bound to the address they were extracted from, so this is the one
+    /// to use when the downloads go through the pool too.
+    PerVideo,
+}
+
+impl FromStr for Rotation {
+    type Err = anyhow::Error;
+
+    fn from_str(rotation: &str) -> Result<Self> {
+        match rotation {
+            "request" => Ok(Rotation::PerRequest),
+            "video" => Ok(Rotation::PerVideo),
+            _ => bail!(
+                "Invalid rotation \"{}\", expected \"request\" or \"video\".",
+                rotation
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProxyPoolOptions {
+    pub routes: Vec<Route>,
+    pub rotation: Rotation,
+    /// Failures in a row after which a route is evicted from the pool.
+    pub max_failures: u32,
+}
+
+impl Default for ProxyPoolOptions {
+    fn default() -> Self {
+        Self {
+            routes: Vec::new(),
+            rotation: Rotation::default(),
+            max_failures: 3,
+        }
+    }
+}
+
+/// Rotates through one `T` per route, like an HTTP client, skipping the routes that failed
+/// `ProxyPoolOptions::max_failures` times in a row.
+pub struct RoutePool<T> {
+    routes: Vec<(Route, T)>,
+    failures: Vec<AtomicU32>,
+    rotation: Rotation,
+    max_failures: u32,
+    /// Index of the route the next pick starts looking from.
+    next: AtomicUsize,
+}
+
+impl<T> RoutePool<T> {
+    /// Build the `T` of every route of `options` with `build`.
+    pub fn new(options: &ProxyPoolOptions, build: impl Fn(&Route) -> Result<T>) -> Result<Self> {
+        if options.routes.is_empty() {
+            bail!("A proxy pool needs at least one route.");
+        }
+
+        Ok(Self {
+            routes: options
+                .routes
+                .iter()
+                .map(|route| Ok((route.clone(), build(route)?)))
+                .collect::<Result<_>>()?,
+            failures: options.routes.iter().map(|_| AtomicU32::new(0)).collect(),
+            rotation: options.rotation,
+            max_failures: options.max_failures.max(1),
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    fn is_evicted(&self, index: usize) -> bool {
+        self.failures[index].load(Ordering::Relaxed) >= self.max_failures
+    }
+
+    /// The index and `T` of the route to send the next request through, which is the next one with
+    /// `Rotation::PerRequest` and the current one with `Rotation::PerVideo`.
+    pub fn pick(&self) -> Result<(usize, &T)> {
+        let start = match self.rotation {
+            Rotation::PerRequest => self.next.fetch_add(1, Ordering::Relaxed),
+            Rotation::PerVideo => self.next.load(Ordering::Relaxed),
+        };
+
+        (0..self.routes.len())
+            .map(|offset| (start + offset) % self.routes.len())
+            .find(|index| !self.is_evicted(*index))
+            .map(|index| (index, &self.routes[index].1))
+            .ok_or(anyhow!(
+                "Every route of the proxy pool failed and was evicted."
+            ))
+    }
+
+    /// Move on to the next route, called when a new video is extracted or downloaded.
+    pub fn rotate(&self) {
+        if self.rotation == Rotation::PerVideo {
+            self.next.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Record whether a request through the route at `index` succeeded.
+    pub fn report(&self, index: usize, success: bool) {
+        if success {
+            self.failures[index].store(0, Ordering::Relaxed);
+            return;
+        }
+
+        if self.failures[index].fetch_add(1, Ordering::Relaxed) + 1 == self.max_failures {
+            tracing::warn!(
+                "Evicting {} from the proxy pool after {} failures in a row.",
+                self.routes[index].0,
+                self.max_failures
+            );
+        }
+    }
+}
+
+/// Sends every request through a route of a `RoutePool`, counting network errors and 429 responses
+/// as failures of the route.
+pub struct PoolTransport {
+    pool: RoutePool<ReqwestTransport>,
+}
+
+impl PoolTransport {
+    /// Build a client for every route of `options` from `builder`, which configures everything but
+    /// the route.
+    pub fn new(options: &ProxyPoolOptions, builder: impl Fn() -> ClientBuilder) -> Result<Self> {
+        Ok(Self {
+            pool: RoutePool::new(options, |route| {
+                Ok(ReqwestTransport::new(route.apply(builder())?.build()?))
+            })?,
+        })
+    }
+
+    pub fn pool(&self) -> &RoutePool<ReqwestTransport> {
+        &self.pool
+    }
+}
+
+impl HttpTransport for PoolTransport {
+    fn send<'a>(&'a self, request: HttpRequest) -> BoxFuture<'a, Result<HttpResponse>> {
+        Box::pin(async move {
+            let (index, transport) = self.pool.pick()?;
+            let response = transport.send(request).await;
+            self.pool.report(
+                index,
+                response
+                    .as_ref()
+                    .is_ok_and(|response| response.status != 429),
+            );
+
+            response
+        })
+    }
+}
@@ -0,0 +1,198 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tydle::CancellationToken;
+
+use crate::download_manager::{DownloadJob, DownloadManager, DownloadProgress};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+/// A download submitted to the queue.
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobRequest {
+    pub video_id: String,
+    /// Format to download, like "bestaudio" or "bestvideo[height<=720]+bestaudio". Defaults to
+    /// "bestvideo".
+    pub format: Option<String>,
+    /// Exact itag to download, takes precedence over `format`.
+    pub itag: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u64,
+    pub video_id: String,
+    pub format: Option<String>,
+    pub itag: Option<u16>,
+    pub status: JobStatus,
+    /// Path of the downloaded file, once finished.
+    pub path: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Download queue that keeps its jobs in a sled database, so that unfinished jobs
+/// are picked up again when the server restarts.
+pub struct JobQueue {
+    db: sled::Db,
+    manager: DownloadManager,
+    /// Tokens of the jobs that haven't ended yet.
+    tokens: Mutex<HashMap<u64, CancellationToken>>,
+}
+
+impl JobQueue {
+    /// Open the queue stored at `db_path` and restart its unfinished jobs.
+    pub async fn open(db_path: &str, manager: DownloadManager) -> Result<Arc<Self>> {
+        let queue = Arc::new(Self {
+            db: sled::open(db_path)?,
+            manager,
+            tokens: Mutex::new(HashMap::new()),
+        });
+
+        for mut job in queue.list()? {
+            if matches!(job.status, JobStatus::Queued | JobStatus::Running) {
+                log::info!("Resuming job {} ({})", job.id, job.video_id);
+                job.status = JobStatus::Queued;
+                queue.save(&job).await?;
+                queue.spawn(job).await;
+            }
+        }
+
+        Ok(queue)
+    }
+
+    pub async fn submit(self: &Arc<Self>, request: JobRequest) -> Result<Job> {
+        // Reject invalid requests up front instead of failing the job later.
+        Job::download_job(&request.video_id, request.format.as_deref(), request.itag)?;
+
+        let job = Job {
+            id: self.db.generate_id()?,
+            video_id: request.video_id,
+            format: request.format,
+            itag: request.itag,
+            status: JobStatus::Queued,
+            path: None,
+            error: None,
+        };
+
+        self.save(&job).await?;
+        self.spawn(job.clone()).await;
+
+        Ok(job)
+    }
+
+    /// Progress of the jobs run since the queue was opened.
+    pub fn progress(&self) -> DownloadProgress {
+        self.manager.progress()
+    }
+
+    pub fn get(&self, id: u64) -> Result<Option<Job>> {
+        self.db
+            .get(id.to_be_bytes())?
+            .map(|job| Ok(serde_json::from_slice(&job)?))
+            .transpose()
+    }
+
+    pub fn list(&self) -> Result<Vec<Job>> {
+        self.db
+            .iter()
+            .values()
+            .map(|job| Ok(serde_json::from_slice(&job?)?))
+            .collect()
+    }
+
+    /// Cancel a queued or running job. Jobs that already ended are returned unchanged.
+    pub async fn cancel(&self, id: u64) -> Result<Option<Job>> {
+        let Some(mut job) = self.get(id)? else {
+            return Ok(None);
+        };
+
+        if let Some(token) = self.tokens.lock().await.remove(&id) {
+            token.cancel();
+            job.status = JobStatus::Cancelled;
+            self.save(&job).await?;
+        }
+
+        Ok(Some(job))
+    }
+
+    async fn save(&self, job: &Job) -> Result<()> {
+        self.db
+            .insert(job.id.to_be_bytes(), serde_json::to_vec(job)?)?;
+        self.db.flush_async().await?;
+
+        Ok(())
+    }
+
+    async fn spawn(self: &Arc<Self>, mut job: Job) {
+        let token = CancellationToken::new();
+        self.tokens.lock().await.insert(job.id, token.clone());
+
+        let queue = self.clone();
+        let id = job.id;
+        tokio::spawn(async move {
+            let result = tokio::select! {
+                result = queue.run(&mut job, &token) => result,
+                _ = token.cancelled() => {
+                    // The job may have been marked as running after it was cancelled.
+                    if let Ok(Some(mut job)) = queue.get(id) {
+                        job.status = JobStatus::Cancelled;
+                        let _ = queue.save(&job).await;
+                    }
+                    return;
+                }
+            };
+
+            queue.tokens.lock().await.remove(&job.id);
+            match result {
+                Ok(path) => {
+                    log::info!("Job {} finished: {}", job.id, path);
+                    job.status = JobStatus::Finished;
+                    job.path = Some(path);
+                }
+                Err(e) => {
+                    log::error!("Job {} failed: {:#}", job.id, e);
+                    job.status = JobStatus::Failed;
+                    job.error = Some(format!("{:#}", e));
+                }
+            }
+
+            if let Err(e) = queue.save(&job).await {
+                log::error!("Failed to save job {}: {:#}", job.id, e);
+            }
+        });
+    }
+
+    async fn run(&self, job: &mut Job, token: &CancellationToken) -> Result<String> {
+        let mut download_job = Job::download_job(&job.video_id, job.format.as_deref(), job.itag)?;
+        download_job.job_id = Some(job.id);
+
+        job.status = JobStatus::Running;
+        self.manager
+            .download(&download_job, token, self.save(job))
+            .await
+    }
+}
+
+impl Job {
+    /// What the manager downloads for a job, an exact itag taking precedence over the format.
+    fn download_job(
+        video_id: &str,
+        format: Option<&str>,
+        itag: Option<u16>,
+    ) -> Result<DownloadJob> {
+        match itag {
+            Some(itag) => DownloadJob::new(video_id, &itag.to_string()),
+            None => DownloadJob::new(video_id, format.unwrap_or("bestvideo")),
+        }
+    }
+}
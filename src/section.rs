@@ -0,0 +1,129 @@
+use anyhow::{Result, anyhow, bail};
+
+/// Time range of a video to download, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Section {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Section {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+}
+
+/// Parse a section like `*00:01:30-00:04:00`. The end can be `inf` to download until the end of the video.
+pub fn parse_section(section: &str) -> Result<Section> {
+    let range = section
+        .strip_prefix('*')
+        .ok_or(anyhow!("Sections must be time ranges starting with \"*\"."))?;
+    let (start, end) = range.split_once('-').ok_or(anyhow!(
+        "Invalid section \"{}\", expected *START-END.",
+        section
+    ))?;
+
+    let start = parse_timestamp(start)?;
+    let end = match end.trim() {
+        "inf" | "infinite" => f64::INFINITY,
+        end => parse_timestamp(end)?,
+    };
+
+    if end <= start {
+        bail!("The section \"{}\" ends before it starts.", section);
+    }
+
+    Ok(Section { start, end })
+}
+
+/// Parse `[[HH:]MM:]SS[.ms]` into seconds.
+fn parse_timestamp(timestamp: &str) -> Result<f64> {
+    timestamp
+        .trim()
+        .split(':')
+        .try_fold(0.0, |total, part| Ok(total * 60.0 + part.parse::<f64>()?))
+}
+
+/// Subsegment listed in an mp4 `sidx` box.
+#[derive(Debug, Clone, Copy)]
+pub struct SidxReference {
+    /// Byte offset of the subsegment in the file.
+    pub offset: u64,
+    pub size: u64,
+    /// Presentation time of the subsegment's first sample, in seconds.
+    pub start_time: f64,
+    pub duration: f64,
+}
+
+/// Parse the `sidx` box found at byte `index_start` of a fragmented mp4.
+pub fn parse_sidx(data: &[u8], index_start: u64) -> Result<Vec<SidxReference>> {
+    let mut reader = BoxReader { data, pos: 0 };
+
+    let box_size = reader.u32()? as u64;
+    if reader.take(4)? != b"sidx" {
+        bail!("The segment index is not a sidx box.");
+    }
+
+    let version = reader.take(4)?[0];
+    reader.take(4)?; // reference_ID
+    let timescale = reader.u32()? as f64;
+    let (earliest_presentation_time, first_offset) = if version == 0 {
+        (reader.u32()? as u64, reader.u32()? as u64)
+    } else {
+        (reader.u64()?, reader.u64()?)
+    };
+    reader.take(2)?; // reserved
+    let reference_count = reader.u16()?;
+
+    // Offsets are relative to the first byte after the sidx box.
+    let mut offset = index_start + box_size + first_offset;
+    let mut time = earliest_presentation_time as f64 / timescale;
+    let mut references = Vec::with_capacity(reference_count as usize);
+
+    for _ in 0..reference_count {
+        let size = (reader.u32()? & 0x7FFF_FFFF) as u64;
+        let duration = reader.u32()? as f64 / timescale;
+        reader.take(4)?; // SAP info
+
+        references.push(SidxReference {
+            offset,
+            size,
+            start_time: time,
+            duration,
+        });
+
+        offset += size;
+        time += duration;
+    }
+
+    Ok(references)
+}
+
+struct BoxReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BoxReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(anyhow!("The segment index is truncated."))?;
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into()?))
+    }
+}
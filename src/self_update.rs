@@ -0,0 +1,185 @@
+//! Replacing the running binary with the one of the latest GitHub release, once its minisign
+//! signature is verified with the public key tydle was built with.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use minisign_verify::{PublicKey, Signature};
+use reqwest::Client;
+use serde::Deserialize;
+use tokio::fs;
+
+/// Base64 minisign public key the release binaries are signed with, set at build time. Builds
+/// without one can't update themselves, since nothing else proves a release comes from tydle.
+const PUBLIC_KEY: Option<&str> = option_env!("TYDLE_UPDATE_PUBLIC_KEY");
+/// Extension of the asset holding the minisign signature of each binary, like `minisign -S` names it.
+const SIGNATURE_EXTENSION: &str = ".minisig";
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or(anyhow!(
+                "The release {} has no {} asset, see {}",
+                self.tag_name,
+                name,
+                self.html_url
+            ))
+    }
+}
+
+/// Name of the release asset built for this platform, like "tydle-linux-x86_64".
+fn asset_name() -> String {
+    format!(
+        "tydle-{}-{}{}",
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        std::env::consts::EXE_SUFFIX
+    )
+}
+
+/// Major, minor and patch of `version`, like "v0.1.10" or "0.1.10".
+fn parse_version(version: &str) -> Result<(u64, u64, u64)> {
+    let mut parts = version
+        .trim_start_matches('v')
+        .split(['.', '-', '+'])
+        .map(str::parse::<u64>);
+    let mut next = || -> Result<u64> {
+        parts
+            .next()
+            .ok_or(anyhow!("Invalid version \"{}\".", version))?
+            .map_err(|_| anyhow!("Invalid version \"{}\".", version))
+    };
+
+    Ok((next()?, next()?, next()?))
+}
+
+async fn download(client: &Client, url: &str) -> Result<Vec<u8>> {
+    Ok(client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?
+        .to_vec())
+}
+
+/// Replace `exe` with `binary`. The running binary is moved out of the way first, which Windows
+/// allows while it can't be overwritten.
+async fn replace_binary(exe: &Path, binary: &[u8]) -> Result<()> {
+    let with_extension = |extension: &str| -> PathBuf {
+        let mut path = exe.as_os_str().to_owned();
+        path.push(extension);
+        path.into()
+    };
+    let new_path = with_extension(".new");
+    let old_path = with_extension(".old");
+
+    fs::write(&new_path, binary).await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let permissions = fs::metadata(exe).await?.permissions();
+        fs::set_permissions(
+            &new_path,
+            std::fs::Permissions::from_mode(permissions.mode() | 0o755),
+        )
+        .await?;
+    }
+
+    let _ = fs::remove_file(&old_path).await;
+    fs::rename(exe, &old_path).await?;
+    if let Err(e) = fs::rename(&new_path, exe).await {
+        // Put the old binary back rather than leave no tydle at all.
+        fs::rename(&old_path, exe).await?;
+        return Err(e.into());
+    }
+    // Windows keeps the running binary locked, it's removed by the next update instead.
+    let _ = fs::remove_file(&old_path).await;
+
+    Ok(())
+}
+
+/// Update the running binary to the latest release of the repository tydle was built from.
+/// Returns whether it was updated.
+pub async fn self_update() -> Result<bool> {
+    let repository = env!("CARGO_PKG_REPOSITORY")
+        .strip_prefix("https://github.com/")
+        .ok_or(anyhow!("tydle wasn't built from a GitHub repository."))?;
+    let public_key = PUBLIC_KEY.ok_or(anyhow!(
+        "This build of tydle has no public key to verify updates with, set TYDLE_UPDATE_PUBLIC_KEY when building it."
+    ))?;
+    let public_key = PublicKey::from_base64(public_key)
+        .map_err(|e| anyhow!("Invalid TYDLE_UPDATE_PUBLIC_KEY: {}", e))?;
+    let client = Client::builder()
+        .user_agent(concat!("tydle/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+
+    log::info!("Checking the latest release of {}", repository);
+    let release: Release = client
+        .get(format!(
+            "https://api.github.com/repos/{}/releases/latest",
+            repository
+        ))
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    if parse_version(&release.tag_name)? <= parse_version(env!("CARGO_PKG_VERSION"))? {
+        log::info!("tydle {} is up to date", env!("CARGO_PKG_VERSION"));
+        return Ok(false);
+    }
+
+    let name = asset_name();
+    let asset = release.asset(&name)?;
+    let signature = String::from_utf8(
+        download(
+            &client,
+            &release
+                .asset(&format!("{}{}", name, SIGNATURE_EXTENSION))?
+                .browser_download_url,
+        )
+        .await?,
+    )?;
+    let signature = Signature::decode(&signature)
+        .map_err(|e| anyhow!("Invalid signature of {}: {}", name, e))?;
+
+    log::info!("Downloading {} of {}", name, release.tag_name);
+    let binary = download(&client, &asset.browser_download_url).await?;
+    public_key.verify(&binary, &signature, false).map_err(|e| {
+        anyhow!(
+            "The signature of the downloaded {} doesn't match: {}. Not updating.",
+            name,
+            e
+        )
+    })?;
+
+    let exe = std::env::current_exe()?;
+    replace_binary(&exe, &binary).await?;
+    log::info!(
+        "Updated tydle from {} to {}",
+        env!("CARGO_PKG_VERSION"),
+        release.tag_name
+    );
+
+    Ok(true)
+}
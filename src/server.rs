@@ -0,0 +1,311 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use reqwest::Client;
+use serde_json::{Value, json};
+use tokio::{net::TcpListener, sync::Mutex};
+use tydle::{CancellationToken, Extract, Tydle, TydleError, VideoId, YtStream};
+use url::Url;
+
+use crate::{
+    download_manager::{DownloadManager, DownloadProgress},
+    events::Events,
+    exit_code,
+    queue::{Job, JobQueue, JobRequest},
+    sidecar::{format_dict, info_dict},
+    stream_downloader::DownloadOptions,
+};
+
+type ApiResult = Result<Json<Value>, ApiError>;
+
+/// Headers passed between the media player and googlevideo when proxying a stream.
+const PROXIED_REQUEST_HEADERS: &[header::HeaderName] = &[header::RANGE];
+const PROXIED_RESPONSE_HEADERS: &[header::HeaderName] = &[
+    header::CONTENT_TYPE,
+    header::CONTENT_LENGTH,
+    header::CONTENT_RANGE,
+    header::ACCEPT_RANGES,
+];
+
+/// Where and how the download queue of the server keeps its jobs.
+pub struct QueueOptions {
+    pub db_path: String,
+    pub output_dir: PathBuf,
+    pub concurrency: usize,
+    /// Maximum number of connections to a single host across the running jobs.
+    pub host_connections: usize,
+    /// Where the events of the jobs are sent.
+    pub events: Events,
+}
+
+struct AppState {
+    tydle: Tydle,
+    queue: Arc<JobQueue>,
+    /// Client the proxied streams are fetched with.
+    client: Client,
+    /// Resolved stream URLs by video ID and itag, reused until they expire.
+    stream_urls: Mutex<HashMap<(String, u16), String>>,
+}
+
+/// Serve the REST API on `listen` until `cancellation_token` is cancelled.
+/// Every request shares `tydle`, and with it the player and code caches.
+pub async fn serve(
+    tydle: Tydle,
+    download_options: DownloadOptions,
+    listen: SocketAddr,
+    queue_options: QueueOptions,
+    cancellation_token: CancellationToken,
+) -> Result<()> {
+    let manager = DownloadManager::new(
+        tydle.clone(),
+        download_options,
+        queue_options.output_dir,
+        queue_options.concurrency,
+        queue_options.host_connections,
+    )?
+    .events(queue_options.events);
+    let client = manager.downloader().client().clone();
+    let queue = JobQueue::open(&queue_options.db_path, manager).await?;
+    let state = AppState {
+        tydle,
+        queue,
+        client,
+        stream_urls: Mutex::new(HashMap::new()),
+    };
+    let app = Router::new()
+        .route("/video/{id}/info", get(video_info))
+        .route("/video/{id}/formats", get(video_formats))
+        .route("/video/{id}/resolve/{itag}", get(resolve_stream))
+        .route("/video/{id}/stream/{itag}", get(proxy_stream))
+        .route("/jobs", get(list_jobs).post(submit_job))
+        .route("/jobs/{id}", get(job_status).delete(cancel_job))
+        .route("/progress", get(progress))
+        .route("/metrics", get(metrics))
+        .with_state(Arc::new(state));
+
+    let listener = TcpListener::bind(listen).await?;
+    log::info!("Listening on http://{}", listener.local_addr()?);
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(cancellation_token.cancelled_owned())
+        .await?;
+
+    Ok(())
+}
+
+/// Error response carrying the message of the error, with a status derived from its exit code.
+struct ApiError(anyhow::Error);
+
+impl<E: Into<anyhow::Error>> From<E> for ApiError {
+    fn from(err: E) -> Self {
+        Self(err.into())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match exit_code(&self.0) {
+            exit_code::NETWORK => StatusCode::BAD_GATEWAY,
+            exit_code::UNAVAILABLE | exit_code::FORMAT_NOT_FOUND => StatusCode::NOT_FOUND,
+            exit_code::AUTH_REQUIRED => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        log::error!("{:#}", self.0);
+        (status, Json(json!({ "error": format!("{:#}", self.0) }))).into_response()
+    }
+}
+
+async fn video_info(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> ApiResult {
+    let tydle = &state.tydle;
+    let video_id = VideoId::new(&id)?;
+    let manifest = tydle.get_manifest(&video_id).await?;
+    let yt_stream_response = tydle.get_streams_from_manifest(&manifest).await?;
+    let video_info = tydle.get_video_info_from_manifest(&manifest).await?;
+
+    Ok(Json(info_dict(
+        &video_id,
+        &video_info,
+        &yt_stream_response.streams,
+    )))
+}
+
+async fn video_formats(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> ApiResult {
+    let video_id = VideoId::new(&id)?;
+    let yt_stream_response = state.tydle.get_streams(&video_id).await?;
+
+    Ok(Json(
+        yt_stream_response
+            .streams
+            .iter()
+            .map(format_dict)
+            .collect::<Vec<_>>()
+            .into(),
+    ))
+}
+
+async fn resolve_stream(
+    State(state): State<Arc<AppState>>,
+    Path((id, itag)): Path<(String, u16)>,
+) -> ApiResult {
+    let (stream, url) = resolve_url(&state.tydle, &id, itag).await?;
+    let mut format = format_dict(&stream);
+    format["url"] = url.into();
+
+    Ok(Json(format))
+}
+
+/// Proxy the stream `itag` so that players never see the signed URL. Range requests are passed
+/// through, and the URL is resolved again once it expires or googlevideo stops accepting it.
+async fn proxy_stream(
+    State(state): State<Arc<AppState>>,
+    Path((id, itag)): Path<(String, u16)>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let key = (id, itag);
+    let cached = state.stream_urls.lock().await.get(&key).cloned();
+    let mut url = match cached.filter(|url| !is_expired(url)) {
+        Some(url) => url,
+        None => refresh_stream_url(&state, &key).await?,
+    };
+
+    let mut refreshed = false;
+    let response = loop {
+        let mut request = state.client.get(&url);
+        for name in PROXIED_REQUEST_HEADERS {
+            if let Some(value) = headers.get(name) {
+                request = request.header(name, value);
+            }
+        }
+
+        let response = request.send().await?;
+        if response.status() != StatusCode::FORBIDDEN || refreshed {
+            break response;
+        }
+
+        log::info!(
+            "The URL of {} format {} was rejected, resolving it again.",
+            key.0,
+            key.1
+        );
+        url = refresh_stream_url(&state, &key).await?;
+        refreshed = true;
+    };
+
+    let mut builder = Response::builder().status(response.status());
+    for name in PROXIED_RESPONSE_HEADERS {
+        if let Some(value) = response.headers().get(name) {
+            builder = builder.header(name, value);
+        }
+    }
+
+    Ok(builder.body(Body::from_stream(response.bytes_stream()))?)
+}
+
+/// Metrics of the shared `Tydle` in the Prometheus text exposition format.
+async fn metrics(State(state): State<Arc<AppState>>) -> Result<Response, ApiError> {
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.tydle.metrics()?.to_prometheus(),
+    )
+        .into_response())
+}
+
+async fn submit_job(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<JobRequest>,
+) -> Result<Json<Job>, ApiError> {
+    Ok(Json(state.queue.submit(request).await?))
+}
+
+async fn list_jobs(State(state): State<Arc<AppState>>) -> Result<Json<Vec<Job>>, ApiError> {
+    Ok(Json(state.queue.list()?))
+}
+
+async fn progress(State(state): State<Arc<AppState>>) -> Json<DownloadProgress> {
+    Json(state.queue.progress())
+}
+
+async fn job_status(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u64>,
+) -> Result<Response, ApiError> {
+    Ok(match state.queue.get(id)? {
+        Some(job) => Json(job).into_response(),
+        None => job_not_found(id),
+    })
+}
+
+async fn cancel_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u64>,
+) -> Result<Response, ApiError> {
+    Ok(match state.queue.cancel(id).await? {
+        Some(job) => Json(job).into_response(),
+        None => job_not_found(id),
+    })
+}
+
+fn job_not_found(id: u64) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({ "error": format!("There is no job {}.", id) })),
+    )
+        .into_response()
+}
+
+async fn refresh_stream_url(state: &AppState, key: &(String, u16)) -> Result<String> {
+    let (_, url) = resolve_url(&state.tydle, &key.0, key.1).await?;
+    state
+        .stream_urls
+        .lock()
+        .await
+        .insert(key.clone(), url.clone());
+
+    Ok(url)
+}
+
+/// Whether the `expire` timestamp of a googlevideo URL has passed, or is about to.
+fn is_expired(url: &str) -> bool {
+    let expire = Url::parse(url).ok().and_then(|url| {
+        url.query_pairs()
+            .find(|(k, _)| k == "expire")
+            .and_then(|(_, v)| v.parse::<u64>().ok())
+    });
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    expire.is_some_and(|expire| expire <= now + 60)
+}
+
+/// Find the stream `itag` of the video `id` and its playable URL.
+async fn resolve_url(tydle: &Tydle, id: &str, itag: u16) -> Result<(YtStream, String)> {
+    let video_id = VideoId::new(id)?;
+    let yt_stream_response = tydle.get_streams(&video_id).await?;
+    let stream = yt_stream_response
+        .streams
+        .iter()
+        .find(|stream| stream.itag == itag)
+        .cloned()
+        .ok_or(TydleError::FormatNotFound(itag.to_string()))?;
+
+    let url = tydle.resolve_url(&stream, &yt_stream_response).await?;
+
+    Ok((stream, url))
+}
@@ -0,0 +1,309 @@
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use serde_json::{Value, json};
+use tokio::fs;
+use tydle::{
+    Ext, Fetch, FormatSelection, Tydle, VideoId, YtAgeLimit, YtStream, YtSubtitle,
+    YtSubtitleFormat, YtThumbnail, YtVideoInfo,
+    manifest_gen::{ManifestFormat, ResolvedStream, generate_hls, generate_mpd},
+};
+
+use crate::ffmpeg::run_ffmpeg;
+
+pub fn parse_subtitle_format(format: &str) -> Result<YtSubtitleFormat> {
+    Ok(match format.to_lowercase().as_str() {
+        "vtt" => YtSubtitleFormat::Vtt,
+        "srv1" => YtSubtitleFormat::Srv1,
+        "srv2" => YtSubtitleFormat::Srv2,
+        "srv3" => YtSubtitleFormat::Srv3,
+        "ttml" => YtSubtitleFormat::Ttml,
+        "json3" => YtSubtitleFormat::Json3,
+        _ => bail!("Unknown subtitle format \"{}\".", format),
+    })
+}
+
+pub fn format_dict(stream: &YtStream) -> Value {
+    json!({
+        "format_id": stream.itag.to_string(),
+        "ext": stream.ext.as_str(),
+        "width": stream.width,
+        "height": stream.height,
+        "fps": stream.fps,
+        "tbr": stream.tbr,
+        "asr": stream.asr,
+        "vcodec": stream.codec.vcodec,
+        "acodec": stream.codec.acodec,
+        "filesize": stream.file_size,
+        "filesize_approx": stream.filesize_estimate().map(|estimate| estimate.bytes),
+        "protocol": stream.protocol.as_str(),
+        "format_note": stream.format_note,
+        "quality_label": stream.quality_label,
+        "has_drm": stream.has_drm,
+    })
+}
+
+pub fn subtitle_dict(subtitle: &YtSubtitle) -> Value {
+    json!({
+        "url": subtitle.url,
+        "language": subtitle.language_code,
+        "name": subtitle.name,
+        "auto_generated": subtitle.is_auto_generated,
+    })
+}
+
+pub fn thumbnail_dict(thumbnail: &YtThumbnail) -> Value {
+    json!({
+        "url": thumbnail.url,
+        "width": thumbnail.width,
+        "height": thumbnail.height,
+    })
+}
+
+/// Metadata of the video and its available formats, keyed like output template fields.
+pub fn info_dict(video_id: &VideoId, video_info: &YtVideoInfo, streams: &[YtStream]) -> Value {
+    let formats: Vec<Value> = streams.iter().map(format_dict).collect();
+    let subtitles: Vec<Value> = video_info.subtitles.iter().map(subtitle_dict).collect();
+    let thumbnails: Vec<Value> = video_info.thumbnails.iter().map(thumbnail_dict).collect();
+
+    json!({
+        "id": video_id.as_str(),
+        "webpage_url": format!("https://www.youtube.com/watch?v={}", video_id.as_str()),
+        "title": video_info.title,
+        "description": video_info.description,
+        "duration": video_info.duration,
+        "view_count": video_info.view_count,
+        "channel_id": video_info.channel.get_id(),
+        "channel": video_info.channel.get_name(),
+        "channel_url": video_info.channel.get_url(),
+        "tags": video_info.keywords,
+        "media_type": format!("{:?}", video_info.media_type),
+        "age_limit": match video_info.age_limit {
+            YtAgeLimit::Adult => 18,
+            YtAgeLimit::None => 0,
+        },
+        "availability": video_info.availability.as_str(),
+        "thumbnails": thumbnails,
+        "subtitles": subtitles,
+        "formats": formats,
+    })
+}
+
+/// Add the fields of the format `selection` picked to `info`, for output templates to use, like
+/// yt-dlp does. Merged formats take the video fields of the video stream and join the notes.
+pub fn add_selected_format(info: &mut Value, selection: &FormatSelection) {
+    let streams = selection.streams();
+    let video = &selection.stream;
+    let audio = selection.audio.as_ref().unwrap_or(video);
+    let format_note = streams
+        .iter()
+        .map(|stream| stream.format_note.as_str())
+        .filter(|note| !note.is_empty())
+        .collect::<Vec<_>>()
+        .join("+");
+
+    let fields = json!({
+        "format_id": selection.format_id(),
+        "format_note": format_note,
+        "quality_label": video.quality_label,
+        "ext": selection.ext().as_str(),
+        "width": video.width,
+        "height": video.height,
+        "fps": video.fps,
+        "tbr": streams.iter().map(|stream| stream.tbr).sum::<f64>(),
+        "vcodec": video.codec.vcodec,
+        "acodec": audio.codec.acodec,
+    });
+
+    if let (Some(info), Value::Object(fields)) = (info.as_object_mut(), fields) {
+        info.extend(fields);
+    }
+}
+
+/// Write `info` to `{stem}.info.json`.
+pub async fn write_info_json(info: &Value, stem: &str) -> Result<()> {
+    let path = format!("{}.info.json", stem);
+    fs::write(&path, serde_json::to_string_pretty(info)?).await?;
+    log::info!("Wrote video metadata to {}", path);
+
+    Ok(())
+}
+
+/// Write a manifest of `streams` to `{stem}.mpd`, or `{stem}.m3u8` along with a media playlist for
+/// every stream, for a player to stream them from YouTube.
+pub async fn write_manifest(
+    format: ManifestFormat,
+    streams: &[ResolvedStream<'_>],
+    stem: &str,
+) -> Result<()> {
+    let path = match format {
+        ManifestFormat::Mpd => {
+            let path = format!("{}.mpd", stem);
+            fs::write(&path, generate_mpd(streams)?).await?;
+            path
+        }
+        ManifestFormat::M3u8 => {
+            // The master playlist references the media playlists relative to itself.
+            let stem_path = Path::new(stem);
+            let name = stem_path
+                .file_name()
+                .map_or(stem.into(), |name| name.to_string_lossy());
+            let playlists = generate_hls(streams, &name)?;
+            for (file_name, playlist) in &playlists.media {
+                fs::write(stem_path.with_file_name(file_name), playlist).await?;
+            }
+
+            let path = format!("{}.m3u8", stem);
+            fs::write(&path, playlists.master).await?;
+            path
+        }
+    };
+    log::info!("Wrote manifest to {}", path);
+
+    Ok(())
+}
+
+/// Download the subtitle tracks in `languages` (or every track for "all") to `{stem}.{lang}.{ext}`.
+/// Automatic captions are only written for languages without a manually uploaded track, when `auto_generated` is set.
+/// Write the subtitles of `languages` to `{stem}.{language}.{format}`, returning the tracks written
+/// along with their paths.
+pub async fn write_subtitles<'a>(
+    tydle: &Tydle,
+    video_info: &'a YtVideoInfo,
+    languages: &[String],
+    auto_generated: bool,
+    format: YtSubtitleFormat,
+    stem: &str,
+) -> Result<Vec<(String, &'a YtSubtitle)>> {
+    let wanted = |language_code: &str| {
+        languages
+            .iter()
+            .any(|lang| lang == "all" || lang.eq_ignore_ascii_case(language_code))
+    };
+
+    let mut written: Vec<(String, &YtSubtitle)> = Vec::new();
+
+    // Manual tracks come first so they take precedence over automatic captions of the same language.
+    let mut subtitles: Vec<_> = video_info
+        .subtitles
+        .iter()
+        .filter(|subtitle| wanted(&subtitle.language_code))
+        .filter(|subtitle| auto_generated || !subtitle.is_auto_generated)
+        .collect();
+    subtitles.sort_by_key(|subtitle| subtitle.is_auto_generated);
+
+    for subtitle in subtitles {
+        if written
+            .iter()
+            .any(|(_, written)| written.language_code == subtitle.language_code)
+        {
+            continue;
+        }
+
+        let content = tydle.get_subtitle(subtitle, format).await?;
+        let path = format!("{}.{}.{}", stem, subtitle.language_code, format.as_str());
+        fs::write(&path, content).await?;
+        log::info!("Wrote subtitles to {}", path);
+
+        written.push((path, subtitle));
+    }
+
+    if written.is_empty() {
+        log::warn!("No subtitles found for {}.", languages.join(", "));
+    }
+
+    Ok(written)
+}
+
+/// The ISO 639-2 code of an ISO 639-1 `language_code` like "en" or "pt-BR", which is what the
+/// language tags of mp4 files hold.
+fn iso639_2(language_code: &str) -> Option<&'static str> {
+    let language = language_code.split(['-', '_']).next()?.to_lowercase();
+
+    Some(match language.as_str() {
+        "ar" => "ara",
+        "bn" => "ben",
+        "cs" => "ces",
+        "da" => "dan",
+        "de" => "deu",
+        "el" => "ell",
+        "en" => "eng",
+        "es" => "spa",
+        "fa" => "fas",
+        "fi" => "fin",
+        "fil" => "fil",
+        "fr" => "fra",
+        "he" | "iw" => "heb",
+        "hi" => "hin",
+        "hu" => "hun",
+        "id" => "ind",
+        "it" => "ita",
+        "ja" => "jpn",
+        "ko" => "kor",
+        "ms" => "msa",
+        "nl" => "nld",
+        "no" | "nb" => "nor",
+        "pl" => "pol",
+        "pt" => "por",
+        "ro" => "ron",
+        "ru" => "rus",
+        "sv" => "swe",
+        "ta" => "tam",
+        "th" => "tha",
+        "tr" => "tur",
+        "uk" => "ukr",
+        "ur" => "urd",
+        "vi" => "vie",
+        "zh" => "zho",
+        _ => return None,
+    })
+}
+
+/// Mux the subtitle files of `subtitles` into `media`, tagging each track with its language and
+/// name.
+pub async fn embed_subtitles(
+    media: &str,
+    ext: Ext,
+    subtitles: &[(String, &YtSubtitle)],
+) -> Result<()> {
+    let codec = match ext {
+        Ext::Mp4 | Ext::M4v | Ext::Mov => "mov_text",
+        Ext::Mkv => "srt",
+        Ext::Webm => "webvtt",
+        _ => bail!(
+            "Embedding subtitles in {} files is not supported.",
+            ext.as_str()
+        ),
+    };
+    let temp = format!("{}.temp.{}", media, ext.as_str());
+
+    let mut args = vec!["-y".to_string(), "-i".to_string(), media.to_string()];
+    for (path, _) in subtitles {
+        args.extend(["-i".to_string(), path.clone()]);
+    }
+    // Drop any subtitle track the media already has.
+    args.extend(["-map", "0", "-map", "-0:s?"].map(String::from));
+    for index in 1..=subtitles.len() {
+        args.extend(["-map".to_string(), format!("{}:0", index)]);
+    }
+    args.extend(["-c", "copy", "-c:s", codec].map(String::from));
+    for (index, (_, subtitle)) in subtitles.iter().enumerate() {
+        let language = iso639_2(&subtitle.language_code).unwrap_or(&subtitle.language_code);
+        args.extend([
+            format!("-metadata:s:s:{}", index),
+            format!("language={}", language),
+        ]);
+        if let Some(name) = &subtitle.name {
+            args.extend([
+                format!("-metadata:s:s:{}", index),
+                format!("title={}", name),
+            ]);
+        }
+    }
+    args.push(temp.clone());
+
+    run_ffmpeg(&args.iter().map(String::as_str).collect::<Vec<_>>()).await?;
+    fs::rename(&temp, media).await?;
+
+    Ok(())
+}
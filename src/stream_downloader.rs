@@ -1,27 +1,436 @@
-use anyhow::{Result, anyhow};
+use anyhow::{Result, anyhow, bail};
 use reqwest::Client;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
+use std::path::Path;
 use std::sync::Arc;
-use tokio::fs::File;
-use tokio::io::AsyncSeekExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio::sync::Mutex;
-use tokio::task;
+use tokio::task::JoinSet;
 use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+use tydle::{
+    CancellationToken, Ext, Extract, Tydle, TydleError, VideoId, YtStream, YtStreamResponse,
+    dns::DnsOptions,
+    proxy::{ProxyPoolOptions, Route, RoutePool},
+};
+
+use crate::ffmpeg::run_ffmpeg;
+use crate::postprocess::{PostProcessContext, PostProcessor};
+use crate::section::{Section, parse_sidx};
+
+/// How long a download has to stay under `DownloadOptions::throttled_rate` to count as throttled,
+/// so that a slow start doesn't.
+const THROTTLE_WINDOW: Duration = Duration::from_secs(10);
+
+tokio::task_local! {
+    /// Set by `retry_throttled` around the download it keeps going with after the retry, so that
+    /// it isn't reported as throttled again.
+    static IGNORE_THROTTLING: bool;
+}
+
+/// A download stayed under `DownloadOptions::throttled_rate`, which is what YouTube does to URLs
+/// whose `n` parameter was deciphered wrong.
+#[derive(Debug)]
+pub struct Throttled {
+    /// Bytes per second the download got across all of its workers.
+    pub rate: u64,
+}
+
+impl fmt::Display for Throttled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The download was throttled to {} bytes/s.", self.rate)
+    }
+}
+
+impl std::error::Error for Throttled {}
+
+/// What to do when the output file already exists.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OverwritePolicy {
+    #[default]
+    Overwrite,
+    /// Leave the existing file untouched and skip the download.
+    Skip,
+    /// Keep the existing file and download to a new name with a numbered suffix, like `video (1).mp4`.
+    Rename,
+}
+
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    /// Number of ranges downloaded concurrently.
+    pub workers: usize,
+    /// Size in bytes of each range request. Defaults to splitting the file evenly between workers.
+    pub chunk_size: Option<u64>,
+    /// Number of times a failed range is retried before the download fails.
+    pub retries: u32,
+    /// Maximum download rate in bytes per second across all workers.
+    pub rate_limit: Option<u64>,
+    /// Download rate in bytes per second, across all workers, below which the download fails with
+    /// `Throttled`. Ignored with a `rate_limit`.
+    pub throttled_rate: Option<u64>,
+    /// Continue a partially downloaded file instead of starting over.
+    pub resume: bool,
+    pub overwrite_policy: OverwritePolicy,
+    /// Extra headers sent with every request.
+    pub headers: HashMap<String, String>,
+    /// Local IP address to bind connections to.
+    pub source_ip: Option<IpAddr>,
+    pub dns: DnsOptions,
+    /// Proxies and local addresses downloads are spread over, instead of `source_ip`. Every
+    /// download goes through the next route.
+    pub proxy_pool: Option<ProxyPoolOptions>,
+    /// Negotiate HTTP/2 with hosts that support it, which lets the ranges of a download share one
+    /// connection. HTTP/1.1 is used for every host otherwise.
+    pub http2: bool,
+    /// Maximum number of idle connections kept open to each host for later ranges and downloads.
+    /// Defaults to no limit.
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Interval of TCP keepalive probes, which keep pooled connections from being dropped by NATs.
+    pub tcp_keepalive: Option<Duration>,
+    /// Maximum time to wait for a connection to be established.
+    pub connect_timeout: Option<Duration>,
+    /// Maximum time to wait for the next bytes of a response, so that a stalled connection fails
+    /// and gets retried instead of hanging the download.
+    pub read_timeout: Option<Duration>,
+    /// Counter the bytes of every download made with these options are added to, to follow the
+    /// progress of several downloads at once.
+    pub downloaded_bytes: Option<Arc<AtomicU64>>,
+    /// Write the SHA-256 of finished downloads to a `.sha256` sidecar file.
+    pub sha256: bool,
+    /// Steps run in order on every finished download.
+    pub post_processors: Vec<Arc<dyn PostProcessor>>,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            workers: 1,
+            chunk_size: None,
+            retries: 10,
+            rate_limit: None,
+            throttled_rate: None,
+            resume: true,
+            overwrite_policy: OverwritePolicy::default(),
+            headers: HashMap::new(),
+            source_ip: None,
+            dns: DnsOptions::default(),
+            proxy_pool: None,
+            http2: true,
+            pool_max_idle_per_host: None,
+            tcp_keepalive: Some(Duration::from_secs(60)),
+            connect_timeout: None,
+            read_timeout: None,
+            downloaded_bytes: None,
+            sha256: false,
+            post_processors: Vec::new(),
+        }
+    }
+}
+
+impl DownloadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    pub fn chunk_size(mut self, chunk_size: Option<u64>) -> Self {
+        self.chunk_size = chunk_size.filter(|c| *c > 0);
+        self
+    }
+
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: Option<u64>) -> Self {
+        self.rate_limit = rate_limit.filter(|r| *r > 0);
+        self
+    }
+
+    pub fn throttled_rate(mut self, throttled_rate: Option<u64>) -> Self {
+        self.throttled_rate = throttled_rate;
+        self
+    }
+
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    pub fn overwrite_policy(mut self, overwrite_policy: OverwritePolicy) -> Self {
+        self.overwrite_policy = overwrite_policy;
+        self
+    }
+
+    pub fn header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn source_ip(mut self, source_ip: Option<IpAddr>) -> Self {
+        self.source_ip = source_ip;
+        self
+    }
+
+    pub fn dns(mut self, dns: DnsOptions) -> Self {
+        self.dns = dns;
+        self
+    }
+
+    pub fn proxy_pool(mut self, proxy_pool: Option<ProxyPoolOptions>) -> Self {
+        self.proxy_pool = proxy_pool;
+        self
+    }
+
+    pub fn http2(mut self, http2: bool) -> Self {
+        self.http2 = http2;
+        self
+    }
+
+    pub fn pool_max_idle_per_host(mut self, pool_max_idle_per_host: Option<usize>) -> Self {
+        self.pool_max_idle_per_host = pool_max_idle_per_host;
+        self
+    }
+
+    pub fn tcp_keepalive(mut self, tcp_keepalive: Option<Duration>) -> Self {
+        self.tcp_keepalive = tcp_keepalive;
+        self
+    }
+
+    pub fn connect_timeout(mut self, connect_timeout: Option<Duration>) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    pub fn read_timeout(mut self, read_timeout: Option<Duration>) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    pub fn sha256(mut self, sha256: bool) -> Self {
+        self.sha256 = sha256;
+        self
+    }
+
+    pub fn post_processor<P: PostProcessor + 'static>(mut self, post_processor: P) -> Self {
+        self.post_processors.push(Arc::new(post_processor));
+        self
+    }
+
+    /// Build an HTTP client that sends `headers`, binds to `source_ip` or goes through `route`,
+    /// resolves hosts through `dns`, keeps its connections pooled and gives up on them after the
+    /// configured timeouts.
+    pub fn build_client(&self, route: Option<&Route>) -> Result<Client> {
+        let mut headers = HeaderMap::new();
+
+        for (name, value) in &self.headers {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+
+        let mut builder = self
+            .dns
+            .apply(Client::builder())
+            .default_headers(headers)
+            .local_address(self.source_ip)
+            .tcp_keepalive(self.tcp_keepalive)
+            .tcp_nodelay(true);
+
+        builder = match self.http2 {
+            // The window grows with the measured bandwidth, a fixed one caps fast connections.
+            true => builder.http2_adaptive_window(true),
+            false => builder.http1_only(),
+        };
+        if let Some(pool_max_idle_per_host) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(pool_max_idle_per_host);
+        }
+
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(read_timeout) = self.read_timeout {
+            builder = builder.read_timeout(read_timeout);
+        }
+        if let Some(route) = route {
+            builder = route.apply(builder)?;
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+/// Shared state used to keep every worker under `DownloadOptions::rate_limit`.
+struct RateLimiter {
+    limit: u64,
+    started: Instant,
+    downloaded: AtomicU64,
+}
+
+impl RateLimiter {
+    async fn throttle(&self, bytes: u64) {
+        let downloaded = self.downloaded.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        let expected = Duration::from_secs_f64(downloaded as f64 / self.limit as f64);
+        let elapsed = self.started.elapsed();
+
+        if expected > elapsed {
+            tokio::time::sleep(expected - elapsed).await;
+        }
+    }
+}
+
+/// Shared state used to compare the rate of every worker together to
+/// `DownloadOptions::throttled_rate`.
+struct ThrottleDetector {
+    throttled_rate: u64,
+    started: Instant,
+    downloaded: AtomicU64,
+}
+
+impl ThrottleDetector {
+    fn check(&self, bytes: u64) -> Result<(), Throttled> {
+        let downloaded = self.downloaded.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        let elapsed = self.started.elapsed();
+        if elapsed < THROTTLE_WINDOW {
+            return Ok(());
+        }
+
+        let rate = (downloaded as f64 / elapsed.as_secs_f64()) as u64;
+        match rate < self.throttled_rate {
+            true => Err(Throttled { rate }),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Rate limiting, throttling detection and progress shared by every worker of a download.
+#[derive(Clone)]
+struct Pacing {
+    rate_limiter: Option<Arc<RateLimiter>>,
+    throttle_detector: Option<Arc<ThrottleDetector>>,
+    /// Bytes of the file written so far, including those of a resumed download.
+    written: Arc<AtomicU64>,
+    downloaded_bytes: Option<Arc<AtomicU64>>,
+    /// Counter of the bytes of this download alone.
+    download_bytes: Option<Arc<AtomicU64>>,
+}
 
 pub struct StreamDownloader {
     client: Client,
-    workers: usize,
+    /// A client for every route of `DownloadOptions::proxy_pool`.
+    routes: Option<RoutePool<Client>>,
+    options: DownloadOptions,
 }
 
 impl StreamDownloader {
-    pub fn new(workers: usize) -> Self {
-        Self {
-            client: Client::new(),
-            workers,
-        }
+    pub fn new(options: DownloadOptions) -> Result<Self> {
+        Ok(Self {
+            client: options.build_client(None)?,
+            routes: options
+                .proxy_pool
+                .as_ref()
+                .map(|pool| RoutePool::new(pool, |route| options.build_client(Some(route))))
+                .transpose()?,
+            options,
+        })
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn options(&self) -> &DownloadOptions {
+        &self.options
+    }
+
+    /// Download `url` into `output`, aborting every worker as soon as `cancellation_token` is cancelled.
+    /// The result is checked against the Content-Length of the response and `expected_size`, if known.
+    ///
+    /// Data is written to `{output}.part`, which is renamed to the final path once the download is complete.
+    /// Returns the path the file was saved to, which differs from `output` with `OverwritePolicy::Rename`.
+    pub async fn download(
+        &self,
+        url: &str,
+        output: &str,
+        expected_size: Option<u64>,
+        cancellation_token: &CancellationToken,
+    ) -> Result<String> {
+        self.download_counted(url, output, expected_size, None, cancellation_token)
+            .await
     }
 
-    pub async fn download(&self, url: &str, output: &str) -> Result<()> {
-        let response = self.client.head(url).send().await?;
+    /// Like `download`, also adding the bytes of this download to `download_bytes`.
+    pub async fn download_counted(
+        &self,
+        url: &str,
+        output: &str,
+        expected_size: Option<u64>,
+        download_bytes: Option<Arc<AtomicU64>>,
+        cancellation_token: &CancellationToken,
+    ) -> Result<String> {
+        let Some(routes) = &self.routes else {
+            return self
+                .download_with(
+                    &self.client,
+                    url,
+                    output,
+                    expected_size,
+                    download_bytes,
+                    cancellation_token,
+                )
+                .await;
+        };
+
+        routes.rotate();
+        let (index, client) = routes.pick()?;
+        let result = self
+            .download_with(
+                client,
+                url,
+                output,
+                expected_size,
+                download_bytes,
+                cancellation_token,
+            )
+            .await;
+        // Only network errors say something about the route.
+        routes.report(
+            index,
+            !result
+                .as_ref()
+                .is_err_and(|e| e.chain().any(|cause| cause.is::<reqwest::Error>())),
+        );
+
+        result
+    }
+
+    async fn download_with(
+        &self,
+        client: &Client,
+        url: &str,
+        output: &str,
+        expected_size: Option<u64>,
+        download_bytes: Option<Arc<AtomicU64>>,
+        cancellation_token: &CancellationToken,
+    ) -> Result<String> {
+        let Some(output) = self.output_path(output) else {
+            return Ok(output.to_string());
+        };
+
+        let response = client.head(url).send().await?;
         let len = response
             .headers()
             .get(reqwest::header::CONTENT_LENGTH)
@@ -29,53 +438,483 @@ impl StreamDownloader {
             .to_str()?
             .parse::<u64>()?;
 
+        let part_path = format!("{}.part", output);
+        let progress_path = format!("{}.ranges", part_path);
+        let resuming = self.options.resume
+            && Path::new(&part_path).exists()
+            && Path::new(&progress_path).exists();
+
+        let completed = if resuming {
+            read_completed_ranges(&progress_path).await?
+        } else {
+            Vec::new()
+        };
+
         let file = OpenOptions::new()
             .create(true)
             .write(true)
-            .truncate(true)
-            .open(output)
+            .truncate(!resuming)
+            .open(&part_path)
             .await?;
 
         file.set_len(len).await?;
 
+        if !resuming && Path::new(&progress_path).exists() {
+            fs::remove_file(&progress_path).await?;
+        }
+
+        let progress = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&progress_path)
+            .await?;
+
         let file = Arc::new(Mutex::new(file));
+        let progress = Arc::new(Mutex::new(progress));
 
-        let chunk_size = len / self.workers as u64;
-        let mut tasks = Vec::new();
+        let workers = self.options.workers.max(1);
+        let chunk_size = self
+            .options
+            .chunk_size
+            .unwrap_or_else(|| len.div_ceil(workers as u64))
+            .max(1);
 
-        for i in 0..self.workers {
-            let start = i as u64 * chunk_size;
-            let end = if i == self.workers - 1 {
-                len - 1
+        let mut ranges = VecDeque::new();
+        let mut written = 0;
+        let mut start = 0;
+        while start < len {
+            let end = (start + chunk_size).min(len) - 1;
+            if completed.contains(&(start, end)) {
+                written += end - start + 1;
             } else {
-                start + chunk_size - 1
-            };
+                ranges.push_back((start, end));
+            }
+            start = end + 1;
+        }
+
+        if resuming {
+            log::info!(
+                "Resuming download, {} of {} ranges remaining.",
+                ranges.len(),
+                len.div_ceil(chunk_size)
+            );
+        }
 
+        let ranges = Arc::new(Mutex::new(ranges));
+        let pacing = self.pacing(written, download_bytes);
+        let mut tasks: JoinSet<Result<()>> = JoinSet::new();
+
+        for _ in 0..workers {
             let url = url.to_string();
-            let client = self.client.clone();
+            let client = client.clone();
             let file = Arc::clone(&file);
+            let progress = Arc::clone(&progress);
+            let ranges = Arc::clone(&ranges);
+            let pacing = pacing.clone();
+            let retries = self.options.retries;
+
+            tasks.spawn(async move {
+                loop {
+                    let Some((start, end)) = ranges.lock().await.pop_front() else {
+                        return Ok(());
+                    };
+
+                    download_range_retrying(
+                        &client,
+                        &url,
+                        Arc::clone(&file),
+                        (start, end),
+                        0,
+                        &pacing,
+                        retries,
+                    )
+                    .await?;
+
+                    progress
+                        .lock()
+                        .await
+                        .write_all(format!("{}-{}\n", start, end).as_bytes())
+                        .await?;
+                }
+            });
+        }
+
+        loop {
+            tokio::select! {
+                joined = tasks.join_next() => match joined {
+                    Some(res) => res??,
+                    None => break,
+                },
+                _ = cancellation_token.cancelled() => {
+                    tasks.abort_all();
+                    bail!("Download was cancelled.");
+                }
+            }
+        }
+
+        let got = pacing.written.load(Ordering::Relaxed);
+        for expected in [Some(len), expected_size].into_iter().flatten() {
+            if got != expected {
+                return Err(TydleError::IncompleteDownload { expected, got }.into());
+            }
+        }
+
+        fs::remove_file(&progress_path).await?;
+        fs::rename(&part_path, &output).await?;
+
+        if self.options.sha256 {
+            let digest = sha256_file(&output).await?;
+            let file_name = Path::new(&output)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| output.clone());
+
+            fs::write(
+                format!("{}.sha256", output),
+                format!("{}  {}\n", digest, file_name),
+            )
+            .await?;
+        }
+
+        Ok(output)
+    }
+
+    /// Rate limiting and throttling detection for a new download with `written` bytes already in
+    /// its file, counted in `download_bytes`.
+    fn pacing(&self, written: u64, download_bytes: Option<Arc<AtomicU64>>) -> Pacing {
+        Pacing {
+            rate_limiter: self.options.rate_limit.map(|limit| {
+                Arc::new(RateLimiter {
+                    limit,
+                    started: Instant::now(),
+                    downloaded: AtomicU64::new(0),
+                })
+            }),
+            throttle_detector: self
+                .options
+                .throttled_rate
+                .filter(|_| self.options.rate_limit.is_none())
+                .filter(|_| {
+                    !IGNORE_THROTTLING
+                        .try_with(|ignore| *ignore)
+                        .unwrap_or(false)
+                })
+                .map(|throttled_rate| {
+                    Arc::new(ThrottleDetector {
+                        throttled_rate,
+                        started: Instant::now(),
+                        downloaded: AtomicU64::new(0),
+                    })
+                }),
+            written: Arc::new(AtomicU64::new(written)),
+            downloaded_bytes: self.options.downloaded_bytes.clone(),
+            download_bytes,
+        }
+    }
+
+    /// Download only `section` of `stream` into `output`.
+    ///
+    /// For progressive mp4 and m4a streams with a segment index, only the subsegments covering the
+    /// section are downloaded, with the retries and pacing of `download`. Other streams, like webm,
+    /// are downloaded whole. The result is then cut to the exact section with ffmpeg.
+    pub async fn download_section(
+        &self,
+        url: &str,
+        output: &str,
+        stream: &YtStream,
+        section: Section,
+        cancellation_token: &CancellationToken,
+    ) -> Result<String> {
+        let Some(output) = self.output_path(output) else {
+            return Ok(output.to_string());
+        };
+
+        let (source, offset) = match (stream.ext, stream.init_range, stream.index_range) {
+            (Ext::Mp4 | Ext::M4a, Some((_, init_end)), Some((index_start, index_end))) => {
+                let index = self.fetch_range(url, index_start, index_end).await?;
+                let references = parse_sidx(&index, index_start)?;
+                let covering: Vec<_> = references
+                    .iter()
+                    .filter(|r| {
+                        r.start_time + r.duration > section.start && r.start_time < section.end
+                    })
+                    .collect();
+
+                let (Some(first), Some(last)) = (covering.first(), covering.last()) else {
+                    bail!("The section is outside of the video.");
+                };
+
+                log::info!(
+                    "Downloading {} of {} subsegments.",
+                    covering.len(),
+                    references.len()
+                );
+
+                let source = format!("{}.part", output);
+                let file = Arc::new(Mutex::new(File::create(&source).await?));
+                let pacing = self.pacing(0, None);
+                // The init segment, then the subsegments right after it.
+                let ranges = [
+                    ((0, init_end), 0),
+                    (
+                        (first.offset, last.offset + last.size - 1),
+                        first.offset - (init_end + 1),
+                    ),
+                ];
+
+                for (range, base) in ranges {
+                    tokio::select! {
+                        result = download_range_retrying(
+                            &self.client,
+                            url,
+                            Arc::clone(&file),
+                            range,
+                            base,
+                            &pacing,
+                            self.options.retries,
+                        ) => result?,
+                        _ = cancellation_token.cancelled() => bail!("Download was cancelled."),
+                    }
+                }
+                file.lock().await.flush().await?;
+
+                (source, section.start - first.start_time)
+            }
+            _ => {
+                let source = format!("{}.full.{}", output, stream.ext.as_str());
+                let source = self
+                    .download(url, &source, stream.file_size, cancellation_token)
+                    .await?;
+
+                (source, section.start)
+            }
+        };
+
+        let trimmed = format!("{}.part.{}", output, stream.ext.as_str());
+        let offset = offset.max(0.0).to_string();
+        let duration = section.duration().to_string();
+        let mut args = vec!["-y", "-ss", offset.as_str()];
+        if section.end.is_finite() {
+            args.extend(["-t", duration.as_str()]);
+        }
+        args.extend([
+            "-i",
+            source.as_str(),
+            "-map",
+            "0",
+            "-c",
+            "copy",
+            trimmed.as_str(),
+        ]);
+
+        run_ffmpeg(&args).await?;
+        fs::rename(&trimmed, &output).await?;
+        fs::remove_file(&source).await?;
+
+        Ok(output)
+    }
 
-            tasks.push(task::spawn(async move {
-                download_range(&client, &url, file, start, end).await
-            }));
+    /// Resolve the path to download to according to the overwrite policy, or `None` if the download should be skipped.
+    fn output_path(&self, output: &str) -> Option<String> {
+        if !Path::new(output).exists() {
+            return Some(output.to_string());
         }
 
-        for t in tasks {
-            t.await??;
+        match self.options.overwrite_policy {
+            OverwritePolicy::Overwrite => Some(output.to_string()),
+            OverwritePolicy::Skip => {
+                log::info!("{} has already been downloaded.", output);
+                None
+            }
+            OverwritePolicy::Rename => Some(unused_path(output)),
+        }
+    }
+
+    async fn fetch_range(&self, url: &str, start: u64, end: u64) -> Result<Vec<u8>> {
+        Ok(self
+            .client
+            .get(url)
+            .header(reqwest::header::RANGE, format!("bytes={}-{}", start, end))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?
+            .to_vec())
+    }
+
+    /// Run the configured post-processors on a finished download.
+    pub async fn post_process(&self, ctx: &PostProcessContext<'_>) -> Result<()> {
+        for post_processor in &self.options.post_processors {
+            log::info!(
+                "Running {} post-processor on {}",
+                post_processor.name(),
+                ctx.path
+            );
+            post_processor.run(ctx).await?;
         }
 
         Ok(())
     }
 }
 
+/// First `{stem} (n).{ext}` next to `output` that doesn't exist yet.
+fn unused_path(output: &str) -> String {
+    let path = Path::new(output);
+    let stem = path.with_extension("");
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..)
+        .map(|n| format!("{} ({}){}", stem.to_string_lossy(), n, ext))
+        .find(|candidate| !Path::new(candidate).exists())
+        .unwrap()
+}
+
+async fn sha256_file(path: &str) -> Result<String> {
+    let mut file = File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+async fn read_completed_ranges(progress_path: &str) -> Result<Vec<(u64, u64)>> {
+    let content = fs::read_to_string(progress_path).await?;
+
+    Ok(content
+        .lines()
+        .filter_map(|line| line.split_once('-'))
+        .filter_map(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)))
+        .collect())
+}
+
+/// Run `download` with `source`, the resolved URL of `stream`, and once more with a fresh URL of
+/// the same format if the first attempt was throttled, its `n` parameter deciphered again. A
+/// download throttled again goes on slowly rather than failing.
+pub async fn retry_throttled<F, Fut>(
+    tydle: &Tydle,
+    video_id: &VideoId,
+    stream: &YtStream,
+    stream_response: &YtStreamResponse,
+    source: &str,
+    download: F,
+) -> Result<String>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    match download(source.to_string()).await {
+        Err(e) if e.is::<Throttled>() => {
+            log::warn!(
+                "{} Deciphering the n parameter of format {} again and retrying.",
+                e,
+                stream.itag
+            );
+            tydle.forget_n_signature(stream, &stream_response.player_url)?;
+            // Fresh URLs, and the current player if YouTube rolled out a new one.
+            let stream_response = tydle.get_streams(video_id).await?;
+            let fresh_stream = stream_response
+                .streams
+                .iter()
+                .find(|s| s.itag == stream.itag && s.client == stream.client)
+                .or_else(|| {
+                    stream_response
+                        .streams
+                        .iter()
+                        .find(|s| s.itag == stream.itag)
+                })
+                .ok_or(anyhow!(
+                    "Format {} is gone after extracting the video again.",
+                    stream.itag
+                ))?;
+            let source = tydle.resolve_url(fresh_stream, &stream_response).await?;
+
+            match download(source.clone()).await {
+                Err(e) if e.is::<Throttled>() => {
+                    log::warn!(
+                        "{} It was throttled again after deciphering the n parameter, continuing without checking the rate.",
+                        e
+                    );
+                    IGNORE_THROTTLING.scope(true, download(source)).await
+                }
+                result => result,
+            }
+        }
+        result => result,
+    }
+}
+
+/// Download the bytes `start..=end` of `url` like `download_range`, retrying up to `retries` times.
+async fn download_range_retrying(
+    client: &Client,
+    url: &str,
+    file: Arc<Mutex<File>>,
+    (start, end): (u64, u64),
+    base: u64,
+    pacing: &Pacing,
+    retries: u32,
+) -> Result<()> {
+    let mut offset = start;
+    let mut attempt = 0;
+
+    while let Err(e) = download_range(
+        client,
+        url,
+        Arc::clone(&file),
+        &mut offset,
+        end,
+        base,
+        pacing,
+    )
+    .await
+    {
+        // Retrying the same URL would be throttled again.
+        if attempt >= retries || e.is::<Throttled>() {
+            return Err(e);
+        }
+
+        attempt += 1;
+        log::warn!(
+            "Range {}-{} failed ({}), retrying ({}/{}).",
+            start,
+            end,
+            e,
+            attempt,
+            retries
+        );
+    }
+
+    Ok(())
+}
+
+/// Download the bytes between `offset` and `end`, advancing `offset` so a retry picks up where it stopped.
+/// Byte `base` of `url` is written at the start of `file`.
+#[tracing::instrument(name = "download_range", skip_all, fields(start = *offset, end))]
 async fn download_range(
     client: &Client,
     url: &str,
     file: Arc<Mutex<File>>,
-    start: u64,
+    offset: &mut u64,
     end: u64,
+    base: u64,
+    pacing: &Pacing,
 ) -> Result<()> {
-    let range_header = format!("bytes={}-{}", start, end);
+    if *offset > end {
+        return Ok(());
+    }
+
+    let range_header = format!("bytes={}-{}", offset, end);
 
     let mut resp = client
         .get(url)
@@ -84,13 +923,38 @@ async fn download_range(
         .await?
         .error_for_status()?;
 
-    let mut offset = start;
-
     while let Some(chunk) = resp.chunk().await? {
-        let mut f = file.lock().await;
-        f.seek(std::io::SeekFrom::Start(offset)).await?;
-        f.write_all(&chunk).await?;
-        offset += chunk.len() as u64;
+        {
+            let mut f = file.lock().await;
+            f.seek(std::io::SeekFrom::Start(*offset - base)).await?;
+            f.write_all(&chunk).await?;
+        }
+        *offset += chunk.len() as u64;
+        pacing
+            .written
+            .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        for counter in [&pacing.downloaded_bytes, &pacing.download_bytes]
+            .into_iter()
+            .flatten()
+        {
+            counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        }
+
+        if let Some(limiter) = &pacing.rate_limiter {
+            limiter.throttle(chunk.len() as u64).await;
+        }
+
+        if let Some(detector) = &pacing.throttle_detector {
+            detector.check(chunk.len() as u64)?;
+        }
+    }
+
+    // The server closing the connection early would otherwise leave a hole in the file.
+    if *offset <= end {
+        bail!(
+            "Connection closed with {} bytes of the range left.",
+            end - *offset + 1
+        );
     }
 
     Ok(())
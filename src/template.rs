@@ -0,0 +1,215 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+use serde_json::Value;
+
+/// How the fields of filename templates are made valid file names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilenameMode {
+    /// Only replace the path separator, and what Windows refuses when running on it.
+    Default,
+    /// Replace what Windows refuses in file names, and avoid its reserved device names.
+    Windows,
+    /// ASCII letters, digits, "-", "_" and "." only, with "_" for spaces and everything else.
+    Restricted,
+}
+
+impl FilenameMode {
+    pub fn new(restrict_filenames: bool, windows_filenames: bool) -> Self {
+        match (restrict_filenames, windows_filenames) {
+            (true, _) => Self::Restricted,
+            (false, true) => Self::Windows,
+            _ if cfg!(windows) => Self::Windows,
+            _ => Self::Default,
+        }
+    }
+}
+
+/// Directories of `--paths`: `home` for the final files and `temp` for the formats downloaded
+/// before being merged into them.
+#[derive(Debug, Clone, Default)]
+pub struct Paths {
+    pub home: Option<PathBuf>,
+    pub temp: Option<PathBuf>,
+}
+
+impl Paths {
+    /// Parse entries like "home:Videos" or "temp:/tmp". An entry without a type is the home path.
+    pub fn parse(entries: &[String]) -> Result<Self> {
+        let mut paths = Self::default();
+
+        for entry in entries {
+            match entry.split_once(':') {
+                Some(("home", path)) => paths.home = Some(path.into()),
+                Some(("temp", path)) => paths.temp = Some(path.into()),
+                Some((kind, _))
+                    if kind.len() > 1 && kind.chars().all(|c| c.is_ascii_lowercase()) =>
+                {
+                    bail!("Unknown path type \"{}\", expected home or temp.", kind)
+                }
+                _ => paths.home = Some(entry.into()),
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// `path` in the home directory, unless it's absolute.
+    pub fn home(&self, path: &str) -> String {
+        match &self.home {
+            Some(home) => home.join(path).to_string_lossy().into_owned(),
+            None => path.to_string(),
+        }
+    }
+
+    /// `path`, a file of the home directory, in the temporary directory if there is one.
+    pub fn temp(&self, path: &str) -> String {
+        match (&self.temp, Path::new(path).file_name()) {
+            (Some(temp), Some(file_name)) => temp.join(file_name).to_string_lossy().into_owned(),
+            _ => path.to_string(),
+        }
+    }
+}
+
+/// Evaluate an output template like `%(title)s - %(duration)d` against an info dict.
+/// Supported conversions are `s`, `d` and `j` (JSON), `.` separates nested fields and
+/// `%%` is a literal `%`. Missing fields are rendered as `NA`.
+pub fn render_template(template: &str, info: &Value) -> String {
+    render(template, info, |value| value)
+}
+
+/// `render_template` for file paths, where every field is made a valid file name so that titles
+/// can't add directories. The separators of the template itself are kept.
+pub fn render_filename(template: &str, info: &Value, mode: FilenameMode) -> String {
+    render(template, info, |value| sanitize_filename(&value, mode))
+}
+
+/// Whether `template` has fields to fill in, rather than being a plain path.
+pub fn has_fields(template: &str) -> bool {
+    template.contains("%(")
+}
+
+pub fn sanitize_filename(name: &str, mode: FilenameMode) -> String {
+    let sanitized = match mode {
+        FilenameMode::Default => name.replace(['/', '\0'], "_"),
+        FilenameMode::Windows => {
+            let sanitized = name
+                .chars()
+                .map(|c| match c.is_control() || r#"<>:"/\|?*"#.contains(c) {
+                    true => '_',
+                    false => c,
+                })
+                .collect::<String>();
+            // Windows drops trailing dots and spaces, and opens devices for these names whatever
+            // the extension.
+            let sanitized = sanitized.trim_end_matches(['.', ' ']).to_string();
+            let base = sanitized
+                .split('.')
+                .next()
+                .unwrap_or_default()
+                .to_uppercase();
+            let reserved = matches!(base.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+                || ((base.starts_with("COM") || base.starts_with("LPT"))
+                    && base.len() == 4
+                    && base.ends_with(|c: char| c.is_ascii_digit()));
+            match reserved {
+                true => format!("_{}", sanitized),
+                false => sanitized,
+            }
+        }
+        FilenameMode::Restricted => {
+            let mut sanitized = String::with_capacity(name.len());
+            for c in name.chars() {
+                let c = match c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                    true => c,
+                    false => '_',
+                };
+                if !(c == '_' && sanitized.ends_with('_')) {
+                    sanitized.push(c);
+                }
+            }
+            // A leading "-" would be read as an option by other programs.
+            sanitized
+                .trim_matches('_')
+                .trim_start_matches('-')
+                .to_string()
+        }
+    };
+
+    match sanitized.as_str() {
+        "" | "." | ".." => "_".to_string(),
+        _ => sanitized,
+    }
+}
+
+fn render(template: &str, info: &Value, convert: impl Fn(String) -> String) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(pos) = rest.find('%') {
+        rendered.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        if let Some(after) = rest.strip_prefix("%%") {
+            rendered.push('%');
+            rest = after;
+            continue;
+        }
+
+        let Some((field, conversion, after)) = parse_field(rest) else {
+            rendered.push('%');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let value = field
+            .split('.')
+            .try_fold(info, |value, key| match value {
+                Value::Array(arr) => arr.get(key.parse::<usize>().ok()?),
+                _ => value.get(key),
+            })
+            .filter(|value| !value.is_null());
+
+        rendered.push_str(&convert(match value {
+            None => "NA".to_string(),
+            Some(value) => format_value(value, conversion),
+        }));
+        rest = after;
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Split `%(field)c...` into the field, the conversion character and the remaining template.
+fn parse_field(s: &str) -> Option<(&str, char, &str)> {
+    let inner = s.strip_prefix("%(")?;
+    let end = inner.find(')')?;
+    let field = &inner[..end];
+    let after = &inner[end + 1..];
+    let conversion = after
+        .chars()
+        .next()
+        .filter(|c| matches!(c, 's' | 'd' | 'j'))?;
+
+    Some((field, conversion, &after[1..]))
+}
+
+fn format_value(value: &Value, conversion: char) -> String {
+    match (conversion, value) {
+        ('j', _) => value.to_string(),
+        ('d', Value::Number(n)) => n
+            .as_i64()
+            .map(|n| n.to_string())
+            .or_else(|| n.as_f64().map(|n| (n as i64).to_string()))
+            .unwrap_or_default(),
+        ('d', _) => "NA".to_string(),
+        (_, Value::String(s)) => s.clone(),
+        (_, Value::Array(arr)) => arr
+            .iter()
+            .map(|v| format_value(v, 's'))
+            .collect::<Vec<_>>()
+            .join(", "),
+        (_, value) => value.to_string(),
+    }
+}
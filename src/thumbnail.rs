@@ -0,0 +1,122 @@
+use anyhow::{Result, bail};
+use tokio::fs;
+use tydle::{Ext, Fetch, Tydle, YtThumbnail};
+
+use crate::ffmpeg::{count_video_streams, run_ffmpeg};
+
+/// Image format thumbnails are converted to when YouTube serves them as webp.
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum)]
+pub enum ThumbnailFormat {
+    #[default]
+    Jpg,
+    Png,
+}
+
+impl ThumbnailFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Jpg => "jpg",
+            Self::Png => "png",
+        }
+    }
+}
+
+/// Pick the thumbnail with the largest resolution.
+pub fn best_thumbnail(thumbnails: &[YtThumbnail]) -> Option<&YtThumbnail> {
+    thumbnails
+        .iter()
+        .max_by_key(|t| t.width.unwrap_or_default() * t.height.unwrap_or_default())
+}
+
+/// Download `thumbnail` to `{stem}.{ext}`, converting it to `format` with ffmpeg if it isn't a jpg or png.
+/// Returns the path of the written image.
+pub async fn write_thumbnail(
+    tydle: &Tydle,
+    thumbnail: &YtThumbnail,
+    stem: &str,
+    format: ThumbnailFormat,
+) -> Result<String> {
+    let data = tydle.get_thumbnail(thumbnail).await?;
+
+    let ext = if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "jpg"
+    } else if data.starts_with(b"\x89PNG") {
+        "png"
+    } else {
+        let source = format!("{}.webp", stem);
+        let output = format!("{}.{}", stem, format.as_str());
+        fs::write(&source, &data).await?;
+
+        run_ffmpeg(&["-y", "-i", &source, &output]).await?;
+        fs::remove_file(&source).await?;
+
+        return Ok(output);
+    };
+
+    let output = format!("{}.{}", stem, ext);
+    fs::write(&output, &data).await?;
+
+    Ok(output)
+}
+
+/// Attach the image at `thumbnail` to `media` as its cover art.
+pub async fn embed_thumbnail(media: &str, ext: Ext, thumbnail: &str) -> Result<()> {
+    let temp = format!("{}.temp.{}", media, ext.as_str());
+
+    match ext {
+        Ext::Mp4 | Ext::M4a | Ext::M4v | Ext::Mov => {
+            // The cover comes after the video streams of the media, audio files have none.
+            let disposition = format!("-disposition:v:{}", count_video_streams(media).await?);
+            run_ffmpeg(&[
+                "-y",
+                "-i",
+                media,
+                "-i",
+                thumbnail,
+                "-map",
+                "0",
+                "-map",
+                "1",
+                "-c",
+                "copy",
+                &disposition,
+                "attached_pic",
+                &temp,
+            ])
+            .await?
+        }
+        Ext::Mkv => {
+            let (mimetype, filename) = if thumbnail.ends_with(".png") {
+                ("mimetype=image/png", "filename=cover.png")
+            } else {
+                ("mimetype=image/jpeg", "filename=cover.jpg")
+            };
+
+            run_ffmpeg(&[
+                "-y",
+                "-i",
+                media,
+                "-map",
+                "0",
+                "-c",
+                "copy",
+                "-attach",
+                thumbnail,
+                "-metadata:s:t",
+                mimetype,
+                "-metadata:s:t",
+                filename,
+                &temp,
+            ])
+            .await?
+        }
+        _ => bail!(
+            "Embedding thumbnails in {} files is not supported.",
+            ext.as_str()
+        ),
+    }
+
+    fs::rename(&temp, media).await?;
+
+    Ok(())
+}
@@ -1,24 +1,53 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
 use std::pin::Pin;
-#[cfg(feature = "cipher")]
-use std::sync::Mutex as StdMutex;
-#[cfg(target_arch = "wasm32")]
-use std::sync::Mutex;
+#[cfg(all(feature = "decipher", not(target_arch = "wasm32")))]
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::{future::Future, sync::Arc};
 #[cfg(not(target_arch = "wasm32"))]
-use tokio::sync::Mutex;
+pub use tokio_util::sync::CancellationToken;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::wasm_bindgen;
 
 use crate::YtClient;
-use crate::cache::CacheStore;
-#[cfg(feature = "cipher")]
-use crate::cipher::decipher::{SignatureDecipher, SignatureDecipherHandle};
-use crate::cookies::DomainCookies;
-use crate::yt_interface::{YtManifest, YtStreamResponse, YtVideoInfo};
+use crate::cache::{Cache, MemoryCache, PLAYER_KEY_PREFIXES};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::cache::{DISK_CACHE_TTL, DiskCache};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::challenge::ChallengeSolver;
+#[cfg(all(feature = "decipher", not(target_arch = "wasm32")))]
+use crate::cipher::evaluator::JsEvaluator;
+#[cfg(feature = "decipher")]
+use crate::cipher::{
+    decipher::{SignatureDecipher, SignatureDecipherHandle},
+    evaluator::default_evaluator,
+};
+use crate::cookies::{CookieStore, DomainCookies};
+use crate::error::TydleError;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::extractor::client::ClientOverrides;
+use crate::http::HttpTransport;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::http::Middleware;
+use crate::metrics::TydleMetrics;
+use crate::yt_interface::{
+    ExtractionStrategy, PlaylistId, YtChannel, YtChannelFeed, YtClientInfo, YtManifest, YtPlaylist,
+    YtStreamResponse, YtSubtitle, YtSubtitleFormat, YtThumbnail, YtVideoInfo,
+};
+#[cfg(feature = "decipher")]
+use crate::yt_interface::{YtStream, YtStreamSource};
 use crate::{
-    extractor::extract::{InfoExtractor, YtExtractor},
+    extractor::{
+        auth::ExtractorAuthHandle,
+        client::INNERTUBE_CLIENTS,
+        download::ExtractorDownloadHandle,
+        extract::{InfoExtractor, YtExtractor},
+        feed::ExtractorFeedHandle,
+        player::ExtractorPlayerHandle,
+        playlist::ExtractorPlaylistHandle,
+        ytcfg::ExtractorYtCfgHandle,
+    },
     yt_interface::VideoId,
 };
 
@@ -33,37 +62,404 @@ use crate::{
 pub struct TydleOptions {
     /// Map of cookies extracted from an authenticated YouTube account.
     pub auth_cookies: DomainCookies,
-    /// Attempts to fetch over http instead of https.
+    /// Fetch the YouTube pages, the player and the channel feeds over http instead of https. The
+    /// InnerTube API only answers over https and stream URLs are used as YouTube returns them.
+    /// Can't be combined with `auth_cookies`, which are never sent unencrypted.
     pub prefer_insecure: bool,
     /// Provide an address to set it as the `X-Forwarded-For` header when requesting YouTube.
     pub source_address: String,
+    /// Two letter code of a country, like "DE", to send a random address of as the `X-Forwarded-For`
+    /// header, to work around geo restrictions. Ignored when `source_address` is set.
+    pub geo_bypass_country: Option<String>,
+    /// Local IP address that connections to YouTube are bound to. An unspecified address
+    /// (`0.0.0.0` or `::`) forces connections over IPv4 or IPv6 respectively.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub source_ip: Option<std::net::IpAddr>,
+    /// Custom DNS resolution for requests to YouTube.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub dns: crate::dns::DnsOptions,
+    /// Client used for every request tydle sends, to configure TLS, proxies or connection pooling once.
+    /// When set, `source_ip` and `dns` are ignored since they only apply to the client tydle builds itself.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub http_client: Option<reqwest::Client>,
+    /// Proxies and local addresses requests to YouTube are spread over, instead of `source_ip`.
+    /// Ignored along with `source_ip` when `http_client` is set.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub proxy_pool: Option<crate::proxy::ProxyPoolOptions>,
+    /// Where the player JS, signature timestamps, deciphered signatures, visitor data and PO tokens
+    /// are kept. Takes precedence over `cache_dir`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub cache: Option<Arc<dyn Cache>>,
+    /// Directory of the `DiskCache` used when `cache` isn't set, `DiskCache::default_dir()`
+    /// (`~/.cache/tydle`) by default. Its entries expire after `DISK_CACHE_TTL`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub cache_dir: Option<std::path::PathBuf>,
+    /// Passphrase the visitor data and PO tokens kept in the cache are encrypted with, see
+    /// `credentials::EncryptedCache`.
+    #[cfg(all(feature = "encrypted-credentials", not(target_arch = "wasm32")))]
+    pub credentials_passphrase: Option<String>,
+    /// Keep the cache in memory for the lifetime of the instance instead of in `cache_dir`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub no_cache_dir: bool,
+    /// Directory the watch and embed pages, the ytcfg, every player response and the player JS
+    /// are written to as they're fetched, under a subdirectory per video, to debug extraction when
+    /// YouTube changes something.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub debug_dump_dir: Option<std::path::PathBuf>,
+    /// Directory the sanitized response of every request is recorded to, for
+    /// `fixtures::ReplayTransport` to answer with.
+    #[cfg(all(feature = "test-fixtures", not(target_arch = "wasm32")))]
+    pub record_fixtures_dir: Option<std::path::PathBuf>,
+    /// JavaScript runtime signatures are deciphered with, picked from the enabled features by default.
+    #[cfg(all(feature = "decipher", not(target_arch = "wasm32")))]
+    pub js_evaluator: Option<Arc<dyn JsEvaluator>>,
+    /// Directory holding `yt.solver.lib.min.js` and `yt.solver.core.min.js` of yt-dlp's EJS solver,
+    /// so that deciphering works offline. They are downloaded from GitHub when missing.
+    #[cfg(all(feature = "decipher", not(target_arch = "wasm32")))]
+    pub ejs_dir: Option<std::path::PathBuf>,
+    /// Decipher signatures inside a dedicated Web Worker instead of on the main thread.
+    #[cfg(all(feature = "decipher", target_arch = "wasm32"))]
+    pub js_worker: bool,
+    /// Prefix `downloadStream` appends the percent-encoded stream URLs to, like
+    /// "https://proxy.example/?url=", for browsers where CORS blocks fetching them directly.
+    #[cfg(target_arch = "wasm32")]
+    pub stream_proxy: Option<String>,
+    /// Decipher the signature and `n` parameter of every stream during extraction, so that
+    /// `get_streams` only returns `YtStreamSource::URL` sources.
+    #[cfg(feature = "decipher")]
+    pub auto_decipher: bool,
+    /// Solver signatures and PO tokens are delegated to before tydle handles them itself.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub challenge_solver: Option<Arc<dyn ChallengeSolver>>,
+    /// Endpoint of an `HttpChallengeSolver`, used when `challenge_solver` isn't set.
+    pub challenge_solver_url: Option<String>,
+    /// Transport every request is sent with, for HTTP stacks other than reqwest. Takes precedence over `http_client`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub transport: Option<Arc<dyn HttpTransport>>,
+    /// Hooks run around every request, including the ones sent through `Tydle::transport`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub middlewares: Vec<Arc<dyn Middleware>>,
+    /// Client versions and user agents replacing the compiled ones, see `ClientOverrides`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub client_overrides: ClientOverrides,
+    /// Whether player responses come from the InnerTube API, the watch and embed pages, or the
+    /// pages when the API fails.
+    pub extraction_strategy: ExtractionStrategy,
     /// Provide a default client that tydle will use to request YouTube when it fetches without a specific client internally.
     pub default_client: YtClient,
+    /// Maximum time in seconds a single request to YouTube may take before it is aborted.
+    #[cfg_attr(target_arch = "wasm32", tsify(type = "number | undefined"))]
+    pub request_timeout_secs: Option<u64>,
+    /// Maximum time in seconds to wait for a connection to be established.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub connect_timeout_secs: Option<u64>,
+    /// Maximum time in seconds a single operation, like `get_streams`, may take in total, whatever
+    /// number of requests it sends. Fails with `TydleError::DeadlineExceeded` once it's reached.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub overall_deadline_secs: Option<u64>,
+    /// Minimum time in seconds between two requests to YouTube, to avoid rate limits and bot checks on large batches.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub sleep_requests_secs: Option<f64>,
+    /// Maximum number of requests per minute to each host, with 429 responses pausing every request
+    /// for as long as their `Retry-After` asks. Bursts are queued instead of sent at once.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub requests_per_minute: Option<u32>,
+    /// Token that aborts every in-flight extraction of this instance once cancelled.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub cancellation_token: CancellationToken,
 }
 
+/// Cloning is cheap, clones share their caches and cancellation token.
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Clone)]
 pub struct Tydle {
-    yt_extractor: Arc<Mutex<YtExtractor>>,
-    #[cfg(feature = "cipher")]
-    signature_decipher: Arc<StdMutex<SignatureDecipher>>,
+    yt_extractor: Arc<YtExtractor>,
+    #[cfg(feature = "decipher")]
+    signature_decipher: Arc<SignatureDecipher>,
+    #[cfg(feature = "decipher")]
+    auto_decipher: bool,
+    /// Set once a player has been warmed up in the background.
+    #[cfg(all(feature = "decipher", not(target_arch = "wasm32")))]
+    player_warmed: Arc<AtomicBool>,
+    #[cfg(not(target_arch = "wasm32"))]
+    cancellation_token: CancellationToken,
+    #[cfg(not(target_arch = "wasm32"))]
+    overall_deadline: Option<std::time::Duration>,
 }
 
 impl Tydle {
     #[cfg(not(target_arch = "wasm32"))]
     pub fn new(options: TydleOptions) -> Result<Self> {
-        let player_cache = Arc::new(CacheStore::new());
-        let code_cache = Arc::new(CacheStore::new());
+        let cache = options
+            .cache
+            .clone()
+            .unwrap_or_else(|| default_cache(&options));
+        #[cfg(feature = "encrypted-credentials")]
+        let cache: Arc<dyn Cache> = match &options.credentials_passphrase {
+            Some(passphrase) => {
+                Arc::new(crate::credentials::EncryptedCache::new(cache, passphrase))
+            }
+            None => cache,
+        };
+        let cancellation_token = options.cancellation_token.clone();
+        let overall_deadline = options
+            .overall_deadline_secs
+            .map(std::time::Duration::from_secs);
 
-        let yt_extractor = YtExtractor::new(player_cache.clone(), code_cache.clone(), options)?;
-        #[cfg(feature = "cipher")]
-        let signature_decipher = SignatureDecipher::new(player_cache, code_cache);
+        #[cfg(feature = "decipher")]
+        let (js_evaluator, ejs_dir, auto_decipher) = (
+            options.js_evaluator.clone(),
+            options.ejs_dir.clone(),
+            options.auto_decipher,
+        );
+        let yt_extractor = YtExtractor::new(cache, options)?;
+        #[cfg(feature = "decipher")]
+        let signature_decipher = SignatureDecipher::new(
+            yt_extractor.cache.clone(),
+            yt_extractor.transport.clone(),
+            js_evaluator.unwrap_or_else(default_evaluator),
+        )
+        .ejs_dir(ejs_dir)
+        .challenge_solver(yt_extractor.challenge_solver.clone())
+        .cookie_jar(Some(yt_extractor.cookie_jar.clone()))
+        .metrics(Some(yt_extractor.metrics.clone()));
 
         Ok(Self {
-            yt_extractor: Arc::new(Mutex::new(yt_extractor)),
-            #[cfg(feature = "cipher")]
-            signature_decipher: Arc::new(StdMutex::new(signature_decipher)),
+            yt_extractor: Arc::new(yt_extractor),
+            #[cfg(feature = "decipher")]
+            signature_decipher: Arc::new(signature_decipher),
+            #[cfg(feature = "decipher")]
+            auto_decipher,
+            #[cfg(feature = "decipher")]
+            player_warmed: Arc::new(AtomicBool::new(false)),
+            cancellation_token,
+            overall_deadline,
+        })
+    }
+
+    /// Transport tydle sends its requests with, so that streams can be downloaded over the same connections.
+    pub fn transport(&self) -> &Arc<dyn HttpTransport> {
+        &self.yt_extractor.transport
+    }
+
+    /// User agent `client` requests YouTube with, `client_overrides` included. Streams of some
+    /// clients are only served to it.
+    pub fn client_user_agent(&self, client: &YtClient) -> Option<String> {
+        self.yt_extractor
+            .innertube_client(client)
+            .innertube_context
+            .get("client")?
+            .get("userAgent")?
+            .as_str()
+            .map(str::to_string)
+    }
+
+    /// Download the player JS and extract its signature timestamp, then load the scripts
+    /// signatures are deciphered with, so that the first extraction or download doesn't wait on them.
+    ///
+    /// The first extraction of an instance does this in the background on its own.
+    pub async fn warm_player(&self, video_id: &VideoId) -> Result<(), TydleError> {
+        self.cancellable(async move {
+            let player_url = self
+                .yt_extractor
+                .download_player_url(video_id)
+                .await?
+                .ok_or(anyhow!("Could not find the URL of the player."))?;
+
+            self.warm_player_url(video_id, player_url).await
+        })
+        .await
+    }
+
+    /// Signature timestamp (sts) of the player at `player_url`, which the API is told which player
+    /// is in use with. The player is downloaded unless it's cached.
+    pub async fn signature_timestamp(
+        &self,
+        video_id: &VideoId,
+        player_url: &str,
+    ) -> Result<Option<i64>, TydleError> {
+        self.cancellable(async move {
+            self.yt_extractor
+                .extract_signature_timestamp(video_id, player_url.to_string(), &HashMap::new())
+                .await
+        })
+        .await
+    }
+
+    async fn warm_player_url(&self, video_id: &VideoId, player_url: String) -> Result<()> {
+        self.yt_extractor
+            .extract_signature_timestamp(video_id, player_url, &HashMap::new())
+            .await?;
+        #[cfg(feature = "decipher")]
+        self.signature_decipher.warm_up().await?;
+
+        Ok(())
+    }
+
+    /// Warms the player of the first extraction up without waiting for it. Skipped with
+    /// `auto_decipher`, which needs the player right away anyway.
+    #[cfg(all(feature = "decipher", not(target_arch = "wasm32")))]
+    fn warm_player_in_background(&self, video_id: &VideoId, player_url: &str) {
+        if self.auto_decipher
+            || player_url.is_empty()
+            || self.player_warmed.swap(true, Ordering::Relaxed)
+        {
+            return;
+        }
+
+        let tydle = self.clone();
+        let video_id = video_id.clone();
+        let player_url = player_url.to_string();
+
+        tokio::spawn(async move {
+            match tydle
+                .cancellable(tydle.warm_player_url(&video_id, player_url))
+                .await
+            {
+                Ok(()) => {}
+                Err(e) => tracing::debug!("Could not warm the player up: {}", e),
+            }
+        });
+    }
+
+    /// Cookies of the instance, including the ones YouTube set or rotated since it was created.
+    ///
+    /// Save them with `DomainCookies::to_netscape` and pass them back as `TydleOptions::auth_cookies`
+    /// to keep the same session.
+    pub fn export_cookies(&self) -> Result<DomainCookies, TydleError> {
+        Ok(self.yt_extractor.cookie_jar.all()?)
+    }
+
+    /// Counters of the API calls, downloaded bytes, cache lookups and deciphers of this instance and its clones.
+    pub fn metrics(&self) -> Result<TydleMetrics, TydleError> {
+        Ok(self.yt_extractor.metrics.snapshot()?)
+    }
+
+    /// Check that the `auth_cookies` still sign YouTube in, so that an expired session fails up front
+    /// instead of the extractions that need it.
+    ///
+    /// Fails with `TydleError::LoginRequired` without auth cookies and with `TydleError::AuthExpired`
+    /// when YouTube signed them out.
+    pub async fn check_auth(&self) -> Result<(), TydleError> {
+        self.cancellable(self.yt_extractor.check_auth()).await
+    }
+
+    /// Forget every cached player JS, signature timestamp and deciphered signature, for instance
+    /// after YouTube rolled out a player that breaks deciphering with the cached one. The visitor
+    /// data, PO tokens and the rest of the cache are kept.
+    pub fn clear_player_caches(&self) -> Result<(), TydleError> {
+        for prefix in PLAYER_KEY_PREFIXES {
+            self.yt_extractor.cache.remove_prefixed(prefix)?;
+        }
+        #[cfg(all(feature = "decipher", not(target_arch = "wasm32")))]
+        self.player_warmed.store(false, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// The final URL of `stream` from `stream_response`, deciphering its signature or its `n`
+    /// parameter with the player of the response.
+    #[cfg(feature = "decipher")]
+    pub async fn resolve_url(
+        &self,
+        stream: &YtStream,
+        stream_response: &YtStreamResponse,
+    ) -> Result<String, TydleError> {
+        let player_url = &stream_response.player_url;
+        match &stream.source {
+            YtStreamSource::URL(url) => self.decipher_url(url, player_url).await,
+            YtStreamSource::Signature(signature) => {
+                self.decipher_signature(signature, player_url).await
+            }
+        }
+    }
+
+    /// Forget the deciphered `n` parameter of `stream`, for instance when its download is
+    /// throttled anyway, so that resolving it again deciphers it anew. The rest of the cache is
+    /// kept.
+    #[cfg(feature = "decipher")]
+    pub fn forget_n_signature(
+        &self,
+        stream: &YtStream,
+        player_url: &str,
+    ) -> Result<(), TydleError> {
+        let url = match &stream.source {
+            YtStreamSource::URL(url) => url.clone(),
+            YtStreamSource::Signature(signature) => {
+                match crate::utils::parse_query_string(signature)
+                    .unwrap_or_default()
+                    .remove("url")
+                {
+                    Some(url) => url,
+                    None => return Ok(()),
+                }
+            }
+        };
+
+        self.signature_decipher
+            .forget_n(&url, player_url)
+            .map_err(Into::into)
+    }
+
+    /// Resolves the streams when `TydleOptions::auto_decipher` is set.
+    async fn finish_streams(&self, stream_response: YtStreamResponse) -> Result<YtStreamResponse> {
+        #[cfg(feature = "decipher")]
+        if self.auto_decipher {
+            return self
+                .resolve_streams(stream_response)
+                .await
+                .map_err(Into::into);
+        }
+
+        Ok(stream_response)
+    }
+
+    /// Races `fut` against the instance's cancellation token.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn cancellable<'a, T: 'a>(
+        &'a self,
+        fut: impl Future<Output = Result<T>> + Send + 'a,
+    ) -> Pin<Box<dyn Future<Output = Result<T, TydleError>> + Send + 'a>> {
+        Box::pin(async move {
+            let deadline = async {
+                match self.overall_deadline {
+                    Some(deadline) => tokio::time::sleep(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                res = fut => res.map_err(TydleError::from),
+                _ = self.cancellation_token.cancelled() => Err(TydleError::Cancelled),
+                _ = deadline => Err(TydleError::DeadlineExceeded),
+            }
         })
     }
+
+    #[cfg(target_arch = "wasm32")]
+    fn cancellable<'a, T: 'a>(
+        &'a self,
+        fut: impl Future<Output = Result<T>> + 'a,
+    ) -> Pin<Box<dyn Future<Output = Result<T, TydleError>> + 'a>> {
+        Box::pin(async move { fut.await.map_err(TydleError::from) })
+    }
+}
+
+/// List every client tydle can request YouTube with, along with its capabilities.
+///
+/// ```
+/// for info in tydle::clients() {
+///     println!("{}: cookies = {}", info.client.as_str(), info.supports_cookies);
+/// }
+/// ```
+pub fn clients() -> Vec<YtClientInfo> {
+    let mut clients: Vec<YtClientInfo> = INNERTUBE_CLIENTS
+        .iter()
+        .map(|(client, innertube_client)| innertube_client.to_client_info(*client))
+        .collect();
+
+    clients.sort_by_key(|info| info.client);
+    clients
 }
 
 pub trait Extract {
@@ -195,81 +591,169 @@ pub trait Extract {
     /// }
     /// ```
     fn get_streams<'a>(&'a self, video_id: &'a VideoId) -> Self::ExtractStreamFut<'a>;
+    /// List the videos of a playlist, following every page of it.
+    ///
+    /// With auth cookies, this also reads private playlists and the `WL` (Watch Later) and `LL`
+    /// (Liked Videos) playlists of the account, which fail with `TydleError::LoginRequired` without them.
+    ///
+    /// ```
+    /// use tydle::{Tydle, TydleOptions, Extract, PlaylistId};
+    /// use anyhow::Result;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///   let ty = Tydle::new(TydleOptions{ ..Default::default() })?;
+    ///
+    ///   let playlist_id = PlaylistId::new("PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI")?;
+    ///   let playlist = ty.get_playlist(&playlist_id).await?;
+    ///
+    ///   for entry in playlist.entries {
+    ///     println!("{}: {}", entry.video_id, entry.title);
+    ///   }
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    fn get_playlist<'a>(&'a self, playlist_id: &'a PlaylistId) -> Self::ExtractPlaylistFut<'a>;
+    /// List the latest uploads of a channel from its RSS feed. This is a single cheap request
+    /// that needs no authentication, which suits polling a channel for new videos.
+    ///
+    /// ```
+    /// use tydle::{Tydle, TydleOptions, Extract, YtChannel};
+    /// use anyhow::Result;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///   let ty = Tydle::new(TydleOptions{ ..Default::default() })?;
+    ///
+    ///   let channel = YtChannel::new("UCuAXFkgsw1L7xaCfnd5JJOw", None)?;
+    ///   let feed = ty.get_channel_feed(&channel).await?;
+    ///
+    ///   for entry in feed.entries {
+    ///     println!("{} ({}): {}", entry.video_id, entry.published, entry.title);
+    ///   }
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    fn get_channel_feed<'a>(&'a self, channel: &'a YtChannel) -> Self::ExtractFeedFut<'a>;
+    /// List the videos of the subscriptions feed of the account the auth cookies belong to,
+    /// following every page of it. Fails with `TydleError::LoginRequired` without auth cookies.
+    fn get_subscriptions_feed(&self) -> Self::ExtractPlaylistFut<'_>;
 
-    type ExtractStreamFut<'a>: Future<Output = Result<YtStreamResponse>> + 'a
+    type ExtractStreamFut<'a>: Future<Output = Result<YtStreamResponse, TydleError>> + 'a
+    where
+        Self: 'a;
+    type ExtractInfoFut<'a>: Future<Output = Result<YtVideoInfo, TydleError>> + 'a
+    where
+        Self: 'a;
+    type ExtractManifestFut<'a>: Future<Output = Result<YtManifest, TydleError>> + 'a
+    where
+        Self: 'a;
+    type ExtractPlaylistFut<'a>: Future<Output = Result<YtPlaylist, TydleError>> + 'a
     where
         Self: 'a;
-    type ExtractInfoFut<'a>: Future<Output = Result<YtVideoInfo>> + 'a
+    type ExtractFeedFut<'a>: Future<Output = Result<YtChannelFeed, TydleError>> + 'a
     where
         Self: 'a;
-    type ExtractManifestFut<'a>: Future<Output = Result<YtManifest>> + 'a
+}
+
+pub trait Fetch {
+    /// Download a subtitle track of a video in the given format.
+    ///
+    /// Subtitle tracks are listed in `YtVideoInfo::subtitles`.
+    fn get_subtitle<'a>(
+        &'a self,
+        subtitle: &'a YtSubtitle,
+        format: YtSubtitleFormat,
+    ) -> Self::FetchTextFut<'a>;
+    /// Download the image of a thumbnail listed in `YtVideoInfo::thumbnails`.
+    fn get_thumbnail<'a>(&'a self, thumbnail: &'a YtThumbnail) -> Self::FetchBytesFut<'a>;
+
+    type FetchTextFut<'a>: Future<Output = Result<String, TydleError>> + 'a
+    where
+        Self: 'a;
+    type FetchBytesFut<'a>: Future<Output = Result<Vec<u8>, TydleError>> + 'a
     where
         Self: 'a;
 }
 
-#[cfg(feature = "cipher")]
+#[cfg(feature = "decipher")]
 pub trait Cipher {
     /// Deciphers a stream's signature and returns it's URL.
     fn decipher_signature<'a>(
         &'a self,
-        signature: String,
-        player_url: String,
+        signature: &'a str,
+        player_url: &'a str,
     ) -> Self::DecipherFut<'a>;
-    type DecipherFut<'a>: Future<Output = Result<String>> + 'a
+    /// Deciphers the `n` query parameter of a `YtStreamSource::URL` stream, without which
+    /// YouTube throttles its download. URLs without one are returned as they are.
+    fn decipher_url<'a>(&'a self, url: &'a str, player_url: &'a str) -> Self::DecipherFut<'a>;
+    /// Replaces the source of every stream with its final URL, deciphering signatures
+    /// and `n` parameters. `TydleOptions::auto_decipher` does this during extraction.
+    fn resolve_streams<'a>(&'a self, stream_response: YtStreamResponse) -> Self::ResolveFut<'a>;
+    type DecipherFut<'a>: Future<Output = Result<String, TydleError>> + 'a
+    where
+        Self: 'a;
+    type ResolveFut<'a>: Future<Output = Result<YtStreamResponse, TydleError>> + 'a
     where
         Self: 'a;
 }
 
 impl Extract for Tydle {
     #[cfg(not(target_arch = "wasm32"))]
-    type ExtractStreamFut<'a> = Pin<Box<dyn Future<Output = Result<YtStreamResponse>> + Send + 'a>>;
+    type ExtractStreamFut<'a> =
+        Pin<Box<dyn Future<Output = Result<YtStreamResponse, TydleError>> + Send + 'a>>;
+    #[cfg(not(target_arch = "wasm32"))]
+    type ExtractInfoFut<'a> =
+        Pin<Box<dyn Future<Output = Result<YtVideoInfo, TydleError>> + Send + 'a>>;
+    #[cfg(not(target_arch = "wasm32"))]
+    type ExtractManifestFut<'a> =
+        Pin<Box<dyn Future<Output = Result<YtManifest, TydleError>> + Send + 'a>>;
     #[cfg(not(target_arch = "wasm32"))]
-    type ExtractInfoFut<'a> = Pin<Box<dyn Future<Output = Result<YtVideoInfo>> + Send + 'a>>;
+    type ExtractPlaylistFut<'a> =
+        Pin<Box<dyn Future<Output = Result<YtPlaylist, TydleError>> + Send + 'a>>;
     #[cfg(not(target_arch = "wasm32"))]
-    type ExtractManifestFut<'a> = Pin<Box<dyn Future<Output = Result<YtManifest>> + Send + 'a>>;
+    type ExtractFeedFut<'a> =
+        Pin<Box<dyn Future<Output = Result<YtChannelFeed, TydleError>> + Send + 'a>>;
 
     #[cfg(target_arch = "wasm32")]
-    type ExtractStreamFut<'a> = Pin<Box<dyn Future<Output = Result<YtStreamResponse>> + 'a>>;
+    type ExtractStreamFut<'a> =
+        Pin<Box<dyn Future<Output = Result<YtStreamResponse, TydleError>> + 'a>>;
+    #[cfg(target_arch = "wasm32")]
+    type ExtractInfoFut<'a> = Pin<Box<dyn Future<Output = Result<YtVideoInfo, TydleError>> + 'a>>;
+    #[cfg(target_arch = "wasm32")]
+    type ExtractManifestFut<'a> =
+        Pin<Box<dyn Future<Output = Result<YtManifest, TydleError>> + 'a>>;
     #[cfg(target_arch = "wasm32")]
-    type ExtractInfoFut<'a> = Pin<Box<dyn Future<Output = Result<YtVideoInfo>> + 'a>>;
+    type ExtractPlaylistFut<'a> =
+        Pin<Box<dyn Future<Output = Result<YtPlaylist, TydleError>> + 'a>>;
     #[cfg(target_arch = "wasm32")]
-    type ExtractManifestFut<'a> = Pin<Box<dyn Future<Output = Result<YtManifest>> + 'a>>;
+    type ExtractFeedFut<'a> = Pin<Box<dyn Future<Output = Result<YtChannelFeed, TydleError>> + 'a>>;
 
     fn get_streams<'a>(&'a self, video_id: &'a VideoId) -> Self::ExtractStreamFut<'a> {
-        Box::pin(async move {
-            #[cfg(not(target_arch = "wasm32"))]
-            let extractor = self.yt_extractor.lock().await;
-            #[cfg(target_arch = "wasm32")]
-            let extractor = self
-                .yt_extractor
-                .lock()
-                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
-            extractor.extract_streams(video_id).await
+        self.cancellable(async move {
+            let extractor = &self.yt_extractor;
+            let stream_response = extractor.extract_streams(video_id).await?;
+            #[cfg(all(feature = "decipher", not(target_arch = "wasm32")))]
+            self.warm_player_in_background(video_id, &stream_response.player_url);
+            self.finish_streams(stream_response).await
         })
     }
 
     fn get_manifest<'a>(&'a self, video_id: &'a VideoId) -> Self::ExtractManifestFut<'a> {
-        Box::pin(async move {
-            #[cfg(not(target_arch = "wasm32"))]
-            let extractor = self.yt_extractor.lock().await;
-            #[cfg(target_arch = "wasm32")]
-            let extractor = self
-                .yt_extractor
-                .lock()
-                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
-            extractor.extract_manifest(video_id).await
+        self.cancellable(async move {
+            let extractor = &self.yt_extractor;
+            let manifest = extractor.extract_manifest(video_id).await?;
+            #[cfg(all(feature = "decipher", not(target_arch = "wasm32")))]
+            self.warm_player_in_background(video_id, &manifest.player_url);
+            Ok(manifest)
         })
     }
 
     fn get_video_info<'a>(&'a self, video_id: &'a VideoId) -> Self::ExtractInfoFut<'a> {
-        Box::pin(async move {
-            #[cfg(not(target_arch = "wasm32"))]
-            let extractor = self.yt_extractor.lock().await;
-            #[cfg(target_arch = "wasm32")]
-            let extractor = self
-                .yt_extractor
-                .lock()
-                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        self.cancellable(async move {
+            let extractor = &self.yt_extractor;
             extractor.extract_video_info(video_id).await
         })
     }
@@ -278,15 +762,10 @@ impl Extract for Tydle {
         &'a self,
         manifest: &'a YtManifest,
     ) -> Self::ExtractStreamFut<'a> {
-        Box::pin(async move {
-            #[cfg(not(target_arch = "wasm32"))]
-            let extractor = self.yt_extractor.lock().await;
-            #[cfg(target_arch = "wasm32")]
-            let extractor = self
-                .yt_extractor
-                .lock()
-                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
-            extractor.extract_streams_from_manifest(manifest).await
+        self.cancellable(async move {
+            let extractor = &self.yt_extractor;
+            let stream_response = extractor.extract_streams_from_manifest(manifest).await?;
+            self.finish_streams(stream_response).await
         })
     }
 
@@ -294,34 +773,150 @@ impl Extract for Tydle {
         &'a self,
         manifest: &'a YtManifest,
     ) -> Self::ExtractInfoFut<'a> {
-        Box::pin(async move {
-            #[cfg(not(target_arch = "wasm32"))]
-            let extractor = self.yt_extractor.lock().await;
-            #[cfg(target_arch = "wasm32")]
-            let extractor = self
-                .yt_extractor
-                .lock()
-                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        self.cancellable(async move {
+            let extractor = &self.yt_extractor;
             extractor.extract_video_info_from_manifest(manifest).await
         })
     }
+
+    fn get_playlist<'a>(&'a self, playlist_id: &'a PlaylistId) -> Self::ExtractPlaylistFut<'a> {
+        self.cancellable(async move {
+            let extractor = &self.yt_extractor;
+            extractor.extract_playlist(playlist_id).await
+        })
+    }
+
+    fn get_channel_feed<'a>(&'a self, channel: &'a YtChannel) -> Self::ExtractFeedFut<'a> {
+        self.cancellable(async move {
+            let extractor = &self.yt_extractor;
+            extractor.extract_channel_feed(channel).await
+        })
+    }
+
+    fn get_subscriptions_feed(&self) -> Self::ExtractPlaylistFut<'_> {
+        self.cancellable(async move {
+            let extractor = &self.yt_extractor;
+            extractor.extract_subscriptions_feed().await
+        })
+    }
 }
 
-#[cfg(feature = "cipher")]
+impl Fetch for Tydle {
+    #[cfg(not(target_arch = "wasm32"))]
+    type FetchTextFut<'a> = Pin<Box<dyn Future<Output = Result<String, TydleError>> + Send + 'a>>;
+    #[cfg(not(target_arch = "wasm32"))]
+    type FetchBytesFut<'a> = Pin<Box<dyn Future<Output = Result<Vec<u8>, TydleError>> + Send + 'a>>;
+
+    #[cfg(target_arch = "wasm32")]
+    type FetchTextFut<'a> = Pin<Box<dyn Future<Output = Result<String, TydleError>> + 'a>>;
+    #[cfg(target_arch = "wasm32")]
+    type FetchBytesFut<'a> = Pin<Box<dyn Future<Output = Result<Vec<u8>, TydleError>> + 'a>>;
+
+    fn get_subtitle<'a>(
+        &'a self,
+        subtitle: &'a YtSubtitle,
+        format: YtSubtitleFormat,
+    ) -> Self::FetchTextFut<'a> {
+        self.cancellable(async move {
+            let extractor = &self.yt_extractor;
+            let subtitle_bytes = extractor
+                .download_resource(&subtitle.url_with_format(format)?)
+                .await?;
+            Ok(String::from_utf8(subtitle_bytes)?)
+        })
+    }
+
+    fn get_thumbnail<'a>(&'a self, thumbnail: &'a YtThumbnail) -> Self::FetchBytesFut<'a> {
+        self.cancellable(async move {
+            let extractor = &self.yt_extractor;
+            extractor.download_resource(&thumbnail.url).await
+        })
+    }
+}
+
+/// The `DiskCache` in `cache_dir`, cleaned of its expired entries, or a `MemoryCache` when there's no
+/// cache directory.
+#[cfg(not(target_arch = "wasm32"))]
+fn default_cache(options: &TydleOptions) -> Arc<dyn Cache> {
+    let Some(dir) = options
+        .cache_dir
+        .clone()
+        .or_else(DiskCache::default_dir)
+        .filter(|_| !options.no_cache_dir)
+    else {
+        return Arc::new(MemoryCache::new());
+    };
+
+    let cache = DiskCache::new(dir).ttl(Some(DISK_CACHE_TTL));
+    if let Err(e) = cache.remove_expired() {
+        tracing::warn!(
+            "Failed to clean up the cache in {}: {:#}",
+            cache.dir().display(),
+            e
+        );
+    }
+
+    Arc::new(cache)
+}
+
+/// Errors that aren't network or parsing ones are reported as deciphering failures.
+#[cfg(feature = "decipher")]
+fn cipher_error(err: anyhow::Error) -> TydleError {
+    match TydleError::from(err) {
+        TydleError::Other(e) => TydleError::Cipher(e),
+        e => e,
+    }
+}
+
+#[cfg(feature = "decipher")]
 impl Cipher for Tydle {
-    type DecipherFut<'a> = Pin<Box<dyn Future<Output = Result<String>> + 'a>>;
+    #[cfg(not(target_arch = "wasm32"))]
+    type DecipherFut<'a> = Pin<Box<dyn Future<Output = Result<String, TydleError>> + Send + 'a>>;
+    #[cfg(not(target_arch = "wasm32"))]
+    type ResolveFut<'a> =
+        Pin<Box<dyn Future<Output = Result<YtStreamResponse, TydleError>> + Send + 'a>>;
+    #[cfg(target_arch = "wasm32")]
+    type DecipherFut<'a> = Pin<Box<dyn Future<Output = Result<String, TydleError>> + 'a>>;
+    #[cfg(target_arch = "wasm32")]
+    type ResolveFut<'a> = Pin<Box<dyn Future<Output = Result<YtStreamResponse, TydleError>> + 'a>>;
 
     fn decipher_signature<'a>(
         &'a self,
-        signature: String,
-        player_url: String,
+        signature: &'a str,
+        player_url: &'a str,
     ) -> Self::DecipherFut<'a> {
         Box::pin(async move {
-            let signature_decipher = self
-                .signature_decipher
-                .lock()
-                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
-            signature_decipher.decipher(signature, player_url).await
+            self.signature_decipher
+                .decipher(signature.to_string(), player_url.to_string())
+                .await
+                .map_err(cipher_error)
+        })
+    }
+
+    fn decipher_url<'a>(&'a self, url: &'a str, player_url: &'a str) -> Self::DecipherFut<'a> {
+        Box::pin(async move {
+            self.signature_decipher
+                .decipher_n(url.to_string(), player_url.to_string())
+                .await
+                .map_err(cipher_error)
+        })
+    }
+
+    fn resolve_streams<'a>(
+        &'a self,
+        mut stream_response: YtStreamResponse,
+    ) -> Self::ResolveFut<'a> {
+        Box::pin(async move {
+            let mut urls = Vec::with_capacity(stream_response.streams.len());
+            for stream in stream_response.streams.iter() {
+                urls.push(self.resolve_url(stream, &stream_response).await?);
+            }
+
+            for (stream, url) in stream_response.streams.iter_mut().zip(urls) {
+                stream.source = YtStreamSource::URL(url);
+            }
+
+            Ok(stream_response)
         })
     }
 }
@@ -329,27 +924,38 @@ impl Cipher for Tydle {
 #[cfg(target_arch = "wasm32")]
 mod wasm_api {
     use super::*;
+    use crate::cipher::evaluator::{JsEvaluator, WorkerEvaluator};
     use wasm_bindgen::JsValue;
 
     #[wasm_bindgen]
     impl Tydle {
         #[wasm_bindgen(constructor)]
         pub fn new(options: Option<TydleOptions>) -> Result<Tydle, JsValue> {
-            let player_cache = Arc::new(CacheStore::new());
-            let code_cache = Arc::new(CacheStore::new());
+            let cache: Arc<dyn Cache> = Arc::new(MemoryCache::new());
 
-            let yt_extractor = YtExtractor::new(
-                player_cache.clone(),
-                code_cache.clone(),
-                options.unwrap_or_default(),
-            )
-            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+            let options = options.unwrap_or_default();
+            let (auto_decipher, js_worker) = (options.auto_decipher, options.js_worker);
+            let yt_extractor =
+                YtExtractor::new(cache, options).map_err(|e| JsValue::from_str(&e.to_string()))?;
 
-            let signature_decipher = SignatureDecipher::new(player_cache, code_cache);
+            let js_evaluator: Arc<dyn JsEvaluator> = if js_worker {
+                Arc::new(WorkerEvaluator::new().map_err(|e| JsValue::from_str(&e.to_string()))?)
+            } else {
+                default_evaluator()
+            };
+            let signature_decipher = SignatureDecipher::new(
+                yt_extractor.cache.clone(),
+                yt_extractor.transport.clone(),
+                js_evaluator,
+            )
+            .challenge_solver(yt_extractor.challenge_solver.clone())
+            .cookie_jar(Some(yt_extractor.cookie_jar.clone()))
+            .metrics(Some(yt_extractor.metrics.clone()));
 
             Ok(Tydle {
-                yt_extractor: Arc::new(Mutex::new(yt_extractor)),
-                signature_decipher: Arc::new(Mutex::new(signature_decipher)),
+                yt_extractor: Arc::new(yt_extractor),
+                signature_decipher: Arc::new(signature_decipher),
+                auto_decipher,
             })
         }
 
@@ -366,6 +972,39 @@ mod wasm_api {
                 .map_err(|e| JsValue::from_str(&e.to_string()))?)
         }
 
+        #[wasm_bindgen(js_name = "exportCookies")]
+        pub fn export_cookies_js(&self) -> Result<DomainCookies, JsValue> {
+            self.export_cookies()
+                .map_err(|e| JsValue::from_str(&e.to_string()))
+        }
+
+        /// Metrics of the instance in the Prometheus text exposition format.
+        #[wasm_bindgen(js_name = "prometheusMetrics")]
+        pub fn prometheus_metrics_js(&self) -> Result<String, JsValue> {
+            self.metrics()
+                .map(|metrics| metrics.to_prometheus())
+                .map_err(|e| JsValue::from_str(&e.to_string()))
+        }
+
+        #[wasm_bindgen(js_name = "checkAuth")]
+        pub async fn check_auth_js(&self) -> Result<(), JsValue> {
+            self.check_auth()
+                .await
+                .map_err(|e| JsValue::from_str(&e.to_string()))
+        }
+
+        #[wasm_bindgen(js_name = "warmPlayer")]
+        pub async fn warm_player_js(
+            &self,
+            #[wasm_bindgen(js_name = "videoId")] video_id: String,
+        ) -> Result<(), JsValue> {
+            let id = VideoId::new(&video_id).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+            self.warm_player(&id)
+                .await
+                .map_err(|e| JsValue::from_str(&e.to_string()))
+        }
+
         #[wasm_bindgen(js_name = "fetchVideoInfo")]
         pub async fn fetch_video_info(
             &self,
@@ -414,6 +1053,99 @@ mod wasm_api {
                 .map_err(|e| JsValue::from_str(&e.to_string()))?)
         }
 
+        /// A `ReadableStream` of the stream `itag` of the video, downloaded in ranges as it is read.
+        #[wasm_bindgen(js_name = "downloadStream")]
+        pub async fn download_stream_js(
+            &self,
+            #[wasm_bindgen(js_name = "videoId")] video_id: String,
+            itag: u16,
+        ) -> Result<web_sys::ReadableStream, JsValue> {
+            let id = VideoId::new(&video_id).map_err(|e| JsValue::from_str(&e.to_string()))?;
+            let stream_response = self
+                .get_streams(&id)
+                .await
+                .map_err(|e| JsValue::from_str(&e.to_string()))?;
+            let stream = stream_response
+                .streams
+                .iter()
+                .find(|stream| stream.itag == itag)
+                .ok_or(JsValue::from_str(
+                    &TydleError::FormatNotFound(itag.to_string()).to_string(),
+                ))?;
+            let url = self
+                .resolve_url(stream, &stream_response)
+                .await
+                .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+            crate::web_stream::ranged_stream(
+                self.yt_extractor.transport.clone(),
+                url,
+                self.yt_extractor.tydle_options.stream_proxy.clone(),
+                stream.file_size,
+            )
+        }
+
+        #[wasm_bindgen(js_name = "fetchPlaylist")]
+        pub async fn fetch_playlist(
+            &self,
+            #[wasm_bindgen(js_name = "playlistId")] playlist_id: String,
+        ) -> Result<YtPlaylist, JsValue> {
+            let id =
+                PlaylistId::new(&playlist_id).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+            Ok(self
+                .get_playlist(&id)
+                .await
+                .map_err(|e| JsValue::from_str(&e.to_string()))?)
+        }
+
+        #[wasm_bindgen(js_name = "fetchChannelFeed")]
+        pub async fn fetch_channel_feed(
+            &self,
+            #[wasm_bindgen(js_name = "channelId")] channel_id: String,
+        ) -> Result<YtChannelFeed, JsValue> {
+            let channel =
+                YtChannel::new(channel_id, None).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+            Ok(self
+                .get_channel_feed(&channel)
+                .await
+                .map_err(|e| JsValue::from_str(&e.to_string()))?)
+        }
+
+        #[wasm_bindgen(js_name = "fetchSubscriptionsFeed")]
+        pub async fn fetch_subscriptions_feed(&self) -> Result<YtPlaylist, JsValue> {
+            Ok(self
+                .get_subscriptions_feed()
+                .await
+                .map_err(|e| JsValue::from_str(&e.to_string()))?)
+        }
+
+        #[wasm_bindgen(js_name = "fetchSubtitle")]
+        pub async fn fetch_subtitle(
+            &self,
+            subtitle: YtSubtitle,
+            format: Option<YtSubtitleFormat>,
+        ) -> Result<String, JsValue> {
+            Ok(self
+                .get_subtitle(&subtitle, format.unwrap_or_default())
+                .await
+                .map_err(|e| JsValue::from_str(&e.to_string()))?)
+        }
+
+        #[wasm_bindgen(js_name = "fetchThumbnail")]
+        pub async fn fetch_thumbnail(
+            &self,
+            thumbnail: YtThumbnail,
+        ) -> Result<js_sys::Uint8Array, JsValue> {
+            let thumbnail_bytes = self
+                .get_thumbnail(&thumbnail)
+                .await
+                .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+            Ok(js_sys::Uint8Array::from(thumbnail_bytes.as_slice()))
+        }
+
         #[wasm_bindgen(js_name = "decipherSignature")]
         pub async fn decipher_signature_js(
             &self,
@@ -421,10 +1153,21 @@ mod wasm_api {
             #[wasm_bindgen(js_name = "playerUrl")] player_url: String,
         ) -> Result<String, JsValue> {
             let res = self
-                .decipher_signature(signature, player_url)
+                .decipher_signature(&signature, &player_url)
                 .await
                 .map_err(|e| JsValue::from_str(&e.to_string()))?;
             Ok(res)
         }
+
+        #[wasm_bindgen(js_name = "decipherUrl")]
+        pub async fn decipher_url_js(
+            &self,
+            url: String,
+            #[wasm_bindgen(js_name = "playerUrl")] player_url: String,
+        ) -> Result<String, JsValue> {
+            self.decipher_url(&url, &player_url)
+                .await
+                .map_err(|e| JsValue::from_str(&e.to_string()))
+        }
     }
 }
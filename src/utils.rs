@@ -4,7 +4,7 @@ use maplit::hashmap;
 use std::collections::HashMap;
 
 use crate::yt_interface::Ext;
-#[cfg(feature = "cipher")]
+#[cfg(feature = "decipher")]
 use url::Url;
 use url::form_urlencoded;
 
@@ -21,7 +21,7 @@ pub fn convert_to_query_string(map: &HashMap<String, String>) -> String {
     serializer.finish()
 }
 
-#[cfg(feature = "cipher")]
+#[cfg(feature = "decipher")]
 pub fn replace_n_sig_query_param(
     url_with_sig: &str,
     deciphered_n: String,
@@ -52,9 +52,9 @@ pub fn unix_timestamp_secs() -> f64 {
     epoch.as_secs_f64()
 }
 
-/// Returns the file size in bytes.
+/// Size in bytes of a stream of `tbr` bits per second lasting `duration` milliseconds.
 pub fn file_size_from_tbr(tbr: f64, duration: f64) -> f64 {
-    duration * tbr * (1000 / 8) as f64
+    duration / 1000.0 * tbr / 8.0
 }
 
 pub fn mime_type_to_ext(mime_type: &str) -> Ext {
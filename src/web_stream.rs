@@ -0,0 +1,94 @@
+use std::{cell::Cell, rc::Rc, sync::Arc};
+
+use anyhow::Result;
+use js_sys::{Function, Promise, Uint8Array};
+use url::{Url, form_urlencoded};
+use wasm_bindgen::{JsCast, JsValue, closure::Closure};
+use wasm_bindgen_futures::future_to_promise;
+use web_sys::{ReadableStream, ReadableStreamDefaultController, UnderlyingSource};
+
+use crate::http::{HttpRequest, HttpTransport};
+
+/// Size of each range request, bigger ones are throttled by YouTube.
+const CHUNK_SIZE: u64 = 10 * 1024 * 1024;
+
+/// A `ReadableStream` of the stream at `url`, fetched one range at a time as it is read.
+///
+/// When `proxy` is set, every range is fetched from it with the percent-encoded stream URL appended.
+pub(crate) fn ranged_stream(
+    transport: Arc<dyn HttpTransport>,
+    url: String,
+    proxy: Option<String>,
+    file_size: Option<u64>,
+) -> Result<ReadableStream, JsValue> {
+    let offset = Rc::new(Cell::new(0u64));
+
+    let pull = Closure::<dyn FnMut(ReadableStreamDefaultController) -> Promise>::new(
+        move |controller: ReadableStreamDefaultController| {
+            let transport = transport.clone();
+            let url = url.clone();
+            let proxy = proxy.clone();
+            let offset = offset.clone();
+
+            future_to_promise(async move {
+                let start = offset.get();
+                if file_size.is_some_and(|size| start >= size) {
+                    controller.close()?;
+                    return Ok(JsValue::UNDEFINED);
+                }
+
+                let end = match file_size {
+                    Some(size) => (start + CHUNK_SIZE).min(size) - 1,
+                    None => start + CHUNK_SIZE - 1,
+                };
+                let chunk = fetch_range(&*transport, &url, proxy.as_deref(), start, end)
+                    .await
+                    .map_err(|e| JsValue::from_str(&e.to_string()))?;
+                offset.set(start + chunk.len() as u64);
+
+                if !chunk.is_empty() {
+                    controller.enqueue_with_chunk(&Uint8Array::from(chunk.as_slice()))?;
+                }
+                // A range shorter than requested means the end of the stream was reached.
+                if (chunk.len() as u64) < end - start + 1
+                    || file_size.is_some_and(|size| offset.get() >= size)
+                {
+                    controller.close()?;
+                }
+
+                Ok(JsValue::UNDEFINED)
+            })
+        },
+    );
+
+    let source = UnderlyingSource::new();
+    source.set_pull(&pull.into_js_value().unchecked_into::<Function>());
+
+    ReadableStream::new_with_underlying_source(&source)
+}
+
+async fn fetch_range(
+    transport: &dyn HttpTransport,
+    url: &str,
+    proxy: Option<&str>,
+    start: u64,
+    end: u64,
+) -> Result<Vec<u8>> {
+    // Unlike a `Range` header, the `range` parameter doesn't need a CORS preflight.
+    let url = format!("{}&range={}-{}", url, start, end);
+    let url = match proxy {
+        Some(proxy) => format!(
+            "{}{}",
+            proxy,
+            form_urlencoded::byte_serialize(url.as_bytes()).collect::<String>()
+        ),
+        None => url,
+    };
+
+    transport
+        .send(HttpRequest::get(Url::parse(&url)?))
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await
+}
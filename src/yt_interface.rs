@@ -1,32 +1,42 @@
 use core::fmt;
-use std::{collections::HashMap, ops::Deref, str::FromStr};
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    str::FromStr,
+};
 
 use anyhow::{Result, anyhow, bail};
 use serde_json::Value;
 
 #[derive(Debug)]
 pub enum YtEndpoint {
-    // Browse,
+    Browse,
     Player,
     Next,
+    VisitorId,
 }
 
 impl YtEndpoint {
     pub fn as_str(&self) -> &'static str {
         match self {
-            // Self::Browse => "browse",
+            Self::Browse => "browse",
             Self::Player => "player",
             Self::Next => "next",
+            Self::VisitorId => "visitor_id",
         }
     }
 }
 
 #[cfg_attr(
-    target_arch = "wasm32",
-    derive(serde::Serialize, serde::Deserialize, tsify::Tsify),
-    tsify(into_wasm_abi, from_wasm_abi),
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
     serde(rename_all = "camelCase")
 )]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
 #[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub enum YtClient {
     #[default]
@@ -54,9 +64,12 @@ pub enum YtClient {
     TvEmbedded,
 }
 
-impl YtClient {
-    pub fn from_str(str_client: &str) -> YtClient {
-        match str_client {
+impl FromStr for YtClient {
+    type Err = anyhow::Error;
+
+    /// Parse the name of a client like "android_vr", as returned by `YtClient::as_str`.
+    fn from_str(str_client: &str) -> Result<Self> {
+        Ok(match str_client {
             "web" => Self::Web,
             "web_safari" => Self::WebSafari,
             "web_embedded" => Self::WebEmbedded,
@@ -70,10 +83,18 @@ impl YtClient {
             "tv" => Self::Tv,
             "tv_simply" => Self::TvSimply,
             "tv_embedded" => Self::TvEmbedded,
-            _ => Self::Web, // Return a default client.
-        }
+            _ => bail!("Unknown client \"{}\".", str_client),
+        })
     }
+}
 
+impl fmt::Display for YtClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl YtClient {
     pub fn as_str(&self) -> &'static str {
         match self {
             Self::Web => "web",
@@ -92,6 +113,17 @@ impl YtClient {
         }
     }
 
+    /// User agent the client requests YouTube with, which some servers expect the stream URLs it
+    /// gets to be fetched with too.
+    pub fn user_agent(&self) -> Option<&'static str> {
+        crate::extractor::client::INNERTUBE_CLIENTS
+            .get(self)?
+            .innertube_context
+            .get("client")?
+            .get("userAgent")?
+            .as_str()
+    }
+
     pub fn get_variant(&self) -> &'static str {
         self.as_str()
             .split_once('_')
@@ -107,6 +139,123 @@ impl YtClient {
     }
 }
 
+/// Summary of when a client needs a Proof of Origin Token.
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "camelCase")
+)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PoTokenRequirements {
+    /// Streaming URLs require a PO Token for at least one protocol.
+    pub gvs_required: bool,
+    /// A PO Token is recommended for streaming URLs even if it isn't required.
+    pub gvs_recommended: bool,
+    pub player_required: bool,
+    pub subs_required: bool,
+    /// Premium subscribers can skip the PO Token for every policy that requires one.
+    pub not_required_for_premium: bool,
+}
+
+/// A `YtClient` along with what it supports and requires.
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "camelCase")
+)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct YtClientInfo {
+    pub client: YtClient,
+    pub supports_cookies: bool,
+    pub require_auth: bool,
+    pub require_js_player: bool,
+    pub po_token: PoTokenRequirements,
+}
+
+/// Protocol a stream is downloaded over.
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Deserialize)
+)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Default, Clone, Copy, Hash, Eq, PartialEq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamingProtocol {
+    /// A single file, downloadable with range requests.
+    #[default]
+    Https,
+    /// Fragments listed in a DASH manifest, like the OTF streams of some premieres.
+    Dash,
+    Hls,
+}
+
+impl StreamingProtocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Https => "https",
+            Self::Dash => "dash",
+            Self::Hls => "hls",
+        }
+    }
+}
+
+impl fmt::Display for StreamingProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Where the player responses of a video are extracted from, see `TydleOptions::extraction_strategy`.
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "camelCase")
+)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExtractionStrategy {
+    /// Request them from the InnerTube API with every client.
+    #[default]
+    InnerTube,
+    /// Request them from the InnerTube API, and scrape the watch and embed pages if that fails.
+    Fallback,
+    /// Only scrape the watch and embed pages, without calling the InnerTube API. Fewer formats are
+    /// available, but it keeps working when the API is blocked or changed.
+    Webpage,
+}
+
+impl FromStr for ExtractionStrategy {
+    type Err = anyhow::Error;
+
+    /// Parse "innertube", "fallback" or "webpage".
+    fn from_str(strategy: &str) -> Result<Self> {
+        Ok(match strategy {
+            "innertube" => Self::InnerTube,
+            "fallback" => Self::Fallback,
+            "webpage" => Self::Webpage,
+            _ => bail!("Unknown extraction strategy \"{}\".", strategy),
+        })
+    }
+}
+
 pub(crate) const PREFERRED_LOCALE: &str = "en";
 pub(crate) const YT_DOMAIN: &str = ".youtube.com";
 pub(crate) const YT_URL: &str = "https://www.youtube.com";
@@ -203,12 +352,73 @@ impl fmt::Display for VideoId {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub struct PlaylistId(String);
+
+impl PlaylistId {
+    pub fn new<S: Into<String>>(s: S) -> Result<Self> {
+        let s = s.into();
+        if s.len() < 2 {
+            return Err(anyhow!("invalid playlist ID: {}", s));
+        }
+
+        if !s
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(anyhow!("invalid characters in playlist ID: {}", s));
+        }
+
+        Ok(Self(s))
+    }
+
+    /// Extract the playlist ID from the `list` parameter of a YouTube URL.
+    pub fn from_url(url: &str) -> Result<Self> {
+        let url = url::Url::parse(url)?;
+        let list = url
+            .query_pairs()
+            .find(|(k, _)| k == "list")
+            .ok_or(anyhow!("URL has no playlist: {}", url))?
+            .1;
+
+        Self::new(list)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether this is the Watch Later (`WL`) or Liked Videos (`LL`) playlist of the signed in
+    /// account, which can't be read without authentication cookies.
+    pub fn is_personal(&self) -> bool {
+        matches!(self.0.as_str(), "WL" | "LL")
+    }
+}
+
+impl FromStr for PlaylistId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+impl fmt::Display for PlaylistId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 #[cfg_attr(
-    target_arch = "wasm32",
-    derive(serde::Serialize, serde::Deserialize, tsify::Tsify),
-    tsify(into_wasm_abi, from_wasm_abi),
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
     serde(rename_all = "camelCase")
 )]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct YtChannel {
     id: String,
@@ -229,17 +439,25 @@ impl YtChannel {
         &self.id
     }
 
+    pub fn get_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     pub fn get_url(&self) -> String {
         format!("{}/channel/{}", YT_URL, self.id)
     }
 }
 
 #[cfg_attr(
-    target_arch = "wasm32",
-    derive(serde::Serialize, serde::Deserialize, tsify::Tsify),
-    tsify(into_wasm_abi, from_wasm_abi),
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
     serde(rename_all = "lowercase")
 )]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum YtStreamSource {
     URL(String),
@@ -247,11 +465,15 @@ pub enum YtStreamSource {
 }
 
 #[cfg_attr(
-    target_arch = "wasm32",
-    derive(serde::Serialize, serde::Deserialize, tsify::Tsify),
-    tsify(into_wasm_abi, from_wasm_abi),
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
     serde(rename_all = "camelCase")
 )]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
 #[derive(Debug, Clone)]
 pub struct YtStream {
     pub asr: Option<u64>,
@@ -267,7 +489,11 @@ pub struct YtStream {
     pub tbr: f64,
     pub fps: u16,
     pub audio_track: AudioTrackInfo,
+    /// `qualityLabel`, like "1080p60 HDR", or the quality of audio streams, like "medium".
     pub quality_label: String,
+    /// What sets the stream apart from others of its quality, like "English (default), 1080p60 HDR,
+    /// DRC".
+    pub format_note: String,
     pub is_drc: bool,
     pub projection: Option<String>,
     pub spatial_audio: Option<String>,
@@ -275,11 +501,62 @@ pub struct YtStream {
     pub ext: Ext,
     pub codec: Codec,
     pub is_dash: bool,
+    pub protocol: StreamingProtocol,
+    /// Inclusive byte range of the stream's initialization segment.
+    pub init_range: Option<(u64, u64)>,
+    /// Inclusive byte range of the stream's segment index (`sidx` box for mp4, `Cues` for webm).
+    pub index_range: Option<(u64, u64)>,
+}
+
+impl YtStream {
+    /// The size of the stream, exact when YouTube sent its `contentLength`, estimated from its
+    /// bitrate and duration otherwise. `None` when neither is known.
+    pub fn filesize_estimate(&self) -> Option<SizeEstimate> {
+        match self.file_size {
+            Some(bytes) => Some(SizeEstimate { exact: true, bytes }),
+            None if self.file_size_approx > 0.0 => Some(SizeEstimate {
+                exact: false,
+                bytes: self.file_size_approx.round() as u64,
+            }),
+            None => None,
+        }
+    }
+}
+
+/// Size of a stream in bytes, and whether it's exact or estimated from the bitrate.
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeEstimate {
+    pub exact: bool,
+    pub bytes: u64,
+}
+
+impl std::ops::Add for SizeEstimate {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            exact: self.exact && other.exact,
+            bytes: self.bytes + other.bytes,
+        }
+    }
 }
 
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
 #[cfg_attr(
     target_arch = "wasm32",
-    derive(serde::Serialize, serde::Deserialize, tsify::Tsify),
+    derive(tsify::Tsify),
     tsify(into_wasm_abi, from_wasm_abi)
 )]
 #[derive(Debug, Clone)]
@@ -289,11 +566,15 @@ pub struct Codec {
 }
 
 #[cfg_attr(
-    target_arch = "wasm32",
-    derive(serde::Serialize, serde::Deserialize, tsify::Tsify),
-    tsify(into_wasm_abi, from_wasm_abi),
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
     serde(rename_all = "camelCase")
 )]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
 #[derive(Debug, Clone)]
 pub struct AudioTrackInfo {
     pub display_name: Option<String>,
@@ -304,11 +585,15 @@ pub struct AudioTrackInfo {
 pub type YtStreams = Vec<YtStream>;
 
 #[cfg_attr(
-    target_arch = "wasm32",
-    derive(serde::Serialize, serde::Deserialize, tsify::Tsify),
-    tsify(into_wasm_abi, from_wasm_abi),
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
     serde(rename_all = "camelCase")
 )]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
 #[derive(Debug)]
 pub struct YtStreamList(YtStreams);
 
@@ -330,6 +615,12 @@ impl IntoIterator for YtStreamList {
     }
 }
 
+impl From<YtStreams> for YtStreamList {
+    fn from(streams: YtStreams) -> Self {
+        Self(streams)
+    }
+}
+
 impl Deref for YtStreamList {
     type Target = Vec<YtStream>;
 
@@ -338,6 +629,12 @@ impl Deref for YtStreamList {
     }
 }
 
+impl DerefMut for YtStreamList {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
 pub trait Filterable {
     /// Filter to return video-only streams.
     ///
@@ -472,6 +769,29 @@ pub trait Filterable {
     /// }
     /// ```
     fn only_urls(&self) -> YtStreamList;
+    /// Sort streams from best to worst according to `keys`, earlier keys taking precedence.
+    /// Streams that compare equal on every key are ordered by highest bitrate.
+    ///
+    /// ```
+    /// use tydle::{Tydle, TydleOptions, Extract, VideoId, Filterable, SortKey};
+    /// use anyhow::Result;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///   let ty = Tydle::new(TydleOptions { ..Default::default() })?;
+    ///   // Prefer up to 1080p, then the highest framerate, then AV1.
+    ///   let keys = SortKey::parse_list("res:1080,fps,vcodec:av01")?;
+    ///   let sorted = ty
+    ///      .get_streams(&VideoId::new("dQw4w9WgXcQ")?)
+    ///      .await?
+    ///      .streams
+    ///      .sorted_by(&keys);
+    ///
+    ///   println!("Best stream: {:?}", sorted.first());
+    ///   Ok(())
+    /// }
+    /// ```
+    fn sorted_by(&self, keys: &[SortKey]) -> YtStreamList;
 }
 
 impl Filterable for YtStreamList {
@@ -541,18 +861,372 @@ impl Filterable for YtStreamList {
                 .collect(),
         )
     }
+
+    fn sorted_by(&self, keys: &[SortKey]) -> YtStreamList {
+        let mut streams = self.0.clone();
+        streams.sort_by(|a, b| {
+            keys.iter()
+                .map(|key| key.compare(a, b))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| b.tbr.total_cmp(&a.tbr))
+        });
+
+        YtStreamList(streams)
+    }
+}
+
+/// Property of a stream that `SortKey` compares.
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "camelCase")
+)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    /// Smallest side of the video, `res`.
+    Resolution,
+    Fps,
+    /// Total bitrate, `tbr`.
+    Bitrate,
+    /// Audio sample rate, `asr`.
+    SampleRate,
+    /// Exact file size, `size`.
+    Size,
+    /// Video codec, `vcodec` (or `codec`). Ranked av01 > vp9 > h265 > h264 > vp8 by default.
+    VideoCodec,
+    /// Audio codec, `acodec`. Ranked opus > mp4a > vorbis by default.
+    AudioCodec,
+    /// Container, `ext`. Ranked mp4/m4a > webm by default.
+    Ext,
+}
+
+/// A single stream sorting preference, like `res:1080` or `+size`.
+///
+/// Numeric fields prefer larger values, or with a preferred value the largest value not exceeding it.
+/// Codec and ext fields put the preferred value first and rank the rest by their default order.
+/// A leading `+` reverses the preference.
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "camelCase")
+)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub field: SortField,
+    pub preferred: Option<String>,
+    pub reverse: bool,
+}
+
+impl SortKey {
+    /// Parse comma separated sort keys, like `res:1080,fps,codec:av01`.
+    pub fn parse_list(keys: &str) -> Result<Vec<SortKey>> {
+        keys.split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(SortKey::from_str)
+            .collect()
+    }
+
+    /// Compare two streams, `Ordering::Less` meaning `a` is preferred over `b`.
+    pub fn compare(&self, a: &YtStream, b: &YtStream) -> std::cmp::Ordering {
+        let ordering = self.rank(b).total_cmp(&self.rank(a));
+
+        if self.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    /// Score of a stream for this key, higher is better.
+    fn rank(&self, stream: &YtStream) -> f64 {
+        let number = |value: Option<f64>| {
+            let Some(value) = value else {
+                return f64::NEG_INFINITY;
+            };
+
+            match self
+                .preferred
+                .as_deref()
+                .and_then(|p| p.parse::<f64>().ok())
+            {
+                // Values above the preferred one rank below every value within it, closest first.
+                Some(limit) if value > limit => -value,
+                Some(_) => value + 1e12,
+                None => value,
+            }
+        };
+
+        let ranked = |value: Option<&str>, order: &[&str]| {
+            let Some(value) = value.filter(|v| *v != "none") else {
+                return f64::NEG_INFINITY;
+            };
+
+            if self
+                .preferred
+                .as_deref()
+                .is_some_and(|p| value.starts_with(p))
+            {
+                return order.len() as f64 + 1.0;
+            }
+
+            order
+                .iter()
+                .position(|o| value.starts_with(o))
+                .map(|i| (order.len() - i) as f64)
+                .unwrap_or_default()
+        };
+
+        match self.field {
+            SortField::Resolution => number(
+                stream
+                    .height
+                    .zip(stream.width)
+                    .map(|(h, w)| h.min(w) as f64),
+            ),
+            SortField::Fps => number(Some(stream.fps as f64).filter(|fps| *fps > 0.0)),
+            SortField::Bitrate => number(Some(stream.tbr)),
+            SortField::SampleRate => number(stream.asr.map(|asr| asr as f64)),
+            SortField::Size => number(
+                stream
+                    .filesize_estimate()
+                    .map(|estimate| estimate.bytes as f64),
+            ),
+            SortField::VideoCodec => ranked(
+                stream.codec.vcodec.as_deref(),
+                &["av01", "vp9", "vp09", "hev1", "hvc1", "avc1", "vp8"],
+            ),
+            SortField::AudioCodec => {
+                ranked(stream.codec.acodec.as_deref(), &["opus", "mp4a", "vorbis"])
+            }
+            SortField::Ext => ranked(Some(stream.ext.as_str()), &["mp4", "m4a", "webm"]),
+        }
+    }
 }
 
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(key: &str) -> Result<Self> {
+        let (reverse, key) = match key.strip_prefix('+') {
+            Some(key) => (true, key),
+            None => (false, key),
+        };
+        let (name, preferred) = match key.split_once(':') {
+            Some((name, preferred)) => (name, Some(preferred.to_string())),
+            None => (key, None),
+        };
+
+        let field = match name {
+            "res" => SortField::Resolution,
+            "fps" => SortField::Fps,
+            "tbr" | "br" => SortField::Bitrate,
+            "asr" => SortField::SampleRate,
+            "size" | "filesize" => SortField::Size,
+            "vcodec" | "codec" => SortField::VideoCodec,
+            "acodec" => SortField::AudioCodec,
+            "ext" => SortField::Ext,
+            _ => bail!("Unknown sort key \"{}\".", name),
+        };
+
+        Ok(Self {
+            field,
+            preferred,
+            reverse,
+        })
+    }
+}
+
+/// Something that went wrong during an extraction without failing it, like a client that was
+/// skipped. Every warning is also logged.
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "camelCase", tag = "kind")
+)]
 #[cfg_attr(
     target_arch = "wasm32",
-    derive(serde::Serialize, serde::Deserialize, tsify::Tsify),
-    tsify(into_wasm_abi, from_wasm_abi),
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExtractionWarning {
+    /// The client doesn't support cookies, so it isn't used when authenticated.
+    CookiesUnsupported { client: YtClient },
+    /// The player response of the client failed or was invalid.
+    ClientFailed { client: YtClient, reason: String },
+    /// The video is age-restricted and unavailable to the client without authentication.
+    AgeRestricted { client: YtClient },
+    /// The video is age-restricted and YouTube requires the account to be verified.
+    AccountVerificationRequired { client: YtClient },
+    /// The InnerTube API failed, so the player responses were scraped from the webpages instead.
+    ApiFailed { reason: String },
+    /// YouTube asked the client to confirm it's not a bot, so clients that don't need PO tokens
+    /// were retried with fresh visitor data.
+    BotCheck { client: YtClient },
+    /// The watch page was a bot check interstitial, so only the InnerTube API was used.
+    BotCheckPage,
+    /// Formats of the client were skipped because they are DRM protected.
+    DrmProtected {
+        client: YtClient,
+        /// Whether the request was signed in, the `tv` client can apply DRM to a whole account.
+        authenticated: bool,
+    },
+}
+
+impl fmt::Display for ExtractionWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CookiesUnsupported { client } => write!(
+                f,
+                "Skipping client \"{}\" since it does not support cookies.",
+                client
+            ),
+            Self::ClientFailed { client, reason } => write!(f, "Skipped {}. {}", client, reason),
+            Self::ApiFailed { reason } => write!(
+                f,
+                "Falling back to scraping the webpages since the InnerTube API failed. {}",
+                reason
+            ),
+            Self::BotCheck { client } => write!(
+                f,
+                "YouTube asked client \"{}\" to confirm it is not a bot, retrying with fresh visitor data and clients that don't need PO tokens.",
+                client
+            ),
+            Self::BotCheckPage => write!(
+                f,
+                "The watch page asked to confirm this is not a bot, continuing without it."
+            ),
+            Self::AgeRestricted { client } => write!(
+                f,
+                "Skipping client \"{}\" since the video is age-restricted and unavailable without authentication.",
+                client
+            ),
+            Self::AccountVerificationRequired { client } => write!(
+                f,
+                "Skipping client \"{}\" since the video is age-restricted and YouTube is requiring account verification.",
+                client
+            ),
+            Self::DrmProtected {
+                client,
+                authenticated,
+            } => {
+                write!(
+                    f,
+                    "Some {} client https formats have been skipped as they are DRM protected.",
+                    client
+                )?;
+                if *client == YtClient::Tv {
+                    write!(
+                        f,
+                        " {} may have an experiment that applies DRM to all videos on the `tv` client.\nSee  https://github.com/yt-dlp/yt-dlp/issues/12563  for more details.",
+                        if *authenticated {
+                            "Your account"
+                        } else {
+                            "The current session"
+                        }
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl ExtractionWarning {
+    /// What the warning means for the client it is about, if it made tydle skip or drop it.
+    pub fn client_outcome(&self) -> Option<(YtClient, ClientOutcome)> {
+        match self {
+            Self::CookiesUnsupported { client }
+            | Self::AgeRestricted { client }
+            | Self::AccountVerificationRequired { client } => Some((
+                *client,
+                ClientOutcome::Skipped {
+                    reason: self.to_string(),
+                },
+            )),
+            Self::ClientFailed { client, reason } => Some((
+                *client,
+                ClientOutcome::Failed {
+                    reason: reason.clone(),
+                },
+            )),
+            Self::BotCheck { client } => Some((
+                *client,
+                ClientOutcome::Failed {
+                    reason: self.to_string(),
+                },
+            )),
+            Self::ApiFailed { .. } | Self::BotCheckPage | Self::DrmProtected { .. } => None,
+        }
+    }
+
+    /// Log the warning and add it to `warnings`, unless it is already there.
+    pub(crate) fn push_to(self, warnings: &mut Vec<ExtractionWarning>) {
+        if !warnings.contains(&self) {
+            tracing::warn!("{}", self);
+            warnings.push(self);
+        }
+    }
+}
+
+/// What happened to a client during an extraction, see `YtStreamResponse::client_results`.
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "camelCase", tag = "outcome")
+)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientOutcome {
+    /// The client returned a player response with `formats` streams.
+    Succeeded { formats: usize },
+    /// The client wasn't used or its player response was dropped, like without the cookies an
+    /// age-restricted video needs.
+    Skipped { reason: String },
+    /// Requesting the player response of the client failed.
+    Failed { reason: String },
+}
+
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
     serde(rename_all = "camelCase")
 )]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
 #[derive(Debug)]
 pub struct YtStreamResponse {
     pub player_url: String,
     pub streams: YtStreamList,
+    /// Whether the video is being broadcast live right now.
+    /// Live streams are only playable through `hls_manifest_url` or `dash_manifest_url`.
+    pub is_live: bool,
+    pub hls_manifest_url: Option<String>,
+    pub dash_manifest_url: Option<String>,
+    /// What went wrong while extracting the streams without failing it.
+    #[cfg_attr(any(feature = "serde", target_arch = "wasm32"), serde(default))]
+    pub warnings: Vec<ExtractionWarning>,
+    /// Which clients were tried and how that went, to find out why formats are missing.
+    #[cfg_attr(any(feature = "serde", target_arch = "wasm32"), serde(default))]
+    pub client_results: HashMap<YtClient, ClientOutcome>,
 }
 
 impl YtStreamResponse {
@@ -560,20 +1234,32 @@ impl YtStreamResponse {
         Self {
             player_url,
             streams: YtStreamList(streams),
+            is_live: false,
+            hls_manifest_url: None,
+            dash_manifest_url: None,
+            warnings: Vec::new(),
+            client_results: HashMap::new(),
         }
     }
 }
 
 #[cfg_attr(
-    target_arch = "wasm32",
-    derive(serde::Serialize, serde::Deserialize, tsify::Tsify,),
-    tsify(into_wasm_abi, from_wasm_abi),
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
     serde(rename_all = "camelCase")
 )]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
 #[derive(Debug)]
 pub struct YtManifest {
     pub extracted_manifest: Vec<HashMap<String, Value>>,
     pub player_url: String,
+    /// What went wrong while fetching the player responses, carried over to the streams.
+    #[cfg_attr(any(feature = "serde", target_arch = "wasm32"), serde(default))]
+    pub warnings: Vec<ExtractionWarning>,
 }
 
 impl YtManifest {
@@ -581,16 +1267,21 @@ impl YtManifest {
         Self {
             extracted_manifest,
             player_url,
+            warnings: Vec::new(),
         }
     }
 }
 
 #[cfg_attr(
-    target_arch = "wasm32",
-    derive(serde::Serialize, serde::Deserialize, tsify::Tsify),
-    tsify(into_wasm_abi, from_wasm_abi),
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
     serde(rename_all = "camelCase")
 )]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
 #[derive(Debug, Default)]
 pub enum YtMediaType {
     LiveStream,
@@ -600,11 +1291,15 @@ pub enum YtMediaType {
 }
 
 #[cfg_attr(
-    target_arch = "wasm32",
-    derive(serde::Serialize, serde::Deserialize, tsify::Tsify),
-    tsify(into_wasm_abi, from_wasm_abi),
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
     serde(rename_all = "lowercase")
 )]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
 #[derive(Debug, Default)]
 pub enum YtAgeLimit {
     Adult,
@@ -612,25 +1307,135 @@ pub enum YtAgeLimit {
     None,
 }
 
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 #[cfg_attr(
     target_arch = "wasm32",
-    derive(serde::Serialize, serde::Deserialize, tsify::Tsify),
-    tsify(into_wasm_abi, from_wasm_abi),
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum YtAvailability {
+    #[default]
+    Public,
+    /// Only reachable through its link, no authentication is needed.
+    Unlisted,
+    /// Only extracted with the cookies of an account the video was shared with.
+    Private,
+}
+
+impl YtAvailability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Public => "public",
+            Self::Unlisted => "unlisted",
+            Self::Private => "private",
+        }
+    }
+}
+
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
     serde(rename_all = "camelCase")
 )]
-#[derive(Debug)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Clone)]
 pub struct YtThumbnail {
     pub url: String,
     pub height: Option<u64>,
     pub width: Option<u64>,
 }
 
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum YtSubtitleFormat {
+    #[default]
+    Vtt,
+    Srv1,
+    Srv2,
+    Srv3,
+    Ttml,
+    Json3,
+}
+
+impl YtSubtitleFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Vtt => "vtt",
+            Self::Srv1 => "srv1",
+            Self::Srv2 => "srv2",
+            Self::Srv3 => "srv3",
+            Self::Ttml => "ttml",
+            Self::Json3 => "json3",
+        }
+    }
+}
+
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "camelCase")
+)]
 #[cfg_attr(
     target_arch = "wasm32",
-    derive(serde::Serialize, serde::Deserialize, tsify::Tsify),
-    tsify(into_wasm_abi, from_wasm_abi),
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Clone)]
+pub struct YtSubtitle {
+    /// Timed text URL without a format, use `YtSubtitle::url_with_format` to request one.
+    pub url: String,
+    pub language_code: String,
+    pub name: Option<String>,
+    /// Whether the track was generated by YouTube's automatic speech recognition.
+    pub is_auto_generated: bool,
+}
+
+impl YtSubtitle {
+    pub fn url_with_format(&self, format: YtSubtitleFormat) -> Result<String> {
+        let mut url = url::Url::parse(&self.url)?;
+        let query_pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .into_owned()
+            .filter(|(k, _)| k != "fmt")
+            .collect();
+
+        url.query_pairs_mut()
+            .clear()
+            .extend_pairs(query_pairs)
+            .append_pair("fmt", format.as_str());
+
+        Ok(url.to_string())
+    }
+}
+
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
     serde(rename_all = "camelCase")
 )]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
 #[derive(Debug)]
 pub struct YtVideoInfo {
     pub title: String,
@@ -641,21 +1446,28 @@ pub struct YtVideoInfo {
     pub channel: YtChannel,
     pub keywords: Vec<String>,
     pub thumbnails: Vec<YtThumbnail>,
+    pub subtitles: Vec<YtSubtitle>,
     pub media_type: YtMediaType,
     pub age_limit: YtAgeLimit,
+    pub availability: YtAvailability,
 }
 
 #[cfg_attr(
-    target_arch = "wasm32",
-    derive(serde::Serialize, serde::Deserialize, tsify::Tsify),
-    tsify(into_wasm_abi, from_wasm_abi),
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
     serde(rename_all = "lowercase")
 )]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
 #[derive(Debug, Default, Clone, Copy)]
 pub enum Ext {
     #[default]
     Unknown,
     /// 3gp, renamed because identifiers can't start with a number.
+    #[cfg_attr(any(feature = "serde", target_arch = "wasm32"), serde(rename = "3gp"))]
     ThreeGp,
     Ts,
     Mp4,
@@ -763,3 +1575,146 @@ impl Ext {
         }
     }
 }
+
+impl FromStr for Ext {
+    type Err = anyhow::Error;
+
+    /// Parse an extension like "mp4", as returned by `Ext::as_str`.
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "unknown" => Self::Unknown,
+            "3gp" => Self::ThreeGp,
+            "ts" => Self::Ts,
+            "mp4" => Self::Mp4,
+            "mpeg" => Self::Mpeg,
+            "m3u8" => Self::M3u8,
+            "mov" => Self::Mov,
+            "webm" => Self::Webm,
+            "vp9" => Self::Vp9,
+            "ogv" => Self::Ogv,
+            "flv" => Self::Flv,
+            "m4v" => Self::M4v,
+            "mkv" => Self::Mkv,
+            "mng" => Self::Mng,
+            "asf" => Self::Asf,
+            "wmv" => Self::Wmv,
+            "avi" => Self::Avi,
+            "mpd" => Self::Mpd,
+            "f4m" => Self::F4m,
+            "ism" => Self::Ism,
+            "m4a" => Self::M4a,
+            "mp3" => Self::Mp3,
+            "mka" => Self::Mka,
+            "m3u" => Self::M3u,
+            "aac" => Self::Aac,
+            "flac" => Self::Flac,
+            "mid" => Self::Mid,
+            "ogg" => Self::Ogg,
+            "wav" => Self::Wav,
+            "ra" => Self::Ra,
+            "avif" => Self::Avif,
+            "bmp" => Self::Bmp,
+            "gif" => Self::Gif,
+            "jpg" => Self::Jpg,
+            "png" => Self::Png,
+            "svg" => Self::Svg,
+            "tif" => Self::Tif,
+            "wbmp" => Self::Wbmp,
+            "webp" => Self::Webp,
+            "ico" => Self::Ico,
+            "jng" => Self::Jng,
+            "fs" => Self::Fs,
+            "tt" => Self::Tt,
+            "dfxp" => Self::Dfxp,
+            "ttml" => Self::Ttml,
+            "sami" => Self::Sami,
+            "gz" => Self::Gz,
+            "json" => Self::Json,
+            "xml" => Self::Xml,
+            "zip" => Self::Zip,
+            _ => bail!("Unknown extension \"{}\".", s),
+        })
+    }
+}
+
+impl fmt::Display for Ext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "camelCase")
+)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Clone)]
+pub struct YtPlaylistEntry {
+    pub video_id: String,
+    pub title: String,
+    /// Duration of the video in seconds, missing for live streams and premieres.
+    pub duration: Option<u64>,
+}
+
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "camelCase")
+)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Clone, Default)]
+pub struct YtPlaylist {
+    pub id: String,
+    pub title: String,
+    /// Videos of the playlist in playlist order. Unavailable videos are left out.
+    pub entries: Vec<YtPlaylistEntry>,
+}
+
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "camelCase")
+)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Clone)]
+pub struct YtFeedEntry {
+    pub video_id: String,
+    pub title: String,
+    /// RFC 3339 timestamp of the upload.
+    pub published: String,
+    /// RFC 3339 timestamp of the last change to the video's metadata.
+    pub updated: String,
+    pub description: Option<String>,
+    pub thumbnail: Option<String>,
+    pub view_count: Option<u64>,
+}
+
+#[cfg_attr(
+    any(feature = "serde", target_arch = "wasm32"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "camelCase")
+)]
+#[cfg_attr(
+    target_arch = "wasm32",
+    derive(tsify::Tsify),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[derive(Debug, Clone)]
+pub struct YtChannelFeed {
+    pub channel: YtChannel,
+    /// The latest uploads of the channel, newest first. The feed only carries the last 15.
+    pub entries: Vec<YtFeedEntry>,
+}
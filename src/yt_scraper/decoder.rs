@@ -0,0 +1,240 @@
+//! Decoding of the escaped payloads YouTube embeds in its pages, which serde_json can't read as
+//! they are.
+
+use anyhow::{Result, anyhow};
+use fancy_regex::Regex;
+use serde_json::Value;
+
+/// Decode the HTML entities of `text`, like `&amp;`, `&#39;` and `&#x2F;`. Unknown entities are
+/// kept as they are.
+pub fn decode_html_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..=end]);
+        match entity.and_then(decode_entity) {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[entity.unwrap_or_default().len() + 2..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    let code = match entity {
+        "amp" => return Some('&'),
+        "lt" => return Some('<'),
+        "gt" => return Some('>'),
+        "quot" => return Some('"'),
+        "apos" => return Some('\''),
+        "nbsp" => return Some('\u{a0}'),
+        _ => entity.strip_prefix('#')?,
+    };
+    let code = match code.strip_prefix(['x', 'X']) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => code.parse().ok()?,
+    };
+
+    char::from_u32(code)
+}
+
+/// Resolve the escapes of the body of a JavaScript string literal, like `\x7b`, `\u00e9`, `\u{1F600}`
+/// and `\n`. Malformed escapes are kept as they are.
+pub fn unescape_js_string(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        let Some(escape) = chars.next() else {
+            unescaped.push('\\');
+            break;
+        };
+        match escape {
+            'x' => match take_hex(&mut chars, 2) {
+                Some(code) => unescaped.extend(char::from_u32(code)),
+                None => unescaped.push_str("\\x"),
+            },
+            'u' => match take_unicode_escape(&mut chars) {
+                Some(c) => unescaped.push(c),
+                None => unescaped.push_str("\\u"),
+            },
+            'n' => unescaped.push('\n'),
+            'r' => unescaped.push('\r'),
+            't' => unescaped.push('\t'),
+            'b' => unescaped.push('\u{8}'),
+            'f' => unescaped.push('\u{c}'),
+            'v' => unescaped.push('\u{b}'),
+            '0' => unescaped.push('\0'),
+            // A backslash before a line break continues the literal on the next line.
+            '\n' => {}
+            other => unescaped.push(other),
+        }
+    }
+
+    unescaped
+}
+
+fn take_hex(chars: &mut std::iter::Peekable<std::str::Chars>, len: usize) -> Option<u32> {
+    let hex: String = chars.clone().take(len).collect();
+    if hex.len() != len || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    chars.nth(len - 1);
+
+    u32::from_str_radix(&hex, 16).ok()
+}
+
+/// The character of a `\u` escape whose `\u` was already read, joining surrogate pairs.
+fn take_unicode_escape(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<char> {
+    if chars.peek() == Some(&'{') {
+        let braced: String = chars.clone().skip(1).take_while(|c| *c != '}').collect();
+        let code = u32::from_str_radix(&braced, 16).ok()?;
+        chars.nth(braced.len() + 1);
+        return char::from_u32(code);
+    }
+
+    let code = take_hex(chars, 4)?;
+    if !(0xD800..0xDC00).contains(&code) {
+        return char::from_u32(code);
+    }
+
+    // A high surrogate has to be followed by the escape of a low one.
+    let mut lookahead = chars.clone();
+    if lookahead.next() != Some('\\') || lookahead.next() != Some('u') {
+        return None;
+    }
+    let low = take_hex(&mut lookahead, 4).filter(|low| (0xDC00..0xE000).contains(low))?;
+    *chars = lookahead;
+
+    char::from_u32(0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00))
+}
+
+/// The JSON text of the object that follows the first match of `start_pattern` in `html`, written
+/// either as an object literal or as a string literal holding it escaped, like
+/// `ytInitialData = '\x7b\x22...'`.
+pub fn find_script_json(html: &str, start_pattern: &str) -> Result<Option<String>> {
+    let re = Regex::new(start_pattern)?;
+    let Some(m) = re.find(html)? else {
+        return Ok(None);
+    };
+    let rest = html[m.end()..].trim_start();
+
+    Ok(match rest.chars().next() {
+        Some(quote @ ('\'' | '"')) => {
+            string_literal_body(&rest[1..], quote).map(unescape_js_string)
+        }
+        Some('{') => object_literal(rest).map(json_compatible_escapes),
+        _ => None,
+    })
+}
+
+/// Parse the object `find_script_json` finds, decoding HTML entities when the JSON itself is
+/// invalid, as it is in the attributes of some pages.
+pub fn extract_script_json(html: &str, start_pattern: &str) -> Result<Option<Value>> {
+    let Some(json) = find_script_json(html, start_pattern)? else {
+        return Ok(None);
+    };
+
+    match serde_json::from_str(&json) {
+        Ok(value) => Ok(Some(value)),
+        Err(e) => serde_json::from_str(&decode_html_entities(&json))
+            .map(Some)
+            .map_err(|_| {
+                anyhow!(
+                    "Failed to parse the JSON after \"{}\": {}",
+                    start_pattern,
+                    e
+                )
+            }),
+    }
+}
+
+/// The body of the string literal `text` starts in, up to the closing `quote`.
+fn string_literal_body(text: &str, quote: char) -> Option<&str> {
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == quote => return Some(&text[..i]),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// The object literal `text` starts with, up to its closing brace.
+fn object_literal(text: &str) -> Option<&str> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&text[..=i]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Turn the `\xHH` escapes JavaScript allows but JSON doesn't into `\u00HH`.
+fn json_compatible_escapes(json: &str) -> String {
+    let mut compatible = String::with_capacity(json.len());
+    let mut chars = json.chars();
+
+    while let Some(c) = chars.next() {
+        compatible.push(c);
+        if c != '\\' {
+            continue;
+        }
+
+        match chars.next() {
+            Some('x') => compatible.push_str("u00"),
+            Some(escape) => compatible.push(escape),
+            None => {}
+        }
+    }
+
+    compatible
+}
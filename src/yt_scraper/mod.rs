@@ -0,0 +1,125 @@
+//! Extraction without the InnerTube API, from the player responses YouTube embeds in its watch
+//! and embed pages. Used when the API is blocked or changed, see `ExtractionStrategy`.
+
+pub(crate) mod decoder;
+
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+
+use crate::{
+    extractor::{
+        download::ExtractorDownloadHandle,
+        extract::{InfoExtractor, YtExtractor},
+        player::ExtractorPlayerHandle,
+    },
+    yt_interface::{STREAMING_DATA_CLIENT_NAME, VideoId, YtClient},
+};
+
+pub trait ExtractorScraperHandle {
+    /// The player responses of the watch page `webpage`, and of the embed page when the watch page
+    /// has none with formats, along with the player URL.
+    async fn scrape_player_responses(
+        &self,
+        video_id: &VideoId,
+        webpage: &str,
+        webpage_ytcfg: &HashMap<String, Value>,
+    ) -> Result<(Vec<HashMap<String, Value>>, String)>;
+    /// The player response the embed page carries as escaped JSON in its ytcfg.
+    async fn scrape_embed_player_response(
+        &self,
+        video_id: &VideoId,
+    ) -> Result<(HashMap<String, Value>, String)>;
+}
+
+impl ExtractorScraperHandle for YtExtractor {
+    async fn scrape_player_responses(
+        &self,
+        video_id: &VideoId,
+        webpage: &str,
+        webpage_ytcfg: &HashMap<String, Value>,
+    ) -> Result<(Vec<HashMap<String, Value>>, String)> {
+        let mut prs = Vec::new();
+        let mut player_url = self.get_player_url(&[webpage_ytcfg])?;
+
+        // Some pages carry the player response as an escaped string literal.
+        let mut watch_pr: HashMap<String, Value> =
+            match decoder::extract_script_json(webpage, r"ytInitialPlayerResponse\s*=")? {
+                Some(pr) => serde_json::from_value(pr)?,
+                None => HashMap::new(),
+            };
+        if !watch_pr.is_empty() && self.invalid_player_response(&watch_pr, video_id).is_none() {
+            watch_pr.insert(
+                STREAMING_DATA_CLIENT_NAME.into(),
+                YtClient::Web.as_str().into(),
+            );
+            prs.push(watch_pr);
+        }
+
+        if !prs.iter().any(|pr| pr.contains_key("streamingData")) {
+            tracing::info!(
+                "{}: The watch page has no formats, trying the embed page.",
+                video_id.as_str()
+            );
+
+            match self.scrape_embed_player_response(video_id).await {
+                Ok((embed_pr, embed_player_url)) => {
+                    if player_url.is_empty() {
+                        player_url = embed_player_url;
+                    }
+                    prs.push(embed_pr);
+                }
+                Err(e) => tracing::warn!("Failed to scrape the embed page. {}", e),
+            }
+        }
+
+        if !prs.iter().any(|pr| pr.contains_key("streamingData")) {
+            return Err(prs
+                .iter()
+                .find_map(|pr| self.playability_error(pr))
+                .map(Into::into)
+                .unwrap_or(anyhow!(
+                    "Neither the watch nor the embed page had a player response with formats."
+                )));
+        }
+
+        Ok((prs, player_url))
+    }
+
+    async fn scrape_embed_player_response(
+        &self,
+        video_id: &VideoId,
+    ) -> Result<(HashMap<String, Value>, String)> {
+        let embed_url = format!(
+            "{}://www.youtube.com/embed/{}",
+            self.http_scheme(),
+            video_id.as_str()
+        );
+        let embed_page = self
+            .download_webpage(&embed_url, &YtClient::WebEmbedded, video_id)
+            .await?;
+        self.dump_page(video_id, "embed.html", &embed_page);
+        let embed_ytcfg = self.extract_ytcfg(embed_page)?;
+
+        let embedded_pr = embed_ytcfg
+            .get("PLAYER_VARS")
+            .and_then(|vars| vars.get("embedded_player_response"))
+            .and_then(|pr| pr.as_str())
+            .ok_or(anyhow!("The embed page has no player response."))?;
+        let mut embed_pr: HashMap<String, Value> = serde_json::from_str(embedded_pr)?;
+
+        if let Some(invalid_pr_id) = self.invalid_player_response(&embed_pr, video_id) {
+            return Err(anyhow!(
+                "The embed page has the player response of \"{}\".",
+                invalid_pr_id
+            ));
+        }
+        embed_pr.insert(
+            STREAMING_DATA_CLIENT_NAME.into(),
+            YtClient::WebEmbedded.as_str().into(),
+        );
+
+        Ok((embed_pr, self.get_player_url(&[&embed_ytcfg])?))
+    }
+}
@@ -0,0 +1,112 @@
+//! Extraction of videos whose responses were written to `tests/fixtures` by a
+//! `RecordingTransport`, replayed without the network. The responses are trimmed down to what
+//! extraction reads.
+#![cfg(feature = "test-fixtures")]
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use tydle::{
+    Extract, MemoryCache, Tydle, TydleError, TydleOptions, VideoId, YtAvailability,
+    cookies::{Cookie, DomainCookies},
+    fixtures::ReplayTransport,
+    http::{HttpRequest, Middleware},
+};
+
+/// Records whether each request was sent with the `Authorization` header of signed in sessions.
+#[derive(Default)]
+struct AuthorizationLog(Mutex<Vec<bool>>);
+
+impl Middleware for AuthorizationLog {
+    fn on_request(&self, request: HttpRequest) -> Result<HttpRequest> {
+        let authorized = request
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("authorization"));
+        self.0.lock().unwrap().push(authorized);
+
+        Ok(request)
+    }
+}
+
+fn replay(fixtures: &str, auth_cookies: DomainCookies) -> Tydle {
+    replay_with(fixtures, auth_cookies, vec![])
+}
+
+fn replay_with(
+    fixtures: &str,
+    auth_cookies: DomainCookies,
+    middlewares: Vec<Arc<dyn Middleware>>,
+) -> Tydle {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(fixtures);
+
+    Tydle::new(TydleOptions {
+        transport: Some(Arc::new(ReplayTransport::new(dir))),
+        cache: Some(Arc::new(MemoryCache::new())),
+        auth_cookies,
+        middlewares,
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+/// Cookies of a signed in session, with the values the fixtures were recorded with redacted.
+fn owner_cookies() -> DomainCookies {
+    ["LOGIN_INFO", "SID", "SAPISID", "__Secure-3PAPISID"]
+        .into_iter()
+        .map(|name| Cookie {
+            name: name.to_string(),
+            value: "REDACTED".to_string(),
+            domain: ".youtube.com".to_string(),
+            secure: true,
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn private_video_is_reported_as_private() {
+    let tydle = replay("private", DomainCookies::default());
+
+    let err = tydle
+        .get_video_info(&VideoId::new("Zq3NX0f3UkU").unwrap())
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, TydleError::Private(reason) if reason == "This video is private"),
+        "{:?}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn unlisted_video_is_extracted() {
+    let tydle = replay("unlisted", DomainCookies::default());
+    let video_id = VideoId::new("Xn1uRQgHJuA").unwrap();
+
+    let video_info = tydle.get_video_info(&video_id).await.unwrap();
+    assert_eq!(video_info.title, "Unlisted fixture");
+    assert_eq!(video_info.availability, YtAvailability::Unlisted);
+
+    let streams = tydle.get_streams(&video_id).await.unwrap();
+    assert!(!streams.streams.is_empty());
+}
+
+#[tokio::test]
+async fn private_video_is_extracted_for_its_owner() {
+    let log = Arc::new(AuthorizationLog::default());
+    let tydle = replay_with("owner", owner_cookies(), vec![log.clone()]);
+
+    let video_info = tydle
+        .get_video_info(&VideoId::new("PbL5z2yTfkE").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(video_info.title, "Private fixture");
+    assert_eq!(video_info.availability, YtAvailability::Private);
+    assert!(log.0.lock().unwrap().iter().any(|authorized| *authorized));
+}